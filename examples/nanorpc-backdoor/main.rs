@@ -1,13 +1,12 @@
 //! A server and client implementation for a "backdoor" protocol that allows clients to run arbitrary commands on the server.
 
-use std::{net::SocketAddr, sync::Arc};
+use std::net::SocketAddr;
 
 use argh::FromArgs;
 
 mod protocol;
-use nanorpc::{JrpcRequest, RpcService};
+use nanorpc::HttpTransport;
 use protocol::*;
-use warp::Filter;
 
 /// Runs a server or client for the JSONRPC-over-HTTP-based backdoor protocol
 #[derive(FromArgs, PartialEq, Debug)]
@@ -50,18 +49,9 @@ async fn main() {
     let args: Args = argh::from_env();
     match args.nested {
         Subcommands::Server(server) => {
-            let service = Arc::new(BackdoorService(BackdoorImpl));
-            let endpoint = warp::path("backdoor").and(warp::body::json()).and_then(
-                move |item: JrpcRequest| {
-                    let service = service.clone();
-                    async move {
-                        Ok::<_, warp::Rejection>(
-                            serde_json::to_string(&service.respond_raw(item).await).unwrap(),
-                        )
-                    }
-                },
-            );
-            warp::serve(endpoint).run(server.listen).await;
+            nanorpc::serve_http(server.listen, BackdoorService(BackdoorImpl))
+                .await
+                .expect("cannot serve HTTP");
         }
         Subcommands::Client(cargs) => {
             let client = BackdoorClient(HttpTransport::new(format!(
@@ -1,5 +1,5 @@
 use async_trait::async_trait;
-use nanorpc::{nanorpc_derive, JrpcRequest, JrpcResponse, RpcTransport};
+use nanorpc::nanorpc_derive;
 use tokio::process::Command;
 
 /// The definition of the backdoor protocol. Note that we need to put `[nanorpc_derive]` before `[async_trait]`.
@@ -29,34 +29,3 @@ impl BackdoorProtocol for BackdoorImpl {
         )
     }
 }
-
-/// Transport implementation
-pub struct HttpTransport {
-    client: reqwest::Client,
-    url: String,
-}
-
-impl HttpTransport {
-    pub fn new(url: String) -> Self {
-        Self {
-            url,
-            client: reqwest::Client::new(),
-        }
-    }
-}
-
-#[async_trait]
-impl RpcTransport for HttpTransport {
-    type Error = anyhow::Error;
-
-    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
-        Ok(self
-            .client
-            .post(&self.url)
-            .body(serde_json::to_string(&req)?)
-            .send()
-            .await?
-            .json()
-            .await?)
-    }
-}
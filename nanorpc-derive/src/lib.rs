@@ -1,12 +1,71 @@
 use proc_macro::TokenStream;
 use quote::{quote, ToTokens};
-use syn::{parse_macro_input, spanned::Spanned, ItemTrait, ReturnType, TraitItem, Type};
+use syn::{
+    parse_macro_input, spanned::Spanned, AttributeArgs, ItemTrait, Lit, Meta, NestedMeta,
+    ReturnType, TraitItem, Type,
+};
 
 #[proc_macro_attribute]
 /// This procedural macro should be put on top of a `async_trait` trait with name ending in `...Protocol`, defining all the function signatures in the RPC protocol. Given a trait of name `FooProtocol`, the macro
 /// - automatically derives an `nanorpc::RpcService` implementation for `FooService`, a generated type that wraps around anything that implements `FooProtocol` --- these would be types that are server implementations of the protocol.
 /// - automatically generates `FooClient`, a client-side struct that wraps a `nanorpc::RpcTransport` and has methods mirroring `FooProtocol`.
-pub fn nanorpc_derive(_: TokenStream, input: TokenStream) -> TokenStream {
+///
+/// The macro optionally takes a comma-separated list of options:
+/// - `safe_integers`: integers too big to be exactly represented by a JavaScript number are sent as strings, so that they survive JavaScript intermediaries.
+/// - `float_policy = "error" | "null" | "string"`: what to do with NaN and infinite floats, which JSON cannot represent. Defaults to `"null"`.
+/// - `lenient_params`: the server coerces obvious type mismatches in incoming arguments, like numeric strings where numbers are expected. See `nanorpc::JsonConfig::lenient`.
+/// - `local`: for protocols whose futures are not `Send`, declared with `#[async_trait(?Send)]`. `FooService` then implements `nanorpc::LocalRpcService`, and `FooClient` wraps a `nanorpc::LocalRpcTransport`, so that both work on single-threaded executors and WASM.
+pub fn nanorpc_derive(args: TokenStream, input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as AttributeArgs);
+    let mut safe_integers = false;
+    let mut lenient_params = false;
+    let mut local = false;
+    let mut float_policy = quote! {Null};
+    for arg in args {
+        match arg {
+            NestedMeta::Meta(Meta::Path(path)) if path.is_ident("safe_integers") => {
+                safe_integers = true
+            }
+            NestedMeta::Meta(Meta::Path(path)) if path.is_ident("lenient_params") => {
+                lenient_params = true
+            }
+            NestedMeta::Meta(Meta::Path(path)) if path.is_ident("local") => local = true,
+            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("float_policy") => {
+                float_policy = match nv.lit {
+                    Lit::Str(s) if s.value() == "error" => quote! {Error},
+                    Lit::Str(s) if s.value() == "null" => quote! {Null},
+                    Lit::Str(s) if s.value() == "string" => quote! {String},
+                    _ => panic!("float_policy must be one of \"error\", \"null\", or \"string\""),
+                }
+            }
+            other => panic!(
+                "unknown nanorpc_derive option {:?}",
+                other.to_token_stream().to_string()
+            ),
+        }
+    }
+    // how values are converted to and from JSON in the generated code
+    let json = quote! {
+        (nanorpc::JsonConfig {
+            safe_integers: #safe_integers,
+            float_policy: nanorpc::FloatPolicy::#float_policy,
+            lenient: false,
+        })
+    };
+    let params_json = quote! {
+        (nanorpc::JsonConfig {
+            safe_integers: #safe_integers,
+            float_policy: nanorpc::FloatPolicy::#float_policy,
+            lenient: #lenient_params,
+        })
+    };
+    let encode_failed = quote! {
+        |e: ::serde_json::Error| nanorpc::ServerError {
+            code: 1,
+            message: format!("serialization of result failed: {}", e),
+            details: ::serde_json::Value::Null,
+        }
+    };
     let input = parse_macro_input!(input as ItemTrait);
     let input_again = input.clone();
     let protocol_name = input.ident;
@@ -35,8 +94,24 @@ pub fn nanorpc_derive(_: TokenStream, input: TokenStream) -> TokenStream {
         protocol_name.span(),
     );
 
+    // the traits that the generated service and client are built on
+    let (service_trait, transport_trait, async_trait_attr) = if local {
+        (
+            quote! {nanorpc::LocalRpcService},
+            quote! {nanorpc::LocalRpcTransport},
+            quote! {#[::async_trait::async_trait(?Send)]},
+        )
+    } else {
+        (
+            quote! {nanorpc::RpcService},
+            quote! {nanorpc::RpcTransport},
+            quote! {#[::async_trait::async_trait]},
+        )
+    };
+
     // Generate the server implementation.
     let mut server_match = quote! {};
+    let mut method_names = vec![];
     let mut client_body = quote! {};
     for item in input.items {
         match item {
@@ -57,7 +132,7 @@ pub fn nanorpc_derive(_: TokenStream, input: TokenStream) -> TokenStream {
                         }
                         syn::FnArg::Typed(_) => {
                             let index = idx - offset;
-                            quote! {if let ::std::option::Option::Some(::std::result::Result::Ok(v)) = __nrpc_args.get(#index).map(|v|::serde_json::from_value(v.clone())) {v} else {
+                            quote! {if let ::std::option::Option::Some(::std::result::Result::Ok(v)) = __nrpc_args.get(#index).map(|v|#params_json.from_value(v.clone())) {v} else {
                                 // badly formatted argument
                                 return Some(
                                     ::std::result::Result::Err(nanorpc::ServerError{
@@ -74,6 +149,7 @@ pub fn nanorpc_derive(_: TokenStream, input: TokenStream) -> TokenStream {
                     .unwrap();
                 // let method_call = method_call.to_string();
                 let method_name_str = method_name.to_string();
+                method_names.push(method_name_str.clone());
 
                 // TODO a better heuristic here
                 let is_fallible = inner
@@ -87,20 +163,25 @@ pub fn nanorpc_derive(_: TokenStream, input: TokenStream) -> TokenStream {
                         #server_match
                         #method_name_str => {
                             let raw = #protocol_name::#method_name(#method_call).await;
-                            let ok_mapped = raw.map(|o| ::serde_json::to_value(o).expect("serialization failed"));
-                            let err_mapped = ok_mapped.map_err(|e| nanorpc::ServerError{
-                                code: 1,
-                                message: e.to_string(),
-                                details: ::serde_json::to_value(e).expect("serialization failed")
-                            });
-                            ::std::option::Option::Some(err_mapped)
+                            let mapped = match raw {
+                                ::std::result::Result::Ok(o) => #json.to_value(&o).map_err(#encode_failed),
+                                ::std::result::Result::Err(e) => match #json.to_value(&e) {
+                                    ::std::result::Result::Ok(details) => ::std::result::Result::Err(nanorpc::ServerError{
+                                        code: 1,
+                                        message: e.to_string(),
+                                        details
+                                    }),
+                                    ::std::result::Result::Err(err) => ::std::result::Result::Err((#encode_failed)(err)),
+                                }
+                            };
+                            ::std::option::Option::Some(mapped)
                         }
                     };
                 } else {
                     server_match = quote! {
                         #server_match
                         #method_name_str => {
-                            ::std::option::Option::Some(::std::result::Result::Ok(::serde_json::to_value(#protocol_name::#method_name(#method_call).await).expect("serialization failed")))
+                            ::std::option::Option::Some(#json.to_value(&#protocol_name::#method_name(#method_call).await).map_err(#encode_failed))
                         }
                     };
                 }
@@ -124,7 +205,7 @@ pub fn nanorpc_derive(_: TokenStream, input: TokenStream) -> TokenStream {
                         syn::FnArg::Receiver(_) => None,
                         syn::FnArg::Typed(t) => match t.pat.as_ref() {
                             syn::Pat::Ident(varname) => {
-                                Some(quote! {__vb.push(::serde_json::to_value(&#varname).unwrap())})
+                                Some(quote! {__vb.push(#json.to_value(&#varname).map_err(#error_struct_name::FailedEncode)?)})
                             }
                             v => panic!("wild {:?}", v.to_token_stream()),
                         },
@@ -140,11 +221,11 @@ pub fn nanorpc_derive(_: TokenStream, input: TokenStream) -> TokenStream {
                     quote! {
                         match jsval  {
                             Ok(jsval) => {
-                                let retval = ::serde_json::from_value(jsval).map_err(#error_struct_name::FailedDecode)?;
+                                let retval = #json.from_value(jsval).map_err(#error_struct_name::FailedDecode)?;
                                 Ok(Ok(retval))
                             }
                             Err(serverr) => {
-                                Ok(Err(::serde_json::from_value(serverr.details).map_err(#error_struct_name::FailedDecode)?))
+                                Ok(Err(#json.from_value(serverr.details).map_err(#error_struct_name::FailedDecode)?))
                             }
                         }
                     }
@@ -152,7 +233,7 @@ pub fn nanorpc_derive(_: TokenStream, input: TokenStream) -> TokenStream {
                     quote! {
                         match jsval  {
                             Ok(jsval) => {
-                                let retval: #original_output = ::serde_json::from_value(jsval).map_err(#error_struct_name::FailedDecode)?;
+                                let retval: #original_output = #json.from_value(jsval).map_err(#error_struct_name::FailedDecode)?;
                                 Ok(retval)
                             }
                             Err(serverr) => {
@@ -166,7 +247,7 @@ pub fn nanorpc_derive(_: TokenStream, input: TokenStream) -> TokenStream {
 
                     pub #client_signature {
                         #vec_build;
-                        let result = nanorpc::RpcTransport::call(&self.0, #method_name, &__vb).await.map_err(#error_struct_name::Transport)?;
+                        let result = #transport_trait::call(&self.0, #method_name, &__vb).await.map_err(#error_struct_name::Transport)?;
                         match result {
                             None => Err(#error_struct_name::NotFound),
                             Some(jsval) => {
@@ -184,34 +265,61 @@ pub fn nanorpc_derive(_: TokenStream, input: TokenStream) -> TokenStream {
 
     // Generate the client implementation
     let client_type_comment = format!("Automatically generated client type that communicates to servers implementing the [{protocol_name}] protocol. The easiest way to use this is by using the `From<RpcTransport>` implementation. \n\nSee the [{protocol_name}] trait for further documentation on the functionality of the methods..");
-    let client_impl = quote! {
-        #[doc=#client_type_comment]
-        pub struct #client_struct_name<T: nanorpc::RpcTransport = nanorpc::DynRpcTransport>(pub T);
-
-        impl<T: nanorpc::RpcTransport> ::std::convert::From<T> for #client_struct_name
-            where
-            T::Error: Into<::anyhow::Error> {
-            fn from(transport: T) -> Self {
-                Self(nanorpc::DynRpcTransport::new(transport))
+    let client_impl = if local {
+        quote! {
+            #[doc=#client_type_comment]
+            pub struct #client_struct_name<T: nanorpc::LocalRpcTransport>(pub T);
+
+            impl<T: nanorpc::LocalRpcTransport> ::std::convert::From<T> for #client_struct_name<T> {
+                fn from(transport: T) -> Self {
+                    Self(transport)
+                }
+            }
+
+            impl <__nrpc_T: nanorpc::LocalRpcTransport> #client_struct_name<__nrpc_T> {
+                #client_body
             }
         }
+    } else {
+        quote! {
+            #[doc=#client_type_comment]
+            pub struct #client_struct_name<T: nanorpc::RpcTransport = nanorpc::DynRpcTransport>(pub T);
+
+            impl<T: nanorpc::RpcTransport> ::std::convert::From<T> for #client_struct_name
+                where
+                T::Error: Into<::anyhow::Error> {
+                fn from(transport: T) -> Self {
+                    Self(nanorpc::DynRpcTransport::new(transport))
+                }
+            }
 
-        impl <__nrpc_T: nanorpc::RpcTransport + Send + Sync + 'static> #client_struct_name<__nrpc_T> {
-            #client_body
+            impl <__nrpc_T: nanorpc::RpcTransport + Send + Sync + 'static> #client_struct_name<__nrpc_T> {
+                #client_body
+            }
         }
     };
+    let service_bounds = if local {
+        quote! {#protocol_name + 'static}
+    } else {
+        quote! {#protocol_name + ::std::marker::Sync + ::std::marker::Send + 'static}
+    };
 
     let error_type_comment = format!("Automatically generated error type that {client_struct_name} instances return from its methods");
-    let server_type_comment = format!("Automatically generated struct that wraps any 'business logic' struct implementing [{protocol_name}], and returns a JSON-RPC server implementing [nanorpc::RpcService]. See the [{protocol_name}] trait for further documentation.");
+    let server_type_comment = format!("Automatically generated struct that wraps any 'business logic' struct implementing [{protocol_name}], and returns a JSON-RPC server implementing [{}]. See the [{protocol_name}] trait for further documentation.", if local { "nanorpc::LocalRpcService" } else { "nanorpc::RpcService" });
     let assembled = quote! {
         #input_again
 
         #[doc=#server_type_comment]
         pub struct #server_struct_name<T: #protocol_name>(pub T);
 
-        #[::async_trait::async_trait]
-        impl <__nrpc_T: #protocol_name + ::std::marker::Sync + ::std::marker::Send + 'static> nanorpc::RpcService for #server_struct_name<__nrpc_T> {
-            async fn respond(&self, __nrpc_method: &str, __nrpc_args: Vec<::serde_json::Value>) -> Option<Result<::serde_json::Value, nanorpc::ServerError>> {
+        impl <__nrpc_T: #protocol_name> #server_struct_name<__nrpc_T> {
+            /// The names of all the methods in the protocol.
+            pub const METHODS: &'static [&'static str] = &[#(#method_names),*];
+        }
+
+        #async_trait_attr
+        impl <__nrpc_T: #service_bounds> #service_trait for #server_struct_name<__nrpc_T> {
+            async fn respond(&self, __nrpc_method: &str, __nrpc_args: &[::serde_json::Value]) -> Option<Result<::serde_json::Value, nanorpc::ServerError>> {
                 match __nrpc_method {
                 #server_match
                 _ => {None}
@@ -228,6 +336,8 @@ pub fn nanorpc_derive(_: TokenStream, input: TokenStream) -> TokenStream {
             ServerFail,
             #[error("failed to decode JSON response: {0:?}")]
             FailedDecode(::serde_json::Error),
+            #[error("failed to encode JSON request: {0:?}")]
+            FailedEncode(::serde_json::Error),
             #[error("transport-level error: {0:?}")]
             Transport(T)
         }
@@ -0,0 +1,127 @@
+//! Transports and server adapters for concrete protocols, each behind its own feature.
+
+#[cfg(any(
+    feature = "websocket",
+    feature = "tcp",
+    feature = "hyper1",
+    feature = "webtransport",
+    all(unix, feature = "unix")
+))]
+mod accept;
+#[cfg(feature = "actix")]
+mod actix;
+#[cfg(feature = "axum")]
+mod axum_router;
+#[cfg(all(feature = "compression", any(feature = "tcp", feature = "websocket")))]
+mod compress;
+#[cfg(feature = "wasm")]
+mod fetch;
+#[cfg(feature = "codec")]
+mod framing;
+#[cfg(feature = "grpc")]
+mod grpc;
+#[cfg(any(feature = "http-server", feature = "axum", feature = "warp"))]
+mod http;
+#[cfg(feature = "http-client")]
+mod http_client;
+#[cfg(feature = "http-server")]
+mod http_server;
+#[cfg(feature = "hyper1")]
+mod hyper_service;
+#[cfg(any(
+    feature = "tcp",
+    feature = "stdio",
+    feature = "libp2p",
+    feature = "serial",
+    feature = "sosistab2",
+    feature = "webtransport",
+    all(unix, feature = "unix")
+))]
+mod lines;
+#[cfg(feature = "melnet2")]
+mod melnet;
+#[cfg(feature = "msgpack")]
+mod msgpack;
+#[cfg(feature = "libp2p")]
+mod p2p;
+#[cfg(feature = "serial")]
+mod serial;
+#[cfg(all(unix, feature = "shm"))]
+mod shm;
+#[cfg(feature = "sosistab2")]
+mod sosistab;
+#[cfg(feature = "sse")]
+mod sse;
+#[cfg(feature = "stdio")]
+mod stdio;
+#[cfg(all(unix, feature = "systemd"))]
+mod systemd;
+#[cfg(feature = "tcp")]
+mod tcp;
+#[cfg(feature = "tls")]
+mod tls;
+#[cfg(feature = "udp")]
+mod udp;
+#[cfg(all(unix, feature = "unix"))]
+mod unix;
+#[cfg(feature = "warp")]
+mod warp_filter;
+#[cfg(feature = "websocket")]
+mod websocket;
+#[cfg(feature = "webtransport")]
+mod webtransport;
+#[cfg(feature = "zeromq")]
+mod zmq;
+
+#[cfg(feature = "actix")]
+pub use actix::*;
+#[cfg(feature = "axum")]
+pub use axum_router::*;
+#[cfg(all(feature = "compression", any(feature = "tcp", feature = "websocket")))]
+pub use compress::{Compression, COMPRESSION_METHOD};
+#[cfg(feature = "wasm")]
+pub use fetch::*;
+#[cfg(feature = "codec")]
+pub use framing::*;
+#[cfg(feature = "grpc")]
+pub use grpc::*;
+#[cfg(feature = "http-client")]
+pub use http_client::*;
+#[cfg(feature = "http-server")]
+pub use http_server::*;
+#[cfg(feature = "hyper1")]
+pub use hyper_service::*;
+#[cfg(feature = "melnet2")]
+pub use melnet::*;
+#[cfg(feature = "msgpack")]
+pub use msgpack::MsgpackCodec;
+#[cfg(feature = "libp2p")]
+pub use p2p::*;
+#[cfg(feature = "serial")]
+pub use serial::*;
+#[cfg(all(unix, feature = "shm"))]
+pub use shm::SHARED_MEMORY_METHOD;
+#[cfg(feature = "sosistab2")]
+pub use sosistab::*;
+#[cfg(feature = "sse")]
+pub use sse::*;
+#[cfg(feature = "stdio")]
+pub use stdio::*;
+#[cfg(all(unix, feature = "systemd"))]
+pub use systemd::*;
+#[cfg(feature = "tcp")]
+pub use tcp::*;
+#[cfg(feature = "tls")]
+pub use tls::{cert_fingerprint, rustls};
+#[cfg(feature = "udp")]
+pub use udp::*;
+#[cfg(all(unix, feature = "unix"))]
+pub use unix::*;
+#[cfg(feature = "warp")]
+pub use warp_filter::*;
+#[cfg(feature = "websocket")]
+pub use websocket::*;
+#[cfg(feature = "webtransport")]
+pub use webtransport::*;
+#[cfg(feature = "zeromq")]
+pub use zmq::*;
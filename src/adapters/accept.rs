@@ -0,0 +1,171 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use crate::{timer, ConnInfo, ServeHandle};
+
+/// How long to wait after the first failed accept, doubling up to [MAX_ACCEPT_BACKOFF] while accepting keeps failing.
+const MIN_ACCEPT_BACKOFF: Duration = Duration::from_millis(10);
+const MAX_ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Accepts TCP connections until the [ServeHandle] shuts down, running each through `on_conn` in its own tracked task.
+#[cfg(any(feature = "websocket", feature = "tcp", feature = "hyper1"))]
+pub(crate) async fn accept_tcp<F, Fut>(
+    listener: std::net::TcpListener,
+    handle: &ServeHandle,
+    on_conn: F,
+) -> std::io::Result<()>
+where
+    F: Fn(tokio::net::TcpStream, ConnInfo) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    listener.set_nonblocking(true)?;
+    let listener = tokio::net::TcpListener::from_std(listener)?;
+    accept_loop(
+        handle,
+        || async {
+            let (stream, peer_addr) = listener.accept().await?;
+            let mut conn = ConnInfo::new().with_peer_addr(peer_addr);
+            if let Ok(local_addr) = stream.local_addr() {
+                conn = conn.with_local_addr(local_addr);
+            }
+            Ok((stream, conn))
+        },
+        on_conn,
+    )
+    .await
+}
+
+/// Accepts Unix socket connections until the [ServeHandle] shuts down, running each through `on_conn` in its own tracked task.
+#[cfg(all(unix, feature = "unix"))]
+pub(crate) async fn accept_unix<F, Fut>(
+    listener: std::os::unix::net::UnixListener,
+    handle: &ServeHandle,
+    on_conn: F,
+) -> std::io::Result<()>
+where
+    F: Fn(tokio::net::UnixStream, ConnInfo) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    listener.set_nonblocking(true)?;
+    let listener = tokio::net::UnixListener::from_std(listener)?;
+    accept_loop(
+        handle,
+        || async {
+            let (stream, _) = listener.accept().await?;
+            Ok((stream, ConnInfo::new()))
+        },
+        on_conn,
+    )
+    .await
+}
+
+/// Accepts WebTransport sessions until the [ServeHandle] shuts down, running each through `on_conn` in its own tracked task. The session handshake is left to `on_conn`, so that a slow client cannot hold up the others.
+#[cfg(feature = "webtransport")]
+pub(crate) async fn accept_webtransport<F, Fut>(
+    endpoint: &wtransport::Endpoint<wtransport::endpoint::endpoint_side::Server>,
+    handle: &ServeHandle,
+    on_conn: F,
+) -> std::io::Result<()>
+where
+    F: Fn(wtransport::endpoint::IncomingSession, ConnInfo) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    accept_loop(
+        handle,
+        || async { Ok((endpoint.accept().await, ConnInfo::new())) },
+        on_conn,
+    )
+    .await
+}
+
+/// Runs the accept loop. Accept errors, like running out of file descriptors, usually persist for a while, so accepting is retried with backoff rather than right away.
+async fn accept_loop<T, A, AFut, F, Fut>(
+    handle: &ServeHandle,
+    accept: A,
+    on_conn: F,
+) -> std::io::Result<()>
+where
+    T: Send + 'static,
+    A: Fn() -> AFut,
+    AFut: Future<Output = std::io::Result<(T, ConnInfo)>>,
+    F: Fn(T, ConnInfo) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let shutdown = handle.shutdown_signal();
+    let on_conn = Arc::new(on_conn);
+    let mut backoff = MIN_ACCEPT_BACKOFF;
+    loop {
+        let accepted = futures_lite::future::or(async { Some(accept().await) }, async {
+            shutdown.wait().await;
+            None
+        })
+        .await;
+        let (stream, conn) = match accepted {
+            Some(Ok(accepted)) => accepted,
+            Some(Err(err)) => {
+                log::warn!(
+                    "could not accept connection, retrying in {:?}: {}",
+                    backoff,
+                    err
+                );
+                let slept = futures_lite::future::or(
+                    async {
+                        timer::sleep(backoff).await;
+                        true
+                    },
+                    async {
+                        shutdown.wait().await;
+                        false
+                    },
+                )
+                .await;
+                if !slept {
+                    return Ok(());
+                }
+                backoff = (backoff * 2).min(MAX_ACCEPT_BACKOFF);
+                continue;
+            }
+            None => return Ok(()),
+        };
+        backoff = MIN_ACCEPT_BACKOFF;
+        let conn = handle.prepare_conn(conn);
+        let handle = handle.clone();
+        let on_conn = on_conn.clone();
+        tokio::spawn(async move {
+            handle.track(on_conn(stream, conn)).await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use super::accept_loop;
+    use crate::{ConnInfo, ServeHandle};
+
+    #[tokio::test]
+    async fn test_accept_backoff() {
+        let handle = ServeHandle::new();
+        let attempts = AtomicUsize::new(0);
+        let accepting = accept_loop(
+            &handle,
+            || async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err::<((), ConnInfo), _>(std::io::Error::other("too many open files"))
+            },
+            |_, _| async {},
+        );
+        let shutdown = async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            handle.shutdown(Duration::from_secs(1)).await;
+        };
+        let (result, _) = tokio::join!(accepting, shutdown);
+        result.unwrap();
+        // 10 + 20 + 40 + 80 ms of backoff fit in 200 ms, but not much more
+        let attempts = attempts.load(Ordering::SeqCst);
+        assert!((2..=6).contains(&attempts), "{} attempts", attempts);
+    }
+}
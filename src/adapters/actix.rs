@@ -0,0 +1,122 @@
+use std::sync::Arc;
+
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+
+use crate::{RpcContext, RpcService, ServerConfig};
+
+/// An ActixRpc serves a service as an actix-web [Resource](actix_web::Resource), for mounting it inside an existing actix app. Requests are handled just like by a [crate::HttpServer]: only POST requests with JSON bodies are accepted, and bodies larger than [crate::Limits::max_message_size] are rejected with `413 Payload Too Large`.
+///
+/// actix-web builds a separate app on every worker thread, so an ActixRpc is made once, outside the app factory, and cheaply cloned into it:
+///
+/// ```ignore
+/// let rpc = nanorpc::ActixRpc::new(service);
+/// actix_web::HttpServer::new(move || actix_web::App::new().service(rpc.resource("/rpc")))
+/// ```
+///
+/// The peer's [std::net::SocketAddr] is attached to the context of every call, if known. Requires the `actix` feature.
+pub struct ActixRpc<S: RpcService> {
+    service: Arc<S>,
+    config: Arc<ServerConfig>,
+}
+
+impl<S: RpcService> Clone for ActixRpc<S> {
+    fn clone(&self) -> Self {
+        Self {
+            service: self.service.clone(),
+            config: self.config.clone(),
+        }
+    }
+}
+
+impl<S: RpcService> ActixRpc<S> {
+    /// Creates a new ActixRpc.
+    pub fn new(service: S) -> Self {
+        Self {
+            service: Arc::new(service),
+            config: Arc::new(ServerConfig::default()),
+        }
+    }
+
+    /// Sets the configuration used to handle requests.
+    pub fn with_config(mut self, config: ServerConfig) -> Self {
+        self.config = Arc::new(config);
+        self
+    }
+
+    /// Returns a resource serving the service at the given path. Other methods than POST are answered with `405 Method Not Allowed`.
+    pub fn resource(&self, path: &str) -> actix_web::Resource {
+        let this = self.clone();
+        web::resource(path)
+            .app_data(web::PayloadConfig::new(self.config.limits.max_message_size))
+            .route(web::post().to(move |req: HttpRequest, body: web::Bytes| {
+                let this = this.clone();
+                async move { this.handle_request(req, body).await }
+            }))
+    }
+
+    async fn handle_request(&self, req: HttpRequest, body: web::Bytes) -> HttpResponse {
+        let content_type = self.config.codec.content_type();
+        let is_supported = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|ct| ct.to_str().ok())
+            .and_then(|ct| ct.split(';').next())
+            .map(|mime| mime.trim().eq_ignore_ascii_case(content_type))
+            .unwrap_or(false);
+        if !is_supported {
+            return HttpResponse::UnsupportedMediaType().finish();
+        }
+        let mut ctx = RpcContext::new();
+        if let Some(peer_addr) = req.peer_addr() {
+            ctx.insert_extension(peer_addr);
+        }
+        let resp = self
+            .service
+            .respond_bytes_with_context(ctx, &body, &self.config)
+            .await;
+        HttpResponse::Ok().content_type(content_type).body(resp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, App};
+
+    use crate::{ActixRpc, FnService, Limits, ServerConfig};
+
+    #[actix_web::test]
+    async fn test_actix() {
+        let service = FnService::new(|_, params| async move { Some(Ok(params[0].clone())) });
+        let rpc = ActixRpc::new(service).with_config(ServerConfig {
+            limits: Limits {
+                max_message_size: 100,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let app = test::init_service(App::new().service(rpc.resource("/rpc"))).await;
+        let post = |body: String, content_type: &str| {
+            test::TestRequest::post()
+                .uri("/rpc")
+                .insert_header(("content-type", content_type))
+                .set_payload(body)
+                .to_request()
+        };
+        let resp: serde_json::Value = test::call_and_read_body_json(
+            &app,
+            post(
+                r#"{"jsonrpc": "2.0", "method": "f", "params": [1], "id": 1}"#.into(),
+                "application/json",
+            ),
+        )
+        .await;
+        assert_eq!(resp["result"], 1);
+        let status = |req| async { test::call_service(&app, req).await.status().as_u16() };
+        assert_eq!(status(post("{}".into(), "text/plain")).await, 415);
+        assert_eq!(status(post("x".repeat(101), "application/json")).await, 413);
+        assert_eq!(
+            status(test::TestRequest::get().uri("/rpc").to_request()).await,
+            405
+        );
+    }
+}
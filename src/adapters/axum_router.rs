@@ -0,0 +1,73 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::extract::ConnectInfo;
+use hyper::{Body, Request};
+
+use super::http::respond_http;
+use crate::{RpcService, ServerConfig};
+
+/// Returns an axum [Router](axum::Router) that serves a service at the given path, for mounting nanorpc services inside an existing axum app with something like [Router::merge](axum::Router::merge). Requests are handled just like by a [crate::HttpServer], with batches, parse errors, and size limits taken care of.
+///
+/// If the app is served with [connect info](axum::Router::into_make_service_with_connect_info) for [SocketAddr], the peer's address is attached to the context of every call. Requires the `axum` feature.
+pub fn into_axum_router(service: impl RpcService, path: &str) -> axum::Router {
+    into_axum_router_with_config(service, path, ServerConfig::default())
+}
+
+/// Like [into_axum_router], but with the given configuration.
+pub fn into_axum_router_with_config(
+    service: impl RpcService,
+    path: &str,
+    config: ServerConfig,
+) -> axum::Router {
+    let state = Arc::new((service, config));
+    axum::Router::new().route(
+        path,
+        axum::routing::any(move |req: Request<Body>| async move {
+            let peer_addr = req
+                .extensions()
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|info| info.0);
+            respond_http(&state.0, &state.1, peer_addr, req).await
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use crate::{into_axum_router, FnService};
+
+    #[tokio::test]
+    async fn test_axum() {
+        let service = FnService::new(|_, params| async move { Some(Ok(params[0].clone())) });
+        let app = into_axum_router(service, "/rpc")
+            .route("/health", axum::routing::get(|| async { "ok" }));
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            hyper::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service_with_connect_info::<SocketAddr>()),
+        );
+        let client = reqwest::Client::new();
+        let resp: serde_json::Value = client
+            .post(format!("http://{}/rpc", addr))
+            .header("content-type", "application/json")
+            .body(
+                r#"[{"jsonrpc": "2.0", "method": "f", "params": [1], "id": 1},
+                    {"jsonrpc": "2.0", "method": "f", "params": [2], "id": 2}]"#,
+            )
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(resp[0]["result"], 1);
+        assert_eq!(resp[1]["result"], 2);
+        let get = |path: &str| client.get(format!("http://{}{}", addr, path)).send();
+        assert_eq!(get("/rpc").await.unwrap().status(), 405);
+        assert_eq!(get("/health").await.unwrap().text().await.unwrap(), "ok");
+    }
+}
@@ -0,0 +1,308 @@
+use std::{
+    future::ready,
+    io::{Read, Write},
+    sync::{Arc, Mutex},
+};
+
+use base64::Engine;
+use futures_util::{Sink, SinkExt, Stream, StreamExt};
+
+use crate::{JrpcId, JrpcRequest, JrpcResponse};
+
+/// The method through which a client offers compression to a server. Servers that don't support it answer with an error, so that both sides keep sending plain frames.
+pub const COMPRESSION_METHOD: &str = "rpc.compression";
+
+/// Frames shorter than this are never compressed, since it wouldn't pay off.
+const MIN_COMPRESSED_LEN: usize = 512;
+
+/// A compression algorithm for the frames of a stream transport.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    /// Zstandard, which is fast and compresses well. The best choice when both sides support it.
+    Zstd,
+    /// Gzip, for peers that don't have zstd.
+    Gzip,
+}
+
+impl Compression {
+    fn name(self) -> &'static str {
+        match self {
+            Compression::Zstd => "zstd",
+            Compression::Gzip => "gzip",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [Compression::Zstd, Compression::Gzip]
+            .into_iter()
+            .find(|c| c.name() == name)
+    }
+
+    /// Compresses a frame into a text frame like `~zstd:<base64>`, which can never be mistaken for JSON.
+    fn encode(self, frame: &str) -> String {
+        let compressed = match self {
+            Compression::Zstd => zstd::bulk::compress(frame.as_bytes(), 3).unwrap(),
+            Compression::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+                encoder.write_all(frame.as_bytes()).unwrap();
+                encoder.finish().unwrap()
+            }
+        };
+        format!(
+            "~{}:{}",
+            self.name(),
+            base64::engine::general_purpose::STANDARD.encode(compressed)
+        )
+    }
+}
+
+/// Decompresses a frame made by [Compression::encode], refusing to inflate it beyond `max_len` bytes.
+fn decode(frame: &str, max_len: usize) -> Option<String> {
+    let (name, data) = frame.strip_prefix('~')?.split_once(':')?;
+    let data = base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .ok()?;
+    let decompressed = match Compression::from_name(name)? {
+        Compression::Zstd => zstd::bulk::decompress(&data, max_len).ok()?,
+        Compression::Gzip => {
+            let mut decompressed = vec![];
+            flate2::read::GzDecoder::new(data.as_slice())
+                .take(max_len as u64 + 1)
+                .read_to_end(&mut decompressed)
+                .ok()?;
+            if decompressed.len() > max_len {
+                return None;
+            }
+            decompressed
+        }
+    };
+    String::from_utf8(decompressed).ok()
+}
+
+/// The compression state of one connection.
+#[derive(Default)]
+struct State {
+    /// The algorithms we offer as a client, in order of preference, or accept as a server.
+    supported: Vec<Compression>,
+    is_client: bool,
+    offered: bool,
+    chosen: Option<Compression>,
+    /// A server's answer to an offer, sent before the next frame.
+    answer: Option<String>,
+}
+
+/// Negotiates compression on one connection. Clients offer their algorithms before their first frame, and start compressing once the server picks one; servers start compressing their frames right after answering.
+///
+/// Compressed frames are always understood when received, whatever was negotiated.
+#[derive(Clone, Default)]
+pub(crate) struct Negotiation(Arc<Mutex<State>>);
+
+impl Negotiation {
+    /// Offers the given algorithms, in order of preference. Only has an effect before the first frame is sent.
+    pub(crate) fn client(supported: &[Compression]) -> Self {
+        let this = Self::default();
+        this.set_supported(supported);
+        this.0.lock().unwrap().is_client = true;
+        this
+    }
+
+    /// Accepts the given algorithms, picking the client's favorite among them.
+    pub(crate) fn server(supported: &[Compression]) -> Self {
+        let this = Self::default();
+        this.set_supported(supported);
+        this
+    }
+
+    pub(crate) fn set_supported(&self, supported: &[Compression]) {
+        self.0.lock().unwrap().supported = supported.to_vec();
+    }
+
+    /// Returns the frames to send before the given one, and the frame itself, compressed if it should be.
+    fn outgoing(&self, frame: String) -> (Option<String>, String) {
+        let mut state = self.0.lock().unwrap();
+        let before = if state.is_client && !state.offered {
+            state.offered = true;
+            (!state.supported.is_empty()).then(|| {
+                let offer = JrpcRequest {
+                    jsonrpc: "2.0".into(),
+                    method: COMPRESSION_METHOD.into(),
+                    params: state.supported.iter().map(|c| c.name().into()).collect(),
+                    id: JrpcId::String(COMPRESSION_METHOD.into()),
+                    meta: Default::default(),
+                };
+                serde_json::to_string(&offer).unwrap()
+            })
+        } else {
+            state.answer.take()
+        };
+        let frame = match state.chosen {
+            Some(compression) if frame.len() >= MIN_COMPRESSED_LEN => compression.encode(&frame),
+            _ => frame,
+        };
+        (before, frame)
+    }
+
+    /// Handles an incoming frame, returning it decompressed, or `None` if it was part of the negotiation. Fails if it cannot be decompressed.
+    fn incoming(&self, frame: String, max_len: usize) -> Result<Option<String>, ()> {
+        if frame.starts_with('~') {
+            return decode(&frame, max_len).map(Some).ok_or(());
+        }
+        if !frame.contains(COMPRESSION_METHOD) {
+            return Ok(Some(frame));
+        }
+        let mut state = self.0.lock().unwrap();
+        if state.is_client {
+            match serde_json::from_str::<JrpcResponse>(&frame) {
+                Ok(resp) if resp.id == JrpcId::String(COMPRESSION_METHOD.into()) => {
+                    state.chosen = resp
+                        .result
+                        .as_ref()
+                        .and_then(|name| Compression::from_name(name.as_str()?))
+                        .filter(|c| state.supported.contains(c));
+                    Ok(None)
+                }
+                _ => Ok(Some(frame)),
+            }
+        } else {
+            match serde_json::from_str::<JrpcRequest>(&frame) {
+                Ok(req) if req.method == COMPRESSION_METHOD => {
+                    let offered: Vec<Compression> = req
+                        .params
+                        .iter()
+                        .filter_map(|name| Compression::from_name(name.as_str()?))
+                        .collect();
+                    let chosen = offered.into_iter().find(|c| state.supported.contains(c));
+                    state.chosen = chosen;
+                    state.answer = Some(
+                        serde_json::to_string(&JrpcResponse {
+                            jsonrpc: "2.0".into(),
+                            result: Some(chosen.map(|c| c.name()).into()),
+                            error: None,
+                            id: req.id,
+                        })
+                        .unwrap(),
+                    );
+                    Ok(None)
+                }
+                _ => Ok(Some(frame)),
+            }
+        }
+    }
+}
+
+/// Wraps a framed connection with compression, negotiated as given. Incoming frames that inflate beyond `max_len`, or fail to decompress, end the stream.
+pub(crate) fn compressed_frames<Si, St>(
+    sink: Si,
+    stream: St,
+    negotiation: Negotiation,
+    max_len: usize,
+) -> (
+    impl Sink<String, Error = Si::Error> + Send + Unpin + 'static,
+    impl Stream<Item = String> + Send + Unpin + 'static,
+)
+where
+    Si: Sink<String> + Send + Unpin + 'static,
+    Si::Error: Send,
+    St: Stream<Item = String> + Send + Unpin + 'static,
+{
+    let sink = futures_util::sink::unfold(
+        (sink, negotiation.clone()),
+        |(mut sink, negotiation), frame: String| async move {
+            let (before, frame) = negotiation.outgoing(frame);
+            if let Some(before) = before {
+                sink.send(before).await?;
+            }
+            sink.send(frame).await?;
+            Ok((sink, negotiation))
+        },
+    );
+    let stream = stream
+        .map(move |frame| negotiation.incoming(frame, max_len))
+        .take_while(|frame| {
+            if frame.is_err() {
+                log::warn!("could not decompress frame, closing connection");
+            }
+            ready(frame.is_ok())
+        })
+        .filter_map(|frame| ready(frame.ok().flatten()));
+    (Box::pin(sink), Box::pin(stream))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use futures_util::{Sink, SinkExt, Stream, StreamExt};
+
+    use super::{compressed_frames, decode, Compression, Negotiation};
+
+    type Wire = Arc<Mutex<Vec<String>>>;
+
+    /// One direction of a connection, recording every frame that crosses it.
+    fn pipe(
+        wire: &Wire,
+    ) -> (
+        impl Sink<String, Error = ()> + Send + Unpin + 'static,
+        impl Stream<Item = String> + Send + Unpin + 'static,
+    ) {
+        let (send, recv) = async_channel::unbounded();
+        let sink = futures_util::sink::unfold(send, |send, frame: String| async move {
+            send.send(frame).await.map_err(|_| ())?;
+            Ok(send)
+        });
+        let wire = wire.clone();
+        let stream = recv.inspect(move |frame| wire.lock().unwrap().push(frame.clone()));
+        (Box::pin(sink), Box::pin(stream))
+    }
+
+    #[test]
+    fn test_compression() {
+        smol::future::block_on(async move {
+            let big = format!("[{}]", vec!["\"hello\""; 1000].join(","));
+            for compression in [Compression::Zstd, Compression::Gzip] {
+                let encoded = compression.encode(&big);
+                assert!(encoded.len() < big.len() / 10);
+                assert_eq!(decode(&encoded, big.len()).unwrap(), big);
+                // decompression bombs are refused
+                assert!(decode(&encoded, big.len() - 1).is_none());
+            }
+
+            let wire = Wire::default();
+            let (to_server, from_client) = pipe(&wire);
+            let (to_client, from_server) = pipe(&wire);
+            let (mut client_sink, mut client_stream) = compressed_frames(
+                to_server,
+                from_server,
+                Negotiation::client(&[Compression::Gzip, Compression::Zstd]),
+                1 << 20,
+            );
+            let (mut server_sink, mut server_stream) = compressed_frames(
+                to_client,
+                from_client,
+                Negotiation::server(&[Compression::Zstd, Compression::Gzip]),
+                1 << 20,
+            );
+            // the offer goes before the first frame, which is still plain
+            client_sink.send(big.clone()).await.unwrap();
+            assert_eq!(server_stream.next().await.unwrap(), big);
+            // the answer goes before the first reply, which is already compressed
+            server_sink.send(big.clone()).await.unwrap();
+            assert_eq!(client_stream.next().await.unwrap(), big);
+            client_sink.send(big.clone()).await.unwrap();
+            assert_eq!(server_stream.next().await.unwrap(), big);
+            client_sink.send("[]".into()).await.unwrap();
+            assert_eq!(server_stream.next().await.unwrap(), "[]");
+
+            let wire = wire.lock().unwrap();
+            assert!(wire[0].contains("rpc.compression"));
+            assert_eq!(wire[1], big);
+            assert_eq!(
+                wire[2],
+                r#"{"jsonrpc":"2.0","result":"gzip","id":"rpc.compression"}"#
+            );
+            assert!(wire[3].starts_with("~gzip:") && wire[4].starts_with("~gzip:"));
+            assert_eq!(wire[5], "[]");
+        });
+    }
+}
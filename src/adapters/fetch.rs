@@ -0,0 +1,107 @@
+use async_trait::async_trait;
+use thiserror::Error;
+
+use crate::{JrpcRequest, JrpcResponse, LocalRpcTransport};
+
+/// An error returned by a [FetchTransport].
+#[derive(Error, Debug)]
+pub enum FetchError {
+    #[error("fetch failed: {0}")]
+    Fetch(#[from] gloo_net::Error),
+    #[error("server responded with HTTP status {0}")]
+    Status(u16),
+    #[error("could not decode response: {0}")]
+    Decode(#[from] serde_json::Error),
+}
+
+/// A FetchTransport makes calls from the browser with the `fetch` API, POSTing them as JSON to a single HTTP endpoint just like an [crate::HttpTransport] would. Batches are sent as one request.
+///
+/// The futures of `fetch` are not `Send`, so a FetchTransport implements [LocalRpcTransport] rather than [crate::RpcTransport]. Clients generated with `#[nanorpc_derive(local)]` work with it. Requires the `wasm` feature.
+#[derive(Clone, Debug)]
+pub struct FetchTransport {
+    url: String,
+    headers: Vec<(String, String)>,
+}
+
+impl FetchTransport {
+    /// Creates a new FetchTransport to the given URL, like `"/rpc"` or `"https://example.com/rpc"`.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            headers: vec![],
+        }
+    }
+
+    /// Adds a header to every request, like an `authorization` header.
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    async fn post<T: serde::de::DeserializeOwned>(&self, body: String) -> Result<T, FetchError> {
+        let mut req =
+            gloo_net::http::Request::post(&self.url).header("content-type", "application/json");
+        for (name, value) in self.headers.iter() {
+            req = req.header(name, value);
+        }
+        let resp = req.body(body)?.send().await?;
+        let status = resp.status();
+        let body = resp.binary().await?;
+        // servers may answer JSON-RPC errors with error statuses, so a JSON body takes precedence
+        match serde_json::from_slice(&body) {
+            Ok(resp) => Ok(resp),
+            Err(_) if !resp.ok() => Err(FetchError::Status(status)),
+            Err(err) => Err(FetchError::Decode(err)),
+        }
+    }
+
+    /// Sends a batch of calls as one request, returning the responses in whatever order the server sent them.
+    pub async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, FetchError> {
+        if reqs.is_empty() {
+            return Ok(vec![]);
+        }
+        self.post(serde_json::to_string(&reqs)?).await
+    }
+}
+
+#[async_trait(?Send)]
+impl LocalRpcTransport for FetchTransport {
+    type Error = FetchError;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        self.post(serde_json::to_string(&req)?).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FetchTransport;
+    use crate::{self as nanorpc, nanorpc_derive};
+
+    #[nanorpc_derive(local)]
+    #[async_trait::async_trait(?Send)]
+    pub trait EchoProtocol {
+        async fn echo(&self, s: String) -> String;
+    }
+
+    struct Echo;
+
+    #[async_trait::async_trait(?Send)]
+    impl EchoProtocol for Echo {
+        async fn echo(&self, s: String) -> String {
+            s
+        }
+    }
+
+    #[test]
+    fn test_fetch_client() {
+        // fetch only works in a browser, so this only checks that generated clients and services fit together with a FetchTransport
+        let _service = EchoService(Echo);
+        let client =
+            EchoClient::from(FetchTransport::new("/rpc").with_header("authorization", "x"));
+        let _call = client.echo("hello".into());
+    }
+}
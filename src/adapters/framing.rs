@@ -0,0 +1,302 @@
+use std::marker::PhantomData;
+
+use bytes::{BufMut, BytesMut};
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::{JrpcMessage, Limits};
+
+pub use tokio_util;
+
+/// An error from a [JrpcLinesCodec] or a [JrpcContentLengthCodec]. Every error but a malformed message leaves the stream unusable.
+#[derive(Error, Debug)]
+pub enum FramingError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("malformed message: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("message longer than {0} bytes")]
+    TooLong(usize),
+    #[error("malformed header: {0}")]
+    Header(String),
+}
+
+/// A tokio-util codec framing JSON-RPC messages one per line, like [crate::TcpTransport] and [crate::serve_stdio] do, for building transports on a [tokio_util::codec::Framed].
+///
+/// Anything serializable can be encoded, typically a [crate::JrpcRequest] or a [crate::JrpcResponse], and lines are decoded into `D`, which is a [JrpcMessage] unless set otherwise. Blank lines are skipped. Requires the `codec` feature.
+pub struct JrpcLinesCodec<D = JrpcMessage> {
+    max_len: usize,
+    /// How far the buffer has been searched for a newline.
+    searched: usize,
+    _decoded: PhantomData<fn() -> D>,
+}
+
+impl<D> JrpcLinesCodec<D> {
+    /// Creates a new JrpcLinesCodec, accepting lines up to [Limits::max_message_size] by default.
+    pub fn new() -> Self {
+        Self {
+            max_len: Limits::default().max_message_size,
+            searched: 0,
+            _decoded: PhantomData,
+        }
+    }
+
+    /// Sets the maximum length of a line, beyond which decoding fails with [FramingError::TooLong].
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+}
+
+impl<D> Default for JrpcLinesCodec<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D: DeserializeOwned> Decoder for JrpcLinesCodec<D> {
+    type Item = D;
+    type Error = FramingError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<D>, FramingError> {
+        loop {
+            let Some(idx) = src[self.searched..].iter().position(|&b| b == b'\n') else {
+                self.searched = src.len();
+                if src.len() > self.max_len {
+                    return Err(FramingError::TooLong(self.max_len));
+                }
+                return Ok(None);
+            };
+            let line = src.split_to(self.searched + idx + 1);
+            self.searched = 0;
+            let line = trim_line(&line);
+            if line.len() > self.max_len {
+                return Err(FramingError::TooLong(self.max_len));
+            }
+            if !line.iter().all(u8::is_ascii_whitespace) {
+                return Ok(Some(serde_json::from_slice(line)?));
+            }
+        }
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<D>, FramingError> {
+        if let Some(item) = self.decode(src)? {
+            return Ok(Some(item));
+        }
+        // a final line without a newline still counts
+        let line = src.split();
+        self.searched = 0;
+        if line.iter().all(u8::is_ascii_whitespace) {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_slice(trim_line(&line))?))
+    }
+}
+
+impl<D, E: Serialize> Encoder<E> for JrpcLinesCodec<D> {
+    type Error = FramingError;
+
+    fn encode(&mut self, item: E, dst: &mut BytesMut) -> Result<(), FramingError> {
+        serde_json::to_writer(dst.writer(), &item)?;
+        dst.put_u8(b'\n');
+        Ok(())
+    }
+}
+
+/// Strips the line terminator, including a carriage return.
+fn trim_line(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+/// A tokio-util codec framing JSON-RPC messages behind `Content-Length` headers, like the Language Server Protocol does, for building transports on a [tokio_util::codec::Framed].
+///
+/// Anything serializable can be encoded, and messages are decoded into `D`, which is a [JrpcMessage] unless set otherwise. Headers other than `Content-Length`, like `Content-Type`, are ignored. Requires the `codec` feature.
+pub struct JrpcContentLengthCodec<D = JrpcMessage> {
+    max_len: usize,
+    /// The length of the message whose headers have been read.
+    pending: Option<usize>,
+    _decoded: PhantomData<fn() -> D>,
+}
+
+impl<D> JrpcContentLengthCodec<D> {
+    /// Creates a new JrpcContentLengthCodec, accepting messages up to [Limits::max_message_size] by default.
+    pub fn new() -> Self {
+        Self {
+            max_len: Limits::default().max_message_size,
+            pending: None,
+            _decoded: PhantomData,
+        }
+    }
+
+    /// Sets the maximum length of a message, beyond which decoding fails with [FramingError::TooLong].
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    /// Reads the headers, if they are all there, returning the length of the message.
+    fn decode_headers(&self, src: &mut BytesMut) -> Result<Option<usize>, FramingError> {
+        // headers are short, so anything longer than this cannot be them
+        const MAX_HEADERS_LEN: usize = 4096;
+        let Some(end) = src.windows(4).position(|w| w == b"\r\n\r\n") else {
+            if src.len() > MAX_HEADERS_LEN {
+                return Err(FramingError::Header("headers too long".into()));
+            }
+            return Ok(None);
+        };
+        let headers = src.split_to(end + 4);
+        let headers = std::str::from_utf8(&headers[..end])
+            .map_err(|_| FramingError::Header("headers are not UTF-8".into()))?;
+        let mut len = None;
+        for header in headers.split("\r\n") {
+            let (name, value) = header
+                .split_once(':')
+                .ok_or_else(|| FramingError::Header(header.into()))?;
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                len = Some(
+                    value
+                        .trim()
+                        .parse::<usize>()
+                        .map_err(|_| FramingError::Header(header.into()))?,
+                );
+            }
+        }
+        let len = len.ok_or_else(|| FramingError::Header("missing Content-Length".into()))?;
+        if len > self.max_len {
+            return Err(FramingError::TooLong(self.max_len));
+        }
+        Ok(Some(len))
+    }
+}
+
+impl<D> Default for JrpcContentLengthCodec<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D: DeserializeOwned> Decoder for JrpcContentLengthCodec<D> {
+    type Item = D;
+    type Error = FramingError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<D>, FramingError> {
+        let len = match self.pending {
+            Some(len) => len,
+            None => match self.decode_headers(src)? {
+                Some(len) => len,
+                None => return Ok(None),
+            },
+        };
+        if src.len() < len {
+            self.pending = Some(len);
+            src.reserve(len - src.len());
+            return Ok(None);
+        }
+        self.pending = None;
+        let msg = src.split_to(len);
+        Ok(Some(serde_json::from_slice(&msg)?))
+    }
+}
+
+impl<D, E: Serialize> Encoder<E> for JrpcContentLengthCodec<D> {
+    type Error = FramingError;
+
+    fn encode(&mut self, item: E, dst: &mut BytesMut) -> Result<(), FramingError> {
+        let msg = serde_json::to_vec(&item)?;
+        dst.reserve(msg.len() + 32);
+        dst.put_slice(format!("Content-Length: {}\r\n\r\n", msg.len()).as_bytes());
+        dst.put_slice(&msg);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+    use futures_util::{SinkExt, StreamExt};
+    use tokio_util::codec::{Decoder, Encoder, Framed};
+
+    use super::{FramingError, JrpcContentLengthCodec, JrpcLinesCodec};
+    use crate::{JrpcId, JrpcMessage, JrpcRequest, JrpcResponse};
+
+    fn request(id: i64) -> JrpcRequest {
+        JrpcRequest {
+            jsonrpc: "2.0".into(),
+            method: "echo".into(),
+            params: vec!["line\nbreak".into()],
+            id: JrpcId::Number(id),
+            meta: Default::default(),
+        }
+    }
+
+    /// Echoes requests back as responses over a pair of framed halves, checking that messages arriving in pieces are put back together.
+    async fn roundtrip<C>(codec: impl Fn() -> C)
+    where
+        C: Decoder<Item = JrpcMessage, Error = FramingError>
+            + Encoder<JrpcRequest, Error = FramingError>
+            + Encoder<JrpcResponse, Error = FramingError>
+            + Send
+            + Unpin
+            + 'static,
+    {
+        // a tiny buffer makes every message arrive in several reads
+        let (client, server) = tokio::io::duplex(7);
+        let mut client = Framed::new(client, codec());
+        let mut server = Framed::new(server, codec());
+        tokio::spawn(async move {
+            while let Some(Ok(JrpcMessage::Request(req))) = server.next().await {
+                let resp = JrpcResponse {
+                    jsonrpc: "2.0".into(),
+                    result: Some(req.params[0].clone()),
+                    error: None,
+                    id: req.id,
+                };
+                server.send(resp).await.unwrap();
+            }
+        });
+        for id in 0..3 {
+            client.send(request(id)).await.unwrap();
+            let Some(Ok(JrpcMessage::Response(resp))) = client.next().await else {
+                panic!("expected a response")
+            };
+            assert_eq!(resp.id, JrpcId::Number(id));
+            assert_eq!(resp.result.unwrap(), "line\nbreak");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_framing() {
+        roundtrip(JrpcLinesCodec::new).await;
+        roundtrip(JrpcContentLengthCodec::new).await;
+
+        let line = serde_json::to_string(&request(1)).unwrap();
+        let mut codec = JrpcLinesCodec::<JrpcRequest>::new();
+        let mut buf = BytesMut::from(format!("\r\n{line}\r\n{line}").as_str());
+        assert!(codec.decode(&mut buf).unwrap().is_some());
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+        // the last line may end without a newline
+        assert!(codec.decode_eof(&mut buf).unwrap().is_some());
+        assert!(codec.decode_eof(&mut buf).unwrap().is_none());
+
+        let mut codec = JrpcLinesCodec::<JrpcRequest>::new().with_max_len(8);
+        let mut buf = BytesMut::from(line.as_str());
+        assert!(matches!(
+            codec.decode(&mut buf),
+            Err(FramingError::TooLong(8))
+        ));
+        let mut codec = JrpcContentLengthCodec::<JrpcRequest>::new().with_max_len(8);
+        let mut buf =
+            BytesMut::from("Content-Type: application/json\r\ncontent-length: 100\r\n\r\n");
+        assert!(matches!(
+            codec.decode(&mut buf),
+            Err(FramingError::TooLong(8))
+        ));
+        let mut buf = BytesMut::from("Content-Type: application/json\r\n\r\n");
+        assert!(matches!(
+            codec.decode(&mut buf),
+            Err(FramingError::Header(_))
+        ));
+    }
+}
@@ -0,0 +1,313 @@
+use std::{
+    convert::Infallible,
+    future::Future,
+    net::SocketAddr,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use async_trait::async_trait;
+use bytes::{Buf, BufMut};
+use thiserror::Error;
+use tonic::{
+    body::BoxBody,
+    codec::{Codec, DecodeBuf, Decoder, EncodeBuf, Encoder},
+    codegen::{http, Body, Service},
+    Status,
+};
+
+use crate::{JrpcRequest, JrpcResponse, RpcContext, RpcService, RpcTransport, ServerConfig};
+
+/// The path of the single gRPC method of a [GrpcBridge].
+const CALL_PATH: &str = "/nanorpc.JsonRpc/Call";
+
+/// A codec for the `JsonRpc` message, whose only field carries a serialized JSON-RPC message.
+#[derive(Clone, Copy, Default)]
+struct BridgeCodec;
+
+impl Codec for BridgeCodec {
+    type Encode = Vec<u8>;
+    type Decode = Vec<u8>;
+    type Encoder = BridgeCodec;
+    type Decoder = BridgeCodec;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        *self
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        *self
+    }
+}
+
+impl Encoder for BridgeCodec {
+    type Item = Vec<u8>;
+    type Error = Status;
+
+    fn encode(&mut self, item: Vec<u8>, dst: &mut EncodeBuf<'_>) -> Result<(), Status> {
+        if !item.is_empty() {
+            // field 1, length-delimited
+            dst.put_u8(0x0a);
+            put_varint(dst, item.len() as u64);
+            dst.put_slice(&item);
+        }
+        Ok(())
+    }
+}
+
+impl Decoder for BridgeCodec {
+    type Item = Vec<u8>;
+    type Error = Status;
+
+    fn decode(&mut self, src: &mut DecodeBuf<'_>) -> Result<Option<Vec<u8>>, Status> {
+        let invalid = || Status::invalid_argument("malformed JsonRpc message");
+        let mut payload = vec![];
+        while src.has_remaining() {
+            let key = get_varint(src).ok_or_else(invalid)?;
+            let len = match key & 7 {
+                0 => {
+                    get_varint(src).ok_or_else(invalid)?;
+                    continue;
+                }
+                1 => 8,
+                2 => get_varint(src).ok_or_else(invalid)? as usize,
+                5 => 4,
+                _ => return Err(invalid()),
+            };
+            if src.remaining() < len {
+                return Err(invalid());
+            }
+            let field = src.copy_to_bytes(len);
+            if key >> 3 == 1 && key & 7 == 2 {
+                payload = field.to_vec();
+            }
+        }
+        Ok(Some(payload))
+    }
+}
+
+fn put_varint(dst: &mut impl BufMut, mut value: u64) {
+    while value >= 0x80 {
+        dst.put_u8(value as u8 | 0x80);
+        value >>= 7;
+    }
+    dst.put_u8(value as u8);
+}
+
+fn get_varint(src: &mut impl Buf) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        if !src.has_remaining() {
+            return None;
+        }
+        let byte = src.get_u8();
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// A GrpcBridge exposes a service as a single unary gRPC method, for networks that only let gRPC through. It is a tonic service, so it can be served on its own with [serve_grpc], or next to other gRPC services on a [tonic::transport::Server].
+///
+/// The method is `Call` of the `nanorpc.JsonRpc` service, which takes and returns a `JsonRpc` message carrying serialized JSON-RPC, so that it can also be called from any gRPC tooling:
+///
+/// ```text
+/// syntax = "proto3";
+/// package nanorpc;
+/// message JsonRpc { bytes payload = 1; }
+/// service JsonRpc { rpc Call(JsonRpc) returns (JsonRpc); }
+/// ```
+///
+/// The peer's [SocketAddr] is attached to the context of every call, if known. Requires the `grpc` feature.
+pub struct GrpcBridge<S: RpcService> {
+    service: Arc<S>,
+    config: Arc<ServerConfig>,
+}
+
+impl<S: RpcService> Clone for GrpcBridge<S> {
+    fn clone(&self) -> Self {
+        Self {
+            service: self.service.clone(),
+            config: self.config.clone(),
+        }
+    }
+}
+
+impl<S: RpcService> GrpcBridge<S> {
+    /// Creates a new GrpcBridge.
+    pub fn new(service: S) -> Self {
+        Self {
+            service: Arc::new(service),
+            config: Arc::new(ServerConfig::default()),
+        }
+    }
+
+    /// Sets the configuration used to handle requests.
+    pub fn with_config(mut self, config: ServerConfig) -> Self {
+        self.config = Arc::new(config);
+        self
+    }
+}
+
+impl<S: RpcService> tonic::server::NamedService for GrpcBridge<S> {
+    const NAME: &'static str = "nanorpc.JsonRpc";
+}
+
+impl<S: RpcService> tonic::server::UnaryService<Vec<u8>> for GrpcBridge<S> {
+    type Response = Vec<u8>;
+    type Future = Pin<Box<dyn Future<Output = Result<tonic::Response<Vec<u8>>, Status>> + Send>>;
+
+    fn call(&mut self, request: tonic::Request<Vec<u8>>) -> Self::Future {
+        let this = self.clone();
+        Box::pin(async move {
+            let mut ctx = RpcContext::new();
+            if let Some(peer_addr) = request.remote_addr() {
+                ctx.insert_extension(peer_addr);
+            }
+            let resp = this
+                .service
+                .respond_bytes_with_context(ctx, request.get_ref(), &this.config)
+                .await;
+            Ok(tonic::Response::new(resp))
+        })
+    }
+}
+
+impl<S: RpcService, B> Service<http::Request<B>> for GrpcBridge<S>
+where
+    B: Body + Send + 'static,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>> + Send,
+{
+    type Response = http::Response<BoxBody>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        if req.uri().path() != CALL_PATH {
+            return Box::pin(async { Ok(Status::unimplemented("no such method").to_http()) });
+        }
+        let this = self.clone();
+        Box::pin(async move {
+            let mut grpc = tonic::server::Grpc::new(BridgeCodec)
+                .apply_max_message_size_config(Some(this.config.limits.max_message_size), None);
+            Ok(grpc.unary(this, req).await)
+        })
+    }
+}
+
+/// Serves a service over gRPC on the given address, with the default configuration. See [GrpcBridge].
+pub async fn serve_grpc(addr: SocketAddr, service: impl RpcService) -> Result<(), GrpcError> {
+    tonic::transport::Server::builder()
+        .add_service(GrpcBridge::new(service))
+        .serve(addr)
+        .await?;
+    Ok(())
+}
+
+/// An error returned by a [GrpcTransport].
+#[derive(Error, Debug)]
+pub enum GrpcError {
+    #[error("gRPC transport error: {0}")]
+    Transport(#[from] tonic::transport::Error),
+    #[error("gRPC call failed: {0}")]
+    Status(#[from] Status),
+    #[error("could not decode response: {0}")]
+    Decode(#[from] serde_json::Error),
+}
+
+/// A GrpcTransport makes calls to a [GrpcBridge] over gRPC. Batches are sent as one gRPC call.
+///
+/// Requires the `grpc` feature, and a tokio runtime.
+#[derive(Clone)]
+pub struct GrpcTransport {
+    grpc: tonic::client::Grpc<tonic::transport::Channel>,
+}
+
+impl GrpcTransport {
+    /// Creates a GrpcTransport over an existing channel, which may be shared with other gRPC clients.
+    pub fn new(channel: tonic::transport::Channel) -> Self {
+        Self {
+            grpc: tonic::client::Grpc::new(channel),
+        }
+    }
+
+    /// Creates a GrpcTransport to a URL like `"http://127.0.0.1:50051"`. The connection is made on first use.
+    pub fn connect_lazy(url: &str) -> Result<Self, tonic::transport::Error> {
+        let channel = tonic::transport::Endpoint::from_shared(url.to_string())?.connect_lazy();
+        Ok(Self::new(channel))
+    }
+
+    async fn unary<T: serde::de::DeserializeOwned>(&self, body: Vec<u8>) -> Result<T, GrpcError> {
+        let mut grpc = self.grpc.clone();
+        grpc.ready().await?;
+        let resp = grpc
+            .unary(
+                tonic::Request::new(body),
+                http::uri::PathAndQuery::from_static(CALL_PATH),
+                BridgeCodec,
+            )
+            .await?;
+        Ok(serde_json::from_slice(resp.get_ref())?)
+    }
+}
+
+#[async_trait]
+impl RpcTransport for GrpcTransport {
+    type Error = GrpcError;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        self.unary(serde_json::to_vec(&req)?).await
+    }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        if reqs.is_empty() {
+            return Ok(vec![]);
+        }
+        self.unary(serde_json::to_vec(&reqs)?).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{FnService, GrpcBridge, GrpcTransport, JrpcRequest, RpcTransport};
+
+    #[tokio::test]
+    async fn test_grpc() {
+        let service = FnService::new(|_, params| async move { Some(Ok(params[0].clone())) });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(GrpcBridge::new(service))
+                .serve_with_incoming(futures_util::stream::unfold(listener, |listener| async {
+                    let conn = listener.accept().await.map(|(stream, _)| stream);
+                    Some((conn, listener))
+                })),
+        );
+        let transport = GrpcTransport::connect_lazy(&format!("http://{}", addr)).unwrap();
+        assert_eq!(
+            transport
+                .call("f", &["x".repeat(200).into()])
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap(),
+            "x".repeat(200)
+        );
+        let reqs = (0..3)
+            .map(|i| crate::new_jrpc_request("f", &[i.into()]))
+            .collect::<Vec<JrpcRequest>>();
+        let resps = transport.call_raw_batch(reqs).await.unwrap();
+        assert_eq!(resps[2].result, Some(2.into()));
+    }
+}
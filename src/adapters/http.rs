@@ -0,0 +1,69 @@
+use std::net::SocketAddr;
+
+use hyper::{body::HttpBody, header, Body, Method, Request, Response, StatusCode};
+
+use crate::{RpcContext, RpcService, ServerConfig};
+
+/// Answers an HTTP request carrying a JSON-RPC message, the same way for every HTTP server adapter. Only POST requests with bodies of the content type of [ServerConfig::codec], no larger than [crate::Limits::max_message_size], are accepted.
+pub(crate) async fn respond_http<S: RpcService + ?Sized>(
+    service: &S,
+    config: &ServerConfig,
+    peer_addr: Option<SocketAddr>,
+    req: Request<Body>,
+) -> Response<Body> {
+    if req.method() != Method::POST {
+        return Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header(header::ALLOW, "POST")
+            .body(Body::empty())
+            .unwrap();
+    }
+    if !has_content_type(&req, config.codec.content_type()) {
+        return status(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+    let Some(body) = read_limited(req.into_body(), config.limits.max_message_size).await else {
+        return status(StatusCode::PAYLOAD_TOO_LARGE);
+    };
+    let mut ctx = RpcContext::new();
+    if let Some(peer_addr) = peer_addr {
+        ctx.insert_extension(peer_addr);
+    }
+    let resp = service.respond_bytes_with_context(ctx, &body, config).await;
+    Response::builder()
+        .header(header::CONTENT_TYPE, config.codec.content_type())
+        .body(Body::from(resp))
+        .unwrap()
+}
+
+pub(crate) fn status(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .unwrap()
+}
+
+fn has_content_type(req: &Request<Body>, content_type: &str) -> bool {
+    req.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|ct| ct.to_str().ok())
+        .and_then(|ct| ct.split(';').next())
+        .map(|mime| mime.trim().eq_ignore_ascii_case(content_type))
+        .unwrap_or(false)
+}
+
+/// Reads a body, or returns `None` if it is longer than the limit.
+async fn read_limited(mut body: Body, limit: usize) -> Option<Vec<u8>> {
+    if body.size_hint().lower() > limit as u64 {
+        return None;
+    }
+    let mut buf = vec![];
+    while let Some(chunk) = body.data().await {
+        // a body that fails halfway is treated as truncated, and answered with a parse error
+        let Ok(chunk) = chunk else { break };
+        if buf.len() + chunk.len() > limit {
+            return None;
+        }
+        buf.extend_from_slice(&chunk);
+    }
+    Some(buf)
+}
@@ -0,0 +1,171 @@
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+
+use hyper::{
+    server::conn::AddrStream,
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, StatusCode,
+};
+
+use super::http::{respond_http, status};
+use crate::{RpcService, ServeHandle, ServerConfig};
+
+/// An HttpServer exposes a service as a single JSON-RPC endpoint, answering POST requests with JSON bodies at any path. Bodies larger than [crate::Limits::max_message_size] are rejected with `413 Payload Too Large`, and malformed JSON-RPC with the usual JSON-RPC errors.
+///
+/// The peer's [SocketAddr] is attached to the context of every call. Requires the `http-server` feature, and a tokio runtime.
+pub struct HttpServer<S: RpcService> {
+    service: Arc<S>,
+    config: ServerConfig,
+    handle: ServeHandle,
+    #[cfg(feature = "sse")]
+    events: Option<(String, Arc<crate::SseHub>)>,
+}
+
+impl<S: RpcService> HttpServer<S> {
+    /// Creates a new HttpServer.
+    pub fn new(service: S) -> Self {
+        Self {
+            service: Arc::new(service),
+            config: ServerConfig::default(),
+            handle: ServeHandle::new(),
+            #[cfg(feature = "sse")]
+            events: None,
+        }
+    }
+
+    /// Sets the configuration used to handle requests.
+    pub fn with_config(mut self, config: ServerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Uses the given [ServeHandle], so that the server can be gracefully shut down through it.
+    pub fn with_handle(mut self, handle: ServeHandle) -> Self {
+        self.handle = handle;
+        self
+    }
+
+    /// Serves the event streams of an [crate::SseHub] to GET requests at the given path, like `"/events"`. Requires the `sse` feature.
+    #[cfg(feature = "sse")]
+    pub fn with_events(mut self, path: impl Into<String>, hub: Arc<crate::SseHub>) -> Self {
+        self.events = Some((path.into(), hub));
+        self
+    }
+
+    /// Serves requests on the given address until shut down through the [ServeHandle].
+    pub async fn serve(self, addr: SocketAddr) -> std::io::Result<()> {
+        self.serve_listener(std::net::TcpListener::bind(addr)?)
+            .await
+    }
+
+    /// Serves requests on an already bound listener until shut down through the [ServeHandle].
+    pub async fn serve_listener(self, listener: std::net::TcpListener) -> std::io::Result<()> {
+        listener.set_nonblocking(true)?;
+        let shutdown = self.handle.shutdown_signal();
+        let this = Arc::new(self);
+        let make_service = make_service_fn(move |conn: &AddrStream| {
+            let this = this.clone();
+            let peer_addr = conn.remote_addr();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let this = this.clone();
+                    async move { Ok::<_, Infallible>(this.handle_request(peer_addr, req).await) }
+                }))
+            }
+        });
+        hyper::Server::from_tcp(listener)
+            .map_err(std::io::Error::other)?
+            .serve(make_service)
+            .with_graceful_shutdown(async move { shutdown.wait().await })
+            .await
+            .map_err(std::io::Error::other)
+    }
+
+    async fn handle_request(&self, peer_addr: SocketAddr, req: Request<Body>) -> Response<Body> {
+        #[cfg(feature = "sse")]
+        if let Some((path, hub)) = &self.events {
+            if req.uri().path() == path && req.method() == hyper::Method::GET {
+                return hub.respond_http(&req, self.handle.shutdown_signal());
+            }
+        }
+        self.handle
+            .track(respond_http(
+                self.service.as_ref(),
+                &self.config,
+                Some(peer_addr),
+                req,
+            ))
+            .await
+            .unwrap_or_else(|| status(StatusCode::SERVICE_UNAVAILABLE))
+    }
+}
+
+/// Serves a service over HTTP on the given address, with the default configuration. See [HttpServer].
+pub async fn serve_http(addr: SocketAddr, service: impl RpcService) -> std::io::Result<()> {
+    HttpServer::new(service).serve(addr).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{FnService, HttpServer, Limits, ServeHandle, ServerConfig};
+
+    #[tokio::test]
+    async fn test_http_server() {
+        let service = FnService::new(|_, params| async move { Some(Ok(params[0].clone())) });
+        let handle = ServeHandle::new();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(
+            HttpServer::new(service)
+                .with_config(ServerConfig {
+                    limits: Limits {
+                        max_message_size: 100,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .with_handle(handle.clone())
+                .serve_listener(listener),
+        );
+        let client = reqwest::Client::new();
+        let url = format!("http://{}/", addr);
+        let post = |body: &str, content_type: &str| {
+            client
+                .post(&url)
+                .header("content-type", content_type)
+                .body(body.to_string())
+                .send()
+        };
+        let resp = post(
+            r#"{"jsonrpc": "2.0", "method": "f", "params": [1], "id": 1}"#,
+            "application/json; charset=utf-8",
+        )
+        .await
+        .unwrap();
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.headers()["content-type"], "application/json");
+        let resp: serde_json::Value = resp.json().await.unwrap();
+        assert_eq!(resp["result"], 1);
+
+        let resp: serde_json::Value = post("{", "application/json")
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(resp["error"]["code"], -32700);
+        assert_eq!(post("{}", "text/plain").await.unwrap().status(), 415);
+        assert_eq!(
+            post(&"x".repeat(101), "application/json")
+                .await
+                .unwrap()
+                .status(),
+            413
+        );
+        assert_eq!(client.get(&url).send().await.unwrap().status(), 405);
+
+        handle.shutdown(Duration::from_secs(1)).await;
+        server.await.unwrap().unwrap();
+    }
+}
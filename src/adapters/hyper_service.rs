@@ -0,0 +1,187 @@
+use std::{convert::Infallible, future::Future, net::SocketAddr, pin::Pin, sync::Arc};
+
+use http_body_util::{combinators::BoxBody, BodyExt, Empty, Full, LengthLimitError, Limited};
+use hyper1::{
+    body::{Bytes, Incoming},
+    header, Method, Request, Response, StatusCode,
+};
+
+use super::accept::accept_tcp;
+use crate::{RpcContext, RpcService, ServeHandle, ServerConfig};
+
+/// A HyperService is a hyper 1.x [Service](hyper1::service::Service) that serves an [RpcService], for running it on a hand-rolled hyper server without any framework. Requests are handled just like by a [crate::HttpServer].
+///
+/// Requires the `hyper1` feature. For a ready-made accept loop, see [serve_hyper].
+pub struct HyperService<S: RpcService> {
+    service: Arc<S>,
+    config: Arc<ServerConfig>,
+    peer_addr: Option<SocketAddr>,
+}
+
+impl<S: RpcService> Clone for HyperService<S> {
+    fn clone(&self) -> Self {
+        Self {
+            service: self.service.clone(),
+            config: self.config.clone(),
+            peer_addr: self.peer_addr,
+        }
+    }
+}
+
+impl<S: RpcService> HyperService<S> {
+    /// Creates a new HyperService.
+    pub fn new(service: S) -> Self {
+        Self {
+            service: Arc::new(service),
+            config: Arc::new(ServerConfig::default()),
+            peer_addr: None,
+        }
+    }
+
+    /// Sets the configuration used to handle requests.
+    pub fn with_config(mut self, config: ServerConfig) -> Self {
+        self.config = Arc::new(config);
+        self
+    }
+
+    /// Sets the address of the peer, which is then attached to the context of every call. Since hyper serves one connection per service, this is typically set right before serving a new connection.
+    pub fn with_peer_addr(mut self, peer_addr: SocketAddr) -> Self {
+        self.peer_addr = Some(peer_addr);
+        self
+    }
+
+    async fn handle_request(self, req: Request<Incoming>) -> Response<BoxBody<Bytes, Infallible>> {
+        if req.method() != Method::POST {
+            let mut resp = status(StatusCode::METHOD_NOT_ALLOWED);
+            resp.headers_mut()
+                .insert(header::ALLOW, header::HeaderValue::from_static("POST"));
+            return resp;
+        }
+        let content_type = self.config.codec.content_type();
+        let is_supported = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|ct| ct.to_str().ok())
+            .and_then(|ct| ct.split(';').next())
+            .map(|mime| mime.trim().eq_ignore_ascii_case(content_type))
+            .unwrap_or(false);
+        if !is_supported {
+            return status(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        }
+        let body = match Limited::new(req.into_body(), self.config.limits.max_message_size)
+            .collect()
+            .await
+        {
+            Ok(body) => body.to_bytes(),
+            Err(err) if err.is::<LengthLimitError>() => {
+                return status(StatusCode::PAYLOAD_TOO_LARGE)
+            }
+            Err(_) => return status(StatusCode::BAD_REQUEST),
+        };
+        let mut ctx = RpcContext::new();
+        if let Some(peer_addr) = self.peer_addr {
+            ctx.insert_extension(peer_addr);
+        }
+        let resp = self
+            .service
+            .respond_bytes_with_context(ctx, &body, &self.config)
+            .await;
+        let mut resp = Response::new(Full::new(Bytes::from(resp)).boxed());
+        resp.headers_mut().insert(
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static(content_type),
+        );
+        resp
+    }
+}
+
+impl<S: RpcService> hyper1::service::Service<Request<Incoming>> for HyperService<S> {
+    type Response = Response<BoxBody<Bytes, Infallible>>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Infallible>> + Send>>;
+
+    fn call(&self, req: Request<Incoming>) -> Self::Future {
+        let this = self.clone();
+        Box::pin(async move { Ok(this.handle_request(req).await) })
+    }
+}
+
+fn status(status: StatusCode) -> Response<BoxBody<Bytes, Infallible>> {
+    let mut resp = Response::new(Empty::new().boxed());
+    *resp.status_mut() = status;
+    resp
+}
+
+/// Serves a [HyperService] over HTTP/1 on an already bound listener, until shut down through the [ServeHandle]. Shutting down lets requests in flight finish. Requires the `hyper1` feature, and a tokio runtime.
+pub async fn serve_hyper<S: RpcService>(
+    listener: std::net::TcpListener,
+    service: HyperService<S>,
+    handle: ServeHandle,
+) -> std::io::Result<()> {
+    let shutdown = handle.shutdown_signal();
+    accept_tcp(listener, &handle, move |stream, conn| {
+        let mut service = service.clone();
+        if let Some(peer_addr) = conn.peer_addr {
+            service = service.with_peer_addr(peer_addr);
+        }
+        let shutdown = shutdown.clone();
+        async move {
+            let conn = hyper1::server::conn::http1::Builder::new()
+                .serve_connection(hyper_util::rt::TokioIo::new(stream), service);
+            let mut conn = std::pin::pin!(conn);
+            let res = futures_lite::future::or(async { Some(conn.as_mut().await) }, async {
+                shutdown.wait().await;
+                None
+            })
+            .await;
+            let res = match res {
+                Some(res) => res,
+                None => {
+                    conn.as_mut().graceful_shutdown();
+                    conn.await
+                }
+            };
+            if let Err(err) = res {
+                log::debug!("HTTP connection failed: {}", err);
+            }
+        }
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{serve_hyper, FnService, HyperService, ServeHandle};
+
+    #[tokio::test]
+    async fn test_hyper_service() {
+        let service = FnService::new(|_, params| async move { Some(Ok(params[0].clone())) });
+        let handle = ServeHandle::new();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(serve_hyper(
+            listener,
+            HyperService::new(service),
+            handle.clone(),
+        ));
+        let client = reqwest::Client::new();
+        let url = format!("http://{}/", addr);
+        let resp: serde_json::Value = client
+            .post(&url)
+            .header("content-type", "application/json")
+            .body(r#"{"jsonrpc": "2.0", "method": "f", "params": [1], "id": 1}"#)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(resp["result"], 1);
+        assert_eq!(client.get(&url).send().await.unwrap().status(), 405);
+        drop(client);
+        assert!(handle.shutdown(Duration::from_secs(5)).await);
+        server.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+use futures_util::{Sink, Stream};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+
+use crate::{Limits, Multiplexer};
+
+/// Frames a byte stream as one JSON-RPC message per line. Lines longer than `max_len` end the stream, since there is no way to recover from them.
+pub(crate) fn line_frames<R, W>(
+    reader: R,
+    writer: W,
+    max_len: usize,
+) -> (
+    impl Sink<String, Error = std::io::Error> + Send + Unpin + 'static,
+    impl Stream<Item = String> + Send + Unpin + 'static,
+)
+where
+    R: AsyncRead + Send + Unpin + 'static,
+    W: AsyncWrite + Send + Unpin + 'static,
+{
+    let sink = futures_util::sink::unfold(writer, |mut writer, frame: String| async move {
+        let mut line = frame.into_bytes();
+        line.push(b'\n');
+        writer.write_all(&line).await?;
+        writer.flush().await?;
+        Ok(writer)
+    });
+    let stream =
+        futures_util::stream::unfold(BufReader::new(reader), move |mut reader| async move {
+            let line = read_line(&mut reader, max_len).await?;
+            Some((line, reader))
+        });
+    (Box::pin(sink), Box::pin(stream))
+}
+
+/// Reads a line without its terminator, or `None` at the end of the stream, on errors, or if the line is too long.
+async fn read_line<R: AsyncRead + Unpin>(
+    reader: &mut BufReader<R>,
+    max_len: usize,
+) -> Option<String> {
+    let mut line = vec![];
+    loop {
+        let buf = match reader.fill_buf().await {
+            Ok(buf) => buf,
+            Err(err) => {
+                log::debug!("could not read line: {}", err);
+                return None;
+            }
+        };
+        if buf.is_empty() {
+            // a final line without a newline still counts
+            return (!line.is_empty()).then(|| String::from_utf8_lossy(&line).into_owned());
+        }
+        let (chunk, done) = match buf.iter().position(|&b| b == b'\n') {
+            Some(idx) => (&buf[..idx + 1], true),
+            None => (buf, false),
+        };
+        line.extend_from_slice(chunk);
+        let consumed = chunk.len();
+        reader.consume(consumed);
+        if line.len() > max_len.saturating_add(1) {
+            log::warn!("line longer than {} bytes, closing connection", max_len);
+            return None;
+        }
+        if done {
+            line.pop();
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            return Some(String::from_utf8_lossy(&line).into_owned());
+        }
+    }
+}
+
+/// Runs a [Multiplexer] over a line-framed connection, spawning its driver on tokio.
+pub(crate) fn line_multiplexer<R, W>(
+    reader: R,
+    writer: W,
+    limits: Limits,
+    timeout: Option<Duration>,
+) -> Multiplexer
+where
+    R: AsyncRead + Send + Unpin + 'static,
+    W: AsyncWrite + Send + Unpin + 'static,
+{
+    let (sink, stream) = line_frames(reader, writer, limits.max_message_size);
+    spawn_multiplexer(sink, stream, limits, timeout)
+}
+
+/// Runs a [Multiplexer] over already framed messages, spawning its driver on tokio.
+pub(crate) fn spawn_multiplexer<Si, St>(
+    sink: Si,
+    stream: St,
+    limits: Limits,
+    timeout: Option<Duration>,
+) -> Multiplexer
+where
+    Si: Sink<String> + Send + Unpin + 'static,
+    Si::Error: std::fmt::Debug,
+    St: Stream<Item = String> + Send + Unpin + 'static,
+{
+    let (mux, driver) = Multiplexer::new(sink, stream);
+    tokio::spawn(driver);
+    let mux = mux.with_limits(limits);
+    match timeout {
+        Some(timeout) => mux.with_timeout(timeout),
+        None => mux,
+    }
+}
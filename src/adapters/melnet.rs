@@ -0,0 +1,110 @@
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{JrpcRequest, JrpcResponse, RpcService, RpcTransport, ServerError};
+
+pub use melnet2;
+
+/// Converts between this crate's JSON-RPC types and those of the published nanorpc that melnet2 is built on, which look the same on the wire.
+fn convert<A: Serialize, B: DeserializeOwned>(value: A) -> B {
+    serde_json::to_value(value)
+        .and_then(serde_json::from_value)
+        .expect("JSON-RPC types should convert losslessly")
+}
+
+/// A MelnetTransport makes calls through a melnet2 connection, like one from a [melnet2::Backhaul] or the raw transport that a [melnet2::Swarm] hands to its client-making function, so that clients of this crate can talk to melnet2 peers.
+///
+/// melnet2 carries neither request metadata nor error codes, so they are dropped. Requires the `melnet2` feature.
+pub struct MelnetTransport<T>(T);
+
+impl<T: melnet_nanorpc::RpcTransport> MelnetTransport<T> {
+    /// Wraps a melnet2 connection.
+    pub fn new(inner: T) -> Self {
+        Self(inner)
+    }
+}
+
+#[async_trait]
+impl<T: melnet_nanorpc::RpcTransport> RpcTransport for MelnetTransport<T> {
+    type Error = T::Error;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        Ok(convert(self.0.call_raw(convert(req)).await?))
+    }
+}
+
+/// A MelnetService exposes a service of this crate to melnet2, to be served through [melnet2::Swarm::start_listen] or a [melnet2::Backhaul], so that melnet2 nodes can serve protocols defined with this crate. Requires the `melnet2` feature.
+pub struct MelnetService<S>(S);
+
+impl<S: RpcService> MelnetService<S> {
+    /// Wraps a service.
+    pub fn new(inner: S) -> Self {
+        Self(inner)
+    }
+}
+
+#[async_trait]
+impl<S: RpcService> melnet_nanorpc::RpcService for MelnetService<S> {
+    async fn respond(
+        &self,
+        method: &str,
+        params: Vec<serde_json::Value>,
+    ) -> Option<Result<serde_json::Value, melnet_nanorpc::ServerError>> {
+        let res: Result<_, ServerError> = self.0.respond(method, &params).await?;
+        Some(res.map_err(convert))
+    }
+
+    async fn respond_raw(&self, req: melnet_nanorpc::JrpcRequest) -> melnet_nanorpc::JrpcResponse {
+        convert(self.0.respond_raw(convert(req)).await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use melnet2::{wire::tcp::TcpBackhaul, Backhaul};
+
+    use crate::{FnService, MelnetService, MelnetTransport, RpcTransport, ServerError};
+
+    #[test]
+    fn test_melnet() {
+        smol::future::block_on(async move {
+            let addr = std::net::TcpListener::bind("127.0.0.1:0")
+                .unwrap()
+                .local_addr()
+                .unwrap()
+                .to_string();
+            let backhaul = TcpBackhaul::new();
+            let service = FnService::new(|method, params| {
+                let method = method.to_string();
+                async move {
+                    match method.as_str() {
+                        "echo" => Some(Ok(params[0].clone())),
+                        "fail" => {
+                            Some(Err(ServerError::with_jrpc_code(-32001, "failed", 1.into())))
+                        }
+                        _ => None,
+                    }
+                }
+            });
+            backhaul
+                .start_listen(addr.as_str().into(), MelnetService::new(service))
+                .await
+                .unwrap();
+            let transport = MelnetTransport::new(backhaul.connect(addr.into()).await.unwrap());
+            assert_eq!(
+                transport
+                    .call("echo", &[1.into()])
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .unwrap(),
+                1
+            );
+            assert!(matches!(
+                transport.call("fail", &[]).await.unwrap().unwrap(),
+                Err(err) if err.message == "failed" && err.details == 1
+            ));
+            assert!(transport.call("other", &[]).await.unwrap().is_none());
+        });
+    }
+}
@@ -0,0 +1,479 @@
+use std::fmt;
+
+use base64::Engine;
+use serde::{
+    de::{MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer,
+};
+
+use crate::{Codec, CodecError};
+
+/// A [Codec] for MessagePack, which is more compact and quicker to parse than JSON. Binary values from other MessagePack peers are decoded as base64 strings, which is how [crate::Bytes] params expect them. Requires the `msgpack` feature.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MsgpackCodec;
+
+impl Codec for MsgpackCodec {
+    fn content_type(&self) -> &'static str {
+        "application/msgpack"
+    }
+
+    fn encode_value(&self, msg: &serde_json::Value) -> Vec<u8> {
+        rmp_serde::to_vec(msg).expect("JSON values are always valid MessagePack")
+    }
+
+    fn decode_value(&self, bytes: &[u8]) -> Result<serde_json::Value, CodecError> {
+        rmp_serde::from_slice::<Transcoded>(bytes)
+            .map(|value| value.0)
+            .map_err(|err| CodecError::Other(err.to_string()))
+    }
+}
+
+/// A JSON value decoded from MessagePack, which unlike [serde_json::Value] also takes binary values and non-string map keys.
+struct Transcoded(serde_json::Value);
+
+impl<'de> Deserialize<'de> for Transcoded {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer
+            .deserialize_any(TranscodedVisitor)
+            .map(Transcoded)
+    }
+}
+
+struct TranscodedVisitor;
+
+impl<'de> Visitor<'de> for TranscodedVisitor {
+    type Value = serde_json::Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a MessagePack value")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Self::Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E> {
+        // JSON has no NaN or infinities
+        Ok(serde_json::Number::from_f64(v).map_or(serde_json::Value::Null, Into::into))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<Self::Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E> {
+        Ok(base64::engine::general_purpose::STANDARD.encode(v).into())
+    }
+
+    fn visit_none<E>(self) -> Result<Self::Value, E> {
+        Ok(serde_json::Value::Null)
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E> {
+        Ok(serde_json::Value::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut items = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(1024));
+        while let Some(Transcoded(item)) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(serde_json::Value::Array(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut fields = serde_json::Map::new();
+        while let Some((Transcoded(key), Transcoded(value))) = map.next_entry()? {
+            let key = match key {
+                serde_json::Value::String(key) => key,
+                key => key.to_string(),
+            };
+            fields.insert(key, value);
+        }
+        Ok(serde_json::Value::Object(fields))
+    }
+}
+
+/// Translation between msgpack-rpc, where messages are MessagePack arrays tagged with their type, and JSON-RPC, for the msgpack-rpc mode of [crate::TcpServer] and [crate::TcpTransport].
+#[cfg(feature = "tcp")]
+mod rpc {
+    use futures_util::{Sink, Stream};
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+    use super::Transcoded;
+    use crate::{JrpcError, JrpcId, JrpcMessage, JrpcNotification, JrpcRequest, JrpcResponse};
+
+    const REQUEST: u8 = 0;
+    const RESPONSE: u8 = 1;
+    const NOTIFICATION: u8 = 2;
+
+    /// Frames a byte stream of msgpack-rpc messages as JSON-RPC ones, so that a [crate::Multiplexer] or [crate::serve_connection] can run over it. Messages that cannot be translated either way are dropped, and messages longer than `max_len` end the stream.
+    pub(crate) fn msgpack_rpc_frames<R, W>(
+        reader: R,
+        writer: W,
+        max_len: usize,
+    ) -> (
+        impl Sink<String, Error = std::io::Error> + Send + Unpin + 'static,
+        impl Stream<Item = String> + Send + Unpin + 'static,
+    )
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+        W: AsyncWrite + Send + Unpin + 'static,
+    {
+        let sink = futures_util::sink::unfold(writer, |mut writer, frame: String| async move {
+            // a batch is sent as separate messages, whose responses come back separately too
+            let msgs = match serde_json::from_str::<Vec<JrpcMessage>>(&frame) {
+                Ok(msgs) => msgs,
+                Err(_) => serde_json::from_str::<JrpcMessage>(&frame)
+                    .map(|msg| vec![msg])
+                    .unwrap_or_default(),
+            };
+            for msg in msgs {
+                match to_msgpack_rpc(msg) {
+                    Some(buf) => writer.write_all(&buf).await?,
+                    None => log::warn!("dropping message that msgpack-rpc cannot carry"),
+                }
+            }
+            writer.flush().await?;
+            Ok(writer)
+        });
+        let stream = futures_util::stream::unfold(
+            (reader, Vec::new(), ValueScanner::new()),
+            move |(mut reader, mut buf, mut scanner)| async move {
+                loop {
+                    match scanner.scan(&buf, max_len) {
+                        Ok(Some(len)) => {
+                            scanner = ValueScanner::new();
+                            let decoded = rmp_serde::from_slice::<Transcoded>(&buf[..len]);
+                            buf.drain(..len);
+                            match decoded {
+                                Ok(Transcoded(msg)) => match from_msgpack_rpc(msg) {
+                                    Some(msg) => {
+                                        let frame = serde_json::to_string(&msg).unwrap();
+                                        return Some((frame, (reader, buf, scanner)));
+                                    }
+                                    None => log::warn!("dropping malformed msgpack-rpc message"),
+                                },
+                                Err(err) => {
+                                    log::warn!(
+                                        "malformed MessagePack, closing connection: {}",
+                                        err
+                                    );
+                                    return None;
+                                }
+                            }
+                        }
+                        Ok(None) => {
+                            buf.reserve(65536);
+                            if reader.read_buf(&mut buf).await.ok()? == 0 {
+                                return None;
+                            }
+                        }
+                        Err(err) => {
+                            log::warn!("{}, closing connection", err);
+                            return None;
+                        }
+                    }
+                }
+            },
+        );
+        (Box::pin(sink), Box::pin(stream))
+    }
+
+    /// Finds where a MessagePack value ends by walking its markers, without decoding it. It picks up where it left off as more bytes arrive, so that however slowly a long message trickles in, it is only scanned once and decoded once.
+    pub(super) struct ValueScanner {
+        /// The offset of the next marker.
+        pos: usize,
+        /// The number of values left to scan, including those nested in the ones already scanned.
+        pending: u64,
+    }
+
+    impl ValueScanner {
+        pub(super) fn new() -> Self {
+            Self { pos: 0, pending: 1 }
+        }
+
+        /// Returns the length of the value at the start of `buf`, or `None` if more bytes are needed. Fails if the value is malformed, or would be longer than `max_len`, which is known as soon as its lengths are.
+        pub(super) fn scan(&mut self, buf: &[u8], max_len: usize) -> Result<Option<usize>, String> {
+            while self.pending > 0 {
+                let Some(&marker) = buf.get(self.pos) else {
+                    return Ok(None);
+                };
+                let size_len = match marker {
+                    0xc4 | 0xc7 | 0xd9 => 1,
+                    0xc5 | 0xc8 | 0xda | 0xdc | 0xde => 2,
+                    0xc6 | 0xc9 | 0xdb | 0xdd | 0xdf => 4,
+                    _ => 0,
+                };
+                let Some(size) = buf.get(self.pos + 1..self.pos + 1 + size_len) else {
+                    return Ok(None);
+                };
+                let size = size.iter().fold(0u64, |acc, &b| acc << 8 | b as u64);
+                // the lengths of the header and of the payload, and the number of nested values
+                let (header, payload, nested) = match marker {
+                    0x00..=0x7f | 0xc0 | 0xc2 | 0xc3 | 0xe0..=0xff => (1, 0, 0),
+                    0x80..=0x8f => (1, 0, 2 * (marker & 0x0f) as u64),
+                    0x90..=0x9f => (1, 0, (marker & 0x0f) as u64),
+                    0xa0..=0xbf => (1, (marker & 0x1f) as u64, 0),
+                    0xc1 => return Err("malformed MessagePack".into()),
+                    // bin and str
+                    0xc4..=0xc6 | 0xd9..=0xdb => (1 + size_len, size, 0),
+                    // ext, whose type follows the size
+                    0xc7..=0xc9 => (2 + size_len, size, 0),
+                    0xcc | 0xd0 => (1, 1, 0),
+                    0xcd | 0xd1 => (1, 2, 0),
+                    0xca | 0xce | 0xd2 => (1, 4, 0),
+                    0xcb | 0xcf | 0xd3 => (1, 8, 0),
+                    // fixext, a type and 1 to 16 bytes
+                    0xd4..=0xd8 => (1, 1 + (1 << (marker - 0xd4)), 0),
+                    0xdc | 0xdd => (1 + size_len, 0, size),
+                    0xde | 0xdf => (1 + size_len, 0, 2 * size),
+                };
+                let end = self.pos as u64 + header as u64 + payload;
+                self.pending = self.pending - 1 + nested;
+                // every value pending takes at least a byte
+                if end + self.pending > max_len as u64 {
+                    return Err(format!("message longer than {} bytes", max_len));
+                }
+                self.pos = end as usize;
+            }
+            Ok((self.pos <= buf.len()).then_some(self.pos))
+        }
+    }
+
+    /// Translates a msgpack-rpc message into the JSON-RPC one it stands for.
+    fn from_msgpack_rpc(msg: serde_json::Value) -> Option<JrpcMessage> {
+        let serde_json::Value::Array(fields) = msg else {
+            return None;
+        };
+        let params = |params: serde_json::Value| match params {
+            serde_json::Value::Array(params) => params,
+            param => vec![param],
+        };
+        match <[serde_json::Value; 4]>::try_from(fields) {
+            Ok([kind, id, method, args]) if kind == REQUEST => {
+                Some(JrpcMessage::Request(JrpcRequest {
+                    jsonrpc: "2.0".into(),
+                    method: method.as_str()?.into(),
+                    params: params(args),
+                    id: JrpcId::Number(id.as_i64()?),
+                    meta: Default::default(),
+                }))
+            }
+            Ok([kind, id, error, result]) if kind == RESPONSE => {
+                let id = JrpcId::Number(id.as_i64()?);
+                let resp = match error {
+                    serde_json::Value::Null => JrpcResponse {
+                        jsonrpc: "2.0".into(),
+                        result: Some(result),
+                        error: None,
+                        id,
+                    },
+                    error => JrpcResponse {
+                        jsonrpc: "2.0".into(),
+                        result: None,
+                        error: Some(to_jrpc_error(error)),
+                        id,
+                    },
+                };
+                Some(JrpcMessage::Response(resp))
+            }
+            Ok(_) => None,
+            Err(fields) => match <[serde_json::Value; 3]>::try_from(fields) {
+                Ok([kind, method, args]) if kind == NOTIFICATION => {
+                    Some(JrpcMessage::Notification(JrpcNotification {
+                        jsonrpc: "2.0".into(),
+                        method: method.as_str()?.into(),
+                        params: params(args),
+                    }))
+                }
+                _ => None,
+            },
+        }
+    }
+
+    /// msgpack-rpc errors can be anything, so only those shaped like JSON-RPC errors keep their code.
+    fn to_jrpc_error(error: serde_json::Value) -> JrpcError {
+        if let Ok(error) = serde_json::from_value::<JrpcError>(error.clone()) {
+            return error;
+        }
+        JrpcError {
+            code: -32000,
+            message: match &error {
+                serde_json::Value::String(message) => message.clone(),
+                error => error.to_string(),
+            },
+            data: error,
+        }
+    }
+
+    /// Translates a JSON-RPC message into msgpack-rpc, if it can carry it: IDs must be numbers that fit in 32 bits.
+    fn to_msgpack_rpc(msg: JrpcMessage) -> Option<Vec<u8>> {
+        let msgid = |id: &JrpcId| match id {
+            JrpcId::Number(id) => u32::try_from(*id).ok(),
+            _ => None,
+        };
+        let encoded = match msg {
+            JrpcMessage::Request(req) => {
+                rmp_serde::to_vec(&(REQUEST, msgid(&req.id)?, req.method, req.params))
+            }
+            JrpcMessage::Response(resp) => {
+                let error = resp.error.map(|err| serde_json::to_value(err).unwrap());
+                rmp_serde::to_vec(&(RESPONSE, msgid(&resp.id)?, error, resp.result))
+            }
+            JrpcMessage::Notification(notif) => {
+                rmp_serde::to_vec(&(NOTIFICATION, notif.method, notif.params))
+            }
+        };
+        Some(encoded.expect("JSON values are always valid MessagePack"))
+    }
+}
+
+#[cfg(feature = "tcp")]
+pub(crate) use rpc::msgpack_rpc_frames;
+
+#[cfg(test)]
+mod tests {
+    use crate::{Codec, FnService, JrpcId, JrpcRequest, MsgpackCodec, RpcService, ServerConfig};
+
+    #[test]
+    fn test_msgpack_codec() {
+        smol::future::block_on(async move {
+            let service = FnService::new(|_, params| async move { Some(Ok(params[0].clone())) });
+            let config = ServerConfig {
+                codec: std::sync::Arc::new(MsgpackCodec),
+                ..Default::default()
+            };
+            let req = JrpcRequest {
+                jsonrpc: "2.0".into(),
+                method: "echo".into(),
+                params: vec![serde_json::json!({"n": 1, "s": "hi", "x": [1.5, null]})],
+                id: JrpcId::Number(1),
+                meta: Default::default(),
+            };
+            let encoded = MsgpackCodec.encode_request(&req);
+            assert!(encoded.len() < serde_json::to_vec(&req).unwrap().len());
+            let resp = service.respond_bytes(&encoded, &config).await;
+            let resp = MsgpackCodec.decode_response(&resp).unwrap();
+            assert_eq!(resp.result.unwrap(), req.params[0]);
+
+            // binary values from other peers come out as base64
+            let raw = [0x91, 0xc4, 3, 1, 2, 3];
+            assert_eq!(
+                MsgpackCodec.decode_value(&raw).unwrap(),
+                serde_json::json!(["AQID"])
+            );
+        });
+    }
+
+    #[cfg(feature = "tcp")]
+    #[test]
+    fn test_value_scanner() {
+        use super::rpc::ValueScanner;
+
+        let values = [
+            rmp_serde::to_vec(&(0, 7, "echo", ("hello", -5, 1.5, u64::MAX))).unwrap(),
+            rmp_serde::to_vec(&serde_json::json!({"s": "x".repeat(300), "a": [[], {}]})).unwrap(),
+            // bin and fixext
+            vec![0x92, 0xc4, 3, 1, 2, 3, 0xd6, 1, 0, 0, 0, 0],
+        ];
+        for value in values {
+            // fed a byte at a time, the value is complete once its last byte is there
+            let mut scanner = ValueScanner::new();
+            for end in 0..value.len() {
+                assert_eq!(scanner.scan(&value[..end], 1 << 20), Ok(None));
+            }
+            assert_eq!(scanner.scan(&value, 1 << 20), Ok(Some(value.len())));
+            let mut followed = value.clone();
+            followed.extend([0xc0; 3]);
+            assert_eq!(
+                ValueScanner::new().scan(&followed, 1 << 20),
+                Ok(Some(value.len()))
+            );
+        }
+        // values too long are refused as soon as their lengths arrive
+        assert!(ValueScanner::new()
+            .scan(&[0xdb, 0xff, 0xff, 0xff, 0xff], 1 << 20)
+            .is_err());
+        assert!(ValueScanner::new()
+            .scan(&[0xdd, 0xff, 0xff, 0xff, 0xff], 1 << 20)
+            .is_err());
+        assert!(ValueScanner::new().scan(&[0xc1], 1 << 20).is_err());
+    }
+
+    #[cfg(feature = "tcp")]
+    #[tokio::test]
+    async fn test_msgpack_rpc() {
+        use std::time::Duration;
+
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        use crate::{ConnInfo, RpcTransport, ServeHandle, TcpServer, TcpTransport};
+
+        let handle = ServeHandle::new();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(
+            TcpServer::new(|_: &ConnInfo| {
+                FnService::new(|method, params| {
+                    let method = method.to_string();
+                    async move { (method == "echo").then(|| Ok(params[0].clone())) }
+                })
+            })
+            .with_msgpack_rpc()
+            .with_handle(handle.clone())
+            .serve_listener(listener),
+        );
+
+        // a plain msgpack-rpc client, sending a message split in two
+        let mut raw = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let req = rmp_serde::to_vec(&(0, 7, "echo", ("hello",))).unwrap();
+        raw.write_all(&req[..3]).await.unwrap();
+        raw.flush().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        raw.write_all(&req[3..]).await.unwrap();
+        let mut buf = vec![0; 256];
+        let n = raw.read(&mut buf).await.unwrap();
+        let (kind, id, error, result): (u8, u32, Option<String>, String) =
+            rmp_serde::from_slice(&buf[..n]).unwrap();
+        assert_eq!((kind, id, error, result.as_str()), (1, 7, None, "hello"));
+        let missing = rmp_serde::to_vec(&(0, 8, "nope", ((),))).unwrap();
+        raw.write_all(&missing).await.unwrap();
+        let n = raw.read(&mut buf).await.unwrap();
+        let (_, _, error, _): (u8, u32, serde_json::Value, ()) =
+            rmp_serde::from_slice(&buf[..n]).unwrap();
+        assert_eq!(error["code"], -32601);
+        drop(raw);
+
+        let transport = TcpTransport::new(addr.to_string())
+            .with_msgpack_rpc()
+            .with_timeout(Duration::from_secs(5));
+        let (a, b) = futures_lite::future::zip(
+            transport.call("echo", &[1.into()]),
+            transport.call("echo", &["two".into()]),
+        )
+        .await;
+        assert_eq!(a.unwrap().unwrap().unwrap(), 1);
+        assert_eq!(b.unwrap().unwrap().unwrap(), "two");
+        drop(transport);
+
+        assert!(handle.shutdown(Duration::from_secs(5)).await);
+        server.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,251 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use libp2p::{PeerId, StreamProtocol};
+use libp2p_stream::{AlreadyRegistered, Control, OpenStreamError};
+use thiserror::Error;
+use tokio_util::compat::FuturesAsyncReadCompatExt;
+
+use super::lines::{line_frames, line_multiplexer};
+use crate::{
+    serve_connection, ConnInfo, Identity, JrpcMessage, JrpcRequest, JrpcResponse, Limits,
+    MultiplexError, Multiplexer, RpcTransport, ServeHandle, ServerConfig, SessionFactory,
+};
+
+pub use libp2p_stream;
+
+/// An error returned by a [P2pTransport].
+#[derive(Error, Debug)]
+pub enum P2pError {
+    #[error("could not open stream: {0}")]
+    Open(#[from] OpenStreamError),
+    #[error(transparent)]
+    Multiplex(#[from] MultiplexError),
+}
+
+/// A P2pTransport makes calls to a libp2p peer over a stream of the given protocol, carrying one JSON-RPC message per line, and running many calls concurrently through a [Multiplexer]. Notifications from the peer are available through [P2pTransport::next_incoming].
+///
+/// Streams are opened through the [Control] of a [libp2p_stream::Behaviour] in the node's swarm, which must be driven elsewhere. The stream is opened on first use, and opened again on the next call after it is lost. Requires the `libp2p` feature, and a tokio runtime.
+pub struct P2pTransport {
+    peer: PeerId,
+    protocol: StreamProtocol,
+    timeout: Option<Duration>,
+    limits: Limits,
+    state: async_lock::Mutex<(Control, Option<Arc<Multiplexer>>)>,
+}
+
+impl P2pTransport {
+    /// Creates a new P2pTransport to the given peer and protocol, like `"/myapp/rpc/1"`.
+    pub fn new(control: Control, peer: PeerId, protocol: StreamProtocol) -> Self {
+        Self {
+            peer,
+            protocol,
+            timeout: None,
+            limits: Limits::default(),
+            state: async_lock::Mutex::new((control, None)),
+        }
+    }
+
+    /// Sets a timeout for every call.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the limits that incoming messages are checked against. Lines longer than [Limits::max_message_size] drop the stream.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Waits for the next message from the peer that is not a response, like a notification. Returns `None` once the stream is lost, or if it cannot be opened.
+    pub async fn next_incoming(&self) -> Option<JrpcMessage> {
+        self.connection().await.ok()?.next_incoming().await
+    }
+
+    /// Returns the live stream, opening it again if it was lost.
+    async fn connection(&self) -> Result<Arc<Multiplexer>, P2pError> {
+        let mut state = self.state.lock().await;
+        let (control, mux) = &mut *state;
+        if let Some(mux) = mux.as_ref().filter(|mux| !mux.is_closed()) {
+            return Ok(mux.clone());
+        }
+        let stream = control
+            .open_stream(self.peer, self.protocol.clone())
+            .await?;
+        let (reader, writer) = tokio::io::split(stream.compat());
+        let new_mux = Arc::new(line_multiplexer(reader, writer, self.limits, self.timeout));
+        *mux = Some(new_mux.clone());
+        Ok(new_mux)
+    }
+}
+
+#[async_trait]
+impl RpcTransport for P2pTransport {
+    type Error = P2pError;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        Ok(self.connection().await?.call_raw(req).await?)
+    }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        Ok(self.connection().await?.call_raw_batch(reqs).await?)
+    }
+}
+
+/// A P2pServer handles the streams that libp2p peers open for a protocol, serving each with its own service made by a [SessionFactory], through [serve_connection]. The peer's ID is attached to the context of every call as its [Identity], since libp2p authenticates it.
+///
+/// Shutting down through the [ServeHandle] stops accepting streams, and waits for the open ones to close. Requires the `libp2p` feature, and a tokio runtime.
+pub struct P2pServer<F: SessionFactory> {
+    factory: F,
+    config: ServerConfig,
+    handle: ServeHandle,
+}
+
+impl<F: SessionFactory> P2pServer<F> {
+    /// Creates a new P2pServer.
+    pub fn new(factory: F) -> Self {
+        Self {
+            factory,
+            config: ServerConfig::default(),
+            handle: ServeHandle::new(),
+        }
+    }
+
+    /// Sets the configuration used to handle requests.
+    pub fn with_config(mut self, config: ServerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Uses the given [ServeHandle], so that the server can be gracefully shut down through it.
+    pub fn with_handle(mut self, handle: ServeHandle) -> Self {
+        self.handle = handle;
+        self
+    }
+
+    /// Registers the protocol with the [Control] and serves its streams until shut down through the [ServeHandle]. Fails if something else already handles the protocol.
+    pub async fn serve(
+        self,
+        mut control: Control,
+        protocol: StreamProtocol,
+    ) -> Result<(), AlreadyRegistered> {
+        let mut incoming = control.accept(protocol)?;
+        let shutdown = self.handle.shutdown_signal();
+        let this = Arc::new(self);
+        loop {
+            let accepted = futures_lite::future::or(incoming.next(), async {
+                shutdown.wait().await;
+                None
+            })
+            .await;
+            let Some((peer, stream)) = accepted else {
+                return Ok(());
+            };
+            let conn = this
+                .handle
+                .prepare_conn(ConnInfo::new().with_identity(Identity {
+                    id: peer.to_string(),
+                    roles: vec![],
+                }));
+            let this = this.clone();
+            tokio::spawn(async move {
+                let (reader, writer) = tokio::io::split(stream.compat());
+                let (sink, stream) =
+                    line_frames(reader, writer, this.config.limits.max_message_size);
+                this.handle
+                    .track(serve_connection(
+                        &this.factory,
+                        conn,
+                        sink,
+                        stream,
+                        &this.config,
+                    ))
+                    .await;
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures_util::StreamExt;
+    use libp2p::{
+        core::{transport::MemoryTransport, upgrade::Version},
+        plaintext,
+        swarm::dial_opts::DialOpts,
+        yamux, Multiaddr, StreamProtocol, Swarm, Transport,
+    };
+
+    use crate::{
+        libp2p_stream, ConnInfo, FnService, P2pServer, P2pTransport, RpcTransport, ServeHandle,
+    };
+
+    fn swarm() -> Swarm<libp2p_stream::Behaviour> {
+        libp2p::SwarmBuilder::with_new_identity()
+            .with_tokio()
+            .with_other_transport(|key| {
+                MemoryTransport::default()
+                    .upgrade(Version::V1)
+                    .authenticate(plaintext::Config::new(key))
+                    .multiplex(yamux::Config::default())
+                    .boxed()
+            })
+            .unwrap()
+            .with_behaviour(|_| libp2p_stream::Behaviour::new())
+            .unwrap()
+            .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
+            .build()
+    }
+
+    #[tokio::test]
+    async fn test_p2p() {
+        let protocol = StreamProtocol::new("/nanorpc/test");
+        let addr: Multiaddr = format!("/memory/{}", fastrand::u64(1..)).parse().unwrap();
+        let mut server_swarm = swarm();
+        server_swarm.listen_on(addr.clone()).unwrap();
+        let server_peer = *server_swarm.local_peer_id();
+        let server_control = server_swarm.behaviour().new_control();
+        let mut client_swarm = swarm();
+        let client_peer = *client_swarm.local_peer_id();
+        let client_control = client_swarm.behaviour().new_control();
+        client_swarm
+            .dial(DialOpts::peer_id(server_peer).addresses(vec![addr]).build())
+            .unwrap();
+        tokio::spawn(async move { while server_swarm.next().await.is_some() {} });
+        tokio::spawn(async move { while client_swarm.next().await.is_some() {} });
+
+        let handle = ServeHandle::new();
+        let server = tokio::spawn(
+            P2pServer::new(|conn: &ConnInfo| {
+                let id = conn.identity.clone().map(|identity| identity.id);
+                FnService::new(move |_, _| {
+                    let id = id.clone();
+                    async move { Some(Ok(id.into())) }
+                })
+            })
+            .with_handle(handle.clone())
+            .serve(server_control, protocol.clone()),
+        );
+        let transport = P2pTransport::new(client_control, server_peer, protocol)
+            .with_timeout(Duration::from_secs(5));
+        assert_eq!(
+            transport
+                .call("whoami", &[])
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap(),
+            client_peer.to_string()
+        );
+        drop(transport);
+        assert!(handle.shutdown(Duration::from_secs(5)).await);
+        server.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,172 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures_util::{Sink, Stream};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_serial::SerialStream;
+
+use super::lines::{line_multiplexer, spawn_multiplexer};
+use crate::{
+    JrpcMessage, JrpcRequest, JrpcResponse, Limits, MultiplexError, Multiplexer, RpcTransport,
+};
+
+pub use tokio_serial;
+
+/// How JSON-RPC messages are delimited on a serial line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum SerialFraming {
+    /// One message per line, which is the easiest to emit from firmware and to read in a terminal.
+    #[default]
+    Lines,
+    /// Every message is preceded by its length as a 4-byte big-endian integer, for firmware that would rather not scan for newlines.
+    LengthPrefixed,
+}
+
+/// Frames a byte stream as messages preceded by their length. Messages longer than `max_len` end the stream, since there is no way to recover from them.
+fn length_prefixed_frames<R, W>(
+    reader: R,
+    writer: W,
+    max_len: usize,
+) -> (
+    impl Sink<String, Error = std::io::Error> + Send + Unpin + 'static,
+    impl Stream<Item = String> + Send + Unpin + 'static,
+)
+where
+    R: AsyncRead + Send + Unpin + 'static,
+    W: AsyncWrite + Send + Unpin + 'static,
+{
+    let sink = futures_util::sink::unfold(writer, |mut writer, frame: String| async move {
+        let len = u32::try_from(frame.len())
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "frame too long"))?;
+        let mut buf = len.to_be_bytes().to_vec();
+        buf.extend_from_slice(frame.as_bytes());
+        writer.write_all(&buf).await?;
+        writer.flush().await?;
+        Ok(writer)
+    });
+    let stream = futures_util::stream::unfold(reader, move |mut reader| async move {
+        let len = reader.read_u32().await.ok()? as usize;
+        if len > max_len {
+            log::warn!("frame longer than {} bytes, closing connection", max_len);
+            return None;
+        }
+        let mut frame = vec![0; len];
+        reader.read_exact(&mut frame).await.ok()?;
+        Some((String::from_utf8_lossy(&frame).into_owned(), reader))
+    });
+    (Box::pin(sink), Box::pin(stream))
+}
+
+/// A SerialTransport makes calls over a serial port, to devices like microcontrollers whose firmware speaks JSON-RPC, running many calls concurrently through a [Multiplexer]. Notifications from the device are available through [SerialTransport::next_incoming].
+///
+/// Messages are framed as given by a [SerialFraming]. Requires the `serial` feature, and a tokio runtime.
+pub struct SerialTransport {
+    mux: Multiplexer,
+}
+
+impl SerialTransport {
+    /// Opens the serial port at the given path, like `/dev/ttyUSB0` or `COM3`, at the given baud rate.
+    pub fn open(
+        path: &str,
+        baud_rate: u32,
+        framing: SerialFraming,
+    ) -> Result<Self, tokio_serial::Error> {
+        let port = SerialStream::open(&tokio_serial::new(path, baud_rate))?;
+        Ok(Self::from_stream(port, framing))
+    }
+
+    /// Makes calls over an already opened serial port, or anything else that reads and writes bytes.
+    pub fn from_stream(
+        port: impl AsyncRead + AsyncWrite + Send + 'static,
+        framing: SerialFraming,
+    ) -> Self {
+        let limits = Limits::default();
+        let (reader, writer) = tokio::io::split(port);
+        let mux = match framing {
+            SerialFraming::Lines => line_multiplexer(reader, writer, limits, None),
+            SerialFraming::LengthPrefixed => {
+                let (sink, stream) =
+                    length_prefixed_frames(reader, writer, limits.max_message_size);
+                spawn_multiplexer(sink, stream, limits, None)
+            }
+        };
+        Self { mux }
+    }
+
+    /// Sets a timeout for every call. Worth setting, since a device that resets loses the calls in flight without the port ever closing.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self {
+            mux: self.mux.with_timeout(timeout),
+        }
+    }
+
+    /// Waits for the next message from the device that is not a response, like a notification. Returns `None` once the port is closed.
+    pub async fn next_incoming(&self) -> Option<JrpcMessage> {
+        self.mux.next_incoming().await
+    }
+}
+
+#[async_trait]
+impl RpcTransport for SerialTransport {
+    type Error = MultiplexError;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        self.mux.call_raw(req).await
+    }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        self.mux.call_raw_batch(reqs).await
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::time::Duration;
+
+    use super::{length_prefixed_frames, SerialFraming, SerialTransport};
+    use crate::{
+        adapters::lines::line_frames, serve_connection, tokio_serial::SerialStream, ConnInfo,
+        FnService, RpcTransport, ServerConfig,
+    };
+
+    /// Plays the firmware on the other end of the line, echoing the first parameter.
+    async fn device(port: SerialStream, framing: SerialFraming) {
+        let (reader, writer) = tokio::io::split(port);
+        let service = FnService::new(|_, params| {
+            let param = params[0].clone();
+            async move { Some(Ok(param)) }
+        });
+        let factory = move |_: &ConnInfo| service.clone();
+        let config = ServerConfig::default();
+        match framing {
+            SerialFraming::Lines => {
+                let (sink, stream) = line_frames(reader, writer, 1 << 20);
+                serve_connection(&factory, ConnInfo::new(), sink, stream, &config).await
+            }
+            SerialFraming::LengthPrefixed => {
+                let (sink, stream) = length_prefixed_frames(reader, writer, 1 << 20);
+                serve_connection(&factory, ConnInfo::new(), sink, stream, &config).await
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_serial() {
+        for framing in [SerialFraming::Lines, SerialFraming::LengthPrefixed] {
+            let (host, firmware) = SerialStream::pair().unwrap();
+            tokio::spawn(device(firmware, framing));
+            let transport =
+                SerialTransport::from_stream(host, framing).with_timeout(Duration::from_secs(5));
+            let (text, number) = (["hello\nworld".into()], [2.into()]);
+            let (a, b) = futures_util::join!(
+                transport.call("echo", &text),
+                transport.call("echo", &number)
+            );
+            assert_eq!(a.unwrap().unwrap().unwrap(), "hello\nworld");
+            assert_eq!(b.unwrap().unwrap().unwrap(), 2);
+        }
+    }
+}
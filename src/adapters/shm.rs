@@ -0,0 +1,804 @@
+use std::{
+    fs::File,
+    future::Future,
+    os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    task::{ready, Context, Poll},
+};
+
+use event_listener::{Event, EventListener};
+use tokio::{
+    io::{
+        AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, Interest,
+        ReadBuf,
+    },
+    net::UnixStream,
+};
+
+use crate::{server::error_response, JrpcId, JrpcRequest, JrpcResponse};
+
+/// The method through which a client offers shared memory to a server. Servers that don't support it answer with an error, so that both sides keep using the socket.
+///
+/// The offer only carries the capacity of the rings. A server that accepts it answers `true`, upon which the client sends the memory itself, as a sealed memfd passed over the socket, and the server answers once more with whether it could map it.
+pub const SHARED_MEMORY_METHOD: &str = "rpc.shm";
+
+/// The bytes before the data of each ring: its write position, its read position, and whether the reader and the writer are waiting.
+const HEADER_LEN: usize = 64;
+const HEAD: usize = 0;
+const TAIL: usize = 1;
+const READER_WAITING: usize = 2;
+const WRITER_WAITING: usize = 3;
+
+/// Sent over the socket when the ring the peer reads from has new data.
+const DATA_BELL: u8 = b'd';
+/// Sent over the socket when the ring the peer writes to has room again.
+const SPACE_BELL: u8 = b's';
+/// Sent over the socket along with the memfd, since a message can't consist of a file descriptor alone.
+const MEMORY_BYTE: u8 = b'm';
+
+/// A shared mapping of a whole file.
+struct Mapping {
+    ptr: *mut u8,
+    len: usize,
+}
+
+// SAFETY: the mapping is only ever accessed through atomics and raw copies
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    fn new(file: &File, len: usize) -> std::io::Result<Self> {
+        // SAFETY: maps the whole file, which stays mapped after the file is closed
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(Self {
+            ptr: ptr.cast(),
+            len,
+        })
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        // SAFETY: the rings that point into the mapping hold it alive
+        unsafe {
+            libc::munmap(self.ptr.cast(), self.len);
+        }
+    }
+}
+
+/// One direction of a connection: a single-producer, single-consumer ring of bytes in the mapping. The positions only ever grow, and are taken modulo the capacity.
+///
+/// The peer can write anything into the mapping, so positions that don't make sense are treated as a broken connection, and never lead outside the ring.
+struct Ring {
+    map: Arc<Mapping>,
+    offset: usize,
+    capacity: usize,
+}
+
+impl Ring {
+    /// Returns the two rings of a mapping made for the given capacity.
+    fn pair(map: Mapping, capacity: usize) -> (Ring, Ring) {
+        let map = Arc::new(map);
+        let ring = |offset| Ring {
+            map: map.clone(),
+            offset,
+            capacity,
+        };
+        (ring(0), ring(HEADER_LEN + capacity))
+    }
+
+    fn header(&self, field: usize) -> &AtomicU64 {
+        // SAFETY: the header lies within the mapping, aligned since the mapping is page-aligned and the capacity a multiple of 8
+        unsafe { &*(self.map.ptr.add(self.offset + field * 8) as *const AtomicU64) }
+    }
+
+    fn data(&self) -> *mut u8 {
+        // SAFETY: the data follows the header within the mapping
+        unsafe { self.map.ptr.add(self.offset + HEADER_LEN) }
+    }
+
+    /// Returns the read and write positions, or `None` if they make no sense.
+    fn positions(&self) -> Option<(u64, u64)> {
+        let tail = self.header(TAIL).load(Ordering::SeqCst);
+        let head = self.header(HEAD).load(Ordering::SeqCst);
+        (head.wrapping_sub(tail) <= self.capacity as u64).then_some((tail, head))
+    }
+
+    /// Copies as much of `buf` into the ring as fits.
+    fn push(&self, buf: &[u8]) -> Option<usize> {
+        let (tail, head) = self.positions()?;
+        let len = buf
+            .len()
+            .min(self.capacity - head.wrapping_sub(tail) as usize);
+        let start = (head % self.capacity as u64) as usize;
+        let first = len.min(self.capacity - start);
+        // SAFETY: both pieces lie within the data of the ring
+        unsafe {
+            std::ptr::copy_nonoverlapping(buf.as_ptr(), self.data().add(start), first);
+            std::ptr::copy_nonoverlapping(buf[first..].as_ptr(), self.data(), len - first);
+        }
+        self.header(HEAD)
+            .store(head.wrapping_add(len as u64), Ordering::SeqCst);
+        Some(len)
+    }
+
+    /// Copies as much out of the ring as fits into `buf`.
+    fn pop(&self, buf: &mut [u8]) -> Option<usize> {
+        let (tail, head) = self.positions()?;
+        let len = buf.len().min(head.wrapping_sub(tail) as usize);
+        let start = (tail % self.capacity as u64) as usize;
+        let first = len.min(self.capacity - start);
+        // SAFETY: both pieces lie within the data of the ring
+        unsafe {
+            std::ptr::copy_nonoverlapping(self.data().add(start), buf.as_mut_ptr(), first);
+            std::ptr::copy_nonoverlapping(self.data(), buf[first..].as_mut_ptr(), len - first);
+        }
+        self.header(TAIL)
+            .store(tail.wrapping_add(len as u64), Ordering::SeqCst);
+        Some(len)
+    }
+
+    fn set_waiting(&self, field: usize) {
+        self.header(field).store(1, Ordering::SeqCst);
+    }
+
+    fn take_waiting(&self, field: usize) -> bool {
+        self.header(field).swap(0, Ordering::SeqCst) != 0
+    }
+}
+
+/// Wakeups from the peer, read off the socket.
+#[derive(Default)]
+struct Bells {
+    data: Event,
+    space: Event,
+    closed: AtomicBool,
+}
+
+/// The socket of a connection over shared memory, which only carries bells once the memory is set up. The connection is closed once both halves are dropped.
+struct Doorbell {
+    socket: Arc<UnixStream>,
+    bells: Arc<Bells>,
+    listener: tokio::task::JoinHandle<()>,
+}
+
+impl Doorbell {
+    fn new(socket: UnixStream) -> Self {
+        let socket = Arc::new(socket);
+        let bells = Arc::new(Bells::default());
+        let listener = tokio::spawn(listen_for_bells(socket.clone(), bells.clone()));
+        Self {
+            socket,
+            bells,
+            listener,
+        }
+    }
+
+    fn ring(&self, bell: u8) {
+        // if the socket is full, the peer has bells left to read anyway
+        let _ = self.socket.try_write(&[bell]);
+    }
+
+    fn is_closed(&self) -> bool {
+        self.bells.closed.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for Doorbell {
+    fn drop(&mut self) {
+        self.listener.abort();
+    }
+}
+
+async fn listen_for_bells(socket: Arc<UnixStream>, bells: Arc<Bells>) {
+    let mut buf = [0; 256];
+    loop {
+        if socket.readable().await.is_err() {
+            break;
+        }
+        match socket.try_read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => {
+                if buf[..n].contains(&DATA_BELL) {
+                    bells.data.notify(usize::MAX);
+                }
+                if buf[..n].contains(&SPACE_BELL) {
+                    bells.space.notify(usize::MAX);
+                }
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {}
+            Err(_) => break,
+        }
+    }
+    bells.closed.store(true, Ordering::SeqCst);
+    bells.data.notify(usize::MAX);
+    bells.space.notify(usize::MAX);
+}
+
+fn broken_ring() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        "shared memory ring is corrupt",
+    )
+}
+
+/// The reading half of a connection over shared memory.
+pub(crate) struct ShmReader {
+    ring: Ring,
+    doorbell: Arc<Doorbell>,
+    listener: Option<EventListener>,
+}
+
+impl AsyncRead for ShmReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = &mut *self;
+        loop {
+            // checked first, so that everything sent before the close is read
+            let closed = this.doorbell.is_closed();
+            let n = this
+                .ring
+                .pop(buf.initialize_unfilled())
+                .ok_or_else(broken_ring)?;
+            if n > 0 || closed || buf.remaining() == 0 {
+                buf.advance(n);
+                this.listener = None;
+                if n > 0 && this.ring.take_waiting(WRITER_WAITING) {
+                    this.doorbell.ring(SPACE_BELL);
+                }
+                return Poll::Ready(Ok(()));
+            }
+            match this.listener.as_mut() {
+                // the ring is checked once more after registering, so that no bell is missed
+                None => {
+                    this.listener = Some(this.doorbell.bells.data.listen());
+                    this.ring.set_waiting(READER_WAITING);
+                }
+                Some(listener) => {
+                    ready!(Pin::new(listener).poll(cx));
+                    this.listener = None;
+                }
+            }
+        }
+    }
+}
+
+/// The writing half of a connection over shared memory.
+pub(crate) struct ShmWriter {
+    ring: Ring,
+    doorbell: Arc<Doorbell>,
+    listener: Option<EventListener>,
+}
+
+impl AsyncWrite for ShmWriter {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = &mut *self;
+        loop {
+            if this.doorbell.is_closed() {
+                return Poll::Ready(Err(std::io::ErrorKind::BrokenPipe.into()));
+            }
+            let n = this.ring.push(buf).ok_or_else(broken_ring)?;
+            if n > 0 || buf.is_empty() {
+                this.listener = None;
+                if this.ring.take_waiting(READER_WAITING) {
+                    this.doorbell.ring(DATA_BELL);
+                }
+                return Poll::Ready(Ok(n));
+            }
+            match this.listener.as_mut() {
+                None => {
+                    this.listener = Some(this.doorbell.bells.space.listen());
+                    this.ring.set_waiting(WRITER_WAITING);
+                }
+                Some(listener) => {
+                    ready!(Pin::new(listener).poll(cx));
+                    this.listener = None;
+                }
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+fn halves(read: Ring, write: Ring, socket: UnixStream) -> (ShmReader, ShmWriter) {
+    let doorbell = Arc::new(Doorbell::new(socket));
+    (
+        ShmReader {
+            ring: read,
+            doorbell: doorbell.clone(),
+            listener: None,
+        },
+        ShmWriter {
+            ring: write,
+            doorbell,
+            listener: None,
+        },
+    )
+}
+
+fn file_len(capacity: usize) -> usize {
+    2 * (HEADER_LEN + capacity)
+}
+
+/// The seals that keep the peer from resizing the memory, which would make accessing the mapping crash the process with `SIGBUS`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const REQUIRED_SEALS: libc::c_int = libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_SEAL;
+
+/// Makes a memfd for rings of the given capacity, seals its size, and maps it.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn create_mapping(capacity: usize) -> std::io::Result<(File, Mapping)> {
+    // SAFETY: the name is a valid C string, and the returned descriptor is owned by nothing else
+    let file = unsafe {
+        let fd = libc::memfd_create(
+            c"nanorpc-shm".as_ptr(),
+            libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING,
+        );
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        File::from_raw_fd(fd)
+    };
+    file.set_len(file_len(capacity) as u64)?;
+    // SAFETY: adding seals to a memfd that we own
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_ADD_SEALS, REQUIRED_SEALS) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let mapping = Mapping::new(&file, file_len(capacity))?;
+    Ok((file, mapping))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn create_mapping(_capacity: usize) -> std::io::Result<(File, Mapping)> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+/// Sends a file descriptor over the socket, along with [MEMORY_BYTE].
+async fn send_fd(socket: &UnixStream, fd: RawFd) -> std::io::Result<()> {
+    socket
+        .async_io(Interest::WRITABLE, || {
+            let mut byte = [MEMORY_BYTE];
+            let mut iov = libc::iovec {
+                iov_base: byte.as_mut_ptr().cast(),
+                iov_len: 1,
+            };
+            // u64s, so that the control message is aligned
+            let mut control = [0u64; 8];
+            // SAFETY: the message points at live buffers, and the control buffer has room for one descriptor
+            let sent = unsafe {
+                let mut msg: libc::msghdr = std::mem::zeroed();
+                msg.msg_iov = &mut iov;
+                msg.msg_iovlen = 1;
+                msg.msg_control = control.as_mut_ptr().cast();
+                msg.msg_controllen = libc::CMSG_SPACE(std::mem::size_of::<RawFd>() as u32) as _;
+                let cmsg = libc::CMSG_FIRSTHDR(&msg);
+                (*cmsg).cmsg_level = libc::SOL_SOCKET;
+                (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+                (*cmsg).cmsg_len = libc::CMSG_LEN(std::mem::size_of::<RawFd>() as u32) as _;
+                std::ptr::write_unaligned(libc::CMSG_DATA(cmsg).cast::<RawFd>(), fd);
+                libc::sendmsg(socket.as_raw_fd(), &msg, libc::MSG_NOSIGNAL)
+            };
+            match sent {
+                1 => Ok(()),
+                0 => Err(std::io::ErrorKind::WriteZero.into()),
+                _ => Err(std::io::Error::last_os_error()),
+            }
+        })
+        .await
+}
+
+/// Receives a file descriptor sent with [send_fd]. Any other descriptors that come along are closed.
+async fn recv_fd(socket: &UnixStream) -> std::io::Result<OwnedFd> {
+    socket
+        .async_io(Interest::READABLE, || {
+            let mut byte = [0u8];
+            let mut iov = libc::iovec {
+                iov_base: byte.as_mut_ptr().cast(),
+                iov_len: 1,
+            };
+            let mut control = [0u64; 8];
+            // SAFETY: the message points at live buffers of the given sizes
+            let (received, msg) = unsafe {
+                let mut msg: libc::msghdr = std::mem::zeroed();
+                msg.msg_iov = &mut iov;
+                msg.msg_iovlen = 1;
+                msg.msg_control = control.as_mut_ptr().cast();
+                msg.msg_controllen = std::mem::size_of_val(&control) as _;
+                let received = libc::recvmsg(socket.as_raw_fd(), &mut msg, libc::MSG_CMSG_CLOEXEC);
+                (received, msg)
+            };
+            if received < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            let mut fds = vec![];
+            // SAFETY: walks the control messages that the kernel filled in, taking ownership of the descriptors in them
+            unsafe {
+                let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+                while !cmsg.is_null() {
+                    if (*cmsg).cmsg_level == libc::SOL_SOCKET
+                        && (*cmsg).cmsg_type == libc::SCM_RIGHTS
+                    {
+                        let data = libc::CMSG_DATA(cmsg).cast::<RawFd>();
+                        let len = (*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize;
+                        for i in 0..len / std::mem::size_of::<RawFd>() {
+                            fds.push(OwnedFd::from_raw_fd(std::ptr::read_unaligned(data.add(i))));
+                        }
+                    }
+                    cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+                }
+            }
+            if received == 0 {
+                return Err(std::io::ErrorKind::UnexpectedEof.into());
+            }
+            if byte[0] != MEMORY_BYTE || msg.msg_flags & libc::MSG_CTRUNC != 0 || fds.len() != 1 {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "expected exactly one memfd",
+                ));
+            }
+            Ok(fds.pop().unwrap())
+        })
+        .await
+}
+
+/// Reads an answer of the server off the socket, returning whether it is `true`.
+async fn read_answer(socket: &mut UnixStream) -> std::io::Result<bool> {
+    // read byte by byte, so that nothing after the answer is taken off the socket
+    let mut answer = vec![];
+    loop {
+        match socket.read_u8().await? {
+            b'\n' => break,
+            _ if answer.len() > 4096 => return Err(std::io::ErrorKind::InvalidData.into()),
+            b => answer.push(b),
+        }
+    }
+    Ok(serde_json::from_slice::<JrpcResponse>(&answer)
+        .map(|resp| resp.result == Some(true.into()))
+        .unwrap_or(false))
+}
+
+/// Offers shared memory with rings of the given capacity, rounded up to a multiple of 8, on a fresh connection. Gives the socket back if shared memory cannot be set up, or the server refuses it, so that the connection goes on carrying lines as usual.
+pub(crate) async fn offer_shm(
+    socket: UnixStream,
+    capacity: usize,
+) -> std::io::Result<Result<(ShmReader, ShmWriter), UnixStream>> {
+    let capacity = capacity.max(1).next_multiple_of(8);
+    match create_mapping(capacity) {
+        Ok((file, mapping)) => offer_mapping(socket, &file, mapping, capacity).await,
+        Err(err) => {
+            log::debug!("could not set up shared memory, using the socket: {}", err);
+            Ok(Err(socket))
+        }
+    }
+}
+
+/// Offers the given memory, mapped for rings of the given capacity.
+async fn offer_mapping(
+    mut socket: UnixStream,
+    file: &File,
+    mapping: Mapping,
+    capacity: usize,
+) -> std::io::Result<Result<(ShmReader, ShmWriter), UnixStream>> {
+    let offer = JrpcRequest {
+        jsonrpc: "2.0".into(),
+        method: SHARED_MEMORY_METHOD.into(),
+        params: vec![capacity.into()],
+        id: JrpcId::String(SHARED_MEMORY_METHOD.into()),
+        meta: Default::default(),
+    };
+    let mut line = serde_json::to_vec(&offer).unwrap();
+    line.push(b'\n');
+    socket.write_all(&line).await?;
+    if !read_answer(&mut socket).await? {
+        log::debug!("server refused shared memory, using the socket");
+        return Ok(Err(socket));
+    }
+    send_fd(&socket, file.as_raw_fd()).await?;
+    if !read_answer(&mut socket).await? {
+        log::debug!("server could not map shared memory, using the socket");
+        return Ok(Err(socket));
+    }
+    let (client_to_server, server_to_client) = Ring::pair(mapping, capacity);
+    Ok(Ok(halves(server_to_client, client_to_server, socket)))
+}
+
+/// How a server connection goes on after [accept_shm].
+pub(crate) enum ShmAccept {
+    /// Over shared memory.
+    Shm(ShmReader, ShmWriter),
+    /// Over the socket, starting with the given line.
+    Lines(BufReader<UnixStream>, String),
+}
+
+async fn write_answer(
+    socket: &mut UnixStream,
+    id: JrpcId,
+    result: &std::io::Result<impl Sized>,
+) -> std::io::Result<()> {
+    let answer = match result {
+        Ok(_) => JrpcResponse {
+            jsonrpc: "2.0".into(),
+            result: Some(true.into()),
+            error: None,
+            id,
+        },
+        Err(err) => {
+            log::debug!("refusing shared memory: {}", err);
+            error_response(id, -32602, err.to_string())
+        }
+    };
+    let mut answer = serde_json::to_vec(&answer).unwrap();
+    answer.push(b'\n');
+    socket.write_all(&answer).await
+}
+
+/// Reads the first line of a connection, taking up the client on its offer of shared memory if it makes one, with rings of up to `max_capacity` bytes. Returns `None` if the connection is lost, or the line is longer than `max_len`.
+pub(crate) async fn accept_shm(
+    socket: UnixStream,
+    max_capacity: usize,
+    max_len: usize,
+) -> Option<ShmAccept> {
+    let mut reader = BufReader::new(socket);
+    loop {
+        let mut line = vec![];
+        (&mut reader)
+            .take(max_len as u64 + 1)
+            .read_until(b'\n', &mut line)
+            .await
+            .ok()?;
+        if line.is_empty() || line.len() > max_len + 1 {
+            return None;
+        }
+        let line = String::from_utf8_lossy(&line).into_owned();
+        let offer = match serde_json::from_str::<JrpcRequest>(&line) {
+            Ok(req) if req.method == SHARED_MEMORY_METHOD => req,
+            _ => return Some(ShmAccept::Lines(reader, line)),
+        };
+        // a refused client goes on with lines, as with any server that doesn't support shared memory
+        let capacity = offer_capacity(&offer.params, max_capacity);
+        write_answer(reader.get_mut(), offer.id.clone(), &capacity)
+            .await
+            .ok()?;
+        let Ok(capacity) = capacity else {
+            continue;
+        };
+        // the client waits for the answer, so nothing else is buffered
+        let fd = recv_fd(reader.get_ref()).await.ok()?;
+        let mapping = map_memory(fd, capacity);
+        write_answer(reader.get_mut(), offer.id, &mapping)
+            .await
+            .ok()?;
+        if let Ok(mapping) = mapping {
+            let (client_to_server, server_to_client) = Ring::pair(mapping, capacity);
+            let (reader, writer) = halves(client_to_server, server_to_client, reader.into_inner());
+            return Some(ShmAccept::Shm(reader, writer));
+        }
+    }
+}
+
+/// Returns the capacity of the rings that an offer asks for, if it is acceptable.
+fn offer_capacity(params: &[serde_json::Value], max_capacity: usize) -> std::io::Result<usize> {
+    if cfg!(not(any(target_os = "linux", target_os = "android"))) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "shared memory is not supported on this platform",
+        ));
+    }
+    let capacity = match params {
+        [capacity] => capacity.as_u64().unwrap_or(0) as usize,
+        _ => 0,
+    };
+    if capacity == 0 || !capacity.is_multiple_of(8) || capacity > max_capacity {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "unacceptable capacity",
+        ));
+    }
+    Ok(capacity)
+}
+
+/// Maps memory sent by a client, if it is a memfd of the right size, sealed so that it cannot be resized.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn map_memory(fd: OwnedFd, capacity: usize) -> std::io::Result<Mapping> {
+    let invalid = |msg: &str| std::io::Error::new(std::io::ErrorKind::InvalidInput, msg);
+    let file = File::from(fd);
+    // SAFETY: only reads the seals of a descriptor that we own; files that can't be sealed fail
+    let seals = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GET_SEALS) };
+    if seals < 0 || seals & REQUIRED_SEALS != REQUIRED_SEALS {
+        return Err(invalid("shared memory is not sealed against resizing"));
+    }
+    if file.metadata()?.len() != file_len(capacity) as u64 {
+        return Err(invalid("shared memory has the wrong size"));
+    }
+    Mapping::new(&file, file_len(capacity))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn map_memory(_fd: OwnedFd, _capacity: usize) -> std::io::Result<Mapping> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, os::unix::io::FromRawFd, sync::atomic::Ordering, time::Duration};
+
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    use super::{
+        accept_shm, create_mapping, file_len, offer_mapping, offer_shm, Mapping, Ring, ShmAccept,
+        HEAD, TAIL,
+    };
+    use crate::{ConnInfo, FnService, RpcTransport, ServeHandle, UnixServer, UnixTransport};
+
+    #[tokio::test]
+    async fn test_shm() {
+        // messages far longer than the rings go through in pieces
+        let (client, server) = tokio::net::UnixStream::pair().unwrap();
+        let (accepted, offered) =
+            tokio::join!(accept_shm(server, 1024, 1 << 20), offer_shm(client, 60));
+        let Some(ShmAccept::Shm(server_reader, mut server_writer)) = accepted else {
+            panic!("shared memory refused")
+        };
+        let (client_reader, mut client_writer) = offered.unwrap().unwrap();
+        let big = "x".repeat(100_000);
+        let echo = tokio::spawn(async move {
+            let mut line = String::new();
+            let mut server_reader = BufReader::new(server_reader);
+            server_reader.read_line(&mut line).await.unwrap();
+            server_writer.write_all(line.as_bytes()).await.unwrap();
+        });
+        client_writer
+            .write_all(format!("{}\n", big).as_bytes())
+            .await
+            .unwrap();
+        let mut line = String::new();
+        BufReader::new(client_reader)
+            .read_line(&mut line)
+            .await
+            .unwrap();
+        assert_eq!(line.trim_end(), big);
+        echo.await.unwrap();
+
+        // rings beyond the server's limit are refused, and the connection goes on over the socket
+        let (client, server) = tokio::net::UnixStream::pair().unwrap();
+        let (accepted, offered) = tokio::join!(accept_shm(server, 1024, 1 << 20), async move {
+            let mut client = offered_socket(offer_shm(client, 4096).await);
+            client.write_all(b"[]\n").await.unwrap();
+            client
+        });
+        let Some(ShmAccept::Lines(_, first)) = accepted else {
+            panic!("shared memory accepted")
+        };
+        assert_eq!(first, "[]\n");
+        drop(offered);
+
+        let path = std::env::temp_dir().join(format!("nanorpc-shm-{}.sock", std::process::id()));
+        let handle = ServeHandle::new();
+        let server = tokio::spawn(
+            UnixServer::new(|_: &ConnInfo| {
+                FnService::new(|_, params| async move { Some(Ok(params[0].clone())) })
+            })
+            .with_shared_memory(1 << 20)
+            .with_handle(handle.clone())
+            .serve(path.clone()),
+        );
+        for transport in [
+            UnixTransport::new(&path).with_shared_memory(4096),
+            UnixTransport::new(&path),
+        ] {
+            let transport = transport.with_timeout(Duration::from_secs(5));
+            let mut resp = None;
+            for _ in 0..100 {
+                if let Ok(r) = transport.call("echo", &[big.clone().into()]).await {
+                    resp = Some(r);
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            assert_eq!(resp.unwrap().unwrap().unwrap(), big.as_str());
+        }
+        assert!(handle.shutdown(Duration::from_secs(5)).await);
+        server.await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_ring_positions() {
+        let (_, mapping) = create_mapping(64).unwrap();
+        let (ring, _) = Ring::pair(mapping, 64);
+        let mut buf = [0; 64];
+        // positions wrapping around are fine
+        ring.header(HEAD).store(3, Ordering::SeqCst);
+        ring.header(TAIL).store(u64::MAX - 2, Ordering::SeqCst);
+        assert_eq!(ring.pop(&mut buf), Some(6));
+        assert_eq!(ring.push(&[1; 100]), Some(64));
+        assert_eq!(ring.push(&[1; 100]), Some(0));
+        // a peer writing a read position past the write position breaks the connection
+        let head = ring.header(HEAD).load(Ordering::SeqCst);
+        ring.header(TAIL).store(head + 1, Ordering::SeqCst);
+        assert_eq!(ring.push(b"x"), None);
+        assert_eq!(ring.pop(&mut buf), None);
+    }
+
+    #[tokio::test]
+    async fn test_shm_sealed() {
+        // once offered, the memory can't be resized, which would crash the server on its next access
+        let (client, server) = tokio::net::UnixStream::pair().unwrap();
+        let (file, mapping) = create_mapping(64).unwrap();
+        let (accepted, offered) = tokio::join!(
+            accept_shm(server, 1024, 1 << 20),
+            offer_mapping(client, &file, mapping, 64)
+        );
+        let Some(ShmAccept::Shm(server_reader, _server_writer)) = accepted else {
+            panic!("shared memory refused")
+        };
+        let (_client_reader, mut client_writer) = offered.unwrap().unwrap();
+        assert!(file.set_len(0).is_err());
+        assert!(file.set_len(1 << 20).is_err());
+        client_writer.write_all(b"hello\n").await.unwrap();
+        let mut line = String::new();
+        BufReader::new(server_reader)
+            .read_line(&mut line)
+            .await
+            .unwrap();
+        assert_eq!(line, "hello\n");
+
+        // memory that could still be resized is refused, and the connection goes on over the socket
+        let (client, server) = tokio::net::UnixStream::pair().unwrap();
+        // SAFETY: makes a memfd owned by nothing else
+        let file = unsafe {
+            let fd = libc::memfd_create(c"unsealed".as_ptr(), libc::MFD_CLOEXEC);
+            assert!(fd >= 0);
+            File::from_raw_fd(fd)
+        };
+        file.set_len(file_len(64) as u64).unwrap();
+        let mapping = Mapping::new(&file, file_len(64)).unwrap();
+        let (accepted, offered) = tokio::join!(accept_shm(server, 1024, 1 << 20), async {
+            let mut client = offered_socket(offer_mapping(client, &file, mapping, 64).await);
+            client.write_all(b"[]\n").await.unwrap();
+            client
+        });
+        let Some(ShmAccept::Lines(_, first)) = accepted else {
+            panic!("shared memory accepted")
+        };
+        assert_eq!(first, "[]\n");
+        drop(offered);
+    }
+
+    fn offered_socket<T>(
+        offered: std::io::Result<Result<T, tokio::net::UnixStream>>,
+    ) -> tokio::net::UnixStream {
+        match offered.unwrap() {
+            Ok(_) => panic!("shared memory accepted"),
+            Err(socket) => socket,
+        }
+    }
+}
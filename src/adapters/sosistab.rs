@@ -0,0 +1,287 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use sosistab2::Multiplex;
+use thiserror::Error;
+use tokio_util::compat::FuturesAsyncReadCompatExt;
+
+use super::lines::{line_frames, line_multiplexer};
+use crate::{
+    serve_connection, ConnInfo, Identity, JrpcMessage, JrpcRequest, JrpcResponse, Limits,
+    MultiplexError, Multiplexer, PersistentTransport, RpcTransport, ServeHandle, ServerConfig,
+    SessionFactory,
+};
+
+pub use sosistab2;
+
+/// An error returned by a [SosistabTransport].
+#[derive(Error, Debug)]
+pub enum SosistabError {
+    #[error("could not open stream: {0}")]
+    Open(std::io::Error),
+    #[error(transparent)]
+    Multiplex(#[from] MultiplexError),
+}
+
+/// A SosistabTransport makes calls over a reliable stream of a sosistab2 [Multiplex], one JSON-RPC message per line, running many calls concurrently through a [Multiplexer], so that services inside a tunnel can be reached without going through HTTP. Notifications from the peer are available through [SosistabTransport::next_incoming].
+///
+/// The multiplex carries on over whichever of its pipes still work, and pipes can be added to it as they come and go. The stream is opened on first use, and opened again on the next call after it is lost. Requires the `sosistab2` feature, and a tokio runtime.
+pub struct SosistabTransport {
+    mux: Arc<Multiplex>,
+    timeout: Option<Duration>,
+    limits: Limits,
+    conn: async_lock::Mutex<Option<Arc<Multiplexer>>>,
+}
+
+impl SosistabTransport {
+    /// Creates a new SosistabTransport over the given multiplex.
+    pub fn new(mux: Arc<Multiplex>) -> Self {
+        Self {
+            mux,
+            timeout: None,
+            limits: Limits::default(),
+            conn: async_lock::Mutex::new(None),
+        }
+    }
+
+    /// Sets a timeout for every call.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the limits that incoming messages are checked against. Lines longer than [Limits::max_message_size] drop the stream.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Waits for the next message from the peer that is not a response, like a notification. Returns `None` once the stream is lost, or if it cannot be opened.
+    pub async fn next_incoming(&self) -> Option<JrpcMessage> {
+        self.connection().await.ok()?.next_incoming().await
+    }
+
+    /// Returns the live stream, opening it again if it was lost.
+    async fn connection(&self) -> Result<Arc<Multiplexer>, SosistabError> {
+        let mut conn = self.conn.lock().await;
+        if let Some(conn) = conn.as_ref().filter(|conn| !conn.is_closed()) {
+            return Ok(conn.clone());
+        }
+        let stream = self.mux.open_conn("").await.map_err(SosistabError::Open)?;
+        let (reader, writer) = tokio::io::split(stream.compat());
+        let new_conn = Arc::new(line_multiplexer(reader, writer, self.limits, self.timeout));
+        *conn = Some(new_conn.clone());
+        Ok(new_conn)
+    }
+}
+
+impl PersistentTransport for SosistabTransport {
+    fn is_closed(&self) -> bool {
+        match self.conn.try_lock() {
+            Some(conn) => conn.as_ref().map(|conn| conn.is_closed()).unwrap_or(true),
+            // opening right now
+            None => false,
+        }
+    }
+}
+
+#[async_trait]
+impl RpcTransport for SosistabTransport {
+    type Error = SosistabError;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        Ok(self.connection().await?.call_raw(req).await?)
+    }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        Ok(self.connection().await?.call_raw_batch(reqs).await?)
+    }
+}
+
+/// A SosistabServer serves the streams that the peer opens in a sosistab2 [Multiplex], each with its own service made by a [SessionFactory], through [serve_connection]. Once known, the peer's public key is attached to the context of every call as its [Identity], as a hex string.
+///
+/// Shutting down through the [ServeHandle] stops accepting streams, and waits for the open ones to close. sosistab2 only lets a side know that the other closed a stream once it sends something on it, so idle streams of clients that went away are only cut off once the shutdown times out. Requires the `sosistab2` feature, and a tokio runtime.
+pub struct SosistabServer<F: SessionFactory> {
+    factory: F,
+    config: ServerConfig,
+    handle: ServeHandle,
+}
+
+impl<F: SessionFactory> SosistabServer<F> {
+    /// Creates a new SosistabServer.
+    pub fn new(factory: F) -> Self {
+        Self {
+            factory,
+            config: ServerConfig::default(),
+            handle: ServeHandle::new(),
+        }
+    }
+
+    /// Sets the configuration used to handle requests.
+    pub fn with_config(mut self, config: ServerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Uses the given [ServeHandle], so that the server can be gracefully shut down through it.
+    pub fn with_handle(mut self, handle: ServeHandle) -> Self {
+        self.handle = handle;
+        self
+    }
+
+    /// Serves the streams of the multiplex until shut down through the [ServeHandle]. Fails if the multiplex does.
+    pub async fn serve(self, mux: Arc<Multiplex>) -> std::io::Result<()> {
+        let shutdown = self.handle.shutdown_signal();
+        let this = Arc::new(self);
+        loop {
+            let accepted =
+                futures_lite::future::or(async { Some(mux.accept_conn().await) }, async {
+                    shutdown.wait().await;
+                    None
+                })
+                .await;
+            let stream = match accepted {
+                Some(stream) => stream?,
+                None => return Ok(()),
+            };
+            let conn = match mux.peer_pk() {
+                Some(pk) => ConnInfo::new().with_identity(Identity {
+                    id: hex::encode(pk.as_bytes()),
+                    roles: vec![],
+                }),
+                None => ConnInfo::new(),
+            };
+            let conn = this.handle.prepare_conn(conn);
+            let this = this.clone();
+            tokio::spawn(async move {
+                let (reader, writer) = tokio::io::split(stream.compat());
+                let (sink, stream) =
+                    line_frames(reader, writer, this.config.limits.max_message_size);
+                this.handle
+                    .track(serve_connection(
+                        &this.factory,
+                        conn,
+                        sink,
+                        stream,
+                        &this.config,
+                    ))
+                    .await;
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use async_trait::async_trait;
+    use bytes::Bytes;
+
+    use crate::{
+        sosistab2::{Multiplex, MuxSecret, Pipe},
+        ConnInfo, FnService, RpcTransport, ServeHandle, SosistabServer, SosistabTransport,
+    };
+
+    /// One end of an in-memory pipe.
+    struct MemPipe {
+        send: async_channel::Sender<Bytes>,
+        recv: async_channel::Receiver<Bytes>,
+    }
+
+    #[async_trait]
+    impl Pipe for MemPipe {
+        fn send(&self, to_send: Bytes) {
+            let _ = self.send.try_send(to_send);
+        }
+
+        async fn recv(&self) -> std::io::Result<Bytes> {
+            self.recv
+                .recv()
+                .await
+                .map_err(|_| std::io::ErrorKind::ConnectionReset.into())
+        }
+
+        fn protocol(&self) -> &str {
+            "mem"
+        }
+
+        fn peer_metadata(&self) -> &str {
+            ""
+        }
+
+        fn peer_addr(&self) -> String {
+            "mem".into()
+        }
+    }
+
+    fn serve(mux: &Arc<Multiplex>, handle: &ServeHandle) -> tokio::task::JoinHandle<()> {
+        let server = SosistabServer::new(|conn: &ConnInfo| {
+            let id = conn.identity.clone().map(|identity| identity.id);
+            FnService::new(move |_, _| {
+                let id = id.clone();
+                async move { Some(Ok(id.into())) }
+            })
+        })
+        .with_handle(handle.clone());
+        let mux = mux.clone();
+        tokio::spawn(async move { server.serve(mux).await.unwrap() })
+    }
+
+    #[tokio::test]
+    async fn test_sosistab() {
+        let server_sk = MuxSecret::generate();
+        let server_mux = Arc::new(Multiplex::new(server_sk.clone(), None));
+        let client_sk = MuxSecret::generate();
+        let client_mux = Arc::new(Multiplex::new(
+            client_sk.clone(),
+            Some(server_sk.to_public()),
+        ));
+        let (to_server, from_client) = async_channel::unbounded();
+        let (to_client, from_server) = async_channel::unbounded();
+        client_mux.add_pipe(MemPipe {
+            send: to_server,
+            recv: from_server,
+        });
+        server_mux.add_pipe(MemPipe {
+            send: to_client,
+            recv: from_client,
+        });
+
+        let handle = ServeHandle::new();
+        let server = serve(&server_mux, &handle);
+        let transport = SosistabTransport::new(client_mux).with_timeout(Duration::from_secs(5));
+        let client_id = hex::encode(client_sk.to_public().as_bytes());
+        assert_eq!(
+            transport
+                .call("whoami", &[])
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap(),
+            client_id
+        );
+
+        // the stream is opened again once the server cuts it off
+        assert!(!handle.shutdown(Duration::from_millis(100)).await);
+        server.await.unwrap();
+        let handle = ServeHandle::new();
+        let server = serve(&server_mux, &handle);
+        let mut answer = None;
+        for _ in 0..50 {
+            if let Ok(res) = transport.call("whoami", &[]).await {
+                answer = Some(res.unwrap().unwrap());
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(answer.unwrap(), client_id);
+        drop(transport);
+        // sosistab2 doesn't tell the server about the closed stream until it sends something
+        assert!(!handle.shutdown(Duration::from_millis(100)).await);
+        server.await.unwrap();
+    }
+}
@@ -0,0 +1,309 @@
+use std::{collections::HashMap, convert::Infallible, sync::Mutex, time::Duration};
+
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use hyper::{header, Body, Request, Response, StatusCode};
+
+use crate::{
+    timer, HttpError, HttpTransport, JrpcNotification, JrpcRequest, JrpcResponse, Notifier,
+    RpcContext, RpcTransport, ShutdownSignal,
+};
+
+/// The request metadata key under which an [SseTransport] sends its subscriber ID.
+pub const SSE_SUBSCRIBER_KEY: &str = "subscriber";
+
+/// An SseHub sends notifications to HTTP clients over server-sent events, for servers that can only speak plain HTTP. Every [SseTransport] subscribes with a random subscriber ID, and attaches it to all its calls, so that services can get a [Notifier] for the caller with [SseHub::notifier].
+///
+/// The hub is mounted on an [crate::HttpServer] with [crate::HttpServer::with_events]. Requires the `sse` feature.
+#[derive(Default)]
+pub struct SseHub {
+    subscribers: Mutex<HashMap<String, async_channel::Sender<String>>>,
+}
+
+impl SseHub {
+    /// Creates a new SseHub without any subscribers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a [Notifier] for the subscriber that made a call, or `None` if the call did not come from a connected subscriber.
+    pub fn notifier(&self, ctx: &RpcContext) -> Option<Notifier> {
+        self.notifier_for(ctx.meta.get(SSE_SUBSCRIBER_KEY)?.as_str()?)
+    }
+
+    /// Returns a [Notifier] for the subscriber with the given ID, or `None` if it is not connected.
+    pub fn notifier_for(&self, subscriber: &str) -> Option<Notifier> {
+        let subscribers = self.subscribers.lock().unwrap();
+        let send = subscribers
+            .get(subscriber)
+            .filter(|send| !send.is_closed())?;
+        Some(Notifier(send.clone()))
+    }
+
+    /// Sends a notification to every connected subscriber, returning how many there were.
+    pub fn broadcast(&self, method: impl Into<String>, params: Vec<serde_json::Value>) -> usize {
+        let msg = serde_json::to_string(&JrpcNotification {
+            jsonrpc: "2.0".into(),
+            method: method.into(),
+            params,
+        })
+        .unwrap();
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|_, send| !send.is_closed());
+        subscribers
+            .values()
+            .filter(|send| send.try_send(msg.clone()).is_ok())
+            .count()
+    }
+
+    /// Returns the number of connected subscribers.
+    pub fn subscriber_count(&self) -> usize {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|_, send| !send.is_closed());
+        subscribers.len()
+    }
+
+    /// Answers a request for the event stream of the subscriber given in the `subscriber` query parameter. A subscriber that connects again replaces its earlier stream. The stream ends when the server shuts down.
+    pub(crate) fn respond_http(
+        &self,
+        req: &Request<Body>,
+        shutdown: ShutdownSignal,
+    ) -> Response<Body> {
+        let subscriber = req.uri().query().and_then(|query| {
+            query.split('&').find_map(|pair| {
+                let (key, value) = pair.split_once('=')?;
+                (key == SSE_SUBSCRIBER_KEY && !value.is_empty()).then(|| value.to_string())
+            })
+        });
+        let Some(subscriber) = subscriber else {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::empty())
+                .unwrap();
+        };
+        let (send, recv) = async_channel::unbounded();
+        self.subscribers.lock().unwrap().insert(subscriber, send);
+        let events = recv.map(|msg| format!("data: {}\n\n", msg));
+        // comments keep proxies from closing idle streams
+        let keepalive = futures_util::stream::unfold((), |_| async {
+            timer::sleep(Duration::from_secs(15)).await;
+            Some((": keepalive\n\n".to_string(), ()))
+        });
+        let stream = futures_util::stream::select(events, keepalive)
+            .take_until(async move { shutdown.wait().await })
+            .map(Ok::<_, Infallible>);
+        Response::builder()
+            .header(header::CONTENT_TYPE, "text/event-stream")
+            .header(header::CACHE_CONTROL, "no-cache")
+            .body(Body::wrap_stream(stream))
+            .unwrap()
+    }
+}
+
+/// An SseTransport makes calls through an [HttpTransport], while receiving notifications over server-sent events from an [SseHub], available through [SseTransport::next_incoming]. The event stream is reconnected if it breaks, though notifications sent in between are lost.
+///
+/// Requires the `sse` feature, and a tokio runtime.
+pub struct SseTransport {
+    inner: HttpTransport,
+    subscriber: String,
+    recv_incoming: async_channel::Receiver<JrpcNotification>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl Drop for SseTransport {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl SseTransport {
+    /// Subscribes to the event stream at `events_url`, returning once it is open, and makes calls through `inner`.
+    pub async fn connect(inner: HttpTransport, events_url: &str) -> Result<Self, HttpError> {
+        let subscriber = format!("{:016x}", fastrand::u64(..));
+        let client = reqwest::Client::new();
+        let url = format!(
+            "{}{}{}={}",
+            events_url,
+            if events_url.contains('?') { '&' } else { '?' },
+            SSE_SUBSCRIBER_KEY,
+            subscriber
+        );
+        let open = {
+            let client = client.clone();
+            let url = url.clone();
+            move || {
+                let req = client
+                    .get(&url)
+                    .header(header::ACCEPT.as_str(), "text/event-stream");
+                async move {
+                    let resp = req.send().await?;
+                    if !resp.status().is_success() {
+                        return Err(HttpError::Status(resp.status().as_u16()));
+                    }
+                    Ok(resp)
+                }
+            }
+        };
+        let mut resp = open().await?;
+        let (send_incoming, recv_incoming) = async_channel::unbounded();
+        let task = tokio::spawn(async move {
+            loop {
+                read_events(resp, &send_incoming).await;
+                resp = loop {
+                    timer::sleep(Duration::from_secs(1)).await;
+                    match open().await {
+                        Ok(resp) => break resp,
+                        Err(err) => log::debug!("could not reopen event stream: {}", err),
+                    }
+                };
+            }
+        });
+        Ok(Self {
+            inner,
+            subscriber,
+            recv_incoming,
+            task,
+        })
+    }
+
+    /// Waits for the next notification from the server.
+    pub async fn next_incoming(&self) -> Option<JrpcNotification> {
+        self.recv_incoming.recv().await.ok()
+    }
+
+    fn tag(&self, req: &mut JrpcRequest) {
+        req.meta
+            .insert(SSE_SUBSCRIBER_KEY.into(), self.subscriber.clone().into());
+    }
+}
+
+/// Reads notifications from an event stream until it ends.
+async fn read_events(
+    resp: reqwest::Response,
+    send_incoming: &async_channel::Sender<JrpcNotification>,
+) {
+    let mut stream = resp.bytes_stream();
+    let mut buf = vec![];
+    let mut data = String::new();
+    while let Some(Ok(chunk)) = stream.next().await {
+        buf.extend_from_slice(&chunk);
+        while let Some(idx) = buf.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = buf.drain(..=idx).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+            if line.is_empty() {
+                match serde_json::from_str(&data) {
+                    Ok(notification) => {
+                        let _ = send_incoming.send(notification).await;
+                    }
+                    Err(err) if !data.is_empty() => log::debug!("bad event {:?}: {}", data, err),
+                    Err(_) => {}
+                }
+                data.clear();
+            } else if let Some(value) = line.strip_prefix("data:") {
+                if !data.is_empty() {
+                    data.push('\n');
+                }
+                data.push_str(value.strip_prefix(' ').unwrap_or(value));
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl RpcTransport for SseTransport {
+    type Error = HttpError;
+
+    async fn call_raw(&self, mut req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        self.tag(&mut req);
+        self.inner.call_raw(req).await
+    }
+
+    async fn call_raw_batch(
+        &self,
+        mut reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        reqs.iter_mut().for_each(|req| self.tag(req));
+        self.inner.call_raw_batch(reqs).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use async_trait::async_trait;
+
+    use crate::{
+        HttpServer, HttpTransport, RpcContext, RpcService, RpcTransport, ServeHandle, ServerError,
+        SseHub, SseTransport,
+    };
+
+    struct Subscribe(Arc<SseHub>);
+
+    #[async_trait]
+    impl RpcService for Subscribe {
+        async fn respond(
+            &self,
+            method: &str,
+            params: &[serde_json::Value],
+        ) -> Option<Result<serde_json::Value, ServerError>> {
+            self.respond_with_context(&RpcContext::default(), method, params)
+                .await
+        }
+
+        async fn respond_with_context(
+            &self,
+            ctx: &RpcContext,
+            _method: &str,
+            params: &[serde_json::Value],
+        ) -> Option<Result<serde_json::Value, ServerError>> {
+            let notifier = self.0.notifier(ctx)?;
+            notifier.notify("update", params.to_vec()).await.ok()?;
+            Some(Ok(true.into()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sse() {
+        let hub = Arc::new(SseHub::new());
+        let handle = ServeHandle::new();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(
+            HttpServer::new(Subscribe(hub.clone()))
+                .with_events("/events", hub.clone())
+                .with_handle(handle.clone())
+                .serve_listener(listener),
+        );
+        let transport = SseTransport::connect(
+            HttpTransport::new(format!("http://{}/rpc", addr)),
+            &format!("http://{}/events", addr),
+        )
+        .await
+        .unwrap();
+        assert_eq!(hub.subscriber_count(), 1);
+        assert_eq!(
+            transport
+                .call("subscribe", &[1.into()])
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap(),
+            true
+        );
+        let notification = transport.next_incoming().await.unwrap();
+        assert_eq!(notification.method, "update");
+        assert_eq!(notification.params, vec![serde_json::Value::from(1)]);
+        assert_eq!(hub.broadcast("bye", vec![]), 1);
+        assert_eq!(transport.next_incoming().await.unwrap().method, "bye");
+
+        // plain HTTP callers have no one to notify
+        let plain = HttpTransport::new(format!("http://{}/rpc", addr));
+        assert!(plain.call("subscribe", &[]).await.unwrap().is_none());
+
+        drop(transport);
+        assert!(handle.shutdown(Duration::from_secs(5)).await);
+        server.await.unwrap().unwrap();
+    }
+}
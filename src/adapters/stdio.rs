@@ -0,0 +1,372 @@
+use std::{
+    process::{ExitStatus, Stdio},
+    sync::Arc,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use super::lines::{line_frames, line_multiplexer};
+use crate::{
+    serve_connection, timer, ConnInfo, JrpcMessage, JrpcRequest, JrpcResponse, Limits,
+    MultiplexError, Multiplexer, RpcService, RpcTransport, ServerConfig,
+};
+
+/// A StdioTransport makes calls to a child process over its stdin and stdout, one JSON-RPC message per line, for plugins that are just binaries running [serve_stdio]. The child's stderr is inherited, so it can still log.
+///
+/// The child is killed when the transport is dropped. Requires the `stdio` feature, and a tokio runtime.
+pub struct StdioTransport {
+    mux: Multiplexer,
+    child: Option<tokio::process::Child>,
+}
+
+impl StdioTransport {
+    /// Spawns the given command, and makes calls over its stdio.
+    pub fn spawn(mut command: tokio::process::Command) -> std::io::Result<Self> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let mut this = Self::from_pipes(stdout, stdin);
+        this.child = Some(child);
+        Ok(this)
+    }
+
+    /// Makes calls over an already set up pair of pipes, reading responses from `reader` and writing requests to `writer`.
+    pub fn from_pipes(
+        reader: impl AsyncRead + Send + Unpin + 'static,
+        writer: impl AsyncWrite + Send + Unpin + 'static,
+    ) -> Self {
+        Self {
+            mux: line_multiplexer(reader, writer, Limits::default(), None),
+            child: None,
+        }
+    }
+
+    /// Sets a timeout for every call.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self {
+            mux: self.mux.with_timeout(timeout),
+            child: self.child,
+        }
+    }
+
+    /// Returns the child process, if this transport spawned one.
+    pub fn child(&mut self) -> Option<&mut tokio::process::Child> {
+        self.child.as_mut()
+    }
+
+    /// Waits for the next message from the other side that is not a response, like a notification. Returns `None` once the pipes are closed.
+    pub async fn next_incoming(&self) -> Option<JrpcMessage> {
+        self.mux.next_incoming().await
+    }
+}
+
+#[async_trait]
+impl RpcTransport for StdioTransport {
+    type Error = MultiplexError;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        self.mux.call_raw(req).await
+    }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        self.mux.call_raw_batch(reqs).await
+    }
+}
+
+/// When a [ChildTransport] starts its child again after it exits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum RestartPolicy {
+    /// Never, so that calls fail once the child is gone.
+    Never,
+    /// Only if it crashed, by exiting with an error or being killed by a signal.
+    #[default]
+    OnFailure,
+    /// Whenever it exits.
+    Always,
+}
+
+/// An error returned by a [ChildTransport].
+#[derive(Error, Debug)]
+pub enum ChildError {
+    #[error("could not spawn child: {0}")]
+    Spawn(std::io::Error),
+    #[error("child exited with {0}, and is not restarted")]
+    Exited(ExitStatus),
+    #[error(transparent)]
+    Multiplex(#[from] MultiplexError),
+}
+
+struct Running {
+    mux: Arc<Multiplexer>,
+    child: tokio::process::Child,
+}
+
+impl Running {
+    /// Closes the child's stdin, which a server running [serve_stdio] takes as the signal to exit, and kills it if it hasn't exited within the grace period.
+    async fn stop(self, grace: Duration) -> std::io::Result<ExitStatus> {
+        let Running { mux, mut child } = self;
+        drop(mux);
+        match timer::timeout(grace, child.wait()).await {
+            Some(status) => status,
+            None => {
+                log::warn!("child did not exit within {:?}, killing it", grace);
+                child.kill().await?;
+                child.wait().await
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct ChildState {
+    running: Option<Running>,
+    restarts: u32,
+    /// How the last child exited, if it is not to be restarted.
+    exited: Option<ExitStatus>,
+}
+
+/// A ChildTransport runs a server binary as a child process, and makes calls to it over its stdin and stdout like a [StdioTransport], so that any server running [serve_stdio] can be embedded as a plugin.
+///
+/// The child is spawned on first use. If it exits, calls in flight fail, and the next call starts it again as allowed by the [RestartPolicy], up to a maximum number of restarts if one is set. On drop, the child's stdin is closed so that it can exit cleanly, and it is killed if it hasn't within the grace period. Requires the `stdio` feature, and a tokio runtime.
+pub struct ChildTransport {
+    command: Box<dyn Fn() -> tokio::process::Command + Send + Sync + 'static>,
+    policy: RestartPolicy,
+    max_restarts: Option<u32>,
+    grace: Duration,
+    timeout: Option<Duration>,
+    state: async_lock::Mutex<ChildState>,
+}
+
+impl ChildTransport {
+    /// Creates a new ChildTransport, spawning children with the commands made by the given function. The child is restarted when it crashes, and given 5 seconds to exit.
+    pub fn new(command: impl Fn() -> tokio::process::Command + Send + Sync + 'static) -> Self {
+        Self {
+            command: Box::new(command),
+            policy: RestartPolicy::default(),
+            max_restarts: None,
+            grace: Duration::from_secs(5),
+            timeout: None,
+            state: Default::default(),
+        }
+    }
+
+    /// Sets when the child is started again after it exits.
+    pub fn with_restart_policy(mut self, policy: RestartPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Sets the maximum number of times the child is started again, after which calls fail with [ChildError::Exited].
+    pub fn with_max_restarts(mut self, max_restarts: u32) -> Self {
+        self.max_restarts = Some(max_restarts);
+        self
+    }
+
+    /// Sets how long the child gets to exit after its stdin is closed, before it is killed.
+    pub fn with_grace_period(mut self, grace: Duration) -> Self {
+        self.grace = grace;
+        self
+    }
+
+    /// Sets a timeout for every call.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Returns how many times the child has been started again.
+    pub async fn restarts(&self) -> u32 {
+        self.state.lock().await.restarts
+    }
+
+    /// Waits for the next message from the child that is not a response, like a notification. Returns `None` once the child exits, or if it cannot be started.
+    pub async fn next_incoming(&self) -> Option<JrpcMessage> {
+        self.connection().await.ok()?.next_incoming().await
+    }
+
+    /// Stops the child, as on drop, returning how it exited, or `None` if it wasn't running. The next call starts it again.
+    pub async fn shutdown(&self) -> std::io::Result<Option<ExitStatus>> {
+        let running = self.state.lock().await.running.take();
+        match running {
+            Some(running) => running.stop(self.grace).await.map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the connection to the running child, starting it if it isn't running and may be started.
+    async fn connection(&self) -> Result<Arc<Multiplexer>, ChildError> {
+        let mut state = self.state.lock().await;
+        if let Some(status) = state.exited {
+            return Err(ChildError::Exited(status));
+        }
+        if let Some(running) = state.running.as_mut() {
+            if !running.mux.is_closed() {
+                return Ok(running.mux.clone());
+            }
+            // a child that closed its stdout is of no more use, even if it hasn't exited
+            let status = match timer::timeout(self.grace, running.child.wait()).await {
+                Some(status) => status,
+                None => {
+                    let _ = running.child.start_kill();
+                    running.child.wait().await
+                }
+            };
+            state.running = None;
+            let status = status.map_err(ChildError::Spawn)?;
+            let restart = match self.policy {
+                RestartPolicy::Never => false,
+                RestartPolicy::OnFailure => !status.success(),
+                RestartPolicy::Always => true,
+            } && self.max_restarts.is_none_or(|max| state.restarts < max);
+            if !restart {
+                log::warn!("child exited with {}, not restarting it", status);
+                state.exited = Some(status);
+                return Err(ChildError::Exited(status));
+            }
+            log::warn!("child exited with {}, restarting it", status);
+            state.restarts += 1;
+        }
+        let running = self.spawn().map_err(ChildError::Spawn)?;
+        let mux = running.mux.clone();
+        state.running = Some(running);
+        Ok(mux)
+    }
+
+    fn spawn(&self) -> std::io::Result<Running> {
+        let mut command = (self.command)();
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let mux = line_multiplexer(stdout, stdin, Limits::default(), self.timeout);
+        Ok(Running {
+            mux: Arc::new(mux),
+            child,
+        })
+    }
+}
+
+impl Drop for ChildTransport {
+    fn drop(&mut self) {
+        let Some(running) = self.state.get_mut().running.take() else {
+            return;
+        };
+        // without a runtime, the child is simply killed as it is dropped
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(running.stop(self.grace));
+        }
+    }
+}
+
+#[async_trait]
+impl RpcTransport for ChildTransport {
+    type Error = ChildError;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        Ok(self.connection().await?.call_raw(req).await?)
+    }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        Ok(self.connection().await?.call_raw_batch(reqs).await?)
+    }
+}
+
+/// Serves a service over the current process's stdin and stdout, one JSON-RPC message per line, until stdin closes. This is the other side of a [StdioTransport].
+///
+/// Nothing else may write to stdout while serving, so logs should go to stderr. Notifications can be sent through the [crate::Notifier] in the [ConnInfo] attached to every call. Requires the `stdio` feature, and a tokio runtime.
+pub async fn serve_stdio(service: impl RpcService, config: &ServerConfig) {
+    let service = Arc::new(service);
+    let (sink, stream) = line_frames(
+        tokio::io::stdin(),
+        tokio::io::stdout(),
+        config.limits.max_message_size,
+    );
+    serve_connection(
+        &move |_: &ConnInfo| service.clone(),
+        ConnInfo::new(),
+        sink,
+        stream,
+        config,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{ChildError, ChildTransport, RpcTransport, StdioTransport};
+
+    #[tokio::test]
+    async fn test_stdio() {
+        // a tiny "plugin" that answers every request with its method name
+        let mut command = tokio::process::Command::new("sed");
+        command.args([
+            "-u",
+            r#"s/.*"method":"\([a-z]*\)".*"id":\([0-9]*\).*/{"jsonrpc":"2.0","result":"\1","id":\2}/"#,
+        ]);
+        let transport = StdioTransport::spawn(command)
+            .unwrap()
+            .with_timeout(Duration::from_secs(5));
+        let (a, b) =
+            futures_lite::future::zip(transport.call("hello", &[]), transport.call("world", &[]))
+                .await;
+        assert_eq!(a.unwrap().unwrap().unwrap(), "hello");
+        assert_eq!(b.unwrap().unwrap().unwrap(), "world");
+    }
+
+    /// A plugin that answers every request with its process ID, and crashes when asked to.
+    fn plugin() -> tokio::process::Command {
+        let mut command = tokio::process::Command::new("sh");
+        command.args([
+            "-c",
+            r#"exec sed -u -e '/"crash"/q1' -e 's/.*"id":\([0-9]*\).*/{"jsonrpc":"2.0","result":'$$',"id":\1}/'"#,
+        ]);
+        command
+    }
+
+    #[tokio::test]
+    async fn test_child_transport() {
+        let transport = ChildTransport::new(plugin)
+            .with_max_restarts(1)
+            .with_timeout(Duration::from_secs(5));
+        let pid = transport.call("f", &[]).await.unwrap().unwrap().unwrap();
+        assert_eq!(
+            transport.call("f", &[]).await.unwrap().unwrap().unwrap(),
+            pid
+        );
+
+        // a crash fails the call in flight, and the child is started again for the next one
+        assert!(transport.call("crash", &[]).await.is_err());
+        let new_pid = transport.call("f", &[]).await.unwrap().unwrap().unwrap();
+        assert_ne!(new_pid, pid);
+        assert_eq!(transport.restarts().await, 1);
+        assert!(transport.call("crash", &[]).await.is_err());
+        assert!(matches!(
+            transport.call("f", &[]).await,
+            Err(ChildError::Exited(status)) if status.code() == Some(1)
+        ));
+
+        // a clean exit is not a crash
+        let transport = ChildTransport::new(plugin).with_timeout(Duration::from_secs(5));
+        transport.call("f", &[]).await.unwrap();
+        assert!(transport.shutdown().await.unwrap().unwrap().success());
+        assert_eq!(transport.shutdown().await.unwrap(), None);
+    }
+}
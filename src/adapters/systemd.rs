@@ -0,0 +1,181 @@
+use std::os::unix::{
+    io::{FromRawFd, RawFd},
+    net::UnixListener,
+};
+
+/// The first file descriptor passed by systemd.
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+/// The listening sockets passed to a socket-activated process by systemd, for serving them with something like [crate::TcpServer::serve_listener] or [crate::UnixServer::serve_listener]. Since systemd keeps the sockets open while the service restarts, no connections are refused in between.
+///
+/// Requires the `systemd` feature, and a Unix platform.
+#[derive(Debug, Default)]
+pub struct ListenFds {
+    fds: Vec<Option<(RawFd, Option<String>)>>,
+}
+
+impl ListenFds {
+    /// Takes the sockets passed by systemd, like `sd_listen_fds`, and removes the variables describing them from the environment so that child processes don't pick them up too. Returns no sockets if the process was not socket-activated.
+    ///
+    /// This should be called once, early, before any other threads have been started.
+    pub fn from_env() -> std::io::Result<Self> {
+        let fds = parse(
+            std::env::var("LISTEN_PID").ok().as_deref(),
+            std::env::var("LISTEN_FDS").ok().as_deref(),
+            std::env::var("LISTEN_FDNAMES").ok().as_deref(),
+            std::process::id(),
+        )?;
+        for var in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+            std::env::remove_var(var);
+        }
+        for (fd, _) in fds.iter() {
+            // systemd passes the sockets without close-on-exec, which they shouldn't keep
+            if unsafe { libc::fcntl(*fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        Ok(Self {
+            fds: fds.into_iter().map(Some).collect(),
+        })
+    }
+
+    /// Returns the number of sockets passed, including those already taken.
+    pub fn len(&self) -> usize {
+        self.fds.len()
+    }
+
+    /// Returns whether no sockets were passed.
+    pub fn is_empty(&self) -> bool {
+        self.fds.is_empty()
+    }
+
+    /// Returns the index of the first socket with the given name, as set with `FileDescriptorName=` in the socket unit.
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.fds.iter().position(|fd| {
+            fd.as_ref()
+                .and_then(|(_, fd_name)| fd_name.as_deref())
+                .map(|fd_name| fd_name == name)
+                .unwrap_or(false)
+        })
+    }
+
+    /// Takes the socket at the given index as a TCP listener. Returns `None` if there is no such socket or it was already taken, and an error if it is not a listening TCP socket.
+    pub fn take_tcp(&mut self, idx: usize) -> std::io::Result<Option<std::net::TcpListener>> {
+        self.take(idx, &[libc::AF_INET, libc::AF_INET6])
+            .map(|fd| fd.map(|fd| unsafe { std::net::TcpListener::from_raw_fd(fd) }))
+    }
+
+    /// Takes the socket at the given index as a Unix socket listener. Returns `None` if there is no such socket or it was already taken, and an error if it is not a listening Unix socket.
+    pub fn take_unix(&mut self, idx: usize) -> std::io::Result<Option<UnixListener>> {
+        self.take(idx, &[libc::AF_UNIX])
+            .map(|fd| fd.map(|fd| unsafe { UnixListener::from_raw_fd(fd) }))
+    }
+
+    fn take(&mut self, idx: usize, families: &[libc::c_int]) -> std::io::Result<Option<RawFd>> {
+        let Some(slot) = self.fds.get_mut(idx) else {
+            return Ok(None);
+        };
+        let Some((fd, _)) = slot.as_ref() else {
+            return Ok(None);
+        };
+        let fd = *fd;
+        if !families.contains(&socket_family(fd)?) || !is_listening(fd)? {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("file descriptor {} is not the expected kind of socket", fd),
+            ));
+        }
+        *slot = None;
+        Ok(Some(fd))
+    }
+}
+
+/// Parses the variables set by systemd into file descriptors and their names.
+fn parse(
+    pid: Option<&str>,
+    fds: Option<&str>,
+    names: Option<&str>,
+    our_pid: u32,
+) -> std::io::Result<Vec<(RawFd, Option<String>)>> {
+    let invalid = |what: &str| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("invalid {} from systemd", what),
+        )
+    };
+    let (Some(pid), Some(fds)) = (pid, fds) else {
+        return Ok(vec![]);
+    };
+    // the variables were meant for some other process, like our parent
+    if pid.parse::<u32>().map_err(|_| invalid("LISTEN_PID"))? != our_pid {
+        return Ok(vec![]);
+    }
+    let count: RawFd = fds.parse().map_err(|_| invalid("LISTEN_FDS"))?;
+    let mut names = names.map(|names| names.split(':'));
+    Ok((0..count)
+        .map(|i| {
+            let name = names
+                .as_mut()
+                .and_then(|names| names.next())
+                .map(|name| name.to_string());
+            (SD_LISTEN_FDS_START + i, name)
+        })
+        .collect())
+}
+
+fn socket_family(fd: RawFd) -> std::io::Result<libc::c_int> {
+    let mut addr: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    if unsafe { libc::getsockname(fd, &mut addr as *mut _ as *mut libc::sockaddr, &mut len) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(addr.ss_family as libc::c_int)
+}
+
+fn is_listening(fd: RawFd) -> std::io::Result<bool> {
+    let mut value: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    if unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_ACCEPTCONN,
+            &mut value as *mut _ as *mut libc::c_void,
+            &mut len,
+        )
+    } < 0
+    {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(value != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::io::IntoRawFd;
+
+    use super::{parse, ListenFds};
+
+    #[test]
+    fn test_listen_fds() {
+        assert!(parse(None, None, None, 42).unwrap().is_empty());
+        assert!(parse(Some("41"), Some("2"), None, 42).unwrap().is_empty());
+        assert!(parse(Some("42"), Some("x"), None, 42).is_err());
+        assert_eq!(
+            parse(Some("42"), Some("2"), Some("web:admin"), 42).unwrap(),
+            vec![(3, Some("web".into())), (4, Some("admin".into()))]
+        );
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut fds = ListenFds {
+            fds: vec![Some((listener.into_raw_fd(), Some("web".into())))],
+        };
+        let idx = fds.find("web").unwrap();
+        assert!(fds.take_unix(idx).is_err());
+        let listener = fds.take_tcp(idx).unwrap().unwrap();
+        assert_eq!(listener.local_addr().unwrap(), addr);
+        assert!(fds.take_tcp(idx).unwrap().is_none());
+        assert!(fds.find("web").is_none());
+    }
+}
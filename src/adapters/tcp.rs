@@ -0,0 +1,527 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+#[cfg(feature = "compression")]
+use super::compress::{compressed_frames, Compression, Negotiation};
+#[cfg(any(feature = "compression", feature = "msgpack"))]
+use super::lines::spawn_multiplexer;
+#[cfg(feature = "msgpack")]
+use super::msgpack::msgpack_rpc_frames;
+#[cfg(feature = "tls")]
+use super::tls::{rustls, ClientTls, ServerTls};
+use super::{
+    accept::accept_tcp,
+    lines::{line_frames, line_multiplexer},
+};
+use crate::{
+    serve_connection, ConnInfo, JrpcMessage, JrpcRequest, JrpcResponse, Limits, MultiplexError,
+    Multiplexer, PersistentTransport, RpcTransport, ServeHandle, ServerConfig, SessionFactory,
+};
+
+/// An error returned by a [TcpTransport].
+#[derive(Error, Debug)]
+pub enum TcpError {
+    #[error("could not connect: {0}")]
+    Connect(std::io::Error),
+    #[error(transparent)]
+    Multiplex(#[from] MultiplexError),
+}
+
+/// A TcpTransport makes calls over a TCP connection carrying one JSON-RPC message per line, running many calls concurrently through a [Multiplexer]. Notifications from the server are available through [TcpTransport::next_incoming].
+///
+/// The connection is made on first use, and made again on the next call after it is lost. Calls that were in flight when it was lost fail with [MultiplexError::ConnectionLost]. Requires the `tcp` feature, and a tokio runtime.
+pub struct TcpTransport {
+    addr: String,
+    timeout: Option<Duration>,
+    limits: Limits,
+    mux: async_lock::Mutex<Option<Arc<Multiplexer>>>,
+    #[cfg(feature = "tls")]
+    tls: Option<ClientTls>,
+    #[cfg(feature = "compression")]
+    compression: Vec<Compression>,
+    #[cfg(feature = "msgpack")]
+    msgpack_rpc: bool,
+}
+
+impl TcpTransport {
+    /// Creates a new TcpTransport for an address like `"127.0.0.1:11223"`.
+    pub fn new(addr: impl Into<String>) -> Self {
+        Self {
+            addr: addr.into(),
+            timeout: None,
+            limits: Limits::default(),
+            mux: async_lock::Mutex::new(None),
+            #[cfg(feature = "tls")]
+            tls: None,
+            #[cfg(feature = "compression")]
+            compression: vec![],
+            #[cfg(feature = "msgpack")]
+            msgpack_rpc: false,
+        }
+    }
+
+    /// Sets a timeout for every call.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the limits that incoming messages are checked against. Lines longer than [Limits::max_message_size] drop the connection.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Encrypts the connection with TLS, checking that the server's certificate is valid for `server_name`. The config may carry a client certificate, for servers that require one. Requires the `tls` feature.
+    #[cfg(feature = "tls")]
+    pub fn with_tls(
+        mut self,
+        config: Arc<rustls::ClientConfig>,
+        server_name: impl Into<String>,
+    ) -> Self {
+        self.tls = Some(ClientTls {
+            config,
+            server_name: server_name.into(),
+        });
+        self
+    }
+
+    /// Offers the server to compress large messages with one of the given algorithms, in order of preference, on every connection. Servers that don't support compression keep talking plain JSON. Requires the `compression` feature.
+    #[cfg(feature = "compression")]
+    pub fn with_compression(mut self, compression: &[Compression]) -> Self {
+        self.compression = compression.to_vec();
+        self
+    }
+
+    /// Speaks msgpack-rpc instead of JSON lines, to call msgpack-rpc servers or a [TcpServer] in msgpack-rpc mode. Requests need numeric IDs, which [RpcTransport::call] always uses, and batches are sent as separate requests. Takes precedence over compression. Requires the `msgpack` feature.
+    #[cfg(feature = "msgpack")]
+    pub fn with_msgpack_rpc(mut self) -> Self {
+        self.msgpack_rpc = true;
+        self
+    }
+
+    /// Waits for the next message from the server that is not a response, like a notification. Returns `None` once the connection is lost, or if it cannot be made.
+    pub async fn next_incoming(&self) -> Option<JrpcMessage> {
+        self.connection().await.ok()?.next_incoming().await
+    }
+
+    /// Makes the connection now, if there is no live one, instead of on the next call.
+    pub async fn warm_up(&self) -> Result<(), TcpError> {
+        self.connection().await.map(|_| ())
+    }
+
+    /// Returns the live connection, connecting again if it was lost.
+    async fn connection(&self) -> Result<Arc<Multiplexer>, TcpError> {
+        let mut mux = self.mux.lock().await;
+        if let Some(mux) = mux.as_ref().filter(|mux| !mux.is_closed()) {
+            return Ok(mux.clone());
+        }
+        let new_mux = Arc::new(self.connect().await.map_err(TcpError::Connect)?);
+        *mux = Some(new_mux.clone());
+        Ok(new_mux)
+    }
+
+    async fn connect(&self) -> std::io::Result<Multiplexer> {
+        let stream = tokio::net::TcpStream::connect(&self.addr).await?;
+        let _ = stream.set_nodelay(true);
+        #[cfg(feature = "tls")]
+        if let Some(tls) = &self.tls {
+            let (reader, writer) = tokio::io::split(tls.connect(stream).await?);
+            return Ok(self.multiplexer(reader, writer));
+        }
+        let (reader, writer) = stream.into_split();
+        Ok(self.multiplexer(reader, writer))
+    }
+
+    fn multiplexer(
+        &self,
+        reader: impl AsyncRead + Send + Unpin + 'static,
+        writer: impl AsyncWrite + Send + Unpin + 'static,
+    ) -> Multiplexer {
+        #[cfg(feature = "msgpack")]
+        if self.msgpack_rpc {
+            let (sink, stream) = msgpack_rpc_frames(reader, writer, self.limits.max_message_size);
+            return spawn_multiplexer(sink, stream, self.limits, self.timeout);
+        }
+        #[cfg(feature = "compression")]
+        if !self.compression.is_empty() {
+            let max_len = self.limits.max_message_size;
+            let (sink, stream) = line_frames(reader, writer, max_len);
+            let negotiation = Negotiation::client(&self.compression);
+            let (sink, stream) = compressed_frames(sink, stream, negotiation, max_len);
+            return spawn_multiplexer(sink, stream, self.limits, self.timeout);
+        }
+        line_multiplexer(reader, writer, self.limits, self.timeout)
+    }
+}
+
+impl PersistentTransport for TcpTransport {
+    fn is_closed(&self) -> bool {
+        match self.mux.try_lock() {
+            Some(mux) => mux.as_ref().map(|mux| mux.is_closed()).unwrap_or(true),
+            // connecting right now
+            None => false,
+        }
+    }
+}
+
+#[async_trait]
+impl RpcTransport for TcpTransport {
+    type Error = TcpError;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        Ok(self.connection().await?.call_raw(req).await?)
+    }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        Ok(self.connection().await?.call_raw_batch(reqs).await?)
+    }
+}
+
+/// A TcpServer accepts TCP connections carrying one JSON-RPC message per line, serving each with its own service made by a [SessionFactory], through [serve_connection]. Lines longer than [Limits::max_message_size] drop the connection.
+///
+/// Shutting down through the [ServeHandle] stops accepting connections, and waits for the open ones to close. Requires the `tcp` feature, and a tokio runtime.
+pub struct TcpServer<F: SessionFactory> {
+    factory: F,
+    config: ServerConfig,
+    handle: ServeHandle,
+    #[cfg(feature = "tls")]
+    tls: Option<ServerTls>,
+    #[cfg(feature = "compression")]
+    compression: Vec<Compression>,
+    #[cfg(feature = "msgpack")]
+    msgpack_rpc: bool,
+}
+
+impl<F: SessionFactory> TcpServer<F> {
+    /// Creates a new TcpServer.
+    pub fn new(factory: F) -> Self {
+        Self {
+            factory,
+            config: ServerConfig::default(),
+            handle: ServeHandle::new(),
+            #[cfg(feature = "tls")]
+            tls: None,
+            #[cfg(feature = "compression")]
+            compression: vec![],
+            #[cfg(feature = "msgpack")]
+            msgpack_rpc: false,
+        }
+    }
+
+    /// Sets the configuration used to handle requests.
+    pub fn with_config(mut self, config: ServerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Uses the given [ServeHandle], so that the server can be gracefully shut down through it.
+    pub fn with_handle(mut self, handle: ServeHandle) -> Self {
+        self.handle = handle;
+        self
+    }
+
+    /// Only accepts TLS connections, using the given config. If the config verifies client certificates, the [crate::Identity] of each client is attached to the [ConnInfo] and the context of every call, with the certificate's [cert_fingerprint] as its ID by default. Requires the `tls` feature.
+    #[cfg(feature = "tls")]
+    pub fn with_tls(mut self, config: Arc<rustls::ServerConfig>) -> Self {
+        self.tls = Some(ServerTls::new(config));
+        self
+    }
+
+    /// Maps the DER-encoded certificate of each TLS client to its identity, instead of using the certificate's fingerprint. Clients mapped to `None` are still served, without an identity. Only has an effect together with [TcpServer::with_tls].
+    #[cfg(feature = "tls")]
+    pub fn with_client_identity(
+        mut self,
+        identify: impl Fn(&[u8]) -> Option<crate::Identity> + Send + Sync + 'static,
+    ) -> Self {
+        if let Some(tls) = self.tls.as_mut() {
+            tls.identify = Arc::new(identify);
+        }
+        self
+    }
+
+    /// Compresses large messages to clients that offer one of the given algorithms, picking the client's favorite. Clients that don't offer any are served plain JSON. Requires the `compression` feature.
+    #[cfg(feature = "compression")]
+    pub fn with_compression(mut self, compression: &[Compression]) -> Self {
+        self.compression = compression.to_vec();
+        self
+    }
+
+    /// Speaks msgpack-rpc instead of JSON lines, so that existing msgpack-rpc clients can call the service. Responses go back as msgpack-rpc responses, with errors as maps shaped like JSON-RPC errors, and server-sent notifications as msgpack-rpc notifications. Takes precedence over compression. Requires the `msgpack` feature.
+    #[cfg(feature = "msgpack")]
+    pub fn with_msgpack_rpc(mut self) -> Self {
+        self.msgpack_rpc = true;
+        self
+    }
+
+    /// Serves connections on the given address until shut down through the [ServeHandle].
+    pub async fn serve(self, addr: SocketAddr) -> std::io::Result<()> {
+        self.serve_listener(std::net::TcpListener::bind(addr)?)
+            .await
+    }
+
+    /// Serves connections on an already bound listener until shut down through the [ServeHandle].
+    pub async fn serve_listener(self, listener: std::net::TcpListener) -> std::io::Result<()> {
+        let handle = self.handle.clone();
+        let this = Arc::new(self);
+        accept_tcp(listener, &handle, move |stream, conn| {
+            let this = this.clone();
+            async move {
+                let _ = stream.set_nodelay(true);
+                #[cfg(feature = "tls")]
+                if let Some(tls) = &this.tls {
+                    let Some((stream, identity)) = tls.accept(stream).await else {
+                        return;
+                    };
+                    let conn = match identity {
+                        Some(identity) => conn.with_identity(identity),
+                        None => conn,
+                    };
+                    let (reader, writer) = tokio::io::split(stream);
+                    this.serve_stream(reader, writer, conn).await;
+                    return;
+                }
+                let (reader, writer) = stream.into_split();
+                this.serve_stream(reader, writer, conn).await;
+            }
+        })
+        .await
+    }
+
+    async fn serve_stream(
+        &self,
+        reader: impl AsyncRead + Send + Unpin + 'static,
+        writer: impl AsyncWrite + Send + Unpin + 'static,
+        conn: ConnInfo,
+    ) {
+        let max_len = self.config.limits.max_message_size;
+        #[cfg(feature = "msgpack")]
+        if self.msgpack_rpc {
+            let (sink, stream) = msgpack_rpc_frames(reader, writer, max_len);
+            serve_connection(&self.factory, conn, sink, stream, &self.config).await;
+            return;
+        }
+        let (sink, stream) = line_frames(reader, writer, max_len);
+        #[cfg(feature = "compression")]
+        if !self.compression.is_empty() {
+            let negotiation = Negotiation::server(&self.compression);
+            let (sink, stream) = compressed_frames(sink, stream, negotiation, max_len);
+            serve_connection(&self.factory, conn, sink, stream, &self.config).await;
+            return;
+        }
+        serve_connection(&self.factory, conn, sink, stream, &self.config).await;
+    }
+}
+
+/// Serves newline-delimited JSON-RPC over TCP on the given address, with the default configuration. See [TcpServer].
+pub async fn serve_tcp(addr: SocketAddr, factory: impl SessionFactory) -> std::io::Result<()> {
+    TcpServer::new(factory).serve(addr).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+    use crate::{
+        ConnInfo, FnService, JrpcMessage, PersistentTransport, RpcTransport, ServeHandle,
+        TcpServer, TcpTransport, GOING_AWAY_METHOD,
+    };
+
+    #[tokio::test]
+    async fn test_tcp() {
+        let handle = ServeHandle::new().with_connection_draining();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(
+            TcpServer::new(|conn: &ConnInfo| {
+                let notifier = conn.notifier.clone().unwrap();
+                let conn_id = conn.id;
+                FnService::new(move |method, _| {
+                    let method = method.to_string();
+                    let notifier = notifier.clone();
+                    async move {
+                        if method == "subscribe" {
+                            notifier.notify("update", vec![]).await.unwrap();
+                        }
+                        if method == "slow" {
+                            tokio::time::sleep(Duration::from_millis(300)).await;
+                        }
+                        Some(Ok(conn_id.into()))
+                    }
+                })
+            })
+            .with_handle(handle.clone())
+            .serve_listener(listener),
+        );
+
+        // a raw client sees one message per line
+        let mut raw = tokio::net::TcpStream::connect(addr).await.unwrap();
+        raw.write_all(
+            b"{\"jsonrpc\": \"2.0\", \"method\": \"f\", \"params\": [], \"id\": 1}\r\n\n",
+        )
+        .await
+        .unwrap();
+        let mut line = String::new();
+        tokio::io::BufReader::new(&mut raw)
+            .read_line(&mut line)
+            .await
+            .unwrap();
+        let resp: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(resp["id"], 1);
+        drop(raw);
+
+        let transport = TcpTransport::new(addr.to_string()).with_timeout(Duration::from_secs(5));
+        let (a, b) = futures_lite::future::zip(
+            transport.call("echo", &[]),
+            transport.call("subscribe", &[]),
+        )
+        .await;
+        let conn_id = a.unwrap().unwrap().unwrap();
+        assert_eq!(b.unwrap().unwrap().unwrap(), conn_id);
+        assert!(matches!(
+            transport.next_incoming().await,
+            Some(JrpcMessage::Notification(n)) if n.method == "update"
+        ));
+
+        // draining lets the slow call finish, then tells idle clients to go away and hangs up
+        let mut raw =
+            tokio::io::BufReader::new(tokio::net::TcpStream::connect(addr).await.unwrap());
+        let shutdown = async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            handle.shutdown(Duration::from_secs(5)).await
+        };
+        let (slow, drained) = tokio::join!(transport.call("slow", &[]), shutdown);
+        assert_eq!(slow.unwrap().unwrap().unwrap(), conn_id);
+        assert!(drained);
+        let mut line = String::new();
+        raw.read_line(&mut line).await.unwrap();
+        let notification: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(notification["method"], GOING_AWAY_METHOD);
+        assert_eq!(raw.read_line(&mut line).await.unwrap(), 0);
+        assert!(transport.is_closed());
+        server.await.unwrap().unwrap();
+    }
+
+    #[cfg(feature = "tls")]
+    #[tokio::test]
+    async fn test_tls() {
+        use std::sync::Arc;
+
+        use crate::{cert_fingerprint, rustls};
+
+        let mut ca = rcgen::CertificateParams::new(vec![]);
+        ca.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let ca = rcgen::Certificate::from_params(ca).unwrap();
+        let issue = |name: &str| {
+            let cert = rcgen::Certificate::from_params(rcgen::CertificateParams::new(vec![
+                name.to_string()
+            ]))
+            .unwrap();
+            (
+                rustls::Certificate(cert.serialize_der_with_signer(&ca).unwrap()),
+                rustls::PrivateKey(cert.serialize_private_key_der()),
+            )
+        };
+        let (server_cert, server_key) = issue("localhost");
+        let (client_cert, client_key) = issue("client");
+        let mut roots = rustls::RootCertStore::empty();
+        roots
+            .add(&rustls::Certificate(ca.serialize_der().unwrap()))
+            .unwrap();
+        let server_config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_client_cert_verifier(
+                rustls::server::AllowAnyAuthenticatedClient::new(roots.clone()).boxed(),
+            )
+            .with_single_cert(vec![server_cert], server_key)
+            .unwrap();
+        let client_config = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_client_auth_cert(vec![client_cert.clone()], client_key)
+            .unwrap();
+
+        let handle = ServeHandle::new();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(
+            TcpServer::new(|conn: &ConnInfo| {
+                let id = conn.identity.clone().map(|identity| identity.id);
+                FnService::new(move |_, _| {
+                    let id = id.clone();
+                    async move { Some(Ok(id.into())) }
+                })
+            })
+            .with_tls(Arc::new(server_config))
+            .with_handle(handle.clone())
+            .serve_listener(listener),
+        );
+        let transport = TcpTransport::new(addr.to_string())
+            .with_tls(Arc::new(client_config), "localhost")
+            .with_timeout(Duration::from_secs(5));
+        assert_eq!(
+            transport
+                .call("whoami", &[])
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap(),
+            cert_fingerprint(&client_cert.0)
+        );
+        drop(transport);
+
+        // plaintext clients never get an answer
+        let plain = TcpTransport::new(addr.to_string()).with_timeout(Duration::from_millis(200));
+        assert!(plain.call("whoami", &[]).await.is_err());
+        drop(plain);
+
+        assert!(handle.shutdown(Duration::from_secs(5)).await);
+        server.await.unwrap().unwrap();
+    }
+
+    #[cfg(feature = "compression")]
+    #[tokio::test]
+    async fn test_tcp_compression() {
+        use crate::Compression;
+
+        let handle = ServeHandle::new();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(
+            TcpServer::new(|_: &ConnInfo| {
+                FnService::new(|_, params| async move { Some(Ok(params[0].clone())) })
+            })
+            .with_compression(&[Compression::Gzip])
+            .with_handle(handle.clone())
+            .serve_listener(listener),
+        );
+        let big = "hello".repeat(1000);
+        // both a client that offers compression and one that doesn't get served
+        for compression in [vec![Compression::Zstd, Compression::Gzip], vec![]] {
+            let transport = TcpTransport::new(addr.to_string())
+                .with_compression(&compression)
+                .with_timeout(Duration::from_secs(5));
+            for _ in 0..2 {
+                assert_eq!(
+                    transport
+                        .call("echo", &[big.clone().into()])
+                        .await
+                        .unwrap()
+                        .unwrap()
+                        .unwrap(),
+                    big
+                );
+            }
+        }
+        assert!(handle.shutdown(Duration::from_secs(5)).await);
+        server.await.unwrap().unwrap();
+    }
+}
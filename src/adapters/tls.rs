@@ -0,0 +1,84 @@
+use std::{sync::Arc, time::Duration};
+
+use sha2::{Digest, Sha256};
+use tokio::net::TcpStream;
+use tokio_rustls::{client, server, TlsAcceptor, TlsConnector};
+
+use crate::{timer, Identity};
+
+pub use tokio_rustls::rustls;
+
+/// Returns the fingerprint of a DER-encoded certificate, as `"sha256:"` followed by the hex-encoded SHA-256 hash. This is the default [Identity] ID of TLS clients that present a certificate.
+pub fn cert_fingerprint(cert: &[u8]) -> String {
+    format!("sha256:{}", hex::encode(Sha256::digest(cert)))
+}
+
+/// The TLS settings of a [crate::TcpTransport].
+#[derive(Clone)]
+pub(crate) struct ClientTls {
+    pub(crate) config: Arc<rustls::ClientConfig>,
+    pub(crate) server_name: String,
+}
+
+impl ClientTls {
+    pub(crate) async fn connect(
+        &self,
+        stream: TcpStream,
+    ) -> std::io::Result<client::TlsStream<TcpStream>> {
+        let server_name = rustls::ServerName::try_from(self.server_name.as_str())
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
+        TlsConnector::from(self.config.clone())
+            .connect(server_name, stream)
+            .await
+    }
+}
+
+/// Maps the DER-encoded certificate of a TLS client to its identity.
+pub(crate) type IdentifyFn = Arc<dyn Fn(&[u8]) -> Option<Identity> + Send + Sync + 'static>;
+
+/// The TLS settings of a [crate::TcpServer].
+pub(crate) struct ServerTls {
+    pub(crate) acceptor: TlsAcceptor,
+    pub(crate) identify: IdentifyFn,
+}
+
+impl ServerTls {
+    pub(crate) fn new(config: Arc<rustls::ServerConfig>) -> Self {
+        Self {
+            acceptor: TlsAcceptor::from(config),
+            identify: Arc::new(|cert| {
+                Some(Identity {
+                    id: cert_fingerprint(cert),
+                    roles: vec![],
+                })
+            }),
+        }
+    }
+
+    /// Completes the handshake, returning the stream together with the identity of the client, if it presented a certificate.
+    pub(crate) async fn accept(
+        &self,
+        stream: TcpStream,
+    ) -> Option<(server::TlsStream<TcpStream>, Option<Identity>)> {
+        // don't let clients that never finish the handshake hold on to a connection
+        let stream =
+            match timer::timeout(Duration::from_secs(10), self.acceptor.accept(stream)).await {
+                Some(Ok(stream)) => stream,
+                Some(Err(err)) => {
+                    log::debug!("TLS handshake failed: {}", err);
+                    return None;
+                }
+                None => {
+                    log::debug!("TLS handshake timed out");
+                    return None;
+                }
+            };
+        let identity = stream
+            .get_ref()
+            .1
+            .peer_certificates()
+            .and_then(|certs| certs.first())
+            .and_then(|cert| (self.identify)(&cert.0));
+        Some((stream, identity))
+    }
+}
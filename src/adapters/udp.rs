@@ -0,0 +1,572 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use thiserror::Error;
+use tokio::net::UdpSocket;
+
+use crate::{
+    server::error_response, timer, Codec, JrpcId, JrpcRequest, JrpcResponse, JsonCodec, RpcContext,
+    RpcService, RpcTransport, ServeHandle, ServerConfig,
+};
+
+/// The default maximum size of a datagram, which fits in a 1500-byte Ethernet frame over either IPv4 or IPv6 without fragmenting.
+pub const DEFAULT_MAX_DATAGRAM_SIZE: usize = 1452;
+
+/// An error returned by a [UdpTransport].
+#[derive(Error, Debug)]
+pub enum UdpError {
+    #[error("could not send datagram: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("request of {len} bytes does not fit in a datagram of {max} bytes")]
+    TooLarge { len: usize, max: usize },
+    #[error("timed out waiting for a response")]
+    Timeout,
+}
+
+type Waiters = Arc<Mutex<HashMap<i64, async_channel::Sender<JrpcResponse>>>>;
+
+/// A UdpTransport makes calls in single datagrams, for control traffic on a LAN where setting up a TCP connection costs more than the call itself. Each request and its response must fit in a datagram of [DEFAULT_MAX_DATAGRAM_SIZE] bytes, unless set otherwise with [UdpTransport::with_max_datagram_size].
+///
+/// Requests that are not answered within the retransmission interval are sent again, with the same ID, up to a number of times; a [UdpServer] answers such duplicates without executing them again. Calls fail with [UdpError::Timeout] once the overall timeout elapses. Requires the `udp` feature, and a tokio runtime.
+pub struct UdpTransport {
+    socket: Arc<UdpSocket>,
+    waiters: Waiters,
+    next_id: AtomicI64,
+    timeout: Duration,
+    retransmit_interval: Duration,
+    max_retransmits: u32,
+    max_datagram_size: usize,
+    codec: Arc<dyn Codec>,
+    receiver: tokio::task::JoinHandle<()>,
+}
+
+impl UdpTransport {
+    /// Creates a UdpTransport for the server at the given address, from a local socket bound to an ephemeral port. Calls time out after 5 seconds, with up to 4 retransmissions every 200 milliseconds.
+    pub async fn connect(addr: SocketAddr) -> std::io::Result<Self> {
+        let local: SocketAddr = if addr.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0u16; 8], 0).into()
+        };
+        let socket = UdpSocket::bind(local).await?;
+        socket.connect(addr).await?;
+        Ok(Self::from_socket(socket))
+    }
+
+    /// Creates a UdpTransport over a socket already connected to the server.
+    pub fn from_socket(socket: UdpSocket) -> Self {
+        let socket = Arc::new(socket);
+        let waiters = Waiters::default();
+        let codec: Arc<dyn Codec> = Arc::new(JsonCodec);
+        let receiver = tokio::spawn(receive_responses(
+            socket.clone(),
+            waiters.clone(),
+            codec.clone(),
+        ));
+        Self {
+            socket,
+            waiters,
+            next_id: AtomicI64::new(1),
+            timeout: Duration::from_secs(5),
+            retransmit_interval: Duration::from_millis(200),
+            max_retransmits: 4,
+            max_datagram_size: DEFAULT_MAX_DATAGRAM_SIZE,
+            codec,
+            receiver,
+        }
+    }
+
+    /// Sets the overall timeout for every call, retransmissions included.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets how long to wait for a response before sending a request again, and how many times it may be sent again.
+    pub fn with_retransmission(mut self, interval: Duration, max_retransmits: u32) -> Self {
+        self.retransmit_interval = interval;
+        self.max_retransmits = max_retransmits;
+        self
+    }
+
+    /// Sends every request only once, so that lost datagrams simply make their calls time out. Best for traffic where a late answer is as useless as none.
+    pub fn best_effort(self) -> Self {
+        self.with_retransmission(Duration::MAX, 0)
+    }
+
+    /// Sets the maximum size of a datagram. Requests that don't fit fail with [UdpError::TooLarge] without being sent, so set this to match the path MTU.
+    pub fn with_max_datagram_size(mut self, size: usize) -> Self {
+        self.max_datagram_size = size;
+        self
+    }
+
+    /// Encodes requests and decodes responses with the given codec instead of as JSON. The server must use the same codec, through [ServerConfig::codec].
+    pub fn with_codec(mut self, codec: impl Codec) -> Self {
+        self.codec = Arc::new(codec);
+        // responses are decoded by the receiver, so it starts over with the new codec
+        self.receiver.abort();
+        self.receiver = tokio::spawn(receive_responses(
+            self.socket.clone(),
+            self.waiters.clone(),
+            self.codec.clone(),
+        ));
+        self
+    }
+}
+
+impl Drop for UdpTransport {
+    fn drop(&mut self) {
+        self.receiver.abort();
+    }
+}
+
+/// Hands every response on the socket to the call waiting for it.
+async fn receive_responses(socket: Arc<UdpSocket>, waiters: Waiters, codec: Arc<dyn Codec>) {
+    let mut buf = vec![0; 65536];
+    loop {
+        let len = match socket.recv(&mut buf).await {
+            Ok(len) => len,
+            // errors like ICMP port unreachable leave the socket usable
+            Err(err) => {
+                log::debug!("udp transport could not receive: {}", err);
+                continue;
+            }
+        };
+        let Ok(resp) = codec.decode_response(&buf[..len]) else {
+            log::warn!("udp transport dropping malformed datagram");
+            continue;
+        };
+        let JrpcId::Number(id) = resp.id else {
+            continue;
+        };
+        // duplicate responses find nobody waiting anymore
+        let waiter = waiters.lock().unwrap().remove(&id);
+        if let Some(waiter) = waiter {
+            let _ = waiter.try_send(resp);
+        }
+    }
+}
+
+#[async_trait]
+impl RpcTransport for UdpTransport {
+    type Error = UdpError;
+
+    async fn call_raw(&self, mut req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        let internal_id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let original_id = std::mem::replace(&mut req.id, JrpcId::Number(internal_id));
+        let datagram = self.codec.encode_request(&req);
+        if datagram.len() > self.max_datagram_size {
+            return Err(UdpError::TooLarge {
+                len: datagram.len(),
+                max: self.max_datagram_size,
+            });
+        }
+        let (send, recv) = async_channel::bounded(1);
+        self.waiters.lock().unwrap().insert(internal_id, send);
+        let exchange = async {
+            let mut attempt = 0;
+            loop {
+                self.socket.send(&datagram).await?;
+                if attempt == self.max_retransmits {
+                    return recv.recv().await.map_err(|_| UdpError::Timeout);
+                }
+                if let Some(Ok(resp)) = timer::timeout(self.retransmit_interval, recv.recv()).await
+                {
+                    return Ok(resp);
+                }
+                attempt += 1;
+                log::debug!("retransmitting {} (attempt {})", req.method, attempt + 1);
+            }
+        };
+        let result = timer::timeout(self.timeout, exchange).await;
+        self.waiters.lock().unwrap().remove(&internal_id);
+        let mut resp = result.ok_or(UdpError::Timeout)??;
+        resp.id = original_id;
+        Ok(resp)
+    }
+}
+
+enum Entry {
+    InFlight,
+    Done(Vec<u8>, Instant),
+}
+
+/// Identifies a request by its peer and ID, which is all a retransmission has in common with the original.
+type DedupKey = (SocketAddr, JrpcId);
+
+/// Hashes a datagram, to tell a retransmission from a new request that happens to reuse an ID.
+fn fingerprint(datagram: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    datagram.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// A UdpServer answers requests arriving in single datagrams, like those of a [UdpTransport], with a service. Batches work too, as long as both they and their responses fit in a datagram; responses that don't fit are replaced with a `-32603` error.
+///
+/// Retransmitted requests from the same peer are recognized by their ID for a while, by default 30 seconds, and answered with the response already made, or ignored while it is still being made, so that they are not executed twice. Clients should therefore not reuse IDs within that window, which [UdpTransport] never does. Notifications and batches are never deduplicated, and notifications are not answered. Each call's context carries the peer's [SocketAddr] as an extension. Shutting down through the [ServeHandle] stops receiving requests, and waits for the ones being handled. Requires the `udp` feature, and a tokio runtime.
+pub struct UdpServer<S: RpcService> {
+    service: S,
+    config: ServerConfig,
+    handle: ServeHandle,
+    dedup_window: Duration,
+    max_datagram_size: usize,
+    dedup_capacity: usize,
+    seen: Mutex<HashMap<DedupKey, (u64, Entry)>>,
+}
+
+impl<S: RpcService> UdpServer<S> {
+    /// Creates a new UdpServer.
+    pub fn new(service: S) -> Self {
+        Self {
+            service,
+            config: ServerConfig::default(),
+            handle: ServeHandle::new(),
+            dedup_window: Duration::from_secs(30),
+            max_datagram_size: DEFAULT_MAX_DATAGRAM_SIZE,
+            dedup_capacity: 10000,
+            seen: Default::default(),
+        }
+    }
+
+    /// Sets the configuration used to handle requests.
+    pub fn with_config(mut self, config: ServerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Uses the given [ServeHandle], so that the server can be gracefully shut down through it.
+    pub fn with_handle(mut self, handle: ServeHandle) -> Self {
+        self.handle = handle;
+        self
+    }
+
+    /// Sets how long responses are remembered for answering retransmitted requests. Should be longer than the timeout of the clients.
+    pub fn with_dedup_window(mut self, window: Duration) -> Self {
+        self.dedup_window = window;
+        self
+    }
+
+    /// Sets how many requests are remembered at most, by default 10000. Requests beyond that are still answered, but executed again if retransmitted.
+    pub fn with_dedup_capacity(mut self, capacity: usize) -> Self {
+        self.dedup_capacity = capacity;
+        self
+    }
+
+    /// Sets the maximum size of a response datagram.
+    pub fn with_max_datagram_size(mut self, size: usize) -> Self {
+        self.max_datagram_size = size;
+        self
+    }
+
+    /// Serves requests on the given address until shut down through the [ServeHandle].
+    pub async fn serve(self, addr: SocketAddr) -> std::io::Result<()> {
+        self.serve_socket(UdpSocket::bind(addr).await?).await
+    }
+
+    /// Serves requests on an already bound socket until shut down through the [ServeHandle].
+    pub async fn serve_socket(self, socket: UdpSocket) -> std::io::Result<()> {
+        let shutdown = self.handle.shutdown_signal();
+        let socket = Arc::new(socket);
+        let this = Arc::new(self);
+        let mut buf = vec![0; 65536];
+        loop {
+            let received =
+                futures_lite::future::or(async { Some(socket.recv_from(&mut buf).await) }, async {
+                    shutdown.wait().await;
+                    None
+                })
+                .await;
+            let (len, peer) = match received {
+                Some(Ok(received)) => received,
+                // errors like ICMP port unreachable concern single peers
+                Some(Err(err)) => {
+                    log::debug!("udp server could not receive: {}", err);
+                    continue;
+                }
+                None => return Ok(()),
+            };
+            let datagram = buf[..len].to_vec();
+            let key = this.dedup_key(peer, &datagram);
+            if let Some(resp) = key
+                .as_ref()
+                .and_then(|key| this.check_duplicate(key, &datagram))
+            {
+                if let Some(resp) = resp {
+                    let _ = socket.send_to(&resp, peer).await;
+                }
+                continue;
+            }
+            let this = this.clone();
+            let socket = socket.clone();
+            tokio::spawn(async move {
+                let handle = this.handle.clone();
+                handle
+                    .track(async {
+                        let resp = this.respond(peer, &datagram).await;
+                        if let Some(key) = key {
+                            this.remember(key, &datagram, resp.clone());
+                        }
+                        // notifications have nothing to answer
+                        if resp.is_empty() {
+                            return;
+                        }
+                        if let Err(err) = socket.send_to(&resp, peer).await {
+                            log::debug!("udp server could not send: {}", err);
+                        }
+                    })
+                    .await;
+            });
+        }
+    }
+
+    /// Returns the key to recognize retransmissions of the datagram by, if it is a single request with an ID.
+    fn dedup_key(&self, peer: SocketAddr, datagram: &[u8]) -> Option<DedupKey> {
+        match self.config.codec.decode_request(datagram).ok()?.id {
+            JrpcId::Null => None,
+            id => Some((peer, id)),
+        }
+    }
+
+    /// Returns whether the datagram is a duplicate, with the response to send again if there already is one. Otherwise, marks it as in flight, if there is room.
+    fn check_duplicate(&self, key: &DedupKey, datagram: &[u8]) -> Option<Option<Vec<u8>>> {
+        let mut seen = self.seen.lock().unwrap();
+        let window = self.dedup_window;
+        seen.retain(|_, (_, entry)| match entry {
+            Entry::InFlight => true,
+            Entry::Done(_, at) => at.elapsed() < window,
+        });
+        let fingerprint = fingerprint(datagram);
+        match seen.get(key) {
+            Some((seen_fingerprint, entry)) if *seen_fingerprint == fingerprint => match entry {
+                Entry::InFlight => Some(None),
+                Entry::Done(resp, _) => Some(Some(resp.clone())),
+            },
+            // a new request reusing the ID replaces the old one
+            Some(_) => {
+                seen.insert(key.clone(), (fingerprint, Entry::InFlight));
+                None
+            }
+            None => {
+                if seen.len() < self.dedup_capacity {
+                    seen.insert(key.clone(), (fingerprint, Entry::InFlight));
+                } else {
+                    log::debug!("udp server remembers too many requests to deduplicate another");
+                }
+                None
+            }
+        }
+    }
+
+    /// Remembers the response to a request marked as in flight, unless it was not marked for lack of room, or was replaced since.
+    fn remember(&self, key: DedupKey, datagram: &[u8], resp: Vec<u8>) {
+        let mut seen = self.seen.lock().unwrap();
+        if let Some((seen_fingerprint, entry)) = seen.get_mut(&key) {
+            if *seen_fingerprint == fingerprint(datagram) {
+                *entry = Entry::Done(resp, Instant::now());
+            }
+        }
+    }
+
+    async fn respond(&self, peer: SocketAddr, datagram: &[u8]) -> Vec<u8> {
+        let mut ctx = RpcContext::new();
+        ctx.insert_extension(peer);
+        let resp = self
+            .service
+            .respond_bytes_with_context(ctx, datagram, &self.config)
+            .await;
+        if resp.len() <= self.max_datagram_size {
+            return resp;
+        }
+        let id = self
+            .config
+            .codec
+            .decode_request(datagram)
+            .map(|req| req.id)
+            .unwrap_or(JrpcId::Null);
+        let err = error_response(
+            id,
+            -32603,
+            format!(
+                "response of {} bytes does not fit in a datagram of {} bytes",
+                resp.len(),
+                self.max_datagram_size
+            ),
+        );
+        self.config.codec.encode_response(&err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::SocketAddr,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use tokio::net::UdpSocket;
+
+    use crate::{FnService, RpcTransport, ServeHandle, UdpError, UdpServer, UdpTransport};
+
+    /// Relays datagrams between a client and the server, losing the first one from the client.
+    async fn lossy_relay(server: SocketAddr) -> SocketAddr {
+        let relay = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = relay.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut client = None;
+            let mut lost = false;
+            let mut buf = vec![0; 65536];
+            loop {
+                let (len, from) = relay.recv_from(&mut buf).await.unwrap();
+                if from == server {
+                    relay.send_to(&buf[..len], client.unwrap()).await.unwrap();
+                } else if lost {
+                    client = Some(from);
+                    relay.send_to(&buf[..len], server).await.unwrap();
+                } else {
+                    lost = true;
+                }
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_udp() {
+        let calls = Arc::new(AtomicU64::new(0));
+        let service = {
+            let calls = calls.clone();
+            FnService::new(move |method, params| {
+                let method = method.to_string();
+                let n = calls.fetch_add(1, Ordering::SeqCst);
+                let param = params.first().cloned();
+                async move {
+                    if method == "slow" {
+                        tokio::time::sleep(Duration::from_millis(150)).await;
+                    }
+                    Some(Ok(param.unwrap_or_else(|| n.into())))
+                }
+            })
+        };
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        let handle = ServeHandle::new();
+        let server = tokio::spawn(
+            UdpServer::new(service)
+                .with_handle(handle.clone())
+                .with_max_datagram_size(100)
+                .serve_socket(socket),
+        );
+
+        let transport = UdpTransport::connect(lossy_relay(addr).await)
+            .await
+            .unwrap()
+            .with_retransmission(Duration::from_millis(50), 4);
+        // the lost request is sent again, and the slow one is retransmitted while running, yet executed once
+        assert_eq!(transport.call("f", &[]).await.unwrap().unwrap().unwrap(), 0);
+        assert_eq!(
+            transport.call("slow", &[]).await.unwrap().unwrap().unwrap(),
+            1
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        let big = vec!["x".repeat(200).into()];
+        let transport = transport.with_max_datagram_size(100);
+        assert!(matches!(
+            transport.call("f", &big).await,
+            Err(UdpError::TooLarge { .. })
+        ));
+        // responses that don't fit become errors
+        let transport = transport.with_max_datagram_size(1000);
+        let err = transport
+            .call("f", &big)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(err.jrpc_code(), Some(-32603));
+
+        // without retransmission, a lost request times out
+        let transport = UdpTransport::connect(lossy_relay(addr).await)
+            .await
+            .unwrap()
+            .best_effort()
+            .with_timeout(Duration::from_millis(200));
+        assert!(matches!(
+            transport.call("f", &[]).await,
+            Err(UdpError::Timeout)
+        ));
+        assert!(transport.call("f", &[]).await.unwrap().is_some());
+
+        assert!(handle.shutdown(Duration::from_secs(1)).await);
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_udp_dedup() {
+        let calls = Arc::new(AtomicU64::new(0));
+        let service = {
+            let calls = calls.clone();
+            FnService::new(move |_, _| {
+                let n = calls.fetch_add(1, Ordering::SeqCst);
+                async move { Some(Ok(n.into())) }
+            })
+        };
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(
+            UdpServer::new(service)
+                .with_dedup_capacity(2)
+                .serve_socket(socket),
+        );
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.connect(addr).await.unwrap();
+        let exchange = |req: serde_json::Value| {
+            let client = &client;
+            async move {
+                client
+                    .send(&serde_json::to_vec(&req).unwrap())
+                    .await
+                    .unwrap();
+                let mut buf = vec![0; 65536];
+                let len = tokio::time::timeout(Duration::from_millis(200), client.recv(&mut buf))
+                    .await
+                    .ok()?
+                    .unwrap();
+                let resp: serde_json::Value = serde_json::from_slice(&buf[..len]).unwrap();
+                Some(resp["result"].clone())
+            }
+        };
+        let req = |id: u64, x: u64| serde_json::json!({"jsonrpc": "2.0", "method": "f", "params": [x], "id": id});
+
+        // a retransmission is answered from memory, but a new request reusing the ID is not
+        assert_eq!(exchange(req(1, 0)).await.unwrap(), 0);
+        assert_eq!(exchange(req(1, 0)).await.unwrap(), 0);
+        assert_eq!(exchange(req(1, 1)).await.unwrap(), 1);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // notifications are executed every time, and not answered
+        let notification = serde_json::json!({"jsonrpc": "2.0", "method": "f", "params": []});
+        assert!(exchange(notification.clone()).await.is_none());
+        assert!(exchange(notification).await.is_none());
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+
+        // beyond the capacity, requests are no longer remembered
+        assert_eq!(exchange(req(2, 0)).await.unwrap(), 4);
+        assert_eq!(exchange(req(3, 0)).await.unwrap(), 5);
+        assert_eq!(exchange(req(3, 0)).await.unwrap(), 6);
+        assert_eq!(exchange(req(2, 0)).await.unwrap(), 4);
+    }
+}
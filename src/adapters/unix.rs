@@ -0,0 +1,307 @@
+use std::{
+    os::unix::fs::{FileTypeExt, PermissionsExt},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use thiserror::Error;
+
+#[cfg(feature = "shm")]
+use super::shm::{accept_shm, offer_shm, ShmAccept};
+use super::{
+    accept::accept_unix,
+    lines::{line_frames, line_multiplexer},
+};
+use crate::{
+    serve_connection, JrpcMessage, JrpcRequest, JrpcResponse, Limits, MultiplexError, Multiplexer,
+    PersistentTransport, RpcTransport, ServeHandle, ServerConfig, SessionFactory,
+};
+
+/// An error returned by a [UnixTransport].
+#[derive(Error, Debug)]
+pub enum UnixError {
+    #[error("could not connect: {0}")]
+    Connect(std::io::Error),
+    #[error(transparent)]
+    Multiplex(#[from] MultiplexError),
+}
+
+/// A UnixTransport makes calls over a Unix domain socket carrying one JSON-RPC message per line, just like a [crate::TcpTransport]. It suits control planes of local daemons, where access can be restricted through file permissions.
+///
+/// The connection is made on first use, and made again on the next call after it is lost. Requires the `unix` feature, a Unix platform, and a tokio runtime.
+pub struct UnixTransport {
+    path: PathBuf,
+    timeout: Option<Duration>,
+    limits: Limits,
+    mux: async_lock::Mutex<Option<Arc<Multiplexer>>>,
+    #[cfg(feature = "shm")]
+    shm_capacity: Option<usize>,
+}
+
+impl UnixTransport {
+    /// Creates a new UnixTransport for the socket at the given path.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            timeout: None,
+            limits: Limits::default(),
+            mux: async_lock::Mutex::new(None),
+            #[cfg(feature = "shm")]
+            shm_capacity: None,
+        }
+    }
+
+    /// Sets a timeout for every call.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the limits that incoming messages are checked against. Lines longer than [Limits::max_message_size] drop the connection.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Offers the server to carry messages through shared memory instead of the socket, in two rings of the given capacity, for very high call rates between processes on the same machine. The socket then only wakes the other side up. Falls back to the socket if the server doesn't support it, or shared memory cannot be set up, as on platforms other than Linux and Android. Requires the `shm` feature.
+    ///
+    /// This is experimental. Messages may be longer than the rings, at the cost of waiting for the other side to make room.
+    #[cfg(feature = "shm")]
+    pub fn with_shared_memory(mut self, capacity: usize) -> Self {
+        self.shm_capacity = Some(capacity);
+        self
+    }
+
+    /// Waits for the next message from the server that is not a response, like a notification. Returns `None` once the connection is lost, or if it cannot be made.
+    pub async fn next_incoming(&self) -> Option<JrpcMessage> {
+        self.connection().await.ok()?.next_incoming().await
+    }
+
+    /// Makes the connection now, if there is no live one, instead of on the next call.
+    pub async fn warm_up(&self) -> Result<(), UnixError> {
+        self.connection().await.map(|_| ())
+    }
+
+    /// Returns the live connection, connecting again if it was lost.
+    async fn connection(&self) -> Result<Arc<Multiplexer>, UnixError> {
+        let mut mux = self.mux.lock().await;
+        if let Some(mux) = mux.as_ref().filter(|mux| !mux.is_closed()) {
+            return Ok(mux.clone());
+        }
+        let new_mux = Arc::new(self.connect().await.map_err(UnixError::Connect)?);
+        *mux = Some(new_mux.clone());
+        Ok(new_mux)
+    }
+
+    async fn connect(&self) -> std::io::Result<Multiplexer> {
+        let stream = tokio::net::UnixStream::connect(&self.path).await?;
+        #[cfg(feature = "shm")]
+        let stream = match self.shm_capacity {
+            Some(capacity) => match offer_shm(stream, capacity).await? {
+                Ok((reader, writer)) => {
+                    return Ok(line_multiplexer(reader, writer, self.limits, self.timeout))
+                }
+                Err(stream) => stream,
+            },
+            None => stream,
+        };
+        let (reader, writer) = stream.into_split();
+        Ok(line_multiplexer(reader, writer, self.limits, self.timeout))
+    }
+}
+
+impl PersistentTransport for UnixTransport {
+    fn is_closed(&self) -> bool {
+        match self.mux.try_lock() {
+            Some(mux) => mux.as_ref().map(|mux| mux.is_closed()).unwrap_or(true),
+            // connecting right now
+            None => false,
+        }
+    }
+}
+
+#[async_trait]
+impl RpcTransport for UnixTransport {
+    type Error = UnixError;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        Ok(self.connection().await?.call_raw(req).await?)
+    }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        Ok(self.connection().await?.call_raw_batch(reqs).await?)
+    }
+}
+
+/// A UnixServer accepts connections on a Unix domain socket carrying one JSON-RPC message per line, serving each with its own service made by a [SessionFactory], through [serve_connection].
+///
+/// Shutting down through the [ServeHandle] stops accepting connections, and waits for the open ones to close. Requires the `unix` feature, a Unix platform, and a tokio runtime.
+pub struct UnixServer<F: SessionFactory> {
+    factory: F,
+    config: ServerConfig,
+    handle: ServeHandle,
+    permissions: Option<u32>,
+    #[cfg(feature = "shm")]
+    shm_max_capacity: Option<usize>,
+}
+
+impl<F: SessionFactory> UnixServer<F> {
+    /// Creates a new UnixServer.
+    pub fn new(factory: F) -> Self {
+        Self {
+            factory,
+            config: ServerConfig::default(),
+            handle: ServeHandle::new(),
+            permissions: None,
+            #[cfg(feature = "shm")]
+            shm_max_capacity: None,
+        }
+    }
+
+    /// Sets the configuration used to handle requests.
+    pub fn with_config(mut self, config: ServerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Uses the given [ServeHandle], so that the server can be gracefully shut down through it.
+    pub fn with_handle(mut self, handle: ServeHandle) -> Self {
+        self.handle = handle;
+        self
+    }
+
+    /// Sets the mode of the socket file, like `0o600` to only allow the owner to connect. Otherwise it is left to the process umask.
+    ///
+    /// The mode is applied right after binding, so to close that window entirely, put the socket in a directory that is not accessible to others.
+    pub fn with_permissions(mut self, mode: u32) -> Self {
+        self.permissions = Some(mode);
+        self
+    }
+
+    /// Accepts offers from clients to carry messages through shared memory, with rings of up to `max_capacity` bytes, as made by [UnixTransport::with_shared_memory]. Only memory that the client has sealed against resizing is mapped, so that a client can't crash the server by shrinking it. Shared memory is only supported on Linux and Android; elsewhere, offers are refused. Requires the `shm` feature.
+    #[cfg(feature = "shm")]
+    pub fn with_shared_memory(mut self, max_capacity: usize) -> Self {
+        self.shm_max_capacity = Some(max_capacity);
+        self
+    }
+
+    /// Serves connections on a socket at the given path until shut down through the [ServeHandle]. A stale socket file left at the path is replaced, and the socket file is removed on shutdown.
+    pub async fn serve(self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let path = path.as_ref();
+        if let Ok(meta) = std::fs::symlink_metadata(path) {
+            if !meta.file_type().is_socket() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::AlreadyExists,
+                    format!("{} exists and is not a socket", path.display()),
+                ));
+            }
+            // refuse to take over a socket that someone is still listening on
+            if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::AddrInUse,
+                    format!("{} is in use", path.display()),
+                ));
+            }
+            std::fs::remove_file(path)?;
+        }
+        let listener = std::os::unix::net::UnixListener::bind(path)?;
+        let res = self.serve_listener(listener).await;
+        let _ = std::fs::remove_file(path);
+        res
+    }
+
+    /// Serves connections on an already bound listener until shut down through the [ServeHandle].
+    pub async fn serve_listener(
+        self,
+        listener: std::os::unix::net::UnixListener,
+    ) -> std::io::Result<()> {
+        if let Some(mode) = self.permissions {
+            if let Some(path) = listener.local_addr()?.as_pathname() {
+                std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+            }
+        }
+        let handle = self.handle.clone();
+        let this = Arc::new(self);
+        accept_unix(listener, &handle, move |stream, conn| {
+            let this = this.clone();
+            async move {
+                let max_len = this.config.limits.max_message_size;
+                #[cfg(feature = "shm")]
+                if let Some(max_capacity) = this.shm_max_capacity {
+                    use futures_util::StreamExt;
+                    match accept_shm(stream, max_capacity, max_len).await {
+                        Some(ShmAccept::Shm(reader, writer)) => {
+                            let (sink, stream) = line_frames(reader, writer, max_len);
+                            serve_connection(&this.factory, conn, sink, stream, &this.config).await;
+                        }
+                        Some(ShmAccept::Lines(stream, first)) => {
+                            let (reader, writer) = tokio::io::split(stream);
+                            let (sink, stream) = line_frames(reader, writer, max_len);
+                            let stream =
+                                futures_util::stream::once(std::future::ready(first)).chain(stream);
+                            serve_connection(&this.factory, conn, sink, stream, &this.config).await;
+                        }
+                        None => {}
+                    }
+                    return;
+                }
+                let (reader, writer) = stream.into_split();
+                let (sink, stream) = line_frames(reader, writer, max_len);
+                serve_connection(&this.factory, conn, sink, stream, &this.config).await;
+            }
+        })
+        .await
+    }
+}
+
+/// Serves newline-delimited JSON-RPC on a Unix socket at the given path, with the default configuration. See [UnixServer].
+pub async fn serve_unix(
+    path: impl AsRef<Path>,
+    factory: impl SessionFactory,
+) -> std::io::Result<()> {
+    UnixServer::new(factory).serve(path).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{os::unix::fs::PermissionsExt, time::Duration};
+
+    use crate::{ConnInfo, FnService, RpcTransport, ServeHandle, UnixServer, UnixTransport};
+
+    #[tokio::test]
+    async fn test_unix() {
+        let path = std::env::temp_dir().join(format!("nanorpc-test-{}.sock", std::process::id()));
+        // a stale socket from an earlier run is replaced
+        drop(std::os::unix::net::UnixListener::bind(&path));
+        let handle = ServeHandle::new();
+        let server = tokio::spawn(
+            UnixServer::new(|_: &ConnInfo| {
+                FnService::new(|_, params| async move { Some(Ok(params[0].clone())) })
+            })
+            .with_permissions(0o600)
+            .with_handle(handle.clone())
+            .serve(path.clone()),
+        );
+        let transport = UnixTransport::new(&path).with_timeout(Duration::from_secs(5));
+        let mut resp = None;
+        for _ in 0..100 {
+            if let Ok(r) = transport.call("echo", &[1.into()]).await {
+                resp = Some(r);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(resp.unwrap().unwrap().unwrap(), 1);
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        drop(transport);
+        assert!(handle.shutdown(Duration::from_secs(5)).await);
+        server.await.unwrap().unwrap();
+        assert!(!path.exists());
+    }
+}
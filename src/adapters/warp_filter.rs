@@ -0,0 +1,89 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use futures_util::{Stream, StreamExt};
+use hyper::{Body, HeaderMap, Method, Request};
+use warp::{Buf, Filter};
+
+use super::http::respond_http;
+use crate::{RpcService, ServerConfig};
+
+/// Returns a warp filter that serves a service, for mounting it at some path with something like `warp::path("rpc").and(nanorpc::warp_filter(service))`. Requests are handled just like by a [crate::HttpServer], with batches, parse errors, and size limits taken care of.
+///
+/// The peer's [SocketAddr] is attached to the context of every call, if known. Requires the `warp` feature.
+pub fn warp_filter(
+    service: impl RpcService,
+) -> impl Filter<Extract = (warp::reply::Response,), Error = warp::Rejection>
+       + Clone
+       + Send
+       + Sync
+       + 'static {
+    warp_filter_with_config(service, ServerConfig::default())
+}
+
+/// Like [warp_filter], but with the given configuration.
+pub fn warp_filter_with_config(
+    service: impl RpcService,
+    config: ServerConfig,
+) -> impl Filter<Extract = (warp::reply::Response,), Error = warp::Rejection>
+       + Clone
+       + Send
+       + Sync
+       + 'static {
+    let state = Arc::new((service, config));
+    warp::method()
+        .and(warp::header::headers_cloned())
+        .and(warp::addr::remote())
+        .and(warp::body::stream().map(into_body))
+        .then(
+            move |method: Method, headers: HeaderMap, peer_addr: Option<SocketAddr>, body: Body| {
+                let state = state.clone();
+                async move {
+                    let mut req = Request::new(body);
+                    *req.method_mut() = method;
+                    *req.headers_mut() = headers;
+                    respond_http(&state.0, &state.1, peer_addr, req).await
+                }
+            },
+        )
+}
+
+fn into_body(stream: impl Stream<Item = Result<impl Buf, warp::Error>> + Send + 'static) -> Body {
+    Body::wrap_stream(
+        stream.map(|chunk| chunk.map(|mut chunk| chunk.copy_to_bytes(chunk.remaining()))),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use warp::Filter;
+
+    use crate::{warp_filter, FnService};
+
+    #[tokio::test]
+    async fn test_warp() {
+        let service = FnService::new(|_, params| async move { Some(Ok(params[0].clone())) });
+        let filter = warp::path("rpc").and(warp_filter(service));
+        let (addr, server) = warp::serve(filter).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let client = reqwest::Client::new();
+        let url = format!("http://{}/rpc", addr);
+        let post = |body: &'static str| {
+            client
+                .post(&url)
+                .header("content-type", "application/json")
+                .body(body)
+                .send()
+        };
+        let resp: serde_json::Value =
+            post(r#"{"jsonrpc": "2.0", "method": "f", "params": [1], "id": 1}"#)
+                .await
+                .unwrap()
+                .json()
+                .await
+                .unwrap();
+        assert_eq!(resp["result"], 1);
+        let resp: serde_json::Value = post("[").await.unwrap().json().await.unwrap();
+        assert_eq!(resp["error"]["code"], -32700);
+        assert_eq!(client.get(&url).send().await.unwrap().status(), 405);
+    }
+}
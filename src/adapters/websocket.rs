@@ -0,0 +1,360 @@
+use std::{
+    future::ready,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use futures_util::{Sink, SinkExt, Stream, StreamExt};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_tungstenite::{tungstenite, WebSocketStream};
+
+use super::accept::accept_tcp;
+#[cfg(feature = "compression")]
+use super::compress::{compressed_frames, Compression, Negotiation};
+use crate::{
+    serve_connection, timer, JrpcMessage, JrpcRequest, JrpcResponse, MultiplexError, Multiplexer,
+    PersistentTransport, RpcTransport, ServeHandle, ServerConfig, SessionFactory,
+};
+
+/// How a WebSocket connection is kept alive through NATs and proxies, and how a dead one is detected. Pings are sent every `interval`, and the connection is dropped once nothing at all has been received for `timeout`, failing the calls waiting on it instead of leaving them hanging.
+#[derive(Clone, Copy, Debug)]
+pub struct WsKeepalive {
+    /// How often to send a ping.
+    pub interval: Duration,
+    /// How long the connection may be silent before it is considered dead.
+    pub timeout: Duration,
+}
+
+impl Default for WsKeepalive {
+    /// Pings every 20 seconds, and gives up after 60 seconds of silence.
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(20),
+            timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Splits a WebSocket into a sink and a stream of JSON-RPC frames, one per text message. With a keepalive, the stream ends once the connection goes silent.
+fn frames<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+    ws: WebSocketStream<S>,
+    keepalive: Option<WsKeepalive>,
+) -> (
+    impl Sink<String, Error = tungstenite::Error> + Send + Unpin + 'static,
+    impl Stream<Item = String> + Send + Unpin + 'static,
+) {
+    let (sink, stream) = ws.split();
+    // pings are sent alongside frames
+    let sink = Arc::new(async_lock::Mutex::new(sink));
+    let last_seen = Arc::new(Mutex::new(Instant::now()));
+    let dead = {
+        let sink = sink.clone();
+        let last_seen = last_seen.clone();
+        async move {
+            let Some(keepalive) = keepalive else {
+                return futures_lite::future::pending().await;
+            };
+            loop {
+                timer::sleep(keepalive.interval).await;
+                let silence = last_seen.lock().unwrap().elapsed();
+                if silence >= keepalive.timeout {
+                    log::debug!("WebSocket silent for {:?}, dropping it", silence);
+                    return;
+                }
+                let ping = tungstenite::Message::Ping(vec![]);
+                if sink.lock().await.send(ping).await.is_err() {
+                    return;
+                }
+            }
+        }
+    };
+    let sink = futures_util::sink::unfold(sink, |sink, frame: String| async move {
+        sink.lock()
+            .await
+            .send(tungstenite::Message::Text(frame))
+            .await?;
+        Ok(sink)
+    });
+    let stream = stream
+        .inspect(move |_| *last_seen.lock().unwrap() = Instant::now())
+        .take_until(dead)
+        .take_while(|msg| ready(msg.is_ok()))
+        .filter_map(|msg| {
+            ready(match msg {
+                Ok(tungstenite::Message::Text(frame)) => Some(frame),
+                Ok(tungstenite::Message::Binary(frame)) => String::from_utf8(frame).ok(),
+                _ => None,
+            })
+        });
+    (Box::pin(sink), Box::pin(stream))
+}
+
+/// A WsTransport makes calls over a single WebSocket connection, one JSON-RPC message per text message, running many calls concurrently through a [Multiplexer]. Notifications from the server are available through [WsTransport::next_incoming].
+///
+/// The connection is kept alive with the default [WsKeepalive], unless made with [WsTransport::connect_with_keepalive] or [WsTransport::from_stream_with_keepalive]. Requires the `websocket` feature, and a tokio runtime.
+pub struct WsTransport {
+    mux: Multiplexer,
+    #[cfg(feature = "compression")]
+    negotiation: Negotiation,
+}
+
+impl WsTransport {
+    /// Connects to a WebSocket server at a URL like `"ws://127.0.0.1:11223"`.
+    pub async fn connect(url: &str) -> Result<Self, tungstenite::Error> {
+        Self::connect_with_keepalive(url, Some(WsKeepalive::default())).await
+    }
+
+    /// Connects to a WebSocket server, keeping the connection alive as given, or not at all with `None`.
+    pub async fn connect_with_keepalive(
+        url: &str,
+        keepalive: Option<WsKeepalive>,
+    ) -> Result<Self, tungstenite::Error> {
+        let (ws, _) = tokio_tungstenite::connect_async(url).await?;
+        Ok(Self::from_stream_with_keepalive(ws, keepalive))
+    }
+
+    /// Makes calls over an already established WebSocket.
+    pub fn from_stream<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+        ws: WebSocketStream<S>,
+    ) -> Self {
+        Self::from_stream_with_keepalive(ws, Some(WsKeepalive::default()))
+    }
+
+    /// Makes calls over an already established WebSocket, keeping it alive as given, or not at all with `None`.
+    pub fn from_stream_with_keepalive<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+        ws: WebSocketStream<S>,
+        keepalive: Option<WsKeepalive>,
+    ) -> Self {
+        let (sink, stream) = frames(ws, keepalive);
+        #[cfg(feature = "compression")]
+        {
+            // nothing is offered until with_compression is called
+            let negotiation = Negotiation::client(&[]);
+            let max_len = crate::Limits::default().max_message_size;
+            let (sink, stream) = compressed_frames(sink, stream, negotiation.clone(), max_len);
+            let (mux, driver) = Multiplexer::new(sink, stream);
+            tokio::spawn(driver);
+            Self { mux, negotiation }
+        }
+        #[cfg(not(feature = "compression"))]
+        {
+            let (mux, driver) = Multiplexer::new(sink, stream);
+            tokio::spawn(driver);
+            Self { mux }
+        }
+    }
+
+    /// Sets a timeout for every call.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.mux = self.mux.with_timeout(timeout);
+        self
+    }
+
+    /// Offers the server to compress large messages with one of the given algorithms, in order of preference. Must be called before the first call, since the offer goes out with it. Servers that don't support compression keep talking plain JSON. Requires the `compression` feature.
+    #[cfg(feature = "compression")]
+    pub fn with_compression(self, compression: &[Compression]) -> Self {
+        self.negotiation.set_supported(compression);
+        self
+    }
+
+    /// Waits for the next message from the server that is not a response, like a notification. Returns `None` once the connection is lost.
+    pub async fn next_incoming(&self) -> Option<JrpcMessage> {
+        self.mux.next_incoming().await
+    }
+}
+
+impl PersistentTransport for WsTransport {
+    fn is_closed(&self) -> bool {
+        self.mux.is_closed()
+    }
+}
+
+#[async_trait]
+impl RpcTransport for WsTransport {
+    type Error = MultiplexError;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        self.mux.call_raw(req).await
+    }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        self.mux.call_raw_batch(reqs).await
+    }
+}
+
+/// A WsServer accepts WebSocket connections, serving each with its own service made by a [SessionFactory], through [serve_connection]. Services can send notifications to their connection through the [crate::Notifier] in their [ConnInfo].
+///
+/// Connections are kept alive with the default [WsKeepalive], so that dead clients are noticed. Shutting down through the [ServeHandle] stops accepting connections, and waits for the open ones to close. Requires the `websocket` feature, and a tokio runtime.
+pub struct WsServer<F: SessionFactory> {
+    factory: F,
+    config: ServerConfig,
+    handle: ServeHandle,
+    keepalive: Option<WsKeepalive>,
+    #[cfg(feature = "compression")]
+    compression: Vec<Compression>,
+}
+
+impl<F: SessionFactory> WsServer<F> {
+    /// Creates a new WsServer.
+    pub fn new(factory: F) -> Self {
+        Self {
+            factory,
+            config: ServerConfig::default(),
+            handle: ServeHandle::new(),
+            keepalive: Some(WsKeepalive::default()),
+            #[cfg(feature = "compression")]
+            compression: vec![],
+        }
+    }
+
+    /// Sets the configuration used to handle requests.
+    pub fn with_config(mut self, config: ServerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Uses the given [ServeHandle], so that the server can be gracefully shut down through it.
+    pub fn with_handle(mut self, handle: ServeHandle) -> Self {
+        self.handle = handle;
+        self
+    }
+
+    /// Sets how connections are kept alive, or turns keepalive off with `None`.
+    pub fn with_keepalive(mut self, keepalive: Option<WsKeepalive>) -> Self {
+        self.keepalive = keepalive;
+        self
+    }
+
+    /// Compresses large messages to clients that offer one of the given algorithms, picking the client's favorite. Clients that don't offer any are served plain JSON. Requires the `compression` feature.
+    #[cfg(feature = "compression")]
+    pub fn with_compression(mut self, compression: &[Compression]) -> Self {
+        self.compression = compression.to_vec();
+        self
+    }
+
+    /// Serves connections on the given address until shut down through the [ServeHandle].
+    pub async fn serve(self, addr: SocketAddr) -> std::io::Result<()> {
+        self.serve_listener(std::net::TcpListener::bind(addr)?)
+            .await
+    }
+
+    /// Serves connections on an already bound listener until shut down through the [ServeHandle].
+    pub async fn serve_listener(self, listener: std::net::TcpListener) -> std::io::Result<()> {
+        let handle = self.handle.clone();
+        let this = Arc::new(self);
+        accept_tcp(listener, &handle, move |stream, conn| {
+            let this = this.clone();
+            async move {
+                let peer_addr = conn.peer_addr;
+                let ws = match tokio_tungstenite::accept_async(stream).await {
+                    Ok(ws) => ws,
+                    Err(err) => {
+                        log::debug!("WebSocket handshake with {:?} failed: {}", peer_addr, err);
+                        return;
+                    }
+                };
+                let (sink, stream) = frames(ws, this.keepalive);
+                #[cfg(feature = "compression")]
+                if !this.compression.is_empty() {
+                    let negotiation = Negotiation::server(&this.compression);
+                    let max_len = this.config.limits.max_message_size;
+                    let (sink, stream) = compressed_frames(sink, stream, negotiation, max_len);
+                    serve_connection(&this.factory, conn, sink, stream, &this.config).await;
+                    return;
+                }
+                serve_connection(&this.factory, conn, sink, stream, &this.config).await;
+            }
+        })
+        .await
+    }
+}
+
+/// Serves WebSocket connections on the given address, with the default configuration. See [WsServer].
+pub async fn serve_ws(addr: SocketAddr, factory: impl SessionFactory) -> std::io::Result<()> {
+    WsServer::new(factory).serve(addr).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::{
+        ConnInfo, FnService, JrpcMessage, MultiplexError, PersistentTransport, RpcTransport,
+        ServeHandle, WsKeepalive, WsServer, WsTransport,
+    };
+
+    #[tokio::test]
+    async fn test_websocket() {
+        let handle = ServeHandle::new();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(
+            WsServer::new(|conn: &ConnInfo| {
+                let notifier = conn.notifier.clone().unwrap();
+                FnService::new(move |method, params| {
+                    let method = method.to_string();
+                    let notifier = notifier.clone();
+                    async move {
+                        if method == "subscribe" {
+                            notifier.notify("update", params.clone()).await.unwrap();
+                        }
+                        Some(Ok(params[0].clone()))
+                    }
+                })
+            })
+            .with_handle(handle.clone())
+            .serve_listener(listener),
+        );
+        let transport = WsTransport::connect(&format!("ws://{}", addr))
+            .await
+            .unwrap()
+            .with_timeout(Duration::from_secs(5));
+        let (a, b) = futures_lite::future::zip(
+            transport.call("echo", &[1.into()]),
+            transport.call("subscribe", &[2.into()]),
+        )
+        .await;
+        assert_eq!(a.unwrap().unwrap().unwrap(), 1);
+        assert_eq!(b.unwrap().unwrap().unwrap(), 2);
+        assert!(matches!(
+            transport.next_incoming().await,
+            Some(JrpcMessage::Notification(n)) if n.method == "update"
+        ));
+        drop(transport);
+        assert!(handle.shutdown(Duration::from_secs(5)).await);
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_websocket_keepalive() {
+        // a server that stops reading right after the handshake, like one behind a dead NAT mapping
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let _ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            futures_lite::future::pending::<()>().await
+        });
+        let transport = WsTransport::connect_with_keepalive(
+            &format!("ws://{}", addr),
+            Some(WsKeepalive {
+                interval: Duration::from_millis(50),
+                timeout: Duration::from_millis(120),
+            }),
+        )
+        .await
+        .unwrap();
+        let start = Instant::now();
+        assert_eq!(
+            transport.call("echo", &[1.into()]).await.unwrap_err(),
+            MultiplexError::ConnectionLost
+        );
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(transport.is_closed());
+    }
+}
@@ -0,0 +1,245 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use thiserror::Error;
+use wtransport::{
+    endpoint::endpoint_side,
+    error::{ConnectingError, ConnectionError, StreamOpeningError},
+    ClientConfig, Connection, Endpoint,
+};
+
+use super::{
+    accept::accept_webtransport,
+    lines::{line_frames, line_multiplexer},
+};
+use crate::{
+    serve_connection, ConnInfo, JrpcMessage, JrpcRequest, JrpcResponse, Limits, MultiplexError,
+    Multiplexer, RpcTransport, ServeHandle, ServerConfig, SessionFactory,
+};
+
+pub use wtransport;
+
+/// An error returned when connecting a [WtTransport].
+#[derive(Error, Debug)]
+pub enum WtError {
+    #[error("could not set up endpoint: {0}")]
+    Endpoint(std::io::Error),
+    #[error("could not connect: {0}")]
+    Connect(#[from] ConnectingError),
+    #[error("connection failed: {0}")]
+    Connection(#[from] ConnectionError),
+    #[error("could not open stream: {0}")]
+    Stream(#[from] StreamOpeningError),
+}
+
+/// A WtTransport makes calls over a bidirectional stream of a WebTransport session, one JSON-RPC message per line, running many calls concurrently through a [Multiplexer]. Being QUIC-based, it avoids head-of-line blocking with other traffic of the session and survives network changes. Notifications from the server are available through [WtTransport::next_incoming].
+///
+/// This is experimental. Requires the `webtransport` feature, and a tokio runtime.
+pub struct WtTransport {
+    mux: Multiplexer,
+    conn: Connection,
+}
+
+impl WtTransport {
+    /// Connects to a WebTransport server at a URL like `"https://example.com:4433/rpc"`, with the given client configuration.
+    pub async fn connect(config: ClientConfig, url: &str) -> Result<Self, WtError> {
+        let endpoint = Endpoint::client(config).map_err(WtError::Endpoint)?;
+        let conn = endpoint.connect(url).await?;
+        let (send, recv) = conn.open_bi().await?.await?;
+        let limits = Limits::default();
+        Ok(Self {
+            mux: line_multiplexer(recv, send, limits, None),
+            conn,
+        })
+    }
+
+    /// Sets a timeout for every call.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self {
+            mux: self.mux.with_timeout(timeout),
+            conn: self.conn,
+        }
+    }
+
+    /// Returns the underlying WebTransport session, for things like its round-trip time.
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// Waits for the next message from the server that is not a response, like a notification. Returns `None` once the connection is lost.
+    pub async fn next_incoming(&self) -> Option<JrpcMessage> {
+        self.mux.next_incoming().await
+    }
+}
+
+#[async_trait]
+impl RpcTransport for WtTransport {
+    type Error = MultiplexError;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        self.mux.call_raw(req).await
+    }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        self.mux.call_raw_batch(reqs).await
+    }
+}
+
+/// A WtServer accepts WebTransport sessions, and serves every bidirectional stream that clients open in them with its own service made by a [SessionFactory], through [serve_connection]. Each stream carries one JSON-RPC message per line, so browsers can use it through the `WebTransport` API.
+///
+/// Shutting down through the [ServeHandle] stops accepting sessions, and waits for the open streams to close. This is experimental. Requires the `webtransport` feature, and a tokio runtime.
+pub struct WtServer<F: SessionFactory> {
+    factory: F,
+    config: ServerConfig,
+    handle: ServeHandle,
+}
+
+impl<F: SessionFactory> WtServer<F> {
+    /// Creates a new WtServer.
+    pub fn new(factory: F) -> Self {
+        Self {
+            factory,
+            config: ServerConfig::default(),
+            handle: ServeHandle::new(),
+        }
+    }
+
+    /// Sets the configuration used to handle requests.
+    pub fn with_config(mut self, config: ServerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Uses the given [ServeHandle], so that the server can be gracefully shut down through it.
+    pub fn with_handle(mut self, handle: ServeHandle) -> Self {
+        self.handle = handle;
+        self
+    }
+
+    /// Serves sessions with the given endpoint configuration, which holds the address and the TLS identity, until shut down through the [ServeHandle].
+    pub async fn serve(self, config: wtransport::ServerConfig) -> std::io::Result<()> {
+        self.serve_endpoint(Endpoint::server(config)?).await
+    }
+
+    /// Serves sessions on an already created endpoint until shut down through the [ServeHandle].
+    pub async fn serve_endpoint(
+        self,
+        endpoint: Endpoint<endpoint_side::Server>,
+    ) -> std::io::Result<()> {
+        let handle = self.handle.clone();
+        let this = Arc::new(self);
+        accept_webtransport(&endpoint, &handle, move |incoming, _| {
+            let this = this.clone();
+            async move {
+                let session = match incoming.await {
+                    Ok(request) => request.accept().await,
+                    Err(err) => Err(err),
+                };
+                let session = match session {
+                    Ok(session) => session,
+                    Err(err) => {
+                        log::debug!("WebTransport handshake failed: {}", err);
+                        return;
+                    }
+                };
+                let peer_addr = session.remote_address();
+                let shutdown = this.handle.shutdown_signal();
+                loop {
+                    let accepted =
+                        futures_lite::future::or(async { session.accept_bi().await.ok() }, async {
+                            shutdown.wait().await;
+                            None
+                        })
+                        .await;
+                    let Some((send, recv)) = accepted else {
+                        return;
+                    };
+                    let conn = ConnInfo::new().with_peer_addr(peer_addr);
+                    let this = this.clone();
+                    let handle = this.handle.clone();
+                    tokio::spawn(async move {
+                        let (sink, stream) =
+                            line_frames(recv, send, this.config.limits.max_message_size);
+                        handle
+                            .track(serve_connection(
+                                &this.factory,
+                                conn,
+                                sink,
+                                stream,
+                                &this.config,
+                            ))
+                            .await;
+                    });
+                }
+            }
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        wtransport, ConnInfo, FnService, JrpcMessage, RpcTransport, ServeHandle, WtServer,
+        WtTransport,
+    };
+
+    #[tokio::test]
+    async fn test_webtransport() {
+        let identity = wtransport::Identity::self_signed(["localhost"]).unwrap();
+        let cert_hash = identity.certificate_chain().as_slice()[0].hash();
+        let endpoint = wtransport::Endpoint::server(
+            wtransport::ServerConfig::builder()
+                .with_bind_address("127.0.0.1:0".parse().unwrap())
+                .with_identity(&identity)
+                .build(),
+        )
+        .unwrap();
+        let port = endpoint.local_addr().unwrap().port();
+        let handle = ServeHandle::new();
+        let server = tokio::spawn(
+            WtServer::new(|conn: &ConnInfo| {
+                let notifier = conn.notifier.clone().unwrap();
+                FnService::new(move |method, params| {
+                    let method = method.to_string();
+                    let notifier = notifier.clone();
+                    async move {
+                        if method == "subscribe" {
+                            notifier.notify("update", params.clone()).await.unwrap();
+                        }
+                        Some(Ok(params[0].clone()))
+                    }
+                })
+            })
+            .with_handle(handle.clone())
+            .serve_endpoint(endpoint),
+        );
+        let config = wtransport::ClientConfig::builder()
+            .with_bind_default()
+            .with_server_certificate_hashes([cert_hash])
+            .build();
+        let transport = WtTransport::connect(config, &format!("https://localhost:{}", port))
+            .await
+            .unwrap()
+            .with_timeout(Duration::from_secs(5));
+        let (a, b) = futures_lite::future::zip(
+            transport.call("echo", &[1.into()]),
+            transport.call("subscribe", &[2.into()]),
+        )
+        .await;
+        assert_eq!(a.unwrap().unwrap().unwrap(), 1);
+        assert_eq!(b.unwrap().unwrap().unwrap(), 2);
+        assert!(matches!(
+            transport.next_incoming().await,
+            Some(JrpcMessage::Notification(n)) if n.method == "update"
+        ));
+        transport.connection().close(0u32.into(), b"");
+        assert!(handle.shutdown(Duration::from_secs(5)).await);
+        server.await.unwrap().unwrap();
+    }
+}
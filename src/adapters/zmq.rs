@@ -0,0 +1,308 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use thiserror::Error;
+use zeromq::{Socket, SocketRecv, SocketSend, ZmqMessage};
+
+use crate::{
+    JrpcMessage, JrpcRequest, JrpcResponse, MultiplexError, Multiplexer, RpcService, RpcTransport,
+    ServeHandle, ServerConfig,
+};
+
+/// An error returned by the ZeroMQ transports.
+#[derive(Error, Debug)]
+pub enum ZeromqError {
+    #[error("ZeroMQ error: {0}")]
+    Zmq(#[from] zeromq::ZmqError),
+    #[error("could not decode response: {0}")]
+    Decode(#[from] serde_json::Error),
+    #[error(transparent)]
+    Multiplex(#[from] MultiplexError),
+}
+
+/// Something that happened on a socket shared between sending and receiving.
+enum Event<T> {
+    Send(T),
+    Recv(ZmqMessage),
+}
+
+/// Returns the payload of a message, which is its last frame. The frames before it are the envelope.
+fn payload(msg: &ZmqMessage) -> &[u8] {
+    msg.get(msg.len() - 1)
+        .map(|frame| frame.as_ref())
+        .unwrap_or(&[])
+}
+
+/// A ZmqReqTransport makes calls over a ZeroMQ REQ socket, one at a time. It works with any REP or ROUTER server, like a [ZmqServer].
+///
+/// A call that is cancelled halfway leaves the socket waiting for a response that will never be read, so timeouts should be left to the server. For concurrent calls, use a [ZmqDealerTransport]. Requires the `zeromq` feature, and a tokio runtime.
+pub struct ZmqReqTransport {
+    socket: async_lock::Mutex<zeromq::ReqSocket>,
+}
+
+impl ZmqReqTransport {
+    /// Connects to an endpoint like `"tcp://127.0.0.1:5555"`.
+    pub async fn connect(endpoint: &str) -> Result<Self, ZeromqError> {
+        let mut socket = zeromq::ReqSocket::new();
+        socket.connect(endpoint).await?;
+        Ok(Self {
+            socket: async_lock::Mutex::new(socket),
+        })
+    }
+
+    async fn roundtrip<T: serde::de::DeserializeOwned>(
+        &self,
+        msg: Vec<u8>,
+    ) -> Result<T, ZeromqError> {
+        let mut socket = self.socket.lock().await;
+        socket.send(ZmqMessage::from(msg)).await?;
+        let resp = socket.recv().await?;
+        Ok(serde_json::from_slice(payload(&resp))?)
+    }
+}
+
+#[async_trait]
+impl RpcTransport for ZmqReqTransport {
+    type Error = ZeromqError;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        self.roundtrip(serde_json::to_vec(&req)?).await
+    }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        if reqs.is_empty() {
+            return Ok(vec![]);
+        }
+        self.roundtrip(serde_json::to_vec(&reqs)?).await
+    }
+}
+
+/// A ZmqDealerTransport makes calls over a ZeroMQ DEALER socket, running many calls concurrently through a [Multiplexer]. Messages carry the same envelope as those of a REQ socket, so it works with any REP or ROUTER server, like a [ZmqServer], although REP servers answer one call at a time.
+///
+/// Requires the `zeromq` feature, and a tokio runtime.
+pub struct ZmqDealerTransport {
+    mux: Multiplexer,
+}
+
+impl ZmqDealerTransport {
+    /// Connects to an endpoint like `"tcp://127.0.0.1:5555"`.
+    pub async fn connect(endpoint: &str) -> Result<Self, ZeromqError> {
+        let mut socket = zeromq::DealerSocket::new();
+        socket.connect(endpoint).await?;
+        let (send_outgoing, recv_outgoing) = async_channel::unbounded::<String>();
+        let (send_incoming, recv_incoming) = async_channel::unbounded::<String>();
+        tokio::spawn(async move {
+            loop {
+                // receiving from a socket can be cancelled without losing messages
+                let event = futures_lite::future::or(
+                    async { Some(Event::Send(recv_outgoing.recv().await.ok()?)) },
+                    async { Some(Event::Recv(socket.recv().await.ok()?)) },
+                )
+                .await;
+                match event {
+                    Some(Event::Send(msg)) => {
+                        // the empty delimiter frame of a REQ envelope
+                        let mut out = ZmqMessage::from(Vec::<u8>::new());
+                        out.push_back(msg.into_bytes().into());
+                        if let Err(err) = socket.send(out).await {
+                            log::debug!("DEALER socket failed to send: {}", err);
+                            return;
+                        }
+                    }
+                    Some(Event::Recv(msg)) => {
+                        let msg = String::from_utf8_lossy(payload(&msg)).into_owned();
+                        if send_incoming.send(msg).await.is_err() {
+                            return;
+                        }
+                    }
+                    None => return,
+                }
+            }
+        });
+        let sink = futures_util::sink::unfold(send_outgoing, |send, msg: String| async move {
+            send.send(msg)
+                .await
+                .map_err(|_| MultiplexError::ConnectionLost)?;
+            Ok::<_, MultiplexError>(send)
+        });
+        let (mux, driver) = Multiplexer::new(Box::pin(sink), recv_incoming);
+        tokio::spawn(driver);
+        Ok(Self { mux })
+    }
+
+    /// Sets a timeout for every call.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self {
+            mux: self.mux.with_timeout(timeout),
+        }
+    }
+
+    /// Waits for the next message from the server that is not a response. Returns `None` once the socket is closed.
+    pub async fn next_incoming(&self) -> Option<JrpcMessage> {
+        self.mux.next_incoming().await
+    }
+}
+
+#[async_trait]
+impl RpcTransport for ZmqDealerTransport {
+    type Error = ZeromqError;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        Ok(self.mux.call_raw(req).await?)
+    }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        Ok(self.mux.call_raw_batch(reqs).await?)
+    }
+}
+
+/// A ZmqServer serves a service on a ZeroMQ ROUTER socket, handling calls from any number of REQ and DEALER clients concurrently.
+///
+/// Shutting down through the [ServeHandle] stops taking new calls, and waits for the ones in flight. Requires the `zeromq` feature, and a tokio runtime.
+pub struct ZmqServer<S: RpcService> {
+    service: Arc<S>,
+    config: Arc<ServerConfig>,
+    handle: ServeHandle,
+}
+
+impl<S: RpcService> ZmqServer<S> {
+    /// Creates a new ZmqServer.
+    pub fn new(service: S) -> Self {
+        Self {
+            service: Arc::new(service),
+            config: Arc::new(ServerConfig::default()),
+            handle: ServeHandle::new(),
+        }
+    }
+
+    /// Sets the configuration used to handle requests.
+    pub fn with_config(mut self, config: ServerConfig) -> Self {
+        self.config = Arc::new(config);
+        self
+    }
+
+    /// Uses the given [ServeHandle], so that the server can be gracefully shut down through it.
+    pub fn with_handle(mut self, handle: ServeHandle) -> Self {
+        self.handle = handle;
+        self
+    }
+
+    /// Serves calls on an endpoint like `"tcp://0.0.0.0:5555"` until shut down through the [ServeHandle].
+    pub async fn serve(self, endpoint: &str) -> Result<(), ZeromqError> {
+        self.bind(endpoint).await?.1.await;
+        Ok(())
+    }
+
+    /// Binds to an endpoint, returning the endpoint actually bound, which differs from the given one for port 0, together with a future that serves calls until shut down through the [ServeHandle].
+    pub async fn bind(
+        self,
+        endpoint: &str,
+    ) -> Result<(String, impl Future<Output = ()> + Send + 'static), ZeromqError> {
+        let mut socket = zeromq::RouterSocket::new();
+        let bound = socket.bind(endpoint).await?.to_string();
+        let serve = async move {
+            let shutdown = self.handle.shutdown_signal();
+            let (send_reply, recv_reply) = async_channel::unbounded::<ZmqMessage>();
+            loop {
+                let event = futures_lite::future::or(
+                    async {
+                        shutdown.wait().await;
+                        None
+                    },
+                    futures_lite::future::or(
+                        async { Some(Event::Send(recv_reply.recv().await.ok()?)) },
+                        async { Some(Event::Recv(socket.recv().await.ok()?)) },
+                    ),
+                )
+                .await;
+                match event {
+                    Some(Event::Send(reply)) => {
+                        if let Err(err) = socket.send(reply).await {
+                            log::debug!("could not send reply: {}", err);
+                        }
+                    }
+                    Some(Event::Recv(msg)) => {
+                        let service = self.service.clone();
+                        let config = self.config.clone();
+                        let send_reply = send_reply.clone();
+                        let handle = self.handle.clone();
+                        tokio::spawn(async move {
+                            handle
+                                .track(async move {
+                                    let resp = service.respond_bytes(payload(&msg), &config).await;
+                                    // the reply goes back with the same envelope
+                                    let mut reply = msg;
+                                    reply.split_off(reply.len() - 1);
+                                    reply.push_back(resp.into());
+                                    let _ = send_reply.send(reply).await;
+                                })
+                                .await;
+                        });
+                    }
+                    None => break,
+                }
+            }
+            // let the calls in flight finish and send their replies
+            drop(send_reply);
+            while let Ok(reply) = recv_reply.recv().await {
+                let _ = socket.send(reply).await;
+            }
+        };
+        Ok((bound, serve))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        FnService, RpcTransport, ServeHandle, ZmqDealerTransport, ZmqReqTransport, ZmqServer,
+    };
+
+    #[tokio::test]
+    async fn test_zmq() {
+        let service = FnService::new(|_, params| async move {
+            let delay = params[0].as_u64().unwrap();
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+            Some(Ok(delay.into()))
+        });
+        let handle = ServeHandle::new();
+        let (endpoint, serve) = ZmqServer::new(service)
+            .with_handle(handle.clone())
+            .bind("tcp://127.0.0.1:0")
+            .await
+            .unwrap();
+        let server = tokio::spawn(serve);
+
+        let req = ZmqReqTransport::connect(&endpoint).await.unwrap();
+        assert_eq!(
+            req.call("f", &[1.into()]).await.unwrap().unwrap().unwrap(),
+            1
+        );
+
+        // the slow call doesn't hold up the fast one
+        let dealer = ZmqDealerTransport::connect(&endpoint)
+            .await
+            .unwrap()
+            .with_timeout(Duration::from_secs(5));
+        let slow = async { dealer.call("f", &[200.into()]).await };
+        let fast = async {
+            let resp = dealer.call("f", &[1.into()]).await;
+            (resp, std::time::Instant::now())
+        };
+        let (slow, (fast, fast_done)) = futures_lite::future::zip(slow, fast).await;
+        assert_eq!(slow.unwrap().unwrap().unwrap(), 200);
+        assert_eq!(fast.unwrap().unwrap().unwrap(), 1);
+        assert!(fast_done.elapsed() >= Duration::from_millis(100));
+
+        assert!(handle.shutdown(Duration::from_secs(5)).await);
+        server.await.unwrap();
+    }
+}
@@ -0,0 +1,114 @@
+use crate::{JrpcRequest, JrpcResponse, RpcService, RpcTransport, ServerError};
+
+/// A BlockingTransport wraps an [RpcTransport], offering blocking versions of its methods for synchronous code, like build scripts, FFI callbacks, or `Drop` implementations. The calls are driven to completion on the current thread, so no async runtime is needed.
+///
+/// Transports that depend on a particular runtime's reactor (say, tokio sockets) still need that runtime to be running in the background.
+pub struct BlockingTransport<T: RpcTransport>(pub T);
+
+impl<T: RpcTransport> BlockingTransport<T> {
+    /// Creates a new BlockingTransport.
+    pub fn new(t: T) -> Self {
+        Self(t)
+    }
+
+    /// Blocking version of [RpcTransport::call].
+    pub fn call(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Result<Option<Result<serde_json::Value, ServerError>>, T::Error> {
+        futures_lite::future::block_on(self.0.call(method, params))
+    }
+
+    /// Blocking version of [RpcTransport::call_raw].
+    pub fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, T::Error> {
+        futures_lite::future::block_on(self.0.call_raw(req))
+    }
+
+    /// Blocking version of [RpcTransport::call_batch].
+    pub fn call_batch(&self, reqs: Vec<JrpcRequest>) -> Result<Vec<JrpcResponse>, T::Error> {
+        futures_lite::future::block_on(self.0.call_batch(reqs))
+    }
+}
+
+/// A BlockingService wraps an [RpcService], offering blocking versions of its methods for synchronous servers.
+pub struct BlockingService<S: RpcService>(pub S);
+
+impl<S: RpcService> BlockingService<S> {
+    /// Creates a new BlockingService.
+    pub fn new(s: S) -> Self {
+        Self(s)
+    }
+
+    /// Blocking version of [RpcService::respond].
+    pub fn respond(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        futures_lite::future::block_on(self.0.respond(method, params))
+    }
+
+    /// Blocking version of [RpcService::respond_raw].
+    pub fn respond_raw(&self, jrpc_req: JrpcRequest) -> JrpcResponse {
+        futures_lite::future::block_on(self.0.respond_raw(jrpc_req))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BlockingService, BlockingTransport};
+    use crate::{FnService, FnTransport, JrpcId, JrpcRequest};
+
+    fn service() -> FnService {
+        FnService::new(|method, params| {
+            let method = method.to_string();
+            async move { (method == "double").then(|| Ok((params[0].as_u64().unwrap() * 2).into())) }
+        })
+    }
+
+    fn request(id: i64, x: u64) -> JrpcRequest {
+        JrpcRequest {
+            jsonrpc: "2.0".into(),
+            method: "double".into(),
+            params: vec![x.into()],
+            id: JrpcId::Number(id),
+            meta: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_blocking_service() {
+        let service = BlockingService::new(service());
+        assert_eq!(service.respond("double", &[2.into()]).unwrap().unwrap(), 4);
+        assert!(service.respond("triple", &[2.into()]).is_none());
+        let resp = service.respond_raw(request(7, 5));
+        assert_eq!(resp.id, JrpcId::Number(7));
+        assert_eq!(resp.result.unwrap(), 10);
+    }
+
+    #[test]
+    fn test_blocking_transport() {
+        let service = service();
+        let transport =
+            BlockingTransport::new(FnTransport::<std::convert::Infallible>::loopback(service));
+        assert_eq!(
+            transport
+                .call("double", &[3.into()])
+                .unwrap()
+                .unwrap()
+                .unwrap(),
+            6
+        );
+        assert!(transport.call("triple", &[3.into()]).unwrap().is_none());
+        assert_eq!(
+            transport.call_raw(request(1, 4)).unwrap().result.unwrap(),
+            8
+        );
+        let resps = transport
+            .call_batch(vec![request(1, 1), request(2, 2), request(3, 3)])
+            .unwrap();
+        let results: Vec<_> = resps.into_iter().map(|r| r.result.unwrap()).collect();
+        assert_eq!(results, vec![2, 4, 6]);
+    }
+}
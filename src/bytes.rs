@@ -0,0 +1,198 @@
+use std::{
+    fmt::{Debug, Formatter},
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+};
+
+use base64::Engine;
+use serde::{de::Visitor, Deserialize, Deserializer, Serialize, Serializer};
+
+/// A way of encoding binary data as a JSON string, used by [Bytes].
+pub trait BytesEncoding: Send + Sync + 'static {
+    /// Encodes the bytes into a string.
+    fn encode(bytes: &[u8]) -> String;
+
+    /// Decodes a string back into bytes, returning a description of the problem on failure.
+    fn decode(s: &str) -> Result<Vec<u8>, String>;
+}
+
+/// Standard, padded base64. This is the default encoding of [Bytes].
+pub struct Base64Encoding;
+
+impl BytesEncoding for Base64Encoding {
+    fn encode(bytes: &[u8]) -> String {
+        base64::engine::general_purpose::STANDARD.encode(bytes)
+    }
+
+    fn decode(s: &str) -> Result<Vec<u8>, String> {
+        base64::engine::general_purpose::STANDARD
+            .decode(s)
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Lowercase hexadecimal.
+pub struct HexEncoding;
+
+impl BytesEncoding for HexEncoding {
+    fn encode(bytes: &[u8]) -> String {
+        hex::encode(bytes)
+    }
+
+    fn decode(s: &str) -> Result<Vec<u8>, String> {
+        hex::decode(s).map_err(|e| e.to_string())
+    }
+}
+
+/// Binary data that is transmitted as a string in JSON-RPC params and results, rather than as a JSON array of numbers. By default, the string is base64-encoded; [HexBytes] uses hex instead.
+///
+/// # Example
+///
+/// ```
+/// use nanorpc::{Bytes, HexBytes};
+///
+/// let b64: Bytes = vec![1, 2, 3].into();
+/// assert_eq!(serde_json::to_value(&b64).unwrap(), "AQID");
+/// let hex: HexBytes = vec![1, 2, 3].into();
+/// assert_eq!(serde_json::to_value(&hex).unwrap(), "010203");
+/// ```
+pub struct Bytes<E: BytesEncoding = Base64Encoding> {
+    inner: Vec<u8>,
+    _encoding: PhantomData<E>,
+}
+
+/// Binary data that is transmitted as a hex string.
+pub type HexBytes = Bytes<HexEncoding>;
+
+impl<E: BytesEncoding> Bytes<E> {
+    /// Creates a new Bytes wrapping the given data.
+    pub fn new(inner: Vec<u8>) -> Self {
+        Self {
+            inner,
+            _encoding: PhantomData,
+        }
+    }
+
+    /// Returns the underlying data.
+    pub fn into_inner(self) -> Vec<u8> {
+        self.inner
+    }
+}
+
+impl<E: BytesEncoding> From<Vec<u8>> for Bytes<E> {
+    fn from(inner: Vec<u8>) -> Self {
+        Self::new(inner)
+    }
+}
+
+impl<E: BytesEncoding> From<&[u8]> for Bytes<E> {
+    fn from(inner: &[u8]) -> Self {
+        Self::new(inner.to_vec())
+    }
+}
+
+impl<E: BytesEncoding> From<Bytes<E>> for Vec<u8> {
+    fn from(bytes: Bytes<E>) -> Self {
+        bytes.inner
+    }
+}
+
+impl<E: BytesEncoding> Deref for Bytes<E> {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<E: BytesEncoding> DerefMut for Bytes<E> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+impl<E: BytesEncoding> AsRef<[u8]> for Bytes<E> {
+    fn as_ref(&self) -> &[u8] {
+        &self.inner
+    }
+}
+
+impl<E: BytesEncoding> Clone for Bytes<E> {
+    fn clone(&self) -> Self {
+        Self::new(self.inner.clone())
+    }
+}
+
+impl<E: BytesEncoding> Default for Bytes<E> {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+impl<E: BytesEncoding> PartialEq for Bytes<E> {
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl<E: BytesEncoding> Eq for Bytes<E> {}
+
+impl<E: BytesEncoding> Hash for Bytes<E> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.inner.hash(state)
+    }
+}
+
+impl<E: BytesEncoding> Debug for Bytes<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Bytes({})", E::encode(&self.inner))
+    }
+}
+
+impl<E: BytesEncoding> Serialize for Bytes<E> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&E::encode(&self.inner))
+    }
+}
+
+impl<'de, E: BytesEncoding> Deserialize<'de> for Bytes<E> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct EncodedVisitor<E>(PhantomData<E>);
+
+        impl<'de, E: BytesEncoding> Visitor<'de> for EncodedVisitor<E> {
+            type Value = Bytes<E>;
+
+            fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+                f.write_str("an encoded byte string")
+            }
+
+            // borrowed and owned strings both end up here, so we decode straight from the input without copying it first
+            fn visit_str<Er: serde::de::Error>(self, v: &str) -> Result<Self::Value, Er> {
+                E::decode(v).map(Bytes::new).map_err(Er::custom)
+            }
+        }
+
+        deserializer.deserialize_str(EncodedVisitor(PhantomData))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Bytes, HexBytes};
+
+    #[test]
+    fn test_bytes_roundtrip() {
+        let data = vec![0u8, 1, 254, 255];
+        let b64: Bytes = data.clone().into();
+        let hex: HexBytes = data.clone().into();
+        assert_eq!(serde_json::to_string(&b64).unwrap(), "\"AAH+/w==\"");
+        assert_eq!(serde_json::to_string(&hex).unwrap(), "\"0001feff\"");
+        assert_eq!(serde_json::from_str::<Bytes>("\"AAH+/w==\"").unwrap(), b64);
+        assert_eq!(
+            serde_json::from_value::<HexBytes>("0001feff".into()).unwrap(),
+            hex
+        );
+        assert!(serde_json::from_str::<HexBytes>("\"zz\"").is_err());
+    }
+}
@@ -0,0 +1,206 @@
+use std::fmt::Debug;
+
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+
+use crate::{JrpcRequest, JrpcResponse};
+
+/// An error from decoding a message with a [Codec].
+#[derive(Error, Debug)]
+pub enum CodecError {
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("{0}")]
+    Other(String),
+}
+
+/// A Codec turns JSON-RPC messages into bytes and back, for the bytes-level server entry points, like [crate::RpcService::respond_bytes] through [crate::ServerConfig::codec], and the transports that deal in bytes, like [crate::HttpTransport]. The default is [JsonCodec].
+///
+/// Only [Codec::encode_value] and [Codec::decode_value] need to be implemented, since every message is also a JSON value; the other methods go through them by default, and can be overridden to skip the detour. A batch is a JSON array of messages.
+pub trait Codec: Send + Sync + 'static {
+    /// The MIME type of encoded messages, for transports like HTTP that label them.
+    fn content_type(&self) -> &'static str;
+
+    /// Encodes a message, or a batch of messages.
+    fn encode_value(&self, msg: &serde_json::Value) -> Vec<u8>;
+
+    /// Decodes a message, or a batch of messages.
+    fn decode_value(&self, bytes: &[u8]) -> Result<serde_json::Value, CodecError>;
+
+    /// Returns how deeply arrays and objects nest in an encoded message, if that can be told without decoding it, so that messages exceeding [crate::Limits::max_depth] are rejected before the work of decoding them. By default returns `None`, and the depth is checked after decoding instead.
+    fn nesting_depth(&self, bytes: &[u8]) -> Option<usize> {
+        let _ = bytes;
+        None
+    }
+
+    /// Encodes a request.
+    fn encode_request(&self, req: &JrpcRequest) -> Vec<u8> {
+        self.encode_value(&to_value(req))
+    }
+
+    /// Encodes a batch of requests.
+    fn encode_requests(&self, reqs: &[JrpcRequest]) -> Vec<u8> {
+        self.encode_value(&to_value(reqs))
+    }
+
+    /// Decodes a request.
+    fn decode_request(&self, bytes: &[u8]) -> Result<JrpcRequest, CodecError> {
+        from_value(self.decode_value(bytes)?)
+    }
+
+    /// Encodes a response.
+    fn encode_response(&self, resp: &JrpcResponse) -> Vec<u8> {
+        self.encode_value(&to_value(resp))
+    }
+
+    /// Encodes a batch of responses.
+    fn encode_responses(&self, resps: &[JrpcResponse]) -> Vec<u8> {
+        self.encode_value(&to_value(resps))
+    }
+
+    /// Decodes a response.
+    fn decode_response(&self, bytes: &[u8]) -> Result<JrpcResponse, CodecError> {
+        from_value(self.decode_value(bytes)?)
+    }
+
+    /// Decodes a batch of responses.
+    fn decode_responses(&self, bytes: &[u8]) -> Result<Vec<JrpcResponse>, CodecError> {
+        from_value(self.decode_value(bytes)?)
+    }
+}
+
+impl Debug for dyn Codec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Codec")
+            .field("content_type", &self.content_type())
+            .finish()
+    }
+}
+
+fn to_value<T: Serialize + ?Sized>(msg: &T) -> serde_json::Value {
+    serde_json::to_value(msg).expect("JSON-RPC messages are always valid JSON")
+}
+
+fn from_value<T: DeserializeOwned>(value: serde_json::Value) -> Result<T, CodecError> {
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Plain JSON, which is what JSON-RPC is. This is the default [Codec].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JsonCodec;
+
+impl Codec for JsonCodec {
+    fn content_type(&self) -> &'static str {
+        "application/json"
+    }
+
+    fn encode_value(&self, msg: &serde_json::Value) -> Vec<u8> {
+        serde_json::to_vec(msg).unwrap()
+    }
+
+    fn decode_value(&self, bytes: &[u8]) -> Result<serde_json::Value, CodecError> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+
+    fn nesting_depth(&self, bytes: &[u8]) -> Option<usize> {
+        Some(crate::server::nesting_depth(bytes))
+    }
+
+    fn encode_request(&self, req: &JrpcRequest) -> Vec<u8> {
+        serde_json::to_vec(req).unwrap()
+    }
+
+    fn encode_requests(&self, reqs: &[JrpcRequest]) -> Vec<u8> {
+        serde_json::to_vec(reqs).unwrap()
+    }
+
+    fn decode_request(&self, bytes: &[u8]) -> Result<JrpcRequest, CodecError> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+
+    fn encode_response(&self, resp: &JrpcResponse) -> Vec<u8> {
+        serde_json::to_vec(resp).unwrap()
+    }
+
+    fn encode_responses(&self, resps: &[JrpcResponse]) -> Vec<u8> {
+        serde_json::to_vec(resps).unwrap()
+    }
+
+    fn decode_response(&self, bytes: &[u8]) -> Result<JrpcResponse, CodecError> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+
+    fn decode_responses(&self, bytes: &[u8]) -> Result<Vec<JrpcResponse>, CodecError> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{Codec, CodecError};
+    use crate::{FnService, JrpcId, JrpcRequest, Limits, RpcService, ServerConfig};
+
+    /// JSON written out as hex, which only shares the data model with JSON.
+    struct HexJson;
+
+    impl Codec for HexJson {
+        fn content_type(&self) -> &'static str {
+            "text/x-hex-json"
+        }
+
+        fn encode_value(&self, msg: &serde_json::Value) -> Vec<u8> {
+            hex::encode(serde_json::to_vec(msg).unwrap()).into_bytes()
+        }
+
+        fn decode_value(&self, bytes: &[u8]) -> Result<serde_json::Value, CodecError> {
+            let json = hex::decode(bytes).map_err(|err| CodecError::Other(err.to_string()))?;
+            Ok(serde_json::from_slice(&json)?)
+        }
+    }
+
+    #[test]
+    fn test_codec() {
+        smol::future::block_on(async move {
+            let service = FnService::new(|_, params| async move { Some(Ok(params[0].clone())) });
+            let config = ServerConfig {
+                limits: Limits {
+                    max_depth: 4,
+                    ..Default::default()
+                },
+                codec: Arc::new(HexJson),
+                ..Default::default()
+            };
+            let req = |param: serde_json::Value| JrpcRequest {
+                jsonrpc: "2.0".into(),
+                method: "echo".into(),
+                params: vec![param],
+                id: JrpcId::Number(1),
+                meta: Default::default(),
+            };
+            let codec = &*config.codec;
+            let resp = service
+                .respond_bytes(&codec.encode_request(&req("hi".into())), &config)
+                .await;
+            let resp = codec.decode_response(&resp).unwrap();
+            assert_eq!(resp.result.unwrap(), "hi");
+            let batch = codec.encode_requests(&[req(1.into()), req(2.into())]);
+            let resps = codec
+                .decode_responses(&service.respond_bytes(&batch, &config).await)
+                .unwrap();
+            assert_eq!(resps[1].result.as_ref().unwrap(), 2);
+
+            // the depth is checked once decoded, since hex hides it
+            let deep = codec.encode_request(&req(serde_json::json!([[[[1]]]])));
+            let resp = codec
+                .decode_response(&service.respond_bytes(&deep, &config).await)
+                .unwrap();
+            assert_eq!(resp.error.unwrap().code, -32600);
+            let resp = codec
+                .decode_response(&service.respond_bytes(b"{}", &config).await)
+                .unwrap();
+            assert_eq!(resp.error.unwrap().code, -32700);
+        });
+    }
+}
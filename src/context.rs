@@ -0,0 +1,112 @@
+use std::{
+    any::{Any, TypeId},
+    collections::{BTreeMap, HashMap},
+    fmt::Debug,
+    sync::Arc,
+};
+
+/// Per-request information available to services through [crate::RpcService::respond_with_context], beyond the method and params.
+#[derive(Clone, Default)]
+pub struct RpcContext {
+    /// The metadata the client attached to the request, like trace IDs or auth tokens. Since it comes from the client, it should not be trusted blindly.
+    pub meta: BTreeMap<String, serde_json::Value>,
+    extensions: HashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+}
+
+impl RpcContext {
+    /// Creates an empty context.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attaches a typed value to the context, replacing any previous value of the same type. Server adapters use this to pass along information that does not come from the client, like the peer address or an authenticated identity.
+    pub fn insert_extension<T: Send + Sync + 'static>(&mut self, value: T) {
+        self.extensions.insert(TypeId::of::<T>(), Arc::new(value));
+    }
+
+    /// Gets the value of the given type attached to the context, if any.
+    pub fn extension<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.extensions
+            .get(&TypeId::of::<T>())
+            .and_then(|v| v.downcast_ref())
+    }
+}
+
+impl Debug for RpcContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RpcContext")
+            .field("meta", &self.meta)
+            .field("extensions", &self.extensions.len())
+            .finish()
+    }
+}
+
+/// Options for a single call made through [crate::RpcTransport::call_with_opts].
+#[derive(Clone, Debug, Default)]
+pub struct CallOptions {
+    /// Metadata to attach to the request, which the server sees as [RpcContext::meta].
+    pub meta: BTreeMap<String, serde_json::Value>,
+}
+
+impl CallOptions {
+    /// Adds a metadata entry.
+    pub fn with_meta(
+        mut self,
+        key: impl Into<String>,
+        value: impl Into<serde_json::Value>,
+    ) -> Self {
+        self.meta.insert(key.into(), value.into());
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+
+    use crate::{JrpcRequest, RpcContext, RpcService, ServerError};
+
+    struct TraceService;
+
+    #[async_trait]
+    impl RpcService for TraceService {
+        async fn respond(
+            &self,
+            method: &str,
+            params: &[serde_json::Value],
+        ) -> Option<Result<serde_json::Value, ServerError>> {
+            self.respond_with_context(&RpcContext::default(), method, params)
+                .await
+        }
+
+        async fn respond_with_context(
+            &self,
+            ctx: &RpcContext,
+            _method: &str,
+            _params: &[serde_json::Value],
+        ) -> Option<Result<serde_json::Value, ServerError>> {
+            let peer = ctx.extension::<String>().cloned().unwrap_or_default();
+            Some(Ok(serde_json::json!([ctx.meta.get("trace"), peer])))
+        }
+    }
+
+    #[test]
+    fn test_meta_reaches_context() {
+        smol::future::block_on(async move {
+            let req: JrpcRequest = serde_json::from_str(
+                r#"{"jsonrpc": "2.0", "method": "f", "params": [], "id": 1, "meta": {"trace": "abc"}}"#,
+            )
+            .unwrap();
+            let mut ctx = RpcContext::new();
+            ctx.insert_extension("1.2.3.4".to_string());
+            let resp = TraceService
+                .respond_raw_with_context(ctx, req.clone())
+                .await;
+            assert_eq!(resp.result.unwrap(), serde_json::json!(["abc", "1.2.3.4"]));
+            // the meta field is omitted when empty
+            let mut req = req;
+            req.meta.clear();
+            assert!(!serde_json::to_string(&req).unwrap().contains("meta"));
+        });
+    }
+}
@@ -0,0 +1,121 @@
+use std::fmt::{Display, Formatter};
+
+use crate::{JrpcError, JrpcId, JrpcNotification, JrpcRequest, JrpcResponse};
+
+impl Display for JrpcId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JrpcId::Number(n) => write!(f, "{}", n),
+            JrpcId::String(s) => write!(f, "{}", s),
+            JrpcId::Null => write!(f, "null"),
+        }
+    }
+}
+
+/// Displays a request like `#req-1 add(1, 2)`.
+impl Display for JrpcRequest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.display_redacted(&|_, _, _| false).fmt(f)
+    }
+}
+
+/// Displays a notification like `hello(1, 2)`.
+impl Display for JrpcNotification {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write_call(f, &self.method, &self.params, &|_, _, _| false)
+    }
+}
+
+/// Displays a response like `#req-1 -> 3`, or `#req-1 -> error -32601: Method not found`.
+impl Display for JrpcResponse {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{} -> ", self.id)?;
+        match (&self.result, &self.error) {
+            (_, Some(err)) => err.fmt(f),
+            (Some(result), None) => write!(f, "{}", result),
+            (None, None) => write!(f, "null"),
+        }
+    }
+}
+
+impl Display for JrpcError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "error {}: {}", self.code, self.message)?;
+        if !self.data.is_null() {
+            write!(f, " ({})", self.data)?;
+        }
+        Ok(())
+    }
+}
+
+/// A callback deciding whether a parameter should be hidden when displaying a request. It is given the method, the index of the parameter, and the parameter itself.
+pub type RedactFn<'a> = &'a dyn Fn(&str, usize, &serde_json::Value) -> bool;
+
+impl JrpcRequest {
+    /// Returns something that displays this request, replacing the params for which `redact` returns true with `<redacted>`. This is useful for logging requests that may carry credentials.
+    pub fn display_redacted<'a>(&'a self, redact: RedactFn<'a>) -> impl Display + 'a {
+        struct Redacted<'a>(&'a JrpcRequest, RedactFn<'a>);
+
+        impl Display for Redacted<'_> {
+            fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+                write!(f, "#{} ", self.0.id)?;
+                write_call(f, &self.0.method, &self.0.params, self.1)
+            }
+        }
+
+        Redacted(self, redact)
+    }
+}
+
+fn write_call(
+    f: &mut Formatter<'_>,
+    method: &str,
+    params: &[serde_json::Value],
+    redact: RedactFn,
+) -> std::fmt::Result {
+    write!(f, "{}(", method)?;
+    for (i, param) in params.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        if redact(method, i, param) {
+            write!(f, "<redacted>")?;
+        } else {
+            write!(f, "{}", param)?;
+        }
+    }
+    write!(f, ")")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{server::error_response, JrpcId, JrpcRequest, JrpcResponse};
+
+    #[test]
+    fn test_display() {
+        let req = JrpcRequest {
+            jsonrpc: "2.0".into(),
+            method: "login".into(),
+            params: vec!["alice".into(), "hunter2".into()],
+            id: JrpcId::Number(1),
+            meta: Default::default(),
+        };
+        assert_eq!(req.to_string(), r#"#1 login("alice", "hunter2")"#);
+        assert_eq!(
+            req.display_redacted(&|method, i, _| method == "login" && i == 1)
+                .to_string(),
+            r#"#1 login("alice", <redacted>)"#
+        );
+        let resp = JrpcResponse {
+            jsonrpc: "2.0".into(),
+            result: Some(serde_json::json!({"ok": true})),
+            error: None,
+            id: JrpcId::String("a".into()),
+        };
+        assert_eq!(resp.to_string(), r#"#a -> {"ok":true}"#);
+        assert_eq!(
+            error_response(JrpcId::Null, -32601, "Method not found").to_string(),
+            "#null -> error -32601: Method not found"
+        );
+    }
+}
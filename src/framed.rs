@@ -0,0 +1,264 @@
+use std::{fmt::Debug, future::Future, time::Duration};
+
+use async_trait::async_trait;
+use futures_util::{Sink, SinkExt, Stream, StreamExt};
+
+use crate::{
+    serve_connection, ConnInfo, JrpcMessage, JrpcRequest, JrpcResponse, Limits, MultiplexError,
+    Multiplexer, RpcTransport, ServerConfig, SessionFactory,
+};
+
+/// A FramedTransport makes calls over any connection that sends and receives whole JSON-RPC messages, given as a [Sink] and a [Stream] of [JrpcMessage]s, running many calls concurrently through a [Multiplexer]. This is the easiest way to write a transport for a new kind of connection, like a `Framed` from tokio-util with one of the crate's codecs, whose decoding errors can be dropped with [StreamExt::filter_map].
+///
+/// Messages from the other side that are not responses are available through [FramedTransport::next_incoming], unless the transport was made with [FramedTransport::serving], which answers incoming requests with a service instead. Batches are sent as separate concurrent calls, since a batch is not a single message.
+pub struct FramedTransport {
+    mux: Multiplexer,
+}
+
+impl FramedTransport {
+    /// Creates a new FramedTransport over the given connection, returning it together with a future that drives the connection. The future must be spawned or otherwise polled for calls to make progress; it resolves once the connection is lost.
+    pub fn new<Si, St>(sink: Si, stream: St) -> (Self, impl Future<Output = ()> + Send + 'static)
+    where
+        Si: Sink<JrpcMessage> + Send + Unpin + 'static,
+        Si::Error: Debug + Send,
+        St: Stream<Item = JrpcMessage> + Send + Unpin + 'static,
+    {
+        let (mux, driver) = Multiplexer::new(to_frames(sink), from_frames(stream));
+        (Self { mux }, driver)
+    }
+
+    /// Creates a new FramedTransport over the given connection that is also a server: incoming requests are answered by a service made by the factory, like [serve_connection] would, while calls go the other way. The session's [ConnInfo] carries a [crate::Notifier] for sending notifications to the other side.
+    ///
+    /// Returns the transport together with a future that drives the connection, which resolves once the connection is lost and every response has been sent.
+    pub fn serving<F, Si, St>(
+        factory: F,
+        sink: Si,
+        stream: St,
+        config: ServerConfig,
+    ) -> (Self, impl Future<Output = ()> + Send + 'static)
+    where
+        F: SessionFactory + Send + Sync + 'static,
+        Si: Sink<JrpcMessage> + Send + Unpin + 'static,
+        Si::Error: Debug + Send,
+        St: Stream<Item = JrpcMessage> + Send + Unpin + 'static,
+    {
+        // both sides share the connection, so outgoing frames are merged and incoming ones are sorted
+        let (send_outgoing, recv_outgoing) = async_channel::unbounded::<String>();
+        let (send_requests, recv_requests) = async_channel::unbounded::<String>();
+        let (send_others, recv_others) = async_channel::unbounded::<String>();
+        let outgoing = |send: async_channel::Sender<String>| {
+            Box::pin(futures_util::sink::unfold(
+                send,
+                |send, frame: String| async move {
+                    send.send(frame)
+                        .await
+                        .map_err(|_| MultiplexError::ConnectionLost)?;
+                    Ok::<_, MultiplexError>(send)
+                },
+            ))
+        };
+        let (mux, mux_driver) =
+            Multiplexer::new(outgoing(send_outgoing.clone()), Box::pin(recv_others));
+        let server_sink = outgoing(send_outgoing);
+        let writer = async move {
+            let mut sink = to_frames(sink);
+            while let Ok(frame) = recv_outgoing.recv().await {
+                if let Err(err) = sink.send(frame).await {
+                    log::debug!("framed connection failed to send: {:?}", err);
+                    return;
+                }
+            }
+        };
+        let reader = async move {
+            let mut stream = stream;
+            while let Some(msg) = stream.next().await {
+                let frame = serde_json::to_string(&msg).unwrap();
+                let sent = match msg {
+                    JrpcMessage::Request(_) => send_requests.send(frame).await.is_ok(),
+                    _ => send_others.send(frame).await.is_ok(),
+                };
+                if !sent {
+                    return;
+                }
+            }
+        };
+        let server = async move {
+            serve_connection(
+                &factory,
+                ConnInfo::new(),
+                server_sink,
+                Box::pin(recv_requests),
+                &config,
+            )
+            .await
+        };
+        let driver = async move {
+            // the reader closes both sides when the connection ends, after which the writer sends what is left
+            futures_lite::future::or(
+                async {
+                    futures_lite::future::zip(
+                        reader,
+                        futures_lite::future::zip(mux_driver, server),
+                    )
+                    .await;
+                    futures_lite::future::pending().await
+                },
+                writer,
+            )
+            .await
+        };
+        (Self { mux }, driver)
+    }
+
+    /// Sets a timeout for every call.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self {
+            mux: self.mux.with_timeout(timeout),
+        }
+    }
+
+    /// Sets the limits that incoming messages are checked against.
+    pub fn with_limits(self, limits: Limits) -> Self {
+        Self {
+            mux: self.mux.with_limits(limits),
+        }
+    }
+
+    /// Waits for the next message from the other side that is not a response, like a notification. Returns `None` once the connection is lost.
+    pub async fn next_incoming(&self) -> Option<JrpcMessage> {
+        self.mux.next_incoming().await
+    }
+
+    /// Sends a message that does not expect a response, like a notification.
+    pub async fn send(&self, msg: &JrpcMessage) -> Result<(), MultiplexError> {
+        self.mux.send(msg).await
+    }
+}
+
+/// Turns a sink of messages into a sink of the frames a [Multiplexer] writes, which are always single messages since batches are never sent.
+fn to_frames<Si>(sink: Si) -> impl Sink<String, Error = Si::Error> + Send + Unpin + 'static
+where
+    Si: Sink<JrpcMessage> + Send + Unpin + 'static,
+    Si::Error: Send,
+{
+    Box::pin(sink.with(|frame: String| async move {
+        Ok(serde_json::from_str::<JrpcMessage>(&frame).expect("multiplexer wrote a bad frame"))
+    }))
+}
+
+fn from_frames<St>(stream: St) -> impl Stream<Item = String> + Send + Unpin + 'static
+where
+    St: Stream<Item = JrpcMessage> + Send + Unpin + 'static,
+{
+    stream.map(|msg| serde_json::to_string(&msg).unwrap())
+}
+
+#[async_trait]
+impl RpcTransport for FramedTransport {
+    type Error = MultiplexError;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        self.mux.call_raw(req).await
+    }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        futures_util::future::try_join_all(reqs.into_iter().map(|req| self.mux.call_raw(req))).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::SinkExt;
+
+    use crate::{
+        ConnInfo, FnService, FramedTransport, JrpcMessage, RpcService, RpcTransport, ServerConfig,
+    };
+
+    /// Makes one direction of an in-memory connection carrying messages.
+    fn pipe() -> (
+        impl futures_util::Sink<JrpcMessage, Error = async_channel::SendError<JrpcMessage>>
+            + Send
+            + Unpin
+            + 'static,
+        async_channel::Receiver<JrpcMessage>,
+    ) {
+        let (send, recv) = async_channel::unbounded();
+        let sink = futures_util::sink::unfold(send, |send, msg: JrpcMessage| async move {
+            send.send(msg).await?;
+            Ok(send)
+        });
+        (Box::pin(sink), recv)
+    }
+
+    fn service(name: &'static str) -> impl Fn(&ConnInfo) -> FnService + Send + Sync + 'static {
+        move |_: &ConnInfo| {
+            FnService::new(move |method, params| {
+                let method = method.to_string();
+                async move { (method == "who").then(|| Ok(serde_json::json!([name, params]))) }
+            })
+        }
+    }
+
+    #[test]
+    fn test_framed_transport() {
+        smol::future::block_on(async move {
+            // two peers calling each other over the same connection
+            let (a_sink, b_stream) = pipe();
+            let (b_sink, a_stream) = pipe();
+            let (a, a_driver) =
+                FramedTransport::serving(service("a"), a_sink, a_stream, ServerConfig::default());
+            let (b, b_driver) =
+                FramedTransport::serving(service("b"), b_sink, b_stream, ServerConfig::default());
+            smol::spawn(a_driver).detach();
+            smol::spawn(b_driver).detach();
+            let (from_b, from_a) =
+                futures_lite::future::zip(a.call("who", &[1.into()]), b.call("who", &[2.into()]))
+                    .await;
+            assert_eq!(
+                from_b.unwrap().unwrap().unwrap(),
+                serde_json::json!(["b", [1]])
+            );
+            assert_eq!(
+                from_a.unwrap().unwrap().unwrap(),
+                serde_json::json!(["a", [2]])
+            );
+            let batch = a
+                .call_raw_batch(vec![
+                    crate::JrpcRequest {
+                        jsonrpc: "2.0".into(),
+                        method: "who".into(),
+                        params: vec![],
+                        id: crate::JrpcId::Number(7),
+                        meta: Default::default(),
+                    };
+                    2
+                ])
+                .await
+                .unwrap();
+            assert_eq!(batch.len(), 2);
+            assert_eq!(batch[1].id, crate::JrpcId::Number(7));
+
+            // a plain transport surfaces incoming requests, which can be answered by hand
+            let (client_sink, from_client) = pipe();
+            let (mut server_sink, to_client) = pipe();
+            let (client, driver) = FramedTransport::new(client_sink, to_client);
+            smol::spawn(driver).detach();
+            let answered = smol::spawn(async move {
+                let service = service("manual")(&ConnInfo::new());
+                let Ok(JrpcMessage::Request(req)) = from_client.recv().await else {
+                    panic!("expected a request")
+                };
+                let resp = service.respond_raw(req).await;
+                server_sink.send(JrpcMessage::Response(resp)).await.unwrap();
+            });
+            assert_eq!(
+                client.call("who", &[]).await.unwrap().unwrap().unwrap(),
+                serde_json::json!(["manual", []])
+            );
+            answered.await;
+        });
+    }
+}
@@ -0,0 +1,156 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+mod de;
+mod ser;
+
+/// The largest integer that survives a round trip through a JavaScript number.
+pub const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+/// What to do with NaN and infinite floats, which JSON cannot represent.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FloatPolicy {
+    /// Fail to encode the value.
+    Error,
+    /// Encode them as `null`, like `serde_json` does.
+    #[default]
+    Null,
+    /// Encode them as the strings `"NaN"`, `"Infinity"`, and `"-Infinity"`, and accept those strings where floats are expected.
+    String,
+}
+
+/// Controls how params and results are converted to and from JSON. Code generated by `#[nanorpc_derive]` uses this, configured through the options passed to the macro.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct JsonConfig {
+    /// Whether integers too big to be exactly represented by a JavaScript number are encoded as strings, and accepted as strings where integers are expected.
+    pub safe_integers: bool,
+    /// What to do with NaN and infinite floats.
+    pub float_policy: FloatPolicy,
+    /// Whether obvious type mismatches are coerced when decoding: numeric strings to numbers, numbers and booleans to strings, and 0/1 or `"true"`/`"false"` to booleans. This is meant for servers talking to loosely typed clients.
+    pub lenient: bool,
+}
+
+impl JsonConfig {
+    /// Converts a value to JSON according to this config. With the default config, this is exactly [serde_json::to_value].
+    pub fn to_value<T: Serialize + ?Sized>(&self, value: &T) -> Result<Value, serde_json::Error> {
+        if *self == Self::default() {
+            serde_json::to_value(value)
+        } else {
+            value.serialize(ser::ValueSerializer(*self))
+        }
+    }
+
+    /// Converts JSON to a value according to this config. Plain JSON, as produced by `serde_json`, is always accepted.
+    pub fn from_value<T: DeserializeOwned>(&self, value: Value) -> Result<T, serde_json::Error> {
+        if *self == Self::default() {
+            serde_json::from_value(value)
+        } else {
+            T::deserialize(de::ValueDeserializer {
+                value,
+                config: *self,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Shape {
+        Point,
+        Circle(f64),
+        Rect { w: f64, h: u64 },
+    }
+
+    #[test]
+    fn test_big_ints_roundtrip() {
+        let config = JsonConfig {
+            safe_integers: true,
+            ..Default::default()
+        };
+        let value = (u64::MAX, 5u64, -(1i64 << 60), "12".to_string());
+        let encoded = config.to_value(&value).unwrap();
+        assert_eq!(
+            encoded,
+            json!(["18446744073709551615", 5, "-1152921504606846976", "12"])
+        );
+        let decoded: (u64, u64, i64, String) = config.from_value(encoded).unwrap();
+        assert_eq!(decoded, value);
+        // plain numbers are still accepted
+        let decoded: u64 = config.from_value(json!(u64::MAX)).unwrap();
+        assert_eq!(decoded, u64::MAX);
+    }
+
+    #[test]
+    fn test_float_policy() {
+        let value = vec![1.5, f64::NAN, f64::NEG_INFINITY];
+        let null = JsonConfig::default();
+        assert_eq!(null.to_value(&value).unwrap(), json!([1.5, null, null]));
+        let error = JsonConfig {
+            float_policy: FloatPolicy::Error,
+            ..Default::default()
+        };
+        assert!(error.to_value(&value).is_err());
+        assert!(error.to_value(&[1.5]).is_ok());
+        let string = JsonConfig {
+            float_policy: FloatPolicy::String,
+            ..Default::default()
+        };
+        let encoded = string.to_value(&value).unwrap();
+        assert_eq!(encoded, json!([1.5, "NaN", "-Infinity"]));
+        let decoded: Vec<f64> = string.from_value(encoded).unwrap();
+        assert!(decoded[1].is_nan());
+        assert_eq!(decoded[2], f64::NEG_INFINITY);
+    }
+
+    #[test]
+    fn test_lenient() {
+        let lenient = JsonConfig {
+            lenient: true,
+            ..Default::default()
+        };
+        let decoded: (u32, f64, String, String, bool, bool, Option<i8>) = lenient
+            .from_value(json!(["12", "1.5", 3, true, 1, "false", "-4"]))
+            .unwrap();
+        assert_eq!(
+            decoded,
+            (12, 1.5, "3".into(), "true".into(), true, false, Some(-4))
+        );
+        assert!(lenient.from_value::<bool>(json!(2)).is_err());
+        assert!(lenient.from_value::<u32>(json!("twelve")).is_err());
+        assert!(JsonConfig::default()
+            .from_value::<u32>(json!("12"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_matches_serde_json() {
+        let mut map = BTreeMap::new();
+        map.insert(3u32, Some(Shape::Point));
+        map.insert(4, None);
+        let value = (
+            vec![Shape::Circle(2.0), Shape::Rect { w: 1.0, h: 2 }],
+            map,
+            'c',
+            (),
+        );
+        let config = JsonConfig {
+            safe_integers: true,
+            float_policy: FloatPolicy::String,
+            lenient: true,
+        };
+        let encoded = config.to_value(&value).unwrap();
+        assert_eq!(encoded, serde_json::to_value(&value).unwrap());
+        let decoded: (Vec<Shape>, BTreeMap<u32, Option<Shape>>, char, ()) =
+            config.from_value(encoded).unwrap();
+        assert_eq!(decoded, value);
+    }
+}
@@ -0,0 +1,626 @@
+use serde::{ser::Impossible, Serialize, Serializer};
+use serde_json::{Map, Value};
+
+use super::{FloatPolicy, JsonConfig, MAX_SAFE_INTEGER};
+
+/// Like `serde_json::value::Serializer`, but applying a [JsonConfig].
+pub(super) struct ValueSerializer(pub JsonConfig);
+
+impl ValueSerializer {
+    fn integer(&self, n: impl Into<serde_json::Number>) -> Value {
+        let n = n.into();
+        let big = n.as_u64().map(|n| n > MAX_SAFE_INTEGER).unwrap_or_default()
+            || n.as_i64()
+                .map(|n| n.unsigned_abs() > MAX_SAFE_INTEGER)
+                .unwrap_or_default();
+        if self.0.safe_integers && big {
+            Value::String(n.to_string())
+        } else {
+            Value::Number(n)
+        }
+    }
+}
+
+fn custom_error(msg: impl std::fmt::Display) -> serde_json::Error {
+    serde::ser::Error::custom(msg)
+}
+
+impl Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = serde_json::Error;
+
+    type SerializeSeq = SerializeVec;
+    type SerializeTuple = SerializeVec;
+    type SerializeTupleStruct = SerializeVec;
+    type SerializeTupleVariant = SerializeVec;
+    type SerializeMap = SerializeMap;
+    type SerializeStruct = SerializeMap;
+    type SerializeStructVariant = SerializeMap;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, Self::Error> {
+        Ok(Value::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, Self::Error> {
+        Ok(self.integer(v))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Value, Self::Error> {
+        if let Ok(v) = i64::try_from(v) {
+            self.serialize_i64(v)
+        } else if let Ok(v) = u64::try_from(v) {
+            self.serialize_u64(v)
+        } else if self.0.safe_integers {
+            Ok(Value::String(v.to_string()))
+        } else {
+            Err(custom_error("number out of range"))
+        }
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, Self::Error> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, Self::Error> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, Self::Error> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value, Self::Error> {
+        Ok(self.integer(v))
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Value, Self::Error> {
+        if let Ok(v) = u64::try_from(v) {
+            self.serialize_u64(v)
+        } else if self.0.safe_integers {
+            Ok(Value::String(v.to_string()))
+        } else {
+            Err(custom_error("number out of range"))
+        }
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value, Self::Error> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value, Self::Error> {
+        if let Some(n) = serde_json::Number::from_f64(v) {
+            return Ok(Value::Number(n));
+        }
+        match self.0.float_policy {
+            FloatPolicy::Error => Err(custom_error(format!("cannot encode {} as JSON", v))),
+            FloatPolicy::Null => Ok(Value::Null),
+            FloatPolicy::String => Ok(Value::String(
+                if v.is_nan() {
+                    "NaN"
+                } else if v > 0.0 {
+                    "Infinity"
+                } else {
+                    "-Infinity"
+                }
+                .into(),
+            )),
+        }
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, Self::Error> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, Self::Error> {
+        Ok(Value::String(v.into()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, Self::Error> {
+        Ok(Value::Array(v.iter().map(|b| Value::from(*b)).collect()))
+    }
+
+    fn serialize_none(self) -> Result<Value, Self::Error> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value, Self::Error> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, Self::Error> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Value, Self::Error> {
+        Ok(Value::String(variant.into()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Value, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value, Self::Error> {
+        let mut map = Map::new();
+        map.insert(variant.into(), value.serialize(self)?);
+        Ok(Value::Object(map))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeVec, Self::Error> {
+        Ok(SerializeVec {
+            config: self.0,
+            variant: None,
+            vec: Vec::with_capacity(len.unwrap_or_default()),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeVec, Self::Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SerializeVec, Self::Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeVec, Self::Error> {
+        Ok(SerializeVec {
+            config: self.0,
+            variant: Some(variant),
+            vec: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<SerializeMap, Self::Error> {
+        Ok(SerializeMap {
+            config: self.0,
+            variant: None,
+            map: Map::new(),
+            next_key: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SerializeMap, Self::Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<SerializeMap, Self::Error> {
+        Ok(SerializeMap {
+            config: self.0,
+            variant: Some(variant),
+            map: Map::new(),
+            next_key: None,
+        })
+    }
+}
+
+fn wrap_variant(variant: Option<&'static str>, value: Value) -> Value {
+    if let Some(variant) = variant {
+        let mut map = Map::new();
+        map.insert(variant.into(), value);
+        Value::Object(map)
+    } else {
+        value
+    }
+}
+
+pub(super) struct SerializeVec {
+    config: JsonConfig,
+    variant: Option<&'static str>,
+    vec: Vec<Value>,
+}
+
+impl serde::ser::SerializeSeq for SerializeVec {
+    type Ok = Value;
+    type Error = serde_json::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.vec
+            .push(value.serialize(ValueSerializer(self.config))?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, Self::Error> {
+        Ok(wrap_variant(self.variant, Value::Array(self.vec)))
+    }
+}
+
+impl serde::ser::SerializeTuple for SerializeVec {
+    type Ok = Value;
+    type Error = serde_json::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        serde::ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value, Self::Error> {
+        serde::ser::SerializeSeq::end(self)
+    }
+}
+
+impl serde::ser::SerializeTupleStruct for SerializeVec {
+    type Ok = Value;
+    type Error = serde_json::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        serde::ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value, Self::Error> {
+        serde::ser::SerializeSeq::end(self)
+    }
+}
+
+impl serde::ser::SerializeTupleVariant for SerializeVec {
+    type Ok = Value;
+    type Error = serde_json::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        serde::ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value, Self::Error> {
+        serde::ser::SerializeSeq::end(self)
+    }
+}
+
+pub(super) struct SerializeMap {
+    config: JsonConfig,
+    variant: Option<&'static str>,
+    map: Map<String, Value>,
+    next_key: Option<String>,
+}
+
+impl serde::ser::SerializeMap for SerializeMap {
+    type Ok = Value;
+    type Error = serde_json::Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Self::Error> {
+        self.next_key = Some(key.serialize(MapKeySerializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        let key = self
+            .next_key
+            .take()
+            .expect("serialize_value called before serialize_key");
+        self.map
+            .insert(key, value.serialize(ValueSerializer(self.config))?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, Self::Error> {
+        Ok(wrap_variant(self.variant, Value::Object(self.map)))
+    }
+}
+
+impl serde::ser::SerializeStruct for SerializeMap {
+    type Ok = Value;
+    type Error = serde_json::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        self.map
+            .insert(key.into(), value.serialize(ValueSerializer(self.config))?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, Self::Error> {
+        serde::ser::SerializeMap::end(self)
+    }
+}
+
+impl serde::ser::SerializeStructVariant for SerializeMap {
+    type Ok = Value;
+    type Error = serde_json::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        serde::ser::SerializeStruct::serialize_field(self, key, value)
+    }
+
+    fn end(self) -> Result<Value, Self::Error> {
+        serde::ser::SerializeMap::end(self)
+    }
+}
+
+/// Serializes map keys, which JSON requires to be strings, the same way `serde_json` does.
+struct MapKeySerializer;
+
+macro_rules! key_to_string {
+    ($($method:ident: $ty:ty),*) => {
+        $(
+            fn $method(self, v: $ty) -> Result<String, Self::Error> {
+                Ok(v.to_string())
+            }
+        )*
+    };
+}
+
+impl Serializer for MapKeySerializer {
+    type Ok = String;
+    type Error = serde_json::Error;
+
+    type SerializeSeq = Impossible<String, serde_json::Error>;
+    type SerializeTuple = Impossible<String, serde_json::Error>;
+    type SerializeTupleStruct = Impossible<String, serde_json::Error>;
+    type SerializeTupleVariant = Impossible<String, serde_json::Error>;
+    type SerializeMap = Impossible<String, serde_json::Error>;
+    type SerializeStruct = Impossible<String, serde_json::Error>;
+    type SerializeStructVariant = Impossible<String, serde_json::Error>;
+
+    key_to_string!(
+        serialize_bool: bool,
+        serialize_i8: i8,
+        serialize_i16: i16,
+        serialize_i32: i32,
+        serialize_i64: i64,
+        serialize_i128: i128,
+        serialize_u8: u8,
+        serialize_u16: u16,
+        serialize_u32: u32,
+        serialize_u64: u64,
+        serialize_u128: u128,
+        serialize_char: char,
+        serialize_str: &str
+    );
+
+    fn serialize_f32(self, v: f32) -> Result<String, Self::Error> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<String, Self::Error> {
+        if v.is_finite() {
+            Ok(v.to_string())
+        } else {
+            Err(key_must_be_string())
+        }
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<String, Self::Error> {
+        Err(key_must_be_string())
+    }
+
+    fn serialize_none(self) -> Result<String, Self::Error> {
+        Err(key_must_be_string())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, _value: &T) -> Result<String, Self::Error> {
+        Err(key_must_be_string())
+    }
+
+    fn serialize_unit(self) -> Result<String, Self::Error> {
+        Err(key_must_be_string())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<String, Self::Error> {
+        Err(key_must_be_string())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<String, Self::Error> {
+        Ok(variant.into())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<String, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<String, Self::Error> {
+        Err(key_must_be_string())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Err(key_must_be_string())
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        Err(key_must_be_string())
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        Err(key_must_be_string())
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Err(key_must_be_string())
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Err(key_must_be_string())
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        Err(key_must_be_string())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Err(key_must_be_string())
+    }
+}
+
+fn key_must_be_string() -> serde_json::Error {
+    custom_error("key must be a string")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::{Serialize, Serializer};
+    use serde_json::json;
+
+    use super::ValueSerializer;
+    use crate::{FloatPolicy, JsonConfig};
+
+    #[derive(Serialize)]
+    enum Shape {
+        Point,
+        Circle(f64),
+        Pair(u8, i16),
+        Rect { w: f64, h: u64 },
+    }
+
+    /// Serializes through [Serializer::serialize_bytes], which `Vec<u8>` does not.
+    struct RawBytes(&'static [u8]);
+
+    impl Serialize for RawBytes {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(self.0)
+        }
+    }
+
+    /// Checks that a value, with nothing that the config would change, comes out exactly like from `serde_json`.
+    fn assert_matches<T: Serialize>(value: T) {
+        let expected = serde_json::to_value(&value).unwrap();
+        for config in [
+            JsonConfig::default(),
+            JsonConfig {
+                safe_integers: true,
+                float_policy: FloatPolicy::String,
+                lenient: true,
+            },
+        ] {
+            assert_eq!(value.serialize(ValueSerializer(config)).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_enums() {
+        assert_matches(Shape::Point);
+        assert_matches(Shape::Circle(1.5));
+        assert_matches(Shape::Pair(1, -2));
+        assert_matches(Shape::Rect { w: 1.0, h: 2 });
+        assert_matches(vec![Shape::Point, Shape::Circle(0.0)]);
+    }
+
+    #[test]
+    fn test_integer_keys() {
+        let mut map = BTreeMap::new();
+        map.insert(-3i64, "a");
+        map.insert(4, "b");
+        assert_matches(map);
+        let mut map = BTreeMap::new();
+        map.insert(u128::from(u64::MAX), true);
+        assert_matches(map);
+        let mut map = BTreeMap::new();
+        map.insert('k', ());
+        assert_matches(map);
+    }
+
+    #[test]
+    fn test_options() {
+        assert_matches(Some(1u8));
+        assert_matches(None::<u8>);
+        assert_matches(Some(Some(Shape::Point)));
+        assert_matches(vec![Some("x"), None]);
+    }
+
+    #[test]
+    fn test_bytes() {
+        assert_matches(RawBytes(&[0, 1, 255]));
+        assert_matches(RawBytes(&[]));
+        assert_matches(crate::Bytes::<crate::Base64Encoding>::from(vec![
+            0u8, 1, 255,
+        ]));
+    }
+
+    #[test]
+    fn test_nested_values() {
+        assert_matches(json!({
+            "a": [1, -2, 3.5, null, true, "s"],
+            "b": {"c": {"d": []}, "e": {}},
+        }));
+        assert_matches((json!(null), json!([[1], [2, [3]]])));
+    }
+}
@@ -1,10 +1,45 @@
-#[doc = include_str!("../README.md")]
+#![doc = include_str!("../README.md")]
+mod adapters;
+mod blocking;
+mod bytes;
+mod codec;
+mod context;
+mod display;
+mod framed;
+mod json;
+mod local;
+mod multiplex;
+mod server;
+mod session;
+mod shutdown;
+mod strict;
+mod timer;
 mod utils;
+// empty unless some adapter features are enabled
+#[allow(unused_imports)]
+pub use adapters::*;
+pub use blocking::*;
+pub use bytes::*;
+pub use codec::*;
+pub use context::*;
+pub use display::*;
+pub use framed::*;
+pub use json::*;
+pub use local::*;
+pub use multiplex::*;
+pub use server::*;
+pub use session::*;
+pub use shutdown::*;
+pub use timer::*;
 pub use utils::*;
 
-use std::sync::Arc;
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::Arc,
+};
 
 use async_trait::async_trait;
+use futures_util::StreamExt;
 pub use nanorpc_derive::nanorpc_derive;
 use serde::{Deserialize, Serialize};
 
@@ -14,6 +49,8 @@ use serde::{Deserialize, Serialize};
 pub enum JrpcId {
     Number(i64),
     String(String),
+    /// Only used in error responses to requests whose ID could not be determined.
+    Null,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -21,8 +58,56 @@ pub enum JrpcId {
 pub struct JrpcRequest {
     pub jsonrpc: String,
     pub method: String,
+    #[serde(default)]
     pub params: Vec<serde_json::Value>,
     pub id: JrpcId,
+    /// Metadata, like trace IDs or auth tokens, for transports that have nowhere else to put it. This is a nanorpc extension to JSON-RPC, and is omitted when empty.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub meta: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+/// A raw, JSON-RPC notification: a request that does not get a response. This should usually never be manually constructed.
+pub struct JrpcNotification {
+    pub jsonrpc: String,
+    pub method: String,
+    #[serde(default)]
+    pub params: Vec<serde_json::Value>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(untagged)]
+/// Any raw JSON-RPC message, as seen on a connection where both sides may send requests.
+///
+/// Messages are told apart by their keys: a message with a `method` is a request if it also has an `id`, and a notification otherwise. Anything else must be a response, with a `result` or an `error`.
+pub enum JrpcMessage {
+    Request(JrpcRequest),
+    Notification(JrpcNotification),
+    Response(JrpcResponse),
+}
+
+impl<'de> Deserialize<'de> for JrpcMessage {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let obj = value
+            .as_object()
+            .ok_or_else(|| D::Error::custom("JSON-RPC message must be an object"))?;
+        let msg = if obj.contains_key("method") {
+            if obj.contains_key("id") {
+                serde_json::from_value(value).map(JrpcMessage::Request)
+            } else {
+                serde_json::from_value(value).map(JrpcMessage::Notification)
+            }
+        } else if obj.contains_key("result") || obj.contains_key("error") {
+            serde_json::from_value(value).map(JrpcMessage::Response)
+        } else {
+            return Err(D::Error::custom(
+                "JSON-RPC message is neither a request nor a response",
+            ));
+        };
+        msg.map_err(D::Error::custom)
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -49,11 +134,43 @@ pub struct JrpcError {
 /// A server-returned error message. Contains a string description as well as a structured value.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub struct ServerError {
+    /// The error code. Application errors use small non-negative codes; generated services always use `1`.
+    ///
+    /// The codes that JSON-RPC reserves for itself, from -32768 to -32000, do not fit in a `u32`, so they are stored as the bits of the `i32`, that is `code as u32`. This is what [ServerError::with_jrpc_code] does and [ServerError::jrpc_code] undoes, and how servers decide to send a code over the wire as is rather than as the generic `-1`. Application codes must therefore stay out of the range from `4294934528` to `4294935296`, which would be read as reserved codes.
     pub code: u32,
     pub message: String,
     pub details: serde_json::Value,
 }
 
+impl ServerError {
+    /// Creates an error with one of the codes that JSON-RPC reserves for itself (-32768 to -32000), like the implementation-defined server errors from -32099 to -32000. Unlike application error codes, these are sent over the wire as is. See [ServerError::code] for how they are stored.
+    pub fn with_jrpc_code(
+        code: i32,
+        message: impl Into<String>,
+        details: serde_json::Value,
+    ) -> Self {
+        debug_assert!(
+            JRPC_RESERVED_CODES.contains(&code),
+            "{} is not a reserved JSON-RPC error code",
+            code
+        );
+        Self {
+            code: code as u32,
+            message: message.into(),
+            details,
+        }
+    }
+
+    /// Returns the JSON-RPC error code, if this error has one of the reserved codes.
+    pub fn jrpc_code(&self) -> Option<i64> {
+        let code = self.code as i32;
+        JRPC_RESERVED_CODES.contains(&code).then_some(code as i64)
+    }
+}
+
+/// The error codes that JSON-RPC reserves for itself.
+const JRPC_RESERVED_CODES: std::ops::RangeInclusive<i32> = -32768..=-32000;
+
 /// A struct implementing the [`RpcService`] represents the *server-side* logic of a NanoRPC. The method that needs to be *implemented* is [`RpcService::respond`], but actual servers would typically call [`RpcService::respond_raw`].
 ///
 /// This trait uses the [`::async_trait`] crate, so the autogenerated documentation has somewhat inscrutable function signatures. [`RpcService`] has this "actual" definition:
@@ -66,7 +183,7 @@ pub struct ServerError {
 ///     async fn respond(
 ///         &self,
 ///         method: &str,
-///         params: Vec<serde_json::Value>,
+///         params: &[serde_json::Value],
 ///     ) -> Option<Result<serde_json::Value, ServerError>>;
 ///
 ///     async fn respond_raw(&self, jrpc_req: JrpcRequest) -> JrpcResponse;
@@ -88,7 +205,7 @@ pub struct ServerError {
 /// impl RpcService for BusinessLogic {
 ///     async fn respond(&self,
 ///         method: &str,
-///         params: Vec<serde_json::Value>
+///         params: &[serde_json::Value]
 ///     ) -> Option<Result<serde_json::Value, ServerError>> {
 ///         // business logic here
 ///         todo!()
@@ -110,53 +227,82 @@ pub trait RpcService: Sync + Send + 'static {
     async fn respond(
         &self,
         method: &str,
-        params: Vec<serde_json::Value>,
+        params: &[serde_json::Value],
     ) -> Option<Result<serde_json::Value, ServerError>>;
 
+    /// Like [RpcService::respond], but with access to the [RpcContext] of the request. By default, the context is ignored; services that need it, like authentication wrappers, should implement this method, and have [RpcService::respond] call it with an empty context.
+    async fn respond_with_context(
+        &self,
+        ctx: &RpcContext,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        let _ = ctx;
+        self.respond(method, params).await
+    }
+
     /// Responds to a raw JSON-RPC request, returning a raw JSON-RPC response.
     async fn respond_raw(&self, jrpc_req: JrpcRequest) -> JrpcResponse {
+        self.respond_raw_with_context(RpcContext::default(), jrpc_req)
+            .await
+    }
+
+    /// Like [RpcService::respond_raw], but with a context that the server adapter may have filled with information about the connection. The request's metadata is added to the context.
+    async fn respond_raw_with_context(
+        &self,
+        mut ctx: RpcContext,
+        jrpc_req: JrpcRequest,
+    ) -> JrpcResponse {
         if jrpc_req.jsonrpc != "2.0" {
-            JrpcResponse {
-                id: jrpc_req.id,
-                jsonrpc: "2.0".into(),
-                result: None,
-                error: Some(JrpcError {
-                    code: -32600,
-                    message: "JSON-RPC version wrong".into(),
-                    data: serde_json::Value::Null,
-                }),
-            }
-        } else if let Some(response) = self.respond(&jrpc_req.method, jrpc_req.params).await {
-            match response {
-                Ok(response) => JrpcResponse {
-                    id: jrpc_req.id,
-                    jsonrpc: "2.0".into(),
-                    result: Some(response),
-                    error: None,
-                },
-                Err(err) => JrpcResponse {
-                    id: jrpc_req.id,
-                    jsonrpc: "2.0".into(),
-                    result: None,
-                    error: Some(JrpcError {
-                        code: -1,
-                        message: err.message,
-                        data: err.details,
-                    }),
-                },
-            }
-        } else {
-            JrpcResponse {
-                id: jrpc_req.id,
-                jsonrpc: "2.0".into(),
-                result: None,
-                error: Some(JrpcError {
-                    code: -32601,
-                    message: "Method not found".into(),
-                    data: serde_json::Value::Null,
-                }),
-            }
+            return server::error_response(jrpc_req.id, -32600, "JSON-RPC version wrong");
         }
+        ctx.meta.extend(jrpc_req.meta);
+        let response = self
+            .respond_with_context(&ctx, &jrpc_req.method, &jrpc_req.params)
+            .await;
+        server::to_jrpc_response(jrpc_req.id, response)
+    }
+
+    /// Responds to a batch of raw JSON-RPC requests, handling up to `concurrency` of them at the same time. The responses are returned in the same order as the requests.
+    async fn respond_raw_batch(
+        &self,
+        jrpc_reqs: Vec<JrpcRequest>,
+        concurrency: usize,
+    ) -> Vec<JrpcResponse> {
+        self.respond_raw_batch_with_context(RpcContext::default(), jrpc_reqs, concurrency)
+            .await
+    }
+
+    /// Like [RpcService::respond_raw_batch], but with a context shared by every request in the batch.
+    async fn respond_raw_batch_with_context(
+        &self,
+        ctx: RpcContext,
+        jrpc_reqs: Vec<JrpcRequest>,
+        concurrency: usize,
+    ) -> Vec<JrpcResponse> {
+        futures_util::stream::iter(jrpc_reqs)
+            .map(|req| self.respond_raw_with_context(ctx.clone(), req))
+            .buffered(concurrency.max(1))
+            .collect()
+            .await
+    }
+
+    /// Responds to a serialized JSON-RPC message, which may be either a single request or a batch, returning the serialized response. This is what servers that deal in bytes, like HTTP or TCP servers, would typically call.
+    ///
+    /// Notifications, requests without an `id`, are executed but not answered. If nothing is left to answer, the returned response is empty, and should not be sent at all.
+    async fn respond_bytes(&self, req: &[u8], config: &ServerConfig) -> Vec<u8> {
+        self.respond_bytes_with_context(RpcContext::default(), req, config)
+            .await
+    }
+
+    /// Like [RpcService::respond_bytes], but with a context that the server adapter may have filled with information about the connection.
+    async fn respond_bytes_with_context(
+        &self,
+        ctx: RpcContext,
+        req: &[u8],
+        config: &ServerConfig,
+    ) -> Vec<u8> {
+        server::respond_bytes(self, ctx, req, config).await
     }
 }
 
@@ -165,10 +311,29 @@ impl<T: RpcService + ?Sized> RpcService for Arc<T> {
     async fn respond(
         &self,
         method: &str,
-        params: Vec<serde_json::Value>,
+        params: &[serde_json::Value],
     ) -> Option<Result<serde_json::Value, ServerError>> {
         self.as_ref().respond(method, params).await
     }
+
+    async fn respond_with_context(
+        &self,
+        ctx: &RpcContext,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        self.as_ref()
+            .respond_with_context(ctx, method, params)
+            .await
+    }
+
+    async fn respond_raw_with_context(
+        &self,
+        ctx: RpcContext,
+        jrpc_req: JrpcRequest,
+    ) -> JrpcResponse {
+        self.as_ref().respond_raw_with_context(ctx, jrpc_req).await
+    }
 }
 
 /// A client-side nanorpc transport. The only method that needs to be implemented is [`RpcTransport::call_raw`], but clients typically call [`RpcTransport::call`].
@@ -197,37 +362,95 @@ pub trait RpcTransport: Sync + Send + 'static {
         method: &str,
         params: &[serde_json::Value],
     ) -> Result<Option<Result<serde_json::Value, ServerError>>, Self::Error> {
-        let reqid = format!("req-{}", fastrand::u64(..));
-        let req = JrpcRequest {
-            jsonrpc: "2.0".into(),
-            id: JrpcId::String(reqid),
-            method: method.into(),
-            params: params
-                .iter()
-                .map(|s| serde_json::to_value(s).unwrap())
-                .collect(),
-        };
+        self.call_with_opts(method, params, &CallOptions::default())
+            .await
+    }
+
+    /// Like [RpcTransport::call], but with extra options, like metadata to attach to the request.
+    async fn call_with_opts(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+        opts: &CallOptions,
+    ) -> Result<Option<Result<serde_json::Value, ServerError>>, Self::Error> {
+        let mut req = new_jrpc_request(method, params);
+        req.meta = opts.meta.clone();
         let result = self.call_raw(req).await?;
-        if let Some(res) = result.result {
-            Ok(Some(Ok(res)))
-        } else if let Some(res) = result.error {
-            if res.code == -32600 {
-                Ok(None)
-            } else {
-                Ok(Some(Err(ServerError {
-                    code: res.code as u32,
-                    message: res.message,
-                    details: res.data,
-                })))
-            }
-        } else {
-            // if both result and error are null, that means that the result is actually null and there is no error
-            Ok(Some(Ok(serde_json::Value::Null)))
-        }
+        Ok(from_jrpc_response(result))
     }
 
     /// Sends an RPC call to the remote side, as a raw JSON-RPC request, receiving a raw JSON-RPC response.
     async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error>;
+
+    /// Sends a batch of raw JSON-RPC requests, returning the responses in the same order as the requests. This generally does not need a manual implementation; transports that natively support batching should override [RpcTransport::call_raw_batch] instead.
+    async fn call_batch(&self, reqs: Vec<JrpcRequest>) -> Result<Vec<JrpcResponse>, Self::Error> {
+        let ids: Vec<JrpcId> = reqs.iter().map(|req| req.id.clone()).collect();
+        // requests that share an id are matched with their responses in order
+        let mut responses: HashMap<JrpcId, VecDeque<JrpcResponse>> = HashMap::new();
+        for resp in self.call_raw_batch(reqs).await? {
+            responses
+                .entry(resp.id.clone())
+                .or_default()
+                .push_back(resp);
+        }
+        Ok(ids
+            .into_iter()
+            .map(|id| {
+                responses
+                    .get_mut(&id)
+                    .and_then(|group| group.pop_front())
+                    .unwrap_or_else(|| {
+                        server::error_response(id, -32603, "no response to request in batch")
+                    })
+            })
+            .collect())
+    }
+
+    /// Sends a batch of raw JSON-RPC requests, receiving raw JSON-RPC responses in any order. By default, this sends the requests one by one through [RpcTransport::call_raw]; transports that can send a whole batch at once, like HTTP, should override this.
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        let mut responses = Vec::with_capacity(reqs.len());
+        for req in reqs {
+            responses.push(self.call_raw(req).await?);
+        }
+        Ok(responses)
+    }
+}
+
+/// Creates a JSON-RPC request with a random ID.
+pub(crate) fn new_jrpc_request(method: &str, params: &[serde_json::Value]) -> JrpcRequest {
+    let reqid = format!("req-{}", fastrand::u64(..));
+    JrpcRequest {
+        jsonrpc: "2.0".into(),
+        id: JrpcId::String(reqid),
+        method: method.into(),
+        params: params.to_vec(),
+        meta: BTreeMap::new(),
+    }
+}
+
+/// Interprets a JSON-RPC response the way [RpcTransport::call] returns it.
+pub(crate) fn from_jrpc_response(
+    result: JrpcResponse,
+) -> Option<Result<serde_json::Value, ServerError>> {
+    if let Some(res) = result.result {
+        Some(Ok(res))
+    } else if let Some(res) = result.error {
+        if res.code == -32601 {
+            None
+        } else {
+            Some(Err(ServerError {
+                code: res.code as u32,
+                message: res.message,
+                details: res.data,
+            }))
+        }
+    } else {
+        // if both result and error are null, that means that the result is actually null and there is no error
+        Some(Ok(serde_json::Value::Null))
+    }
 }
 
 #[async_trait]
@@ -237,6 +460,13 @@ impl<T: RpcTransport + ?Sized> RpcTransport for Arc<T> {
     async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
         self.as_ref().call_raw(req).await
     }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        self.as_ref().call_raw_batch(reqs).await
+    }
 }
 
 #[async_trait]
@@ -246,6 +476,13 @@ impl<T: RpcTransport + ?Sized> RpcTransport for Box<T> {
     async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
         self.as_ref().call_raw(req).await
     }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        self.as_ref().call_raw_batch(reqs).await
+    }
 }
 
 // #[async_trait]
@@ -275,6 +512,28 @@ mod tests {
 
     struct Mather;
 
+    #[nanorpc_derive(safe_integers, float_policy = "string", lenient_params)]
+    #[async_trait::async_trait]
+    pub trait BigProtocol {
+        /// Increments a number
+        async fn incr(&self, x: u64) -> u64;
+        /// Divides two numbers
+        async fn div(&self, x: f64, y: f64) -> f64;
+    }
+
+    struct Bigger;
+
+    #[async_trait::async_trait]
+    impl BigProtocol for Bigger {
+        async fn incr(&self, x: u64) -> u64 {
+            x + 1
+        }
+
+        async fn div(&self, x: f64, y: f64) -> f64 {
+            x / y
+        }
+    }
+
     #[async_trait::async_trait]
     impl MathProtocol for Mather {
         async fn add(&self, x: f64, y: f64) -> f64 {
@@ -294,22 +553,208 @@ mod tests {
     fn test_notfound_macro() {
         smol::future::block_on(async move {
             let service = MathService(Mather);
+            assert_eq!(service.respond("!nonexistent!", &[]).await, None);
+        });
+    }
+
+    struct LoopbackTransport(MathService<Mather>);
+
+    #[async_trait::async_trait]
+    impl nanorpc::RpcTransport for LoopbackTransport {
+        type Error = std::convert::Infallible;
+
+        async fn call_raw(
+            &self,
+            req: nanorpc::JrpcRequest,
+        ) -> Result<nanorpc::JrpcResponse, Self::Error> {
+            if req.method == "invalid" {
+                Ok(crate::server::error_response(req.id, -32600, "bad request"))
+            } else {
+                Ok(self.0.respond_raw(req).await)
+            }
+        }
+    }
+
+    /// Answers batches with the responses of a service, rearranged by a function.
+    struct ShuffleTransport(fn(Vec<nanorpc::JrpcResponse>) -> Vec<nanorpc::JrpcResponse>);
+
+    #[async_trait::async_trait]
+    impl nanorpc::RpcTransport for ShuffleTransport {
+        type Error = std::convert::Infallible;
+
+        async fn call_raw(
+            &self,
+            req: nanorpc::JrpcRequest,
+        ) -> Result<nanorpc::JrpcResponse, Self::Error> {
+            Ok(MathService(Mather).respond_raw(req).await)
+        }
+
+        async fn call_raw_batch(
+            &self,
+            reqs: Vec<nanorpc::JrpcRequest>,
+        ) -> Result<Vec<nanorpc::JrpcResponse>, Self::Error> {
+            Ok((self.0)(
+                MathService(Mather).respond_raw_batch(reqs, 1).await,
+            ))
+        }
+    }
+
+    fn add_request(id: i64, x: f64) -> nanorpc::JrpcRequest {
+        nanorpc::JrpcRequest {
+            jsonrpc: "2.0".into(),
+            method: "add".into(),
+            params: vec![x.into(), 0.into()],
+            id: nanorpc::JrpcId::Number(id),
+            meta: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_jrpc_codes() {
+        let err = ServerError::with_jrpc_code(-32001, "overloaded", serde_json::Value::Null);
+        assert_eq!(err.code, 4294935295);
+        assert_eq!(err.jrpc_code(), Some(-32001));
+        // reserved codes go over the wire as is, and come back the same
+        let resp = crate::server::to_jrpc_response(nanorpc::JrpcId::Null, Some(Err(err.clone())));
+        assert_eq!(resp.error.as_ref().unwrap().code, -32001);
+        assert_eq!(crate::from_jrpc_response(resp), Some(Err(err)));
+        // application codes are sent as -1
+        let app = ServerError {
+            code: 7,
+            message: "nope".into(),
+            details: serde_json::Value::Null,
+        };
+        assert_eq!(app.jrpc_code(), None);
+        let resp = crate::server::to_jrpc_response(nanorpc::JrpcId::Null, Some(Err(app)));
+        assert_eq!(resp.error.unwrap().code, -1);
+    }
+
+    #[test]
+    fn test_call_batch() {
+        smol::future::block_on(async move {
+            use nanorpc::RpcTransport;
+            let results = |resps: Vec<nanorpc::JrpcResponse>| {
+                resps
+                    .into_iter()
+                    .map(|resp| {
+                        resp.result
+                            .unwrap_or_else(|| resp.error.unwrap().code.into())
+                    })
+                    .collect::<serde_json::Value>()
+            };
+            let reqs = vec![
+                add_request(1, 10.0),
+                add_request(2, 20.0),
+                add_request(3, 30.0),
+            ];
+            // responses come back in request order, even when the transport reorders them
+            let reversed = ShuffleTransport(|mut resps| {
+                resps.reverse();
+                resps
+            });
+            assert_eq!(
+                results(reversed.call_batch(reqs.clone()).await.unwrap()),
+                serde_json::json!([10.0, 20.0, 30.0])
+            );
+            // missing responses are filled in with errors
+            let lossy = ShuffleTransport(|mut resps| {
+                resps.remove(1);
+                resps
+            });
+            let resps = lossy.call_batch(reqs).await.unwrap();
+            assert_eq!(resps[1].id, nanorpc::JrpcId::Number(2));
+            assert_eq!(results(resps), serde_json::json!([10.0, -32603, 30.0]));
+            // requests sharing an id get their responses in order
+            let dups = vec![
+                add_request(1, 10.0),
+                add_request(2, 20.0),
+                add_request(1, 30.0),
+            ];
+            let resps = ShuffleTransport(|resps| resps)
+                .call_batch(dups)
+                .await
+                .unwrap();
+            assert_eq!(results(resps), serde_json::json!([10.0, 20.0, 30.0]));
+        });
+    }
+
+    #[test]
+    fn test_notfound_transport() {
+        smol::future::block_on(async move {
+            use nanorpc::RpcTransport;
+            let transport = LoopbackTransport(MathService(Mather));
+            // the server answers unknown methods with -32601, which the client reports as Ok(None)
+            assert_eq!(transport.call("!nonexistent!", &[]).await.unwrap(), None);
+            // other protocol-level errors are not mistaken for a missing method
+            let err = transport
+                .call("invalid", &[])
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap_err();
+            assert_eq!(err.message, "bad request");
+            assert_eq!(
+                transport
+                    .call("add", &[1.into(), 2.into()])
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .unwrap(),
+                serde_json::Value::from(3.0)
+            );
+        });
+    }
+
+    #[test]
+    fn test_json_config_macro() {
+        smol::future::block_on(async move {
+            let service = BigService(Bigger);
             assert_eq!(
                 service
-                    .respond("!nonexistent!", serde_json::from_str("[]").unwrap())
-                    .await,
-                None
+                    .respond("incr", &["18014398509481984".into()])
+                    .await
+                    .unwrap()
+                    .unwrap(),
+                serde_json::Value::from("18014398509481985")
+            );
+            assert_eq!(
+                service.respond("incr", &[1.into()]).await.unwrap().unwrap(),
+                serde_json::Value::from(2)
+            );
+            assert_eq!(
+                service
+                    .respond("incr", &["1".into()])
+                    .await
+                    .unwrap()
+                    .unwrap(),
+                serde_json::Value::from(2)
+            );
+            assert_eq!(
+                service
+                    .respond("div", &["-Infinity".into(), 0.into()])
+                    .await
+                    .unwrap()
+                    .unwrap(),
+                serde_json::Value::from("-Infinity")
             );
         });
     }
 
+    #[test]
+    fn test_methods_list() {
+        assert_eq!(
+            MathService::<Mather>::METHODS,
+            &["add", "mult", "maybe_fail"]
+        );
+    }
+
     #[test]
     fn test_simple_macro() {
         smol::future::block_on(async move {
             let service = MathService(Mather);
             assert_eq!(
                 service
-                    .respond("maybe_fail", serde_json::from_str("[]").unwrap())
+                    .respond("maybe_fail", &[])
                     .await
                     .unwrap()
                     .unwrap_err(),
@@ -321,7 +766,7 @@ mod tests {
             );
             assert_eq!(
                 service
-                    .respond("add", serde_json::from_str("[1, 2]").unwrap())
+                    .respond("add", &[1.into(), 2.into()])
                     .await
                     .unwrap()
                     .unwrap(),
@@ -0,0 +1,162 @@
+use async_trait::async_trait;
+
+use crate::{
+    from_jrpc_response, new_jrpc_request, server, JrpcRequest, JrpcResponse, RpcService,
+    RpcTransport, ServerError,
+};
+
+/// A counterpart of [RpcService] whose futures do not have to be `Send`, for single-threaded executors and WASM. Every [RpcService] is also a LocalRpcService. Services generated with `#[nanorpc_derive(local)]` implement only this trait.
+///
+/// Since the methods have the same names as those of [RpcService], avoid importing both traits in the same scope.
+#[async_trait(?Send)]
+pub trait LocalRpcService: 'static {
+    /// Responds to an RPC call with method `str` and dynamically typed arguments `args`. The service should return `None` to indicate that this method does not exist at all.
+    async fn respond(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>>;
+
+    /// Responds to a raw JSON-RPC request, returning a raw JSON-RPC response.
+    async fn respond_raw(&self, jrpc_req: JrpcRequest) -> JrpcResponse {
+        if jrpc_req.jsonrpc != "2.0" {
+            return server::error_response(jrpc_req.id, -32600, "JSON-RPC version wrong");
+        }
+        let response = self.respond(&jrpc_req.method, &jrpc_req.params).await;
+        server::to_jrpc_response(jrpc_req.id, response)
+    }
+}
+
+#[async_trait(?Send)]
+impl<T: RpcService> LocalRpcService for T {
+    async fn respond(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        RpcService::respond(self, method, params).await
+    }
+
+    async fn respond_raw(&self, jrpc_req: JrpcRequest) -> JrpcResponse {
+        RpcService::respond_raw(self, jrpc_req).await
+    }
+}
+
+/// A counterpart of [RpcTransport] whose futures do not have to be `Send`, for single-threaded executors and WASM. Every [RpcTransport] is also a LocalRpcTransport. Clients generated with `#[nanorpc_derive(local)]` work with any LocalRpcTransport.
+///
+/// Since the methods have the same names as those of [RpcTransport], avoid importing both traits in the same scope.
+#[async_trait(?Send)]
+pub trait LocalRpcTransport: 'static {
+    /// This error type represents *transport-level* errors, like communication errors and such.
+    type Error: 'static;
+
+    /// Sends an RPC call to the remote side, returning the result. `Ok(None)` means that there is no transport-level error, but that the verb does not exist. This generally does not need a manual implementation.
+    async fn call(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Result<Option<Result<serde_json::Value, ServerError>>, Self::Error> {
+        let result = self.call_raw(new_jrpc_request(method, params)).await?;
+        Ok(from_jrpc_response(result))
+    }
+
+    /// Sends an RPC call to the remote side, as a raw JSON-RPC request, receiving a raw JSON-RPC response.
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error>;
+}
+
+#[async_trait(?Send)]
+impl<T: RpcTransport> LocalRpcTransport for T {
+    type Error = T::Error;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        RpcTransport::call_raw(self, req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::{LocalRpcService, LocalRpcTransport};
+    use crate::{self as nanorpc, nanorpc_derive, JrpcRequest, JrpcResponse, ServerError};
+
+    /// A service that is not `Send` at all, which is what these traits exist for.
+    struct Counter(std::cell::Cell<u64>);
+
+    #[async_trait::async_trait(?Send)]
+    impl LocalRpcService for Rc<Counter> {
+        async fn respond(
+            &self,
+            method: &str,
+            _params: &[serde_json::Value],
+        ) -> Option<Result<serde_json::Value, ServerError>> {
+            (method == "incr").then(|| {
+                self.0.set(self.0.get() + 1);
+                Ok(self.0.get().into())
+            })
+        }
+    }
+
+    struct LoopbackTransport(Rc<Counter>);
+
+    #[async_trait::async_trait(?Send)]
+    impl LocalRpcTransport for LoopbackTransport {
+        type Error = std::convert::Infallible;
+
+        async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+            Ok(self.0.respond_raw(req).await)
+        }
+    }
+
+    #[test]
+    fn test_local_loopback() {
+        smol::future::block_on(async move {
+            let transport = LoopbackTransport(Rc::new(Counter(Default::default())));
+            transport.call("incr", &[]).await.unwrap();
+            assert_eq!(
+                transport.call("incr", &[]).await.unwrap().unwrap().unwrap(),
+                2
+            );
+            assert!(transport.call("decr", &[]).await.unwrap().is_none());
+        });
+    }
+
+    #[nanorpc_derive(local)]
+    #[async_trait::async_trait(?Send)]
+    pub trait TallyProtocol {
+        /// Adds to the tally, returning the new total
+        async fn add(&self, x: u64) -> u64;
+    }
+
+    #[async_trait::async_trait(?Send)]
+    impl TallyProtocol for Rc<Counter> {
+        async fn add(&self, x: u64) -> u64 {
+            // holding an Rc across an await point makes this future not Send
+            let this = self.clone();
+            smol::future::yield_now().await;
+            this.0.set(this.0.get() + x);
+            this.0.get()
+        }
+    }
+
+    struct TallyLoopback(TallyService<Rc<Counter>>);
+
+    #[async_trait::async_trait(?Send)]
+    impl LocalRpcTransport for TallyLoopback {
+        type Error = std::convert::Infallible;
+
+        async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+            Ok(self.0.respond_raw(req).await)
+        }
+    }
+
+    #[test]
+    fn test_local_macro() {
+        smol::future::block_on(async move {
+            let service = TallyService(Rc::new(Counter(Default::default())));
+            let client = TallyClient::from(TallyLoopback(service));
+            assert_eq!(client.add(2).await.unwrap(), 2);
+            assert_eq!(client.add(3).await.unwrap(), 5);
+        });
+    }
+}
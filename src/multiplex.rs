@@ -0,0 +1,426 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    future::Future,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use futures_util::{Sink, SinkExt, Stream, StreamExt};
+use thiserror::Error;
+
+use crate::{
+    server::LimitError, timer, JrpcId, JrpcMessage, JrpcRequest, JrpcResponse, Limits,
+    RpcTransport, GOING_AWAY_METHOD,
+};
+
+/// How many incoming requests and notifications are kept around for [Multiplexer::next_incoming]. Once that many are waiting, further ones are dropped.
+const INCOMING_CAPACITY: usize = 1024;
+
+/// An error returned by a [Multiplexer].
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum MultiplexError {
+    #[error("timed out waiting for a response")]
+    Timeout,
+    #[error("connection lost")]
+    ConnectionLost,
+}
+
+/// A Multiplexer runs many concurrent calls over a single persistent connection that carries one JSON-RPC message per frame, like a WebSocket or a TCP stream of JSON lines. It matches responses to the calls waiting for them, and surfaces all other incoming messages (requests and notifications from the other side) through [Multiplexer::next_incoming].
+///
+/// Outgoing request IDs are replaced with fresh ones internally, so callers never have to worry about reusing IDs. A Multiplexer is itself an [RpcTransport].
+pub struct Multiplexer {
+    send_outgoing: async_channel::Sender<String>,
+    recv_incoming: async_channel::Receiver<JrpcMessage>,
+    pending: Arc<Mutex<Pending>>,
+    next_id: AtomicI64,
+    timeout: Option<Duration>,
+}
+
+#[derive(Default)]
+struct Pending {
+    waiters: HashMap<i64, async_channel::Sender<JrpcResponse>>,
+    closed: bool,
+    /// Whether the other side said it is going away, so that no new calls should be made.
+    going_away: bool,
+    limits: Limits,
+}
+
+impl Multiplexer {
+    /// Creates a new Multiplexer over the given framed connection, returning it together with a future that drives the connection. The future must be spawned or otherwise polled for the Multiplexer to make progress; it resolves once the connection is lost.
+    pub fn new<Si, St>(sink: Si, stream: St) -> (Self, impl Future<Output = ()> + Send + 'static)
+    where
+        Si: Sink<String> + Send + Unpin + 'static,
+        Si::Error: Debug,
+        St: Stream<Item = String> + Send + Unpin + 'static,
+    {
+        let (send_outgoing, recv_outgoing) = async_channel::unbounded();
+        let (send_incoming, recv_incoming) = async_channel::bounded(INCOMING_CAPACITY);
+        // errors about messages that violate the limits are sent back from the reader
+        let (send_rejection, recv_rejection) = async_channel::unbounded::<String>();
+        let pending = Arc::new(Mutex::new(Pending::default()));
+        let driver = {
+            let pending = pending.clone();
+            async move {
+                let writer = async {
+                    let mut sink = sink;
+                    loop {
+                        let msg = futures_lite::future::or(
+                            async { recv_outgoing.recv().await.ok() },
+                            async {
+                                match recv_rejection.recv().await {
+                                    Ok(msg) => Some(msg),
+                                    Err(_) => futures_lite::future::pending().await,
+                                }
+                            },
+                        )
+                        .await;
+                        let Some(msg) = msg else { return };
+                        if let Err(err) = sink.send(msg).await {
+                            log::debug!("multiplexer connection failed to send: {:?}", err);
+                            return;
+                        }
+                    }
+                };
+                let reader = async {
+                    let mut stream = stream;
+                    while let Some(frame) = stream.next().await {
+                        let limits = pending.lock().unwrap().limits;
+                        match limits
+                            .parse::<IncomingFrame>(frame.as_bytes())
+                            .and_then(|frame| match &frame {
+                                IncomingFrame::Batch(msgs) => {
+                                    limits.check_batch(msgs.len()).map(|_| frame)
+                                }
+                                _ => Ok(frame),
+                            }) {
+                            Ok(IncomingFrame::Single(msg)) => {
+                                dispatch(&pending, &send_incoming, msg);
+                            }
+                            Ok(IncomingFrame::Batch(msgs)) => {
+                                for msg in msgs {
+                                    dispatch(&pending, &send_incoming, msg);
+                                }
+                            }
+                            Err(LimitError::Parse(err)) => {
+                                log::warn!("multiplexer dropping malformed message: {}", err)
+                            }
+                            Err(err) => {
+                                let resp = err.into_response();
+                                log::warn!("multiplexer rejecting message: {}", resp);
+                                let _ =
+                                    send_rejection.try_send(serde_json::to_string(&resp).unwrap());
+                            }
+                        }
+                    }
+                };
+                futures_lite::future::or(writer, reader).await;
+                // dropping the waiters wakes up everybody still waiting
+                let mut pending = pending.lock().unwrap();
+                pending.closed = true;
+                pending.waiters.clear();
+            }
+        };
+        (
+            Self {
+                send_outgoing,
+                recv_incoming,
+                pending,
+                next_id: AtomicI64::new(1),
+                timeout: None,
+            },
+            driver,
+        )
+    }
+
+    /// Sets a timeout for every call made through this Multiplexer.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the limits that incoming messages are checked against. Messages violating them are answered with an error and otherwise ignored.
+    pub fn with_limits(self, limits: Limits) -> Self {
+        self.pending.lock().unwrap().limits = limits;
+        self
+    }
+
+    /// Returns whether the connection has been lost, or is about to be, because the other side sent a [GOING_AWAY_METHOD] notification. New calls fail then, while calls in flight still get their responses.
+    pub fn is_closed(&self) -> bool {
+        let pending = self.pending.lock().unwrap();
+        pending.closed || pending.going_away
+    }
+
+    /// Waits for the next incoming message that is not a response to one of our calls. Returns `None` once the connection is lost.
+    ///
+    /// Only a limited number of messages are queued up waiting for this to be called; when the queue is full, new incoming messages are dropped with a warning. Pure clients that never call this method therefore do not accumulate messages.
+    pub async fn next_incoming(&self) -> Option<JrpcMessage> {
+        self.recv_incoming.recv().await.ok()
+    }
+
+    /// Sends a message that does not expect a response, like a notification or a response to an incoming request.
+    pub async fn send(&self, msg: &JrpcMessage) -> Result<(), MultiplexError> {
+        self.send_outgoing
+            .send(serde_json::to_string(msg).unwrap())
+            .await
+            .map_err(|_| MultiplexError::ConnectionLost)
+    }
+
+    /// Registers waiters for a set of requests, rewriting their IDs, and returns the original IDs with the receivers.
+    #[allow(clippy::type_complexity)]
+    fn register(
+        &self,
+        reqs: &mut [JrpcRequest],
+    ) -> Result<Vec<(i64, JrpcId, async_channel::Receiver<JrpcResponse>)>, MultiplexError> {
+        let mut pending = self.pending.lock().unwrap();
+        if pending.closed || pending.going_away {
+            return Err(MultiplexError::ConnectionLost);
+        }
+        Ok(reqs
+            .iter_mut()
+            .map(|req| {
+                let internal_id = self.next_id.fetch_add(1, Ordering::Relaxed);
+                let original_id = std::mem::replace(&mut req.id, JrpcId::Number(internal_id));
+                let (send, recv) = async_channel::bounded(1);
+                pending.waiters.insert(internal_id, send);
+                (internal_id, original_id, recv)
+            })
+            .collect())
+    }
+
+    async fn call_many(
+        &self,
+        mut reqs: Vec<JrpcRequest>,
+        batch: bool,
+    ) -> Result<Vec<JrpcResponse>, MultiplexError> {
+        let waiters = self.register(&mut reqs)?;
+        let internal_ids: Vec<i64> = waiters.iter().map(|w| w.0).collect();
+        let result = async {
+            if batch {
+                self.send_outgoing
+                    .send(serde_json::to_string(&reqs).unwrap())
+                    .await
+                    .map_err(|_| MultiplexError::ConnectionLost)?;
+            } else {
+                for req in reqs.iter() {
+                    self.send_outgoing
+                        .send(serde_json::to_string(req).unwrap())
+                        .await
+                        .map_err(|_| MultiplexError::ConnectionLost)?;
+                }
+            }
+            let mut responses = Vec::with_capacity(waiters.len());
+            for (_, original_id, recv) in waiters {
+                let mut resp = recv
+                    .recv()
+                    .await
+                    .map_err(|_| MultiplexError::ConnectionLost)?;
+                resp.id = original_id;
+                responses.push(resp);
+            }
+            Ok(responses)
+        };
+        let result = if let Some(timeout) = self.timeout {
+            timer::timeout(timeout, result)
+                .await
+                .unwrap_or(Err(MultiplexError::Timeout))
+        } else {
+            result.await
+        };
+        if result.is_err() {
+            let mut pending = self.pending.lock().unwrap();
+            for id in internal_ids {
+                pending.waiters.remove(&id);
+            }
+        }
+        result
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum IncomingFrame {
+    Single(JrpcMessage),
+    Batch(Vec<JrpcMessage>),
+}
+
+fn dispatch(
+    pending: &Mutex<Pending>,
+    send_incoming: &async_channel::Sender<JrpcMessage>,
+    msg: JrpcMessage,
+) {
+    match msg {
+        JrpcMessage::Response(resp) => {
+            let waiter = match &resp.id {
+                JrpcId::Number(id) => pending.lock().unwrap().waiters.remove(id),
+                _ => None,
+            };
+            if let Some(waiter) = waiter {
+                let _ = waiter.try_send(resp);
+            } else {
+                log::warn!("multiplexer dropping response to unknown id {:?}", resp.id);
+            }
+        }
+        msg => {
+            if matches!(&msg, JrpcMessage::Notification(n) if n.method == GOING_AWAY_METHOD) {
+                pending.lock().unwrap().going_away = true;
+            }
+            if let Err(async_channel::TrySendError::Full(msg)) = send_incoming.try_send(msg) {
+                log::warn!(
+                    "multiplexer dropping incoming message, queue full: {:?}",
+                    msg
+                );
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl RpcTransport for Multiplexer {
+    type Error = MultiplexError;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        Ok(self.call_many(vec![req], false).await?.pop().unwrap())
+    }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        if reqs.is_empty() {
+            return Ok(vec![]);
+        }
+        self.call_many(reqs, true).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        FnService, JrpcMessage, JrpcNotification, MultiplexError, Multiplexer, RpcService,
+        RpcTransport,
+    };
+
+    #[test]
+    fn test_multiplex_loopback() {
+        smol::future::block_on(async move {
+            let (to_server, from_client) = async_channel::unbounded::<String>();
+            let (to_client, from_server) = async_channel::unbounded::<String>();
+            let sink = futures_util::sink::unfold(to_server, |to_server, msg: String| async move {
+                to_server.send(msg).await.map_err(|_| ())?;
+                Ok::<_, ()>(to_server)
+            });
+            let (mux, driver) = Multiplexer::new(Box::pin(sink), Box::pin(from_server));
+            let mux = mux.with_timeout(Duration::from_millis(200));
+            smol::spawn(driver).detach();
+            // a server that answers in reverse order, and sends a notification first
+            let service = FnService::new(|method, params| {
+                let method = method.to_string();
+                async move { (method == "echo").then(|| Ok(params[0].clone())) }
+            });
+            let server = smol::spawn(async move {
+                let a: JrpcMessage =
+                    serde_json::from_str(&from_client.recv().await.unwrap()).unwrap();
+                let b: JrpcMessage =
+                    serde_json::from_str(&from_client.recv().await.unwrap()).unwrap();
+                let notif = JrpcMessage::Notification(JrpcNotification {
+                    jsonrpc: "2.0".into(),
+                    method: "hello".into(),
+                    params: vec![],
+                });
+                to_client
+                    .send(serde_json::to_string(&notif).unwrap())
+                    .await
+                    .unwrap();
+                for msg in [b, a] {
+                    if let JrpcMessage::Request(req) = msg {
+                        let resp = service.respond_raw(req).await;
+                        to_client
+                            .send(serde_json::to_string(&resp).unwrap())
+                            .await
+                            .unwrap();
+                    }
+                }
+                // never answer the third call, then hang up
+                from_client.recv().await.unwrap();
+                smol::Timer::after(Duration::from_millis(400)).await;
+            });
+            let (a, b) = futures_lite::future::zip(
+                mux.call("echo", &[1.into()]),
+                mux.call("echo", &[2.into()]),
+            )
+            .await;
+            assert_eq!(a.unwrap().unwrap().unwrap(), 1);
+            assert_eq!(b.unwrap().unwrap().unwrap(), 2);
+            assert!(matches!(
+                mux.next_incoming().await,
+                Some(JrpcMessage::Notification(n)) if n.method == "hello"
+            ));
+            assert_eq!(
+                mux.call("echo", &[3.into()]).await.unwrap_err(),
+                MultiplexError::Timeout
+            );
+            server.await;
+            assert!(mux.next_incoming().await.is_none());
+            assert_eq!(
+                mux.call("echo", &[4.into()]).await.unwrap_err(),
+                MultiplexError::ConnectionLost
+            );
+        });
+    }
+
+    #[test]
+    fn test_multiplex_paramless_request() {
+        smol::future::block_on(async move {
+            let (to_server, from_client) = async_channel::unbounded::<String>();
+            let (to_client, from_server) = async_channel::unbounded::<String>();
+            let sink = futures_util::sink::unfold(to_server, |to_server, msg: String| async move {
+                to_server.send(msg).await.map_err(|_| ())?;
+                Ok::<_, ()>(to_server)
+            });
+            let (mux, driver) = Multiplexer::new(Box::pin(sink), Box::pin(from_server));
+            smol::spawn(driver).detach();
+            let server = smol::spawn(async move {
+                let req: JrpcMessage =
+                    serde_json::from_str(&from_client.recv().await.unwrap()).unwrap();
+                let JrpcMessage::Request(req) = req else {
+                    panic!("expected a request")
+                };
+                // a request of our own, without params, reusing the id of the pending call
+                to_client
+                    .send(format!(
+                        r#"{{"jsonrpc":"2.0","method":"x","id":{}}}"#,
+                        req.id
+                    ))
+                    .await
+                    .unwrap();
+                // a response with neither a result nor an error is dropped
+                to_client
+                    .send(format!(r#"{{"jsonrpc":"2.0","id":{}}}"#, req.id))
+                    .await
+                    .unwrap();
+                let resp = FnService::new(|_, _| async move { Some(Ok(5.into())) })
+                    .respond_raw(req)
+                    .await;
+                to_client
+                    .send(serde_json::to_string(&resp).unwrap())
+                    .await
+                    .unwrap();
+                from_client.recv().await.ok();
+            });
+            assert_eq!(mux.call("f", &[]).await.unwrap().unwrap().unwrap(), 5);
+            assert!(matches!(
+                mux.next_incoming().await,
+                Some(JrpcMessage::Request(r)) if r.method == "x" && r.params.is_empty()
+            ));
+            drop(mux);
+            server.await;
+        });
+    }
+}
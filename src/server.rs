@@ -0,0 +1,418 @@
+use std::sync::Arc;
+
+use crate::{
+    Codec, CodecError, JrpcError, JrpcId, JrpcRequest, JrpcResponse, JsonCodec, RpcContext,
+    RpcService, ServerError,
+};
+
+/// Configuration for the bytes-level server entry point, [RpcService::respond_bytes].
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    /// The maximum number of requests within one batch that are handled concurrently.
+    pub batch_concurrency: usize,
+    /// Whether requests are parsed strictly, with [JrpcRequest::from_value_strict].
+    pub strict: bool,
+    /// Limits on the size and shape of incoming requests.
+    pub limits: Limits,
+    /// How requests and responses are encoded. Servers that carry text, like the TCP and WebSocket ones, always speak JSON regardless.
+    pub codec: Arc<dyn Codec>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            batch_concurrency: 16,
+            strict: false,
+            limits: Limits::default(),
+            codec: Arc::new(JsonCodec),
+        }
+    }
+}
+
+/// Limits on incoming messages, guarding against requests that are expensive to even parse. Violations are answered with `-32600` errors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// The maximum size of a message, in bytes.
+    pub max_message_size: usize,
+    /// The maximum number of requests in a batch.
+    pub max_batch_len: usize,
+    /// The maximum nesting depth of arrays and objects.
+    pub max_depth: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_message_size: 16 * 1024 * 1024,
+            max_batch_len: 1024,
+            max_depth: 64,
+        }
+    }
+}
+
+impl Limits {
+    /// Parses a message, checking it against the limits.
+    pub(crate) fn parse<T: serde::de::DeserializeOwned>(
+        &self,
+        msg: &[u8],
+    ) -> Result<T, LimitError> {
+        self.check_size(msg.len())?;
+        self.check_depth(nesting_depth(msg))?;
+        serde_json::from_slice(msg).map_err(LimitError::Parse)
+    }
+
+    fn check_size(&self, len: usize) -> Result<(), LimitError> {
+        if len > self.max_message_size {
+            return Err(LimitError::Violated(format!(
+                "message of {} bytes exceeds the limit of {} bytes",
+                len, self.max_message_size
+            )));
+        }
+        Ok(())
+    }
+
+    fn check_depth(&self, depth: usize) -> Result<(), LimitError> {
+        if depth > self.max_depth {
+            return Err(LimitError::Violated(format!(
+                "message nested deeper than {} levels",
+                self.max_depth
+            )));
+        }
+        Ok(())
+    }
+
+    /// Decodes a message with the given codec, checking it against the limits.
+    pub(crate) fn decode(
+        &self,
+        codec: &dyn Codec,
+        msg: &[u8],
+    ) -> Result<serde_json::Value, LimitError> {
+        self.check_size(msg.len())?;
+        let depth = codec.nesting_depth(msg);
+        if let Some(depth) = depth {
+            self.check_depth(depth)?;
+        }
+        let value = codec.decode_value(msg).map_err(LimitError::Decode)?;
+        if depth.is_none() {
+            self.check_depth(value_depth(&value))?;
+        }
+        Ok(value)
+    }
+
+    /// Checks the length of a batch.
+    pub(crate) fn check_batch(&self, len: usize) -> Result<(), LimitError> {
+        if len > self.max_batch_len {
+            Err(LimitError::Violated(format!(
+                "batch of {} requests exceeds the limit of {}",
+                len, self.max_batch_len
+            )))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+pub(crate) enum LimitError {
+    Parse(serde_json::Error),
+    Decode(CodecError),
+    Violated(String),
+}
+
+impl LimitError {
+    pub(crate) fn into_response(self) -> JrpcResponse {
+        match self {
+            LimitError::Parse(err) => error_response(JrpcId::Null, -32700, err.to_string()),
+            LimitError::Decode(err) => error_response(JrpcId::Null, -32700, err.to_string()),
+            LimitError::Violated(msg) => error_response(JrpcId::Null, -32600, msg),
+        }
+    }
+}
+
+/// The deepest nesting of arrays and objects in some JSON, without parsing it.
+pub(crate) fn nesting_depth(json: &[u8]) -> usize {
+    let mut depth = 0usize;
+    let mut max_depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for &b in json {
+        if in_string {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+        } else {
+            match b {
+                b'"' => in_string = true,
+                b'[' | b'{' => {
+                    depth += 1;
+                    max_depth = max_depth.max(depth);
+                }
+                b']' | b'}' => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+    }
+    max_depth
+}
+
+/// The deepest nesting of arrays and objects in an already decoded message.
+fn value_depth(value: &serde_json::Value) -> usize {
+    match value {
+        serde_json::Value::Array(items) => 1 + items.iter().map(value_depth).max().unwrap_or(0),
+        serde_json::Value::Object(fields) => {
+            1 + fields.values().map(value_depth).max().unwrap_or(0)
+        }
+        _ => 0,
+    }
+}
+
+impl ServerConfig {
+    /// Parses a request, also returning whether it is a notification, which must not be answered.
+    fn parse_request(
+        &self,
+        mut req: serde_json::Value,
+    ) -> Result<(JrpcRequest, bool), serde_json::Error> {
+        // notifications are requests without an id, and are handled like requests with a null one
+        let notification = match &mut req {
+            serde_json::Value::Object(obj) if !obj.contains_key("id") => {
+                obj.insert("id".into(), serde_json::Value::Null);
+                true
+            }
+            _ => false,
+        };
+        let req = if self.strict {
+            JrpcRequest::from_value_strict(req)?
+        } else {
+            serde_json::from_value(req)?
+        };
+        Ok((req, notification))
+    }
+}
+
+pub(crate) async fn respond_bytes<S: RpcService + ?Sized>(
+    service: &S,
+    ctx: RpcContext,
+    req: &[u8],
+    config: &ServerConfig,
+) -> Vec<u8> {
+    let codec = &*config.codec;
+    let req = match config.limits.decode(codec, req) {
+        Ok(req) => req,
+        Err(err) => return codec.encode_response(&err.into_response()),
+    };
+    match req {
+        serde_json::Value::Array(batch) if !batch.is_empty() => {
+            if let Err(err) = config.limits.check_batch(batch.len()) {
+                return codec.encode_response(&err.into_response());
+            }
+            // requests that fail to parse are answered immediately, and the rest are handled together
+            let mut responses: Vec<Result<JrpcResponse, bool>> = Vec::with_capacity(batch.len());
+            let mut valid = vec![];
+            for req in batch {
+                match config.parse_request(req) {
+                    Ok((req, notification)) => {
+                        responses.push(Err(notification));
+                        valid.push(req);
+                    }
+                    Err(err) => {
+                        responses.push(Ok(error_response(JrpcId::Null, -32600, err.to_string())))
+                    }
+                }
+            }
+            let mut handled = service
+                .respond_raw_batch_with_context(ctx, valid, config.batch_concurrency)
+                .await
+                .into_iter();
+            // notifications are executed, but left out of the response
+            let responses: Vec<JrpcResponse> = responses
+                .into_iter()
+                .filter_map(|resp| match resp {
+                    Ok(resp) => Some(resp),
+                    Err(notification) => {
+                        let resp = handled.next().unwrap();
+                        (!notification).then_some(resp)
+                    }
+                })
+                .collect();
+            if responses.is_empty() {
+                return vec![];
+            }
+            codec.encode_responses(&responses)
+        }
+        req => match config.parse_request(req) {
+            Ok((req, notification)) => {
+                let response = service.respond_raw_with_context(ctx, req).await;
+                if notification {
+                    vec![]
+                } else {
+                    codec.encode_response(&response)
+                }
+            }
+            Err(err) => {
+                codec.encode_response(&error_response(JrpcId::Null, -32600, err.to_string()))
+            }
+        },
+    }
+}
+
+pub(crate) fn error_response(id: JrpcId, code: i64, message: impl Into<String>) -> JrpcResponse {
+    JrpcResponse {
+        id,
+        jsonrpc: "2.0".into(),
+        result: None,
+        error: Some(JrpcError {
+            code,
+            message: message.into(),
+            data: serde_json::Value::Null,
+        }),
+    }
+}
+
+/// Turns the result of [RpcService::respond] into a JSON-RPC response.
+pub(crate) fn to_jrpc_response(
+    id: JrpcId,
+    response: Option<Result<serde_json::Value, ServerError>>,
+) -> JrpcResponse {
+    match response {
+        Some(Ok(response)) => JrpcResponse {
+            id,
+            jsonrpc: "2.0".into(),
+            result: Some(response),
+            error: None,
+        },
+        Some(Err(err)) => JrpcResponse {
+            id,
+            jsonrpc: "2.0".into(),
+            result: None,
+            error: Some(JrpcError {
+                code: err.jrpc_code().unwrap_or(-1),
+                message: err.message,
+                data: err.details,
+            }),
+        },
+        None => error_response(id, -32601, "Method not found"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use crate::{FnService, Limits, RpcService, ServerConfig};
+
+    #[test]
+    fn test_batch_order() {
+        smol::future::block_on(async move {
+            // earlier requests take longer, so they finish last
+            let service = FnService::new(|_, params| async move {
+                let n = params[0].as_u64().unwrap();
+                smol::Timer::after(Duration::from_millis(50 - n * 10)).await;
+                Some(Ok(n.into()))
+            });
+            let batch = br#"[
+                {"jsonrpc": "2.0", "method": "f", "params": [0], "id": 0},
+                {"jsonrpc": "2.0", "method": "f", "params": [1], "id": 1},
+                {"bad": "request"},
+                {"jsonrpc": "2.0", "method": "f", "params": [2], "id": 2}
+            ]"#;
+            let response: serde_json::Value = serde_json::from_slice(
+                &service.respond_bytes(batch, &ServerConfig::default()).await,
+            )
+            .unwrap();
+            assert_eq!(response[0]["result"], 0);
+            assert_eq!(response[1]["result"], 1);
+            assert_eq!(response[2]["error"]["code"], -32600);
+            assert_eq!(response[3]["result"], 2);
+
+            let response: serde_json::Value = serde_json::from_slice(
+                &service.respond_bytes(b"{", &ServerConfig::default()).await,
+            )
+            .unwrap();
+            assert_eq!(response["error"]["code"], -32700);
+            assert!(response["id"].is_null());
+        });
+    }
+
+    #[test]
+    fn test_notifications() {
+        smol::future::block_on(async move {
+            let calls = Arc::new(AtomicUsize::new(0));
+            let service = {
+                let calls = calls.clone();
+                FnService::new(move |_, _| {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    async move { Some(Ok(().into())) }
+                })
+            };
+            let config = ServerConfig::default();
+            let notification = br#"{"jsonrpc": "2.0", "method": "f", "params": []}"#;
+            assert!(service
+                .respond_bytes(notification, &config)
+                .await
+                .is_empty());
+            let batch = br#"[
+                {"jsonrpc": "2.0", "method": "f", "params": []},
+                {"jsonrpc": "2.0", "method": "f", "params": [], "id": 1},
+                {"jsonrpc": "2.0", "method": "f", "params": []}
+            ]"#;
+            let response: serde_json::Value =
+                serde_json::from_slice(&service.respond_bytes(batch, &config).await).unwrap();
+            assert_eq!(response.as_array().unwrap().len(), 1);
+            assert_eq!(response[0]["id"], 1);
+            let batch = br#"[
+                {"jsonrpc": "2.0", "method": "f", "params": []},
+                {"jsonrpc": "2.0", "method": "f", "params": []}
+            ]"#;
+            assert!(service.respond_bytes(batch, &config).await.is_empty());
+            assert_eq!(calls.load(Ordering::SeqCst), 6);
+        });
+    }
+
+    #[test]
+    fn test_limits() {
+        smol::future::block_on(async move {
+            let service = FnService::new(|_, _| async move { Some(Ok(().into())) });
+            let config = ServerConfig {
+                limits: Limits {
+                    max_message_size: 200,
+                    max_batch_len: 2,
+                    max_depth: 3,
+                },
+                ..Default::default()
+            };
+            let respond = |req: String| {
+                let service = &service;
+                let config = &config;
+                async move {
+                    let resp = service.respond_bytes(req.as_bytes(), config).await;
+                    serde_json::from_slice::<serde_json::Value>(&resp).unwrap()
+                }
+            };
+            let req = r#"{"jsonrpc": "2.0", "method": "f", "params": [[]], "id": 1}"#;
+            assert!(respond(req.into()).await["result"].is_null());
+            // brackets inside strings don't count
+            let req = r#"{"jsonrpc": "2.0", "method": "f", "params": ["[[[[\"]]"], "id": 1}"#;
+            assert!(respond(req.into()).await["result"].is_null());
+            let req = r#"{"jsonrpc": "2.0", "method": "f", "params": [[[]]], "id": 1}"#;
+            assert_eq!(respond(req.into()).await["error"]["code"], -32600);
+            let req = format!(
+                r#"{{"jsonrpc": "2.0", "method": "{}", "params": [], "id": 1}}"#,
+                "f".repeat(200)
+            );
+            assert_eq!(respond(req).await["error"]["code"], -32600);
+            let req = r#"{"jsonrpc": "2.0", "method": "f", "params": [], "id": 1}"#;
+            assert_eq!(
+                respond(format!("[{req}, {req}, {req}]")).await["error"]["code"],
+                -32600
+            );
+        });
+    }
+}
@@ -0,0 +1,321 @@
+use std::{
+    fmt::Debug,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use async_trait::async_trait;
+use futures_util::{Sink, SinkExt, Stream, StreamExt};
+
+use crate::{
+    Identity, JrpcNotification, JsonCodec, MultiplexError, RpcContext, RpcService, ServerConfig,
+    ShutdownSignal,
+};
+
+/// The method of the notification that [serve_connection] sends once it has drained a connection, right before closing it. Clients should make new calls on a new connection from then on, which a [crate::Multiplexer] does by reporting itself closed.
+pub const GOING_AWAY_METHOD: &str = "rpc.goingAway";
+
+/// Information about a connection to a stream-oriented server, for a [SessionFactory]. [serve_connection] also attaches it to the [RpcContext] of every call as an extension, together with the peer's [SocketAddr] and [Identity] if known.
+#[derive(Clone, Debug)]
+pub struct ConnInfo {
+    /// A process-wide unique ID for the connection.
+    pub id: u64,
+    /// The address of the other side, if the connection has one.
+    pub peer_addr: Option<SocketAddr>,
+    /// The address of our side, if the connection has one.
+    pub local_addr: Option<SocketAddr>,
+    /// The verified identity of the other side, if the connection authenticated it, like with a TLS client certificate. [serve_connection] attaches it to the context of every call.
+    pub identity: Option<Identity>,
+    /// Sends notifications to the other side, if the connection supports them. [serve_connection] always sets this.
+    pub notifier: Option<Notifier>,
+    /// A signal that makes [serve_connection] drain the connection, if set. Servers set this when their [crate::ServeHandle] drains connections.
+    pub drain: Option<ShutdownSignal>,
+}
+
+impl Default for ConnInfo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConnInfo {
+    /// Creates the info for a new connection, with a fresh ID and no addresses.
+    pub fn new() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            peer_addr: None,
+            local_addr: None,
+            identity: None,
+            notifier: None,
+            drain: None,
+        }
+    }
+
+    /// Sets the address of the other side.
+    pub fn with_peer_addr(mut self, addr: SocketAddr) -> Self {
+        self.peer_addr = Some(addr);
+        self
+    }
+
+    /// Sets the address of our side.
+    pub fn with_local_addr(mut self, addr: SocketAddr) -> Self {
+        self.local_addr = Some(addr);
+        self
+    }
+
+    /// Sets the verified identity of the other side.
+    pub fn with_identity(mut self, identity: Identity) -> Self {
+        self.identity = Some(identity);
+        self
+    }
+
+    /// Drains the connection once the signal fires: no more requests are read, and once every response has been sent, the other side is told with a [GOING_AWAY_METHOD] notification and the connection is closed.
+    pub fn with_drain(mut self, signal: ShutdownSignal) -> Self {
+        self.drain = Some(signal);
+        self
+    }
+}
+
+/// A Notifier sends server-initiated notifications to the other side of a connection, like subscription updates. It can be kept around after the call that got it returns, but stops working once the connection closes.
+#[derive(Clone)]
+pub struct Notifier(pub(crate) async_channel::Sender<String>);
+
+impl Debug for Notifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Notifier")
+            .field("closed", &self.is_closed())
+            .finish()
+    }
+}
+
+impl Notifier {
+    /// Sends a notification.
+    pub async fn notify(
+        &self,
+        method: impl Into<String>,
+        params: Vec<serde_json::Value>,
+    ) -> Result<(), MultiplexError> {
+        self.send(&JrpcNotification {
+            jsonrpc: "2.0".into(),
+            method: method.into(),
+            params,
+        })
+        .await
+    }
+
+    /// Sends an already built notification.
+    pub async fn send(&self, notification: &JrpcNotification) -> Result<(), MultiplexError> {
+        self.0
+            .send(serde_json::to_string(notification).unwrap())
+            .await
+            .map_err(|_| MultiplexError::ConnectionLost)
+    }
+
+    /// Forwards notifications, like those of a [crate::Broadcaster] subscription, until either they run out or the connection closes.
+    pub async fn forward(&self, notifications: async_channel::Receiver<JrpcNotification>) {
+        while let Ok(notification) = notifications.recv().await {
+            if self.send(&notification).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Returns whether the connection has closed.
+    pub fn is_closed(&self) -> bool {
+        self.0.is_closed()
+    }
+}
+
+/// A SessionFactory makes a service for every connection to a stream-oriented server, so that the service can hold per-session state, like subscriptions or a logged-in user. The service is dropped when the connection closes.
+///
+/// This is implemented for closures of the form `Fn(&ConnInfo) -> S`. Servers without per-session state can share one service between all connections with something like `move |_: &ConnInfo| service.clone()`, where `service` is an `Arc`.
+#[async_trait]
+pub trait SessionFactory: Send + Sync + 'static {
+    /// The type of service made for each connection.
+    type Service: RpcService;
+
+    /// Makes the service for a new connection.
+    async fn make_service(&self, conn: &ConnInfo) -> Self::Service;
+}
+
+#[async_trait]
+impl<S: RpcService, F: Fn(&ConnInfo) -> S + Send + Sync + 'static> SessionFactory for F {
+    type Service = S;
+
+    async fn make_service(&self, conn: &ConnInfo) -> S {
+        self(conn)
+    }
+}
+
+/// Serves one connection that carries one JSON-RPC message per frame, like a WebSocket or a TCP stream of JSON lines, with a service made by the factory. Up to [ServerConfig::batch_concurrency] messages are handled concurrently, so responses may be sent in a different order than the requests arrived.
+///
+/// The session's [ConnInfo] carries a [Notifier] for sending notifications over the connection. Resolves once the incoming stream ends and every response has been sent, or the connection fails, or the connection has been drained as set by [ConnInfo::with_drain]. The session's service is dropped then.
+pub async fn serve_connection<F, Si, St>(
+    factory: &F,
+    conn: ConnInfo,
+    sink: Si,
+    stream: St,
+    config: &ServerConfig,
+) where
+    F: SessionFactory,
+    Si: Sink<String> + Unpin,
+    Si::Error: Debug,
+    St: Stream<Item = String> + Unpin,
+{
+    // frames are text, so they always carry JSON whatever the codec
+    let config = &ServerConfig {
+        codec: Arc::new(JsonCodec),
+        ..config.clone()
+    };
+    let (send_outgoing, recv_outgoing) = async_channel::unbounded::<String>();
+    let mut conn = conn;
+    conn.notifier = Some(Notifier(send_outgoing.clone()));
+    let service = factory.make_service(&conn).await;
+    let drain = conn.drain.clone();
+    let mut ctx = RpcContext::new();
+    if let Some(peer_addr) = conn.peer_addr {
+        ctx.insert_extension(peer_addr);
+    }
+    if let Some(identity) = conn.identity.clone() {
+        ctx.insert_extension(identity);
+    }
+    ctx.insert_extension(conn);
+    let reader = async move {
+        let drain_signal = drain.clone();
+        let stream = stream.take_until(Box::pin(async move {
+            match drain_signal {
+                Some(signal) => signal.wait().await,
+                None => futures_lite::future::pending().await,
+            }
+        }));
+        stream
+            .for_each_concurrent(config.batch_concurrency.max(1), |frame| {
+                let service = &service;
+                let ctx = ctx.clone();
+                let send_outgoing = send_outgoing.clone();
+                async move {
+                    if frame.trim().is_empty() {
+                        return;
+                    }
+                    let resp = service
+                        .respond_bytes_with_context(ctx, frame.as_bytes(), config)
+                        .await;
+                    if resp.is_empty() {
+                        // only notifications, which get no response
+                        return;
+                    }
+                    let _ = send_outgoing
+                        .send(String::from_utf8(resp).expect("serde_json always writes UTF-8"))
+                        .await;
+                }
+            })
+            .await;
+        if drain.is_some_and(|signal| signal.is_triggered()) {
+            let going_away = JrpcNotification {
+                jsonrpc: "2.0".into(),
+                method: GOING_AWAY_METHOD.into(),
+                params: vec![],
+            };
+            let _ = send_outgoing
+                .send(serde_json::to_string(&going_away).unwrap())
+                .await;
+        }
+        // notifiers may outlive the connection, so the channel must be closed explicitly
+        send_outgoing.close();
+    };
+    let writer = async move {
+        let mut sink = sink;
+        while let Ok(msg) = recv_outgoing.recv().await {
+            if let Err(err) = sink.send(msg).await {
+                log::debug!("session connection failed to send: {:?}", err);
+                return;
+            }
+        }
+    };
+    futures_lite::future::or(
+        async {
+            reader.await;
+            // the writer returns once it has sent everything left in the closed channel
+            futures_lite::future::pending().await
+        },
+        writer,
+    )
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    };
+
+    use crate::{serve_connection, ConnInfo, FnService, ServerConfig};
+
+    #[test]
+    fn test_sessions() {
+        smol::future::block_on(async move {
+            let live = Arc::new(AtomicU64::new(0));
+            let factory = {
+                let live = live.clone();
+                move |conn: &ConnInfo| {
+                    // each session counts its own calls
+                    let calls = Arc::new(AtomicU64::new(0));
+                    let conn_id = conn.id;
+                    let guard = LiveGuard::new(live.clone());
+                    let notifier = conn.notifier.clone().unwrap();
+                    FnService::new(move |_, _| {
+                        let _ = &guard;
+                        let n = calls.fetch_add(1, Ordering::SeqCst);
+                        let notifier = notifier.clone();
+                        async move {
+                            notifier.notify("tick", vec![n.into()]).await.unwrap();
+                            Some(Ok(serde_json::json!([conn_id, n])))
+                        }
+                    })
+                }
+            };
+            let config = ServerConfig {
+                batch_concurrency: 1,
+                ..Default::default()
+            };
+            let req = r#"{"jsonrpc": "2.0", "method": "f", "params": [], "id": 1}"#.to_string();
+            for _ in 0..2 {
+                let conn = ConnInfo::new();
+                let conn_id = conn.id;
+                let mut out = vec![];
+                let sink = futures_util::sink::unfold(&mut out, |out, msg: String| async move {
+                    out.push(msg);
+                    Ok::<_, ()>(out)
+                });
+                let stream = futures_util::stream::iter(vec![req.clone(), "".into(), req.clone()]);
+                serve_connection(&factory, conn, Box::pin(sink), stream, &config).await;
+                assert_eq!(out.len(), 4);
+                let tick: serde_json::Value = serde_json::from_str(&out[2]).unwrap();
+                assert_eq!(tick["method"], "tick");
+                let last: serde_json::Value = serde_json::from_str(&out[3]).unwrap();
+                assert_eq!(last["result"], serde_json::json!([conn_id, 1]));
+                assert_eq!(live.load(Ordering::SeqCst), 0);
+            }
+        });
+    }
+
+    struct LiveGuard(Arc<AtomicU64>);
+
+    impl LiveGuard {
+        fn new(live: Arc<AtomicU64>) -> Self {
+            live.fetch_add(1, Ordering::SeqCst);
+            Self(live)
+        }
+    }
+
+    impl Drop for LiveGuard {
+        fn drop(&mut self) {
+            self.0.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use std::{
+    fmt::Debug,
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use event_listener::Event;
+
+use crate::{timer, ConnInfo};
+
+/// A one-shot signal, shared between clones, that tells a server to shut down.
+#[derive(Clone, Default)]
+pub struct ShutdownSignal {
+    inner: Arc<SignalInner>,
+}
+
+impl Debug for ShutdownSignal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShutdownSignal")
+            .field("triggered", &self.is_triggered())
+            .finish()
+    }
+}
+
+#[derive(Default)]
+struct SignalInner {
+    triggered: AtomicBool,
+    event: Event,
+}
+
+impl ShutdownSignal {
+    /// Creates a new, untriggered signal.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Triggers the signal, waking up everybody waiting on it. Triggering an already-triggered signal does nothing.
+    pub fn trigger(&self) {
+        self.inner.triggered.store(true, Ordering::SeqCst);
+        self.inner.event.notify(usize::MAX);
+    }
+
+    /// Returns whether the signal has been triggered.
+    pub fn is_triggered(&self) -> bool {
+        self.inner.triggered.load(Ordering::SeqCst)
+    }
+
+    /// Waits until the signal is triggered.
+    pub async fn wait(&self) {
+        loop {
+            if self.is_triggered() {
+                return;
+            }
+            let listener = self.inner.event.listen();
+            if self.is_triggered() {
+                return;
+            }
+            listener.await;
+        }
+    }
+}
+
+/// A ServeHandle tracks the in-flight requests of a server, so that it can be gracefully shut down. Server adapters should run every `respond_raw` future through [ServeHandle::track], and stop accepting connections once [ServeHandle::shutdown_signal] fires.
+#[derive(Clone, Default)]
+pub struct ServeHandle {
+    inner: Arc<HandleInner>,
+}
+
+#[derive(Default)]
+struct HandleInner {
+    shutdown: ShutdownSignal,
+    cancel: ShutdownSignal,
+    in_flight: AtomicUsize,
+    idle: Event,
+    drain_connections: AtomicBool,
+}
+
+impl ServeHandle {
+    /// Creates a new ServeHandle.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes stream-based servers, like [crate::TcpServer], drain their open connections on shutdown instead of waiting for clients to close them: each connection stops reading requests, sends the responses still in flight, tells the client with a [crate::GOING_AWAY_METHOD] notification, and closes. Clients that reconnect, like [crate::TcpTransport], then make new calls on another connection, while the calls in flight still get their responses.
+    pub fn with_connection_draining(self) -> Self {
+        self.inner.drain_connections.store(true, Ordering::SeqCst);
+        self
+    }
+
+    /// Returns whether stream-based servers drain their open connections on shutdown.
+    pub fn drains_connections(&self) -> bool {
+        self.inner.drain_connections.load(Ordering::SeqCst)
+    }
+
+    /// Makes a new connection drain on shutdown, if this handle drains connections. Servers built on [crate::serve_connection] should pass the [ConnInfo] of every connection through this.
+    pub fn prepare_conn(&self, conn: ConnInfo) -> ConnInfo {
+        if self.drains_connections() {
+            conn.with_drain(self.shutdown_signal())
+        } else {
+            conn
+        }
+    }
+
+    /// Returns the signal that fires when the server should stop accepting new requests.
+    pub fn shutdown_signal(&self) -> ShutdownSignal {
+        self.inner.shutdown.clone()
+    }
+
+    /// Returns whether new requests are still being accepted.
+    pub fn is_accepting(&self) -> bool {
+        !self.inner.shutdown.is_triggered()
+    }
+
+    /// Returns the number of requests currently in flight.
+    pub fn in_flight(&self) -> usize {
+        self.inner.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Runs a request-handling future, keeping track of it while it is in flight. Returns `None` if the server is already shutting down, or if the future was cancelled because shutdown timed out.
+    pub async fn track<F: Future>(&self, fut: F) -> Option<F::Output> {
+        self.inner.in_flight.fetch_add(1, Ordering::SeqCst);
+        let _guard = InFlightGuard(&self.inner);
+        if !self.is_accepting() {
+            return None;
+        }
+        futures_lite::future::or(async { Some(fut.await) }, async {
+            self.inner.cancel.wait().await;
+            None
+        })
+        .await
+    }
+
+    /// Gracefully shuts down: stops accepting new requests, waits up to `timeout` for in-flight requests to finish, then cancels whatever is left. Returns whether every in-flight request finished in time.
+    pub async fn shutdown(&self, timeout: Duration) -> bool {
+        self.inner.shutdown.trigger();
+        let finished = timer::timeout(timeout, self.wait_idle()).await.is_some();
+        if !finished {
+            self.inner.cancel.trigger();
+            self.wait_idle().await;
+        }
+        finished
+    }
+
+    async fn wait_idle(&self) {
+        loop {
+            if self.in_flight() == 0 {
+                return;
+            }
+            let listener = self.inner.idle.listen();
+            if self.in_flight() == 0 {
+                return;
+            }
+            listener.await;
+        }
+    }
+}
+
+struct InFlightGuard<'a>(&'a HandleInner);
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        if self.0.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify(usize::MAX);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::ServeHandle;
+
+    #[test]
+    fn test_shutdown_cancels_stragglers() {
+        smol::future::block_on(async move {
+            let handle = ServeHandle::new();
+            let fast = smol::spawn({
+                let handle = handle.clone();
+                async move { handle.track(async { 1 }).await }
+            });
+            assert_eq!(fast.await, Some(1));
+            let slow = smol::spawn({
+                let handle = handle.clone();
+                async move {
+                    handle
+                        .track(smol::Timer::after(Duration::from_secs(60)))
+                        .await
+                }
+            });
+            while handle.in_flight() == 0 {
+                smol::future::yield_now().await;
+            }
+            assert!(!handle.shutdown(Duration::from_millis(50)).await);
+            assert!(slow.await.is_none());
+            assert_eq!(handle.in_flight(), 0);
+            assert!(handle.track(async { 2 }).await.is_none());
+        });
+    }
+}
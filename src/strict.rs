@@ -0,0 +1,127 @@
+use std::collections::BTreeMap;
+
+use serde::{de::Error, Deserialize, Deserializer};
+
+use crate::{JrpcError, JrpcId, JrpcRequest, JrpcResponse};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StrictRequest {
+    jsonrpc: String,
+    method: String,
+    params: Vec<serde_json::Value>,
+    id: JrpcId,
+    #[serde(default)]
+    meta: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StrictResponse {
+    jsonrpc: String,
+    // distinguishes between a missing result and a null one
+    #[serde(default, deserialize_with = "present")]
+    result: Option<serde_json::Value>,
+    #[serde(default)]
+    error: Option<StrictError>,
+    id: JrpcId,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StrictError {
+    code: i64,
+    message: String,
+    #[serde(default)]
+    data: serde_json::Value,
+}
+
+fn present<'de, D: Deserializer<'de>>(d: D) -> Result<Option<serde_json::Value>, D::Error> {
+    serde_json::Value::deserialize(d).map(Some)
+}
+
+fn check_version(jsonrpc: &str) -> Result<(), serde_json::Error> {
+    if jsonrpc == "2.0" {
+        Ok(())
+    } else {
+        Err(serde_json::Error::custom(format!(
+            "unsupported JSON-RPC version {:?}",
+            jsonrpc
+        )))
+    }
+}
+
+impl JrpcRequest {
+    /// Parses a request strictly, for servers that must be conservative about what they accept: unknown fields are rejected, and `jsonrpc` must be `"2.0"`. Plain deserialization is more permissive.
+    pub fn from_value_strict(value: serde_json::Value) -> Result<Self, serde_json::Error> {
+        let req: StrictRequest = serde_json::from_value(value)?;
+        check_version(&req.jsonrpc)?;
+        Ok(Self {
+            jsonrpc: req.jsonrpc,
+            method: req.method,
+            params: req.params,
+            id: req.id,
+            meta: req.meta,
+        })
+    }
+}
+
+impl JrpcResponse {
+    /// Parses a response strictly: unknown fields are rejected, `jsonrpc` must be `"2.0"`, and exactly one of `result` and `error` must be present. Plain deserialization is more permissive.
+    pub fn from_value_strict(value: serde_json::Value) -> Result<Self, serde_json::Error> {
+        let resp: StrictResponse = serde_json::from_value(value)?;
+        check_version(&resp.jsonrpc)?;
+        match (resp.result, resp.error) {
+            (Some(_), Some(_)) => Err(serde_json::Error::custom(
+                "response has both a result and an error",
+            )),
+            (None, None) => Err(serde_json::Error::custom(
+                "response has neither a result nor an error",
+            )),
+            (result, error) => Ok(Self {
+                jsonrpc: resp.jsonrpc,
+                result,
+                error: error.map(|e| JrpcError {
+                    code: e.code,
+                    message: e.message,
+                    data: e.data,
+                }),
+                id: resp.id,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{JrpcRequest, JrpcResponse};
+
+    #[test]
+    fn test_strict_parsing() {
+        let req = json!({"jsonrpc": "2.0", "method": "f", "params": [], "id": 1});
+        assert!(JrpcRequest::from_value_strict(req.clone()).is_ok());
+        let mut extra = req.clone();
+        extra["extra"] = json!(1);
+        assert!(JrpcRequest::from_value_strict(extra.clone()).is_err());
+        assert!(serde_json::from_value::<JrpcRequest>(extra).is_ok());
+        let mut old = req;
+        old["jsonrpc"] = json!("1.0");
+        assert!(JrpcRequest::from_value_strict(old).is_err());
+
+        assert!(JrpcResponse::from_value_strict(
+            json!({"jsonrpc": "2.0", "result": null, "id": 1})
+        )
+        .is_ok());
+        assert!(JrpcResponse::from_value_strict(json!({"jsonrpc": "2.0", "id": 1})).is_err());
+        let both = json!({
+            "jsonrpc": "2.0",
+            "result": 1,
+            "error": {"code": 1, "message": "x", "data": null},
+            "id": 1
+        });
+        assert!(JrpcResponse::from_value_strict(both.clone()).is_err());
+        assert!(serde_json::from_value::<JrpcResponse>(both).is_ok());
+    }
+}
@@ -0,0 +1,53 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+/// A source of timers. All of nanorpc's timeouts, retries, and keepalives go through the global Timer, so that they work under any async runtime.
+///
+/// By default, [futures_timer] is used, which runs its own timer thread. Applications can use their runtime's own timers instead by calling [set_timer] at startup.
+pub trait Timer: Send + Sync + 'static {
+    /// Returns a future that resolves after `duration` has elapsed.
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+}
+
+/// The default [Timer], based on [futures_timer].
+pub struct FuturesTimer;
+
+impl Timer for FuturesTimer {
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        Box::pin(futures_timer::Delay::new(duration))
+    }
+}
+
+static GLOBAL_TIMER: OnceLock<Arc<dyn Timer>> = OnceLock::new();
+
+/// Sets the global [Timer]. This can only be done once, before any timer is used; otherwise, the timer is given back as an error.
+pub fn set_timer<T: Timer>(timer: T) -> Result<(), T> {
+    let mut timer = Some(timer);
+    GLOBAL_TIMER.get_or_init(|| Arc::new(timer.take().unwrap()));
+    match timer {
+        Some(timer) => Err(timer),
+        None => Ok(()),
+    }
+}
+
+fn global_timer() -> &'static Arc<dyn Timer> {
+    GLOBAL_TIMER.get_or_init(|| Arc::new(FuturesTimer))
+}
+
+/// Sleeps for the given duration, using the global timer.
+pub(crate) fn sleep(duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+    global_timer().sleep(duration)
+}
+
+/// Runs a future with a timeout, returning `None` if it timed out.
+pub(crate) async fn timeout<F: Future>(duration: Duration, fut: F) -> Option<F::Output> {
+    futures_lite::future::or(async { Some(fut.await) }, async {
+        sleep(duration).await;
+        None
+    })
+    .await
+}
@@ -1,13 +1,105 @@
+mod acl;
+mod aggregate;
+mod audit;
+mod auth;
+mod balance;
+mod batching;
+mod broadcast;
+mod cache;
+mod capabilities;
+mod channel;
+mod concurrency;
+mod conn_pool;
+mod deadline;
+mod dedup;
+mod fallback;
+mod gateway;
+mod hedge;
+mod logging;
+mod map_err;
+mod metrics;
+mod mock;
+mod namespace;
+mod observe;
+mod ping;
+mod pool;
+mod priority;
+mod queue;
+mod ratelimit;
+mod reconnect;
+mod record;
+mod redact;
+mod registry;
+mod resolve;
+mod retry;
+mod router;
+mod swap;
+mod tenant;
+mod throttle;
+mod timeout;
+#[cfg(feature = "tracing")]
+mod trace;
+pub use acl::*;
+pub use aggregate::*;
+pub use audit::*;
+pub use auth::*;
+pub use balance::*;
+pub use batching::*;
+pub use broadcast::*;
+pub use cache::*;
+pub use capabilities::*;
+pub use channel::*;
+pub use concurrency::*;
+pub use conn_pool::*;
+pub use deadline::*;
+pub use dedup::*;
+pub use fallback::*;
+pub use gateway::*;
+pub use hedge::*;
+pub use logging::*;
+pub use map_err::*;
+pub use metrics::*;
+pub use mock::*;
+pub use namespace::*;
+pub use observe::*;
+pub use ping::*;
+pub use pool::*;
+pub use priority::*;
+pub use queue::*;
+pub use ratelimit::*;
+pub use reconnect::*;
+pub use record::*;
+pub use redact::*;
+pub use registry::*;
+pub use resolve::*;
+pub use retry::*;
+pub use router::*;
+pub use swap::*;
+pub use tenant::*;
+pub use throttle::*;
+pub use timeout::*;
+#[cfg(feature = "tracing")]
+pub use trace::*;
+
 use std::{pin::Pin, sync::Arc};
 
-use crate::{JrpcRequest, JrpcResponse, RpcService, RpcTransport, ServerError};
+use crate::{JrpcRequest, JrpcResponse, RpcContext, RpcService, RpcTransport, ServerError};
 use async_trait::async_trait;
 use futures_lite::future::Boxed;
 
 /// A typed-erased RpcTransport, returning the commonly used dynamically-typed error [anyhow::Error]. Use this type instead of `Box<RpcTransport<...>>` to work around some sharp edges around actual trait objects.
+///
+/// The futures it returns are `Send`, so it can be used from spawned tasks on multithreaded executors.
+#[allow(clippy::type_complexity)]
 pub struct DynRpcTransport {
     raw_caller:
         Box<dyn Fn(JrpcRequest) -> Boxed<anyhow::Result<JrpcResponse>> + Send + Sync + 'static>,
+    raw_batch_caller: Box<
+        dyn Fn(Vec<JrpcRequest>) -> Boxed<anyhow::Result<Vec<JrpcResponse>>>
+            + Send
+            + Sync
+            + 'static,
+    >,
 }
 
 impl DynRpcTransport {
@@ -17,11 +109,39 @@ impl DynRpcTransport {
         T::Error: Into<anyhow::Error>,
     {
         let t = Arc::new(t);
+        let t2 = t.clone();
         Self {
             raw_caller: Box::new(move |req| {
                 let t = t.clone();
                 Box::pin(async move { t.call_raw(req).await.map_err(|e| e.into()) })
             }),
+            raw_batch_caller: Box::new(move |reqs| {
+                let t = t2.clone();
+                Box::pin(async move { t.call_raw_batch(reqs).await.map_err(|e| e.into()) })
+            }),
+        }
+    }
+
+    /// Creates a new dynamically-typed RpcTransport directly from a function implementing [RpcTransport::call_raw]. Batches are sent one call at a time.
+    pub fn from_fn<Fut, Fun>(f: Fun) -> Self
+    where
+        Fut: std::future::Future<Output = anyhow::Result<JrpcResponse>> + Send + 'static,
+        Fun: Fn(JrpcRequest) -> Fut + Send + Sync + 'static,
+    {
+        let f = Arc::new(f);
+        let f2 = f.clone();
+        Self {
+            raw_caller: Box::new(move |req| Box::pin(f(req))),
+            raw_batch_caller: Box::new(move |reqs| {
+                let f = f2.clone();
+                Box::pin(async move {
+                    let mut resps = Vec::with_capacity(reqs.len());
+                    for req in reqs {
+                        resps.push(f(req).await?);
+                    }
+                    Ok(resps)
+                })
+            }),
         }
     }
 }
@@ -32,6 +152,52 @@ impl RpcTransport for DynRpcTransport {
     async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
         (self.raw_caller)(req).await
     }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        (self.raw_batch_caller)(reqs).await
+    }
+}
+
+/// A type-erased RpcService, so that services of different types can be stored together, like in a `Vec` or `HashMap`. Cloning a DynRpcService is cheap, and clones share the same underlying service.
+#[derive(Clone)]
+pub struct DynRpcService(Arc<dyn RpcService>);
+
+impl DynRpcService {
+    /// Creates a new dynamically-typed RpcService.
+    pub fn new<S: RpcService>(s: S) -> Self {
+        Self(Arc::new(s))
+    }
+}
+
+#[async_trait]
+impl RpcService for DynRpcService {
+    async fn respond(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        self.0.respond(method, params).await
+    }
+
+    async fn respond_with_context(
+        &self,
+        ctx: &RpcContext,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        self.0.respond_with_context(ctx, method, params).await
+    }
+
+    async fn respond_raw_with_context(
+        &self,
+        ctx: RpcContext,
+        jrpc_req: JrpcRequest,
+    ) -> JrpcResponse {
+        self.0.respond_raw_with_context(ctx, jrpc_req).await
+    }
 }
 
 /// An OrService responds to a call by trying one service then another.
@@ -49,17 +215,125 @@ impl<T: RpcService, U: RpcService> RpcService for OrService<T, U> {
     async fn respond(
         &self,
         method: &str,
-        params: Vec<serde_json::Value>,
+        params: &[serde_json::Value],
     ) -> Option<Result<serde_json::Value, ServerError>> {
-        if let Some(res) = self.0.respond(method, params.clone()).await {
+        self.respond_with_context(&RpcContext::default(), method, params)
+            .await
+    }
+
+    async fn respond_with_context(
+        &self,
+        ctx: &RpcContext,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        if let Some(res) = self.0.respond_with_context(ctx, method, params).await {
             Some(res)
         } else {
-            self.1.respond(method, params).await
+            self.1.respond_with_context(ctx, method, params).await
+        }
+    }
+}
+
+/// A FallbackService passes every call that the inner service has no method for to a catch-all handler, which gets the raw method and params. This is handy for proxies and shims.
+pub struct FallbackService<S: RpcService> {
+    inner: S,
+    handler: FnService,
+}
+
+impl<S: RpcService> FallbackService<S> {
+    /// Creates a new FallbackService.
+    pub fn new<
+        Fut: std::future::Future<Output = Option<Result<serde_json::Value, ServerError>>>
+            + Send
+            + 'static,
+        Fun: Fn(&str, Vec<serde_json::Value>) -> Fut + Send + Sync + 'static,
+    >(
+        inner: S,
+        handler: Fun,
+    ) -> Self {
+        Self {
+            inner,
+            handler: FnService::new(handler),
+        }
+    }
+}
+
+#[async_trait]
+impl<S: RpcService> RpcService for FallbackService<S> {
+    async fn respond(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        self.respond_with_context(&RpcContext::default(), method, params)
+            .await
+    }
+
+    async fn respond_with_context(
+        &self,
+        ctx: &RpcContext,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        match self.inner.respond_with_context(ctx, method, params).await {
+            Some(res) => Some(res),
+            None => self.handler.respond(method, params).await,
         }
     }
 }
 
-/// A FnTransport wraps around a function that directly
+/// An AnyOfService responds to a call by trying each of its services in order, until one of them has the method. It generalizes [OrService] to any number of services.
+#[derive(Default)]
+pub struct AnyOfService(Vec<Box<dyn RpcService>>);
+
+impl AnyOfService {
+    /// Creates a new AnyOfService.
+    pub fn new(services: Vec<Box<dyn RpcService>>) -> Self {
+        Self(services)
+    }
+
+    /// Adds a service, to be tried after all the existing ones.
+    pub fn push(&mut self, service: impl RpcService) {
+        self.0.push(Box::new(service))
+    }
+}
+
+#[async_trait::async_trait]
+impl RpcService for AnyOfService {
+    async fn respond(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        self.respond_with_context(&RpcContext::default(), method, params)
+            .await
+    }
+
+    async fn respond_with_context(
+        &self,
+        ctx: &RpcContext,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        for service in self.0.iter() {
+            if let Some(res) = service.respond_with_context(ctx, method, params).await {
+                return Some(res);
+            }
+        }
+        None
+    }
+}
+
+/// Convenience methods for composing [RpcService]s.
+pub trait RpcServiceExt: RpcService + Sized {
+    /// Combines this service with another, which handles the calls that this one doesn't have a method for.
+    fn or<U: RpcService>(self, other: U) -> OrService<Self, U> {
+        OrService::new(self, other)
+    }
+}
+
+impl<T: RpcService> RpcServiceExt for T {}
 
 /// A FnService wraps around a function that directly implements [Service::call_raw].
 #[allow(clippy::type_complexity)]
@@ -104,8 +378,170 @@ impl RpcService for FnService {
     async fn respond(
         &self,
         method: &str,
-        params: Vec<serde_json::Value>,
+        params: &[serde_json::Value],
     ) -> Option<Result<serde_json::Value, ServerError>> {
-        self.0(method, params).await
+        self.0(method, params.to_vec()).await
+    }
+}
+
+/// A FnTransport wraps around a function that directly implements [RpcTransport::call_raw].
+#[allow(clippy::type_complexity)]
+pub struct FnTransport<E>(
+    Arc<
+        dyn Fn(
+                JrpcRequest,
+            ) -> Pin<
+                Box<dyn std::future::Future<Output = Result<JrpcResponse, E>> + Send + 'static>,
+            > + Sync
+            + Send
+            + 'static,
+    >,
+);
+
+impl<E> Clone for FnTransport<E> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<E: Sync + Send + 'static> FnTransport<E> {
+    pub fn new<
+        Fut: std::future::Future<Output = Result<JrpcResponse, E>> + Send + 'static,
+        Fun: Fn(JrpcRequest) -> Fut + Send + Sync + 'static,
+    >(
+        f: Fun,
+    ) -> Self {
+        Self(Arc::new(move |req| Box::pin(f(req))))
+    }
+}
+
+#[cfg(test)]
+impl<E: Sync + Send + 'static> FnTransport<E> {
+    /// A transport that answers every call with a service in the same process, for tests.
+    pub(crate) fn loopback(service: impl RpcService) -> Self {
+        Self::loopback_with(service, |_| Ok(()))
+    }
+
+    /// Like [FnTransport::loopback], but every request is first passed to `check`, which can fail the call with a transport error instead.
+    pub(crate) fn loopback_with(
+        service: impl RpcService,
+        check: impl Fn(&JrpcRequest) -> Result<(), E> + Send + Sync + 'static,
+    ) -> Self {
+        let service = Arc::new(service);
+        Self::new(move |req| {
+            let service = service.clone();
+            let checked = check(&req);
+            async move {
+                checked?;
+                Ok(service.respond_raw(req).await)
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl<E: Sync + Send + 'static> RpcTransport for FnTransport<E> {
+    type Error = E;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        self.0(req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        AnyOfService, DynRpcService, DynRpcTransport, FallbackService, FnService, FnTransport,
+        RpcService, RpcServiceExt, RpcTransport,
+    };
+
+    fn only(name: &'static str) -> FnService {
+        FnService::new(move |method, _| {
+            let found = method == name;
+            async move { found.then(|| Ok(name.into())) }
+        })
+    }
+
+    #[test]
+    fn test_service_composition() {
+        smol::future::block_on(async move {
+            let chained = only("a").or(only("b")).or(only("c"));
+            let mut any_of = AnyOfService::default();
+            for name in ["a", "b", "c"] {
+                any_of.push(only(name));
+            }
+            for name in ["a", "b", "c"] {
+                assert_eq!(chained.respond(name, &[]).await.unwrap().unwrap(), name);
+                assert_eq!(any_of.respond(name, &[]).await.unwrap().unwrap(), name);
+            }
+            assert!(chained.respond("d", &[]).await.is_none());
+            assert!(any_of.respond("d", &[]).await.is_none());
+        });
+    }
+
+    #[test]
+    fn test_fallback_service() {
+        smol::future::block_on(async move {
+            let service = FallbackService::new(only("a"), |method, params| {
+                let reply = format!("{}{:?}", method, params);
+                async move { Some(Ok(reply.into())) }
+            });
+            assert_eq!(service.respond("a", &[]).await.unwrap().unwrap(), "a");
+            assert_eq!(
+                service.respond("b", &[1.into()]).await.unwrap().unwrap(),
+                "b[Number(1)]"
+            );
+        });
+    }
+
+    #[test]
+    fn test_dyn_transport_spawn() {
+        fn assert_send<T: Send>(t: T) -> T {
+            t
+        }
+        let service = only("a");
+        let transport = std::sync::Arc::new(DynRpcTransport::new(FnTransport::<
+            std::convert::Infallible,
+        >::loopback(service)));
+        let task = smol::spawn(assert_send(async move {
+            transport.call("a", &[]).await.unwrap().unwrap().unwrap()
+        }));
+        assert_eq!(smol::future::block_on(task), "a");
+    }
+
+    #[test]
+    fn test_dyn_transport_from_fn() {
+        smol::future::block_on(async move {
+            let service = std::sync::Arc::new(only("a"));
+            let transport = DynRpcTransport::from_fn(move |req| {
+                let service = service.clone();
+                async move { Ok(service.respond_raw(req).await) }
+            });
+            assert_eq!(
+                transport.call("a", &[]).await.unwrap().unwrap().unwrap(),
+                "a"
+            );
+            let fails = DynRpcTransport::from_fn(|_| async { anyhow::bail!("offline") });
+            assert!(fails.call("a", &[]).await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_dyn_service() {
+        smol::future::block_on(async move {
+            let mut services: HashMap<&str, DynRpcService> = HashMap::new();
+            services.insert("one", DynRpcService::new(only("a")));
+            services.insert("two", DynRpcService::new(only("b").or(only("c"))));
+            assert_eq!(
+                services["one"].respond("a", &[]).await.unwrap().unwrap(),
+                "a"
+            );
+            assert_eq!(
+                services["two"].respond("c", &[]).await.unwrap().unwrap(),
+                "c"
+            );
+        });
     }
 }
@@ -0,0 +1,229 @@
+use async_trait::async_trait;
+
+use crate::{Identity, RpcContext, RpcService, ServerError, UNAUTHORIZED_CODE};
+
+/// The JSON-RPC error code for calls rejected because the caller is not allowed to call the method.
+pub const FORBIDDEN_CODE: i32 = -32004;
+
+/// Who may call the methods matching an [AclService] rule.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Requirement {
+    /// Anybody, even without an [Identity].
+    Public,
+    /// Any caller with an [Identity].
+    Authenticated,
+    /// Callers with at least one of the given roles.
+    AnyRole(Vec<String>),
+    /// Callers whose [Identity::id], like a user name or key ID, is one of the given ones.
+    AnyId(Vec<String>),
+    /// Nobody.
+    Deny,
+}
+
+impl Requirement {
+    /// A requirement for a single role.
+    pub fn role(role: impl Into<String>) -> Self {
+        Self::AnyRole(vec![role.into()])
+    }
+
+    fn allows(&self, identity: Option<&Identity>) -> bool {
+        match (self, identity) {
+            (Requirement::Public, _) => true,
+            (Requirement::Deny, _) | (_, None) => false,
+            (Requirement::Authenticated, Some(_)) => true,
+            (Requirement::AnyRole(roles), Some(identity)) => {
+                identity.roles.iter().any(|role| roles.contains(role))
+            }
+            (Requirement::AnyId(ids), Some(identity)) => ids.contains(&identity.id),
+        }
+    }
+}
+
+/// An AclService checks every call against a list of rules, mapping method patterns to a [Requirement] on the caller's [Identity], which is typically attached by an [crate::AuthService] further out.
+///
+/// Patterns are globs, where `*` matches any run of characters and `?` matches a single one, so `"admin.*"` covers every method with that prefix. The first matching rule applies, and methods matching no rule are denied. Denied calls get a [FORBIDDEN_CODE] error, or an [UNAUTHORIZED_CODE] error if the caller has no identity at all.
+pub struct AclService<S: RpcService> {
+    inner: S,
+    rules: Vec<(String, Requirement)>,
+    default: Requirement,
+}
+
+impl<S: RpcService> AclService<S> {
+    /// Creates a new AclService with no rules, which denies everything.
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            rules: vec![],
+            default: Requirement::Deny,
+        }
+    }
+
+    /// Adds a rule, which applies to methods matching the pattern that no earlier rule matches.
+    pub fn with_rule(mut self, pattern: impl Into<String>, requirement: Requirement) -> Self {
+        self.rules.push((pattern.into(), requirement));
+        self
+    }
+
+    /// Sets the requirement for methods matching no rule. Defaults to [Requirement::Deny].
+    pub fn with_default(mut self, requirement: Requirement) -> Self {
+        self.default = requirement;
+        self
+    }
+
+    /// Returns the requirement that applies to a method.
+    pub fn requirement(&self, method: &str) -> &Requirement {
+        self.rules
+            .iter()
+            .find(|(pattern, _)| glob_matches(pattern.as_bytes(), method.as_bytes()))
+            .map(|(_, requirement)| requirement)
+            .unwrap_or(&self.default)
+    }
+}
+
+/// Matches a string against a pattern where `*` matches any run of characters and `?` any one character.
+///
+/// Method names come from clients, so this never backtracks further than the last `*`, which keeps it linear in practice even for patterns with many of them.
+pub(crate) fn glob_matches(pattern: &[u8], s: &[u8]) -> bool {
+    let (mut p, mut i) = (0, 0);
+    // the position after the last `*` seen, and where in `s` it started matching
+    let mut star = None;
+    while i < s.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                p += 1;
+                star = Some((p, i));
+            }
+            Some(&c) if c == b'?' || c == s[i] => {
+                p += 1;
+                i += 1;
+            }
+            _ => match star {
+                // let the last `*` match one more character
+                Some((star_p, star_i)) => {
+                    p = star_p;
+                    i = star_i + 1;
+                    star = Some((star_p, star_i + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+#[async_trait]
+impl<S: RpcService> RpcService for AclService<S> {
+    async fn respond(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        self.respond_with_context(&RpcContext::default(), method, params)
+            .await
+    }
+
+    async fn respond_with_context(
+        &self,
+        ctx: &RpcContext,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        let identity = ctx.extension::<Identity>();
+        if self.requirement(method).allows(identity) {
+            return self.inner.respond_with_context(ctx, method, params).await;
+        }
+        log::debug!(
+            "denying {} to {:?}",
+            method,
+            identity.map(|identity| &identity.id)
+        );
+        Some(Err(if identity.is_some() {
+            ServerError::with_jrpc_code(FORBIDDEN_CODE, "forbidden", serde_json::Value::Null)
+        } else {
+            ServerError::with_jrpc_code(UNAUTHORIZED_CODE, "unauthorized", serde_json::Value::Null)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::glob_matches;
+    use crate::{
+        AclService, FnService, Identity, Requirement, RpcContext, RpcService, FORBIDDEN_CODE,
+        UNAUTHORIZED_CODE,
+    };
+
+    #[test]
+    fn test_acl() {
+        smol::future::block_on(async move {
+            let service = AclService::new(FnService::new(|_, _| async move { Some(Ok(1.into())) }))
+                .with_rule("admin.shutdown", Requirement::AnyId(vec!["root".into()]))
+                .with_rule("admin.*", Requirement::role("admin"))
+                .with_rule("get_?", Requirement::Public)
+                .with_rule("*", Requirement::Authenticated);
+            let call = |identity: Option<(&str, &str)>, method: &'static str| {
+                let service = &service;
+                let mut ctx = RpcContext::new();
+                if let Some((id, role)) = identity {
+                    ctx.insert_extension(Identity {
+                        id: id.into(),
+                        roles: vec![role.into()],
+                    });
+                }
+                async move {
+                    match service.respond_with_context(&ctx, method, &[]).await {
+                        Some(Ok(_)) => None,
+                        Some(Err(err)) => err.jrpc_code(),
+                        None => unreachable!(),
+                    }
+                }
+            };
+            let forbidden = Some(FORBIDDEN_CODE as i64);
+            let unauthorized = Some(UNAUTHORIZED_CODE as i64);
+            assert_eq!(call(None, "get_x").await, None);
+            assert_eq!(call(None, "get_xy").await, unauthorized);
+            assert_eq!(call(Some(("bob", "user")), "get_xy").await, None);
+            assert_eq!(call(Some(("bob", "user")), "admin.stats").await, forbidden);
+            assert_eq!(call(Some(("bob", "admin")), "admin.stats").await, None);
+            assert_eq!(
+                call(Some(("bob", "admin")), "admin.shutdown").await,
+                forbidden
+            );
+            assert_eq!(call(Some(("root", "user")), "admin.shutdown").await, None);
+
+            // deny by default
+            let service = AclService::new(FnService::new(|_, _| async move { Some(Ok(1.into())) }));
+            assert!(service.respond("f", &[]).await.unwrap().is_err());
+        });
+    }
+
+    #[test]
+    fn test_glob_matches() {
+        for (pattern, s, matches) in [
+            ("", "", true),
+            ("", "a", false),
+            ("*", "", true),
+            ("a*", "abc", true),
+            ("*c", "abc", true),
+            ("a*c", "ac", true),
+            ("a*b*c", "axxbyyc", true),
+            ("a*b*c", "axxbyy", false),
+            ("?", "", false),
+            ("a?c", "abc", true),
+            ("*?", "", false),
+            ("**a", "ba", true),
+        ] {
+            assert_eq!(
+                glob_matches(pattern.as_bytes(), s.as_bytes()),
+                matches,
+                "{:?} against {:?}",
+                pattern,
+                s
+            );
+        }
+        // exponential for a backtracking matcher
+        let pattern = "*a".repeat(20) + "b";
+        let s = "a".repeat(10_000);
+        assert!(!glob_matches(pattern.as_bytes(), s.as_bytes()));
+    }
+}
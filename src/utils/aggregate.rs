@@ -0,0 +1,209 @@
+use async_trait::async_trait;
+
+use crate::{CallOptions, DynRpcTransport, RpcContext, RpcService, RpcTransport, ServerError};
+
+/// The outcome of a call to one upstream of an [AggregatorService].
+pub type UpstreamResult = anyhow::Result<Option<Result<serde_json::Value, ServerError>>>;
+
+/// Merges the outcomes of a call to every upstream of an [AggregatorService], given in the same order as the upstreams, into one response. This is implemented for closures of the form `Fn(Vec<UpstreamResult>) -> Option<Result<serde_json::Value, ServerError>>`.
+pub trait Combiner: Send + Sync + 'static {
+    /// Merges the outcomes into one response.
+    fn combine(
+        &self,
+        results: Vec<UpstreamResult>,
+    ) -> Option<Result<serde_json::Value, ServerError>>;
+}
+
+impl<F> Combiner for F
+where
+    F: Fn(Vec<UpstreamResult>) -> Option<Result<serde_json::Value, ServerError>>
+        + Send
+        + Sync
+        + 'static,
+{
+    fn combine(
+        &self,
+        results: Vec<UpstreamResult>,
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        self(results)
+    }
+}
+
+fn aggregate_error(message: impl Into<String>) -> ServerError {
+    ServerError {
+        code: 1,
+        message: message.into(),
+        details: serde_json::Value::Null,
+    }
+}
+
+/// Responds with the first successful result, in upstream order, or else the first server error.
+pub struct FirstSuccess;
+
+impl Combiner for FirstSuccess {
+    fn combine(
+        &self,
+        results: Vec<UpstreamResult>,
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        let mut first_err = None;
+        let mut found = false;
+        for result in results {
+            match result {
+                Ok(Some(Ok(value))) => return Some(Ok(value)),
+                Ok(Some(Err(err))) => {
+                    found = true;
+                    first_err.get_or_insert(err);
+                }
+                Ok(None) => {}
+                Err(_) => found = true,
+            }
+        }
+        if !found {
+            return None;
+        }
+        Some(Err(
+            first_err.unwrap_or_else(|| aggregate_error("every upstream failed"))
+        ))
+    }
+}
+
+/// Responds with a result only if at least this many upstreams returned exactly that result, to cross-check replicas.
+pub struct Quorum(pub usize);
+
+impl Combiner for Quorum {
+    fn combine(
+        &self,
+        results: Vec<UpstreamResult>,
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        let mut tallies: Vec<(serde_json::Value, usize)> = vec![];
+        let mut found = false;
+        for result in results {
+            match result {
+                Ok(Some(Ok(value))) => {
+                    found = true;
+                    match tallies.iter_mut().find(|(v, _)| *v == value) {
+                        Some(tally) => tally.1 += 1,
+                        None => tallies.push((value, 1)),
+                    }
+                }
+                Ok(None) => {}
+                _ => found = true,
+            }
+        }
+        if !found {
+            return None;
+        }
+        match tallies.into_iter().max_by_key(|(_, count)| *count) {
+            Some((value, count)) if count >= self.0 => Some(Ok(value)),
+            _ => Some(Err(aggregate_error(format!(
+                "fewer than {} upstreams agreed",
+                self.0
+            )))),
+        }
+    }
+}
+
+/// Responds with an array of every upstream's result, in upstream order, with `null` for upstreams that failed.
+pub struct CollectAll;
+
+impl Combiner for CollectAll {
+    fn combine(
+        &self,
+        results: Vec<UpstreamResult>,
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        Some(Ok(results
+            .into_iter()
+            .map(|result| match result {
+                Ok(Some(Ok(value))) => value,
+                _ => serde_json::Value::Null,
+            })
+            .collect()))
+    }
+}
+
+/// An AggregatorService forwards every call to several upstream servers concurrently, waits for all of them, and merges their responses with a [Combiner], like [FirstSuccess], [Quorum], or [CollectAll].
+pub struct AggregatorService<C: Combiner> {
+    upstreams: Vec<DynRpcTransport>,
+    combiner: C,
+}
+
+impl<C: Combiner> AggregatorService<C> {
+    /// Creates a new AggregatorService.
+    pub fn new(upstreams: Vec<DynRpcTransport>, combiner: C) -> Self {
+        Self {
+            upstreams,
+            combiner,
+        }
+    }
+}
+
+#[async_trait]
+impl<C: Combiner> RpcService for AggregatorService<C> {
+    async fn respond(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        self.respond_with_context(&RpcContext::default(), method, params)
+            .await
+    }
+
+    async fn respond_with_context(
+        &self,
+        ctx: &RpcContext,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        let opts = CallOptions {
+            meta: ctx.meta.clone(),
+        };
+        let results = futures_util::future::join_all(
+            self.upstreams
+                .iter()
+                .map(|upstream| upstream.call_with_opts(method, params, &opts)),
+        )
+        .await;
+        self.combiner.combine(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use crate::{
+        AggregatorService, CollectAll, DynRpcTransport, FirstSuccess, FnService, FnTransport,
+        Quorum, RpcService,
+    };
+
+    fn upstream(answer: u64) -> DynRpcTransport {
+        DynRpcTransport::new(FnTransport::<Infallible>::loopback(FnService::new(
+            move |_, _| async move { Some(Ok(answer.into())) },
+        )))
+    }
+
+    fn down() -> DynRpcTransport {
+        DynRpcTransport::from_fn(|_| async { anyhow::bail!("down") })
+    }
+
+    #[test]
+    fn test_aggregate() {
+        smol::future::block_on(async move {
+            let first =
+                AggregatorService::new(vec![down(), upstream(1), upstream(2)], FirstSuccess);
+            assert_eq!(first.respond("f", &[]).await.unwrap().unwrap(), 1);
+            let quorum = AggregatorService::new(
+                vec![upstream(1), upstream(2), upstream(2), down()],
+                Quorum(2),
+            );
+            assert_eq!(quorum.respond("f", &[]).await.unwrap().unwrap(), 2);
+            let no_quorum = AggregatorService::new(vec![upstream(1), upstream(2)], Quorum(2));
+            assert!(no_quorum.respond("f", &[]).await.unwrap().is_err());
+            let all = AggregatorService::new(vec![upstream(1), down()], CollectAll);
+            assert_eq!(
+                all.respond("f", &[]).await.unwrap().unwrap(),
+                serde_json::json!([1, null])
+            );
+        });
+    }
+}
@@ -0,0 +1,266 @@
+use std::{
+    io::Write,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{utils::acl::glob_matches, Identity, Redactor, RpcContext, RpcService, ServerError};
+
+/// How an audited call turned out.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOutcome {
+    Success,
+    Error { code: i64, message: String },
+    NotFound,
+}
+
+/// One entry in an audit log, written by an [AuditService].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// When the call finished, in milliseconds since the UNIX epoch.
+    pub timestamp: u64,
+    /// The [Identity::id] of the caller, if known.
+    pub identity: Option<String>,
+    /// The method called.
+    pub method: String,
+    /// The hex-encoded SHA-256 hash of the JSON-encoded params.
+    pub params_hash: String,
+    /// The params themselves, with sensitive values redacted, if enabled with [AuditService::with_params].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<Vec<serde_json::Value>>,
+    /// How the call turned out.
+    pub outcome: AuditOutcome,
+    /// With chaining, the hex-encoded SHA-256 hash of the previous record's hash followed by this record with `hash` unset. See [verify_audit_chain].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+impl AuditRecord {
+    fn chain_hash(&self, prev: &str) -> String {
+        let unchained = AuditRecord {
+            hash: None,
+            ..self.clone()
+        };
+        let mut hasher = Sha256::new();
+        hasher.update(prev.as_bytes());
+        hasher.update(serde_json::to_vec(&unchained).unwrap());
+        hex::encode(hasher.finalize())
+    }
+}
+
+/// Checks that a sequence of chained audit records, starting from the beginning of the log, has not been tampered with. Records removed from the end cannot be detected.
+pub fn verify_audit_chain(records: &[AuditRecord]) -> bool {
+    let mut prev = String::new();
+    for record in records {
+        match &record.hash {
+            Some(hash) if *hash == record.chain_hash(&prev) => prev = hash.clone(),
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// Where an [AuditService] writes its records. Sinks should be append-only. This is implemented for closures of the form `Fn(AuditRecord)`.
+#[async_trait]
+pub trait AuditSink: Send + Sync + 'static {
+    /// Appends a record to the log.
+    async fn append(&self, record: AuditRecord);
+}
+
+#[async_trait]
+impl<F: Fn(AuditRecord) + Send + Sync + 'static> AuditSink for F {
+    async fn append(&self, record: AuditRecord) {
+        self(record)
+    }
+}
+
+/// An [AuditSink] that writes one JSON record per line to a writer, like a file opened for appending.
+pub struct JsonLinesAuditSink<W: Write + Send + 'static>(Mutex<W>);
+
+impl<W: Write + Send + 'static> JsonLinesAuditSink<W> {
+    /// Creates a new JsonLinesAuditSink.
+    pub fn new(writer: W) -> Self {
+        Self(Mutex::new(writer))
+    }
+}
+
+#[async_trait]
+impl<W: Write + Send + 'static> AuditSink for JsonLinesAuditSink<W> {
+    async fn append(&self, record: AuditRecord) {
+        let mut writer = self.0.lock().unwrap();
+        let line = serde_json::to_string(&record).unwrap();
+        if let Err(err) = writeln!(writer, "{}", line).and_then(|_| writer.flush()) {
+            log::error!("could not write audit record: {}", err);
+        }
+    }
+}
+
+/// An AuditService writes an [AuditRecord] to a sink for every call to the inner service, once it finishes: who called what, a hash of the params, and the outcome. Params themselves are only logged if enabled with [AuditService::with_params], through a [Redactor].
+///
+/// By default, every call is audited; [AuditService::with_audited_methods] narrows this down, for example to state-changing methods. With [AuditService::with_chaining], each record includes a hash over the previous one, so that edits and deletions in the middle of the log can be detected with [verify_audit_chain].
+pub struct AuditService<S: RpcService, K: AuditSink> {
+    inner: S,
+    sink: K,
+    patterns: Vec<String>,
+    params: Option<Redactor>,
+    // the last hash, held while appending so that the log is in chain order
+    chain: Option<async_lock::Mutex<String>>,
+}
+
+impl<S: RpcService, K: AuditSink> AuditService<S, K> {
+    /// Creates a new AuditService.
+    pub fn new(inner: S, sink: K) -> Self {
+        Self {
+            inner,
+            sink,
+            patterns: vec![],
+            params: None,
+            chain: None,
+        }
+    }
+
+    /// Only audits methods matching one of the given patterns, which are globs like in [crate::AclService].
+    pub fn with_audited_methods(mut self, patterns: &[&str]) -> Self {
+        self.patterns = patterns.iter().map(|p| p.to_string()).collect();
+        self
+    }
+
+    /// Includes the params in the records, with the values hidden by the [Redactor] redacted.
+    pub fn with_params(mut self, redactor: Redactor) -> Self {
+        self.params = Some(redactor);
+        self
+    }
+
+    /// Enables tamper-evident chaining, starting a new chain.
+    pub fn with_chaining(mut self) -> Self {
+        self.chain = Some(async_lock::Mutex::new(String::new()));
+        self
+    }
+
+    /// Enables tamper-evident chaining, continuing an existing log whose last record has the given hash.
+    pub fn with_chaining_from(mut self, last_hash: impl Into<String>) -> Self {
+        self.chain = Some(async_lock::Mutex::new(last_hash.into()));
+        self
+    }
+
+    fn is_audited(&self, method: &str) -> bool {
+        self.patterns.is_empty()
+            || self
+                .patterns
+                .iter()
+                .any(|pattern| glob_matches(pattern.as_bytes(), method.as_bytes()))
+    }
+}
+
+#[async_trait]
+impl<S: RpcService, K: AuditSink> RpcService for AuditService<S, K> {
+    async fn respond(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        self.respond_with_context(&RpcContext::default(), method, params)
+            .await
+    }
+
+    async fn respond_with_context(
+        &self,
+        ctx: &RpcContext,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        if !self.is_audited(method) {
+            return self.inner.respond_with_context(ctx, method, params).await;
+        }
+        let response = self.inner.respond_with_context(ctx, method, params).await;
+        let mut record = AuditRecord {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            identity: ctx.extension::<Identity>().map(|i| i.id.clone()),
+            method: method.to_string(),
+            params_hash: hex::encode(Sha256::digest(serde_json::to_vec(params).unwrap())),
+            params: self
+                .params
+                .as_ref()
+                .map(|redactor| redactor.redact_params(method, params).into_owned()),
+            outcome: match &response {
+                Some(Ok(_)) => AuditOutcome::Success,
+                Some(Err(err)) => AuditOutcome::Error {
+                    code: err.jrpc_code().unwrap_or(err.code as i64),
+                    message: err.message.clone(),
+                },
+                None => AuditOutcome::NotFound,
+            },
+            hash: None,
+        };
+        match &self.chain {
+            Some(chain) => {
+                let mut last = chain.lock().await;
+                let hash = record.chain_hash(&last);
+                record.hash = Some(hash.clone());
+                self.sink.append(record).await;
+                *last = hash;
+            }
+            None => self.sink.append(record).await,
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{
+        verify_audit_chain, AuditOutcome, AuditRecord, AuditService, FnService, Identity, Redactor,
+        RpcContext, RpcService,
+    };
+
+    #[test]
+    fn test_audit_chain() {
+        smol::future::block_on(async move {
+            let log = Arc::new(Mutex::new(Vec::<AuditRecord>::new()));
+            let service = AuditService::new(
+                FnService::new(|method, _| {
+                    let found = method != "missing";
+                    async move { found.then(|| Ok(1.into())) }
+                }),
+                {
+                    let log = log.clone();
+                    move |record| log.lock().unwrap().push(record)
+                },
+            )
+            .with_audited_methods(&["set_*", "missing"])
+            .with_params(Redactor::new().with_param("set_*", 0))
+            .with_chaining();
+            let mut ctx = RpcContext::new();
+            ctx.insert_extension(Identity {
+                id: "alice".into(),
+                roles: vec![],
+            });
+            service.respond_with_context(&ctx, "get_x", &[]).await;
+            service
+                .respond_with_context(&ctx, "set_x", &["secret".into()])
+                .await;
+            service.respond("missing", &[]).await;
+            let mut records = log.lock().unwrap().clone();
+            assert_eq!(records.len(), 2);
+            assert_eq!(records[0].identity.as_deref(), Some("alice"));
+            assert!(!serde_json::to_string(&records[0])
+                .unwrap()
+                .contains("secret"));
+            assert_eq!(records[0].params, Some(vec!["[redacted]".into()]));
+            assert_eq!(records[1].outcome, AuditOutcome::NotFound);
+            assert!(verify_audit_chain(&records));
+            records[0].identity = Some("mallory".into());
+            assert!(!verify_audit_chain(&records));
+        });
+    }
+}
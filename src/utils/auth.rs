@@ -0,0 +1,241 @@
+use std::{borrow::Cow, collections::HashSet};
+
+use async_trait::async_trait;
+
+use crate::{RpcContext, RpcService, ServerError};
+
+/// The JSON-RPC error code for calls rejected because of a missing or invalid credential.
+pub const UNAUTHORIZED_CODE: i32 = -32001;
+
+/// A credential that a server adapter attached to the [RpcContext] as an extension, like an HTTP bearer token.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Credential(pub String);
+
+/// The verified identity of a caller. [AuthService] attaches it to the [RpcContext] as an extension, for inner services and other wrappers to use.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Identity {
+    /// Who the caller is, like a user name or key ID.
+    pub id: String,
+    /// The roles that the caller has.
+    pub roles: Vec<String>,
+}
+
+/// Checks credentials for an [AuthService]. This is implemented for closures of the form `Fn(&str) -> Option<Identity>`.
+#[async_trait]
+pub trait Verifier: Send + Sync + 'static {
+    /// Checks a credential, returning the identity it belongs to, or `None` if it is not valid.
+    async fn verify(&self, credential: &str) -> Option<Identity>;
+}
+
+#[async_trait]
+impl<F: Fn(&str) -> Option<Identity> + Send + Sync + 'static> Verifier for F {
+    async fn verify(&self, credential: &str) -> Option<Identity> {
+        self(credential)
+    }
+}
+
+/// Where an [AuthService] finds the credential in a call.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CredentialSource {
+    /// The parameter at the given index, which must be a string. If it holds a valid credential, it is removed from the parameters before they reach the inner service; otherwise, like for public methods called without a credential, the parameters are passed on untouched.
+    Param(usize),
+    /// The request metadata entry with the given key, which must be a string.
+    Meta(String),
+    /// The [Credential] extension of the context.
+    Context,
+}
+
+/// An AuthService rejects calls without a valid credential with an [UNAUTHORIZED_CODE] error, before the inner service ever sees them. Calls that pass have the caller's [Identity] attached to their context.
+///
+/// By default, the credential is taken from the `"auth"` metadata entry, and every method requires one.
+pub struct AuthService<S: RpcService, V: Verifier> {
+    inner: S,
+    verifier: V,
+    source: CredentialSource,
+    public: HashSet<String>,
+}
+
+impl<S: RpcService, V: Verifier> AuthService<S, V> {
+    /// Creates a new AuthService.
+    pub fn new(inner: S, verifier: V) -> Self {
+        Self {
+            inner,
+            verifier,
+            source: CredentialSource::Meta("auth".into()),
+            public: HashSet::new(),
+        }
+    }
+
+    /// Sets where the credential is taken from.
+    pub fn with_source(mut self, source: CredentialSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Makes a method callable without a credential.
+    pub fn with_public_method(mut self, method: impl Into<String>) -> Self {
+        self.public.insert(method.into());
+        self
+    }
+
+    fn credential<'a>(
+        &self,
+        ctx: &'a RpcContext,
+        params: &'a [serde_json::Value],
+    ) -> Option<&'a str> {
+        match &self.source {
+            CredentialSource::Param(idx) => params.get(*idx)?.as_str(),
+            CredentialSource::Meta(key) => ctx.meta.get(key)?.as_str(),
+            CredentialSource::Context => ctx.extension::<Credential>().map(|c| c.0.as_str()),
+        }
+    }
+}
+
+#[async_trait]
+impl<S: RpcService, V: Verifier> RpcService for AuthService<S, V> {
+    async fn respond(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        self.respond_with_context(&RpcContext::default(), method, params)
+            .await
+    }
+
+    async fn respond_with_context(
+        &self,
+        ctx: &RpcContext,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        let is_public = self.public.contains(method);
+        let identity = match self.credential(ctx, params) {
+            Some(credential) => self.verifier.verify(credential).await,
+            None => None,
+        };
+        let params = match self.source {
+            CredentialSource::Param(idx) if identity.is_some() => {
+                let mut params = params.to_vec();
+                params.remove(idx);
+                Cow::Owned(params)
+            }
+            _ => Cow::Borrowed(params),
+        };
+        match identity {
+            Some(identity) => {
+                let mut ctx = ctx.clone();
+                ctx.insert_extension(identity);
+                self.inner.respond_with_context(&ctx, method, &params).await
+            }
+            None if is_public => self.inner.respond_with_context(ctx, method, &params).await,
+            None => Some(Err(ServerError::with_jrpc_code(
+                UNAUTHORIZED_CODE,
+                "unauthorized",
+                serde_json::Value::Null,
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+
+    use crate::{
+        AuthService, CredentialSource, Identity, RpcContext, RpcService, ServerError,
+        UNAUTHORIZED_CODE,
+    };
+
+    struct WhoAmI;
+
+    #[async_trait]
+    impl RpcService for WhoAmI {
+        async fn respond(
+            &self,
+            method: &str,
+            params: &[serde_json::Value],
+        ) -> Option<Result<serde_json::Value, ServerError>> {
+            self.respond_with_context(&RpcContext::default(), method, params)
+                .await
+        }
+
+        async fn respond_with_context(
+            &self,
+            ctx: &RpcContext,
+            _method: &str,
+            params: &[serde_json::Value],
+        ) -> Option<Result<serde_json::Value, ServerError>> {
+            let id = ctx.extension::<Identity>().map(|i| i.id.clone());
+            Some(Ok(serde_json::json!([id, params])))
+        }
+    }
+
+    fn verify(credential: &str) -> Option<Identity> {
+        (credential == "secret").then(|| Identity {
+            id: "alice".into(),
+            roles: vec![],
+        })
+    }
+
+    #[test]
+    fn test_auth() {
+        smol::future::block_on(async move {
+            let service = AuthService::new(WhoAmI, verify).with_public_method("hello");
+            let mut ctx = RpcContext::new();
+            assert_eq!(
+                service
+                    .respond_with_context(&ctx, "f", &[])
+                    .await
+                    .unwrap()
+                    .unwrap_err()
+                    .jrpc_code(),
+                Some(UNAUTHORIZED_CODE as i64)
+            );
+            assert_eq!(
+                service.respond("hello", &[]).await.unwrap().unwrap(),
+                serde_json::json!([null, []])
+            );
+            ctx.meta.insert("auth".into(), "secret".into());
+            assert_eq!(
+                service
+                    .respond_with_context(&ctx, "f", &[])
+                    .await
+                    .unwrap()
+                    .unwrap(),
+                serde_json::json!(["alice", []])
+            );
+
+            let service = service.with_source(CredentialSource::Param(0));
+            assert_eq!(
+                service
+                    .respond("f", &["secret".into(), 1.into()])
+                    .await
+                    .unwrap()
+                    .unwrap(),
+                serde_json::json!(["alice", [1]])
+            );
+            assert!(service
+                .respond("f", &["wrong".into(), 1.into()])
+                .await
+                .unwrap()
+                .is_err());
+            // public methods keep their params when called without a credential
+            assert_eq!(
+                service
+                    .respond("hello", &["world".into(), 1.into()])
+                    .await
+                    .unwrap()
+                    .unwrap(),
+                serde_json::json!([null, ["world", 1]])
+            );
+            assert_eq!(
+                service
+                    .respond("hello", &["secret".into(), 1.into()])
+                    .await
+                    .unwrap()
+                    .unwrap(),
+                serde_json::json!(["alice", [1]])
+            );
+        });
+    }
+}
@@ -0,0 +1,283 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    sync::{
+        atomic::{AtomicU32, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+
+use crate::{JrpcRequest, JrpcResponse, RpcTransport};
+
+/// How a [LoadBalancingTransport] picks a backend for each call.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BalanceStrategy {
+    /// Cycle through the backends in order.
+    RoundRobin,
+    /// Pick the backend with the fewest calls in flight.
+    LeastInFlight,
+    /// Pick a backend uniformly at random.
+    Random,
+    /// Pick a backend by hashing the session key in the request metadata (see [LoadBalancingTransport::with_session_key]), so that calls in the same session consistently reach the same backend. When that backend is unhealthy, only its sessions move elsewhere. Calls without a session key are distributed round-robin.
+    Sticky,
+}
+
+struct Backend<T> {
+    transport: T,
+    in_flight: AtomicUsize,
+    failures: AtomicU32,
+    unhealthy_until: Mutex<Option<Instant>>,
+}
+
+impl<T> Backend<T> {
+    fn is_healthy(&self) -> bool {
+        self.unhealthy_until
+            .lock()
+            .unwrap()
+            .map(|until| until <= Instant::now())
+            .unwrap_or(true)
+    }
+}
+
+/// A LoadBalancingTransport distributes calls across a pool of transports to equivalent backends.
+///
+/// Backends that fail several calls in a row at the transport level are considered unhealthy and skipped for a while, unless every backend is unhealthy. Failed calls are not retried on another backend; wrap the LoadBalancingTransport in a [crate::RetryTransport] for that.
+pub struct LoadBalancingTransport<T: RpcTransport> {
+    backends: Vec<Backend<T>>,
+    strategy: BalanceStrategy,
+    next: AtomicUsize,
+    failure_threshold: u32,
+    cooldown: Duration,
+    session_key: String,
+}
+
+impl<T: RpcTransport> LoadBalancingTransport<T> {
+    /// Creates a new LoadBalancingTransport. Panics if there are no transports.
+    pub fn new(inner: Vec<T>, strategy: BalanceStrategy) -> Self {
+        assert!(
+            !inner.is_empty(),
+            "LoadBalancingTransport needs at least one transport"
+        );
+        Self {
+            backends: inner
+                .into_iter()
+                .map(|transport| Backend {
+                    transport,
+                    in_flight: AtomicUsize::new(0),
+                    failures: AtomicU32::new(0),
+                    unhealthy_until: Mutex::new(None),
+                })
+                .collect(),
+            strategy,
+            next: AtomicUsize::new(0),
+            failure_threshold: 3,
+            cooldown: Duration::from_secs(10),
+            session_key: "session".into(),
+        }
+    }
+
+    /// Sets how many consecutive failures make a backend unhealthy, and for how long it is then skipped.
+    pub fn with_health(mut self, failure_threshold: u32, cooldown: Duration) -> Self {
+        self.failure_threshold = failure_threshold.max(1);
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Sets the request metadata key holding the session key for [BalanceStrategy::Sticky]. Defaults to `"session"`.
+    pub fn with_session_key(mut self, key: impl Into<String>) -> Self {
+        self.session_key = key.into();
+        self
+    }
+
+    /// Returns whether the backend at the given index is currently considered healthy.
+    pub fn is_healthy(&self, idx: usize) -> bool {
+        self.backends[idx].is_healthy()
+    }
+
+    /// Returns the number of calls in flight to the backend at the given index.
+    pub fn in_flight(&self, idx: usize) -> usize {
+        self.backends[idx].in_flight.load(Ordering::Relaxed)
+    }
+
+    fn pick(&self, req: Option<&JrpcRequest>) -> usize {
+        let mut candidates: Vec<usize> = (0..self.backends.len())
+            .filter(|&i| self.backends[i].is_healthy())
+            .collect();
+        if candidates.is_empty() {
+            candidates = (0..self.backends.len()).collect();
+        }
+        let session = req.and_then(|req| req.meta.get(&self.session_key));
+        match (self.strategy, session) {
+            (BalanceStrategy::Sticky, Some(session)) => {
+                // rendezvous hashing: the session goes to the candidate with the highest score
+                let session = session.to_string();
+                candidates
+                    .into_iter()
+                    .max_by_key(|&i| {
+                        let mut hasher = DefaultHasher::new();
+                        (&session, i).hash(&mut hasher);
+                        hasher.finish()
+                    })
+                    .unwrap()
+            }
+            (BalanceStrategy::RoundRobin | BalanceStrategy::Sticky, _) => {
+                candidates[self.next.fetch_add(1, Ordering::Relaxed) % candidates.len()]
+            }
+            (BalanceStrategy::LeastInFlight, _) => candidates
+                .into_iter()
+                .min_by_key(|&i| self.backends[i].in_flight.load(Ordering::Relaxed))
+                .unwrap(),
+            (BalanceStrategy::Random, _) => candidates[fastrand::usize(..candidates.len())],
+        }
+    }
+
+    async fn with_backend<'a, R, F, Fut>(&'a self, idx: usize, f: F) -> Result<R, T::Error>
+    where
+        F: FnOnce(&'a T) -> Fut,
+        Fut: std::future::Future<Output = Result<R, T::Error>>,
+    {
+        let backend = &self.backends[idx];
+        let result = {
+            let _guard = InFlightGuard::new(&backend.in_flight);
+            f(&backend.transport).await
+        };
+        if result.is_ok() {
+            backend.failures.store(0, Ordering::Relaxed);
+            *backend.unhealthy_until.lock().unwrap() = None;
+        } else if backend.failures.fetch_add(1, Ordering::Relaxed) + 1 >= self.failure_threshold {
+            *backend.unhealthy_until.lock().unwrap() = Some(Instant::now() + self.cooldown);
+        }
+        result
+    }
+}
+
+/// Counts a call as in flight until dropped, even if the call is cancelled.
+struct InFlightGuard<'a>(&'a AtomicUsize);
+
+impl<'a> InFlightGuard<'a> {
+    fn new(counter: &'a AtomicUsize) -> Self {
+        counter.fetch_add(1, Ordering::Relaxed);
+        Self(counter)
+    }
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[async_trait]
+impl<T: RpcTransport> RpcTransport for LoadBalancingTransport<T> {
+    type Error = T::Error;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        self.with_backend(self.pick(Some(&req)), |t| t.call_raw(req))
+            .await
+    }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        // a batch follows the session of its first request
+        self.with_backend(self.pick(reqs.first()), |t| t.call_raw_batch(reqs))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use crate::{
+        BalanceStrategy, CallOptions, FnService, FnTransport, LoadBalancingTransport, RpcTransport,
+    };
+
+    fn backend(name: &'static str, up: Arc<AtomicBool>) -> FnTransport<()> {
+        FnTransport::loopback_with(
+            FnService::new(move |_, _| async move { Some(Ok(name.into())) }),
+            move |_| up.load(Ordering::SeqCst).then_some(()).ok_or(()),
+        )
+    }
+
+    #[test]
+    fn test_round_robin_health() {
+        smol::future::block_on(async move {
+            let b_up = Arc::new(AtomicBool::new(true));
+            let transport = LoadBalancingTransport::new(
+                vec![
+                    backend("a", Arc::new(AtomicBool::new(true))),
+                    backend("b", b_up.clone()),
+                ],
+                BalanceStrategy::RoundRobin,
+            )
+            .with_health(1, Duration::from_secs(60));
+            let mut seen = vec![];
+            for _ in 0..4 {
+                seen.push(transport.call("f", &[]).await.unwrap().unwrap().unwrap());
+            }
+            assert_eq!(seen, vec!["a", "b", "a", "b"]);
+            b_up.store(false, Ordering::SeqCst);
+            while transport.call("f", &[]).await.is_ok() {}
+            assert!(!transport.is_healthy(1));
+            for _ in 0..4 {
+                assert_eq!(
+                    transport.call("f", &[]).await.unwrap().unwrap().unwrap(),
+                    "a"
+                );
+            }
+        });
+    }
+
+    #[test]
+    fn test_sticky_sessions() {
+        smol::future::block_on(async move {
+            let ups: Vec<Arc<AtomicBool>> =
+                (0..3).map(|_| Arc::new(AtomicBool::new(true))).collect();
+            let transport = LoadBalancingTransport::new(
+                vec![
+                    backend("a", ups[0].clone()),
+                    backend("b", ups[1].clone()),
+                    backend("c", ups[2].clone()),
+                ],
+                BalanceStrategy::Sticky,
+            )
+            .with_health(1, Duration::from_secs(60));
+            let call = |session: usize| {
+                let transport = &transport;
+                async move {
+                    let opts = CallOptions::default().with_meta("session", session);
+                    transport.call_with_opts("f", &[], &opts).await
+                }
+            };
+            let mut assigned = vec![];
+            for session in 0..20 {
+                let backend = call(session).await.unwrap().unwrap().unwrap();
+                assert_eq!(call(session).await.unwrap().unwrap().unwrap(), backend);
+                assigned.push(backend);
+            }
+            // take down a backend that has sessions, and only its sessions move
+            let down = assigned[0].as_str().unwrap().to_string();
+            ups[(down.as_bytes()[0] - b'a') as usize].store(false, Ordering::SeqCst);
+            assert!(call(0).await.is_err());
+            for (session, before) in assigned.iter().enumerate() {
+                let after = call(session).await.unwrap().unwrap().unwrap();
+                if *before == down {
+                    assert_ne!(after, down);
+                } else {
+                    assert_eq!(after, *before);
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,214 @@
+use std::{
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use thiserror::Error;
+
+use crate::{timer, JrpcId, JrpcRequest, JrpcResponse, RpcTransport};
+
+/// An error returned by a [BatchingTransport].
+#[derive(Error, Debug)]
+pub enum BatchError<E> {
+    /// The batch containing the call failed at the transport level.
+    #[error(transparent)]
+    Transport(Arc<E>),
+    /// The server did not answer the call within the batch.
+    #[error("no response to the call in the batch")]
+    MissingResponse,
+}
+
+type Waiter<E> = async_channel::Sender<Result<JrpcResponse, BatchError<E>>>;
+
+/// A call waiting to be batched, with its internal ID in the request, and its original ID.
+type PendingCall<E> = (JrpcRequest, JrpcId, Waiter<E>);
+
+/// A BatchingTransport coalesces calls made at around the same time into JSON-RPC batches, sent through [RpcTransport::call_raw_batch] of the inner transport. A batch is sent once the first call in it has waited for the batching window, or as soon as it is full.
+///
+/// There is no background task: batches are sent by one of the callers waiting on them.
+pub struct BatchingTransport<T: RpcTransport> {
+    inner: T,
+    window: Duration,
+    max_batch: usize,
+    next_id: AtomicI64,
+    pending: Mutex<Vec<PendingCall<T::Error>>>,
+}
+
+impl<T: RpcTransport> BatchingTransport<T> {
+    /// Creates a new BatchingTransport, with a batching window of 5 milliseconds and at most 100 calls per batch.
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            window: Duration::from_millis(5),
+            max_batch: 100,
+            next_id: AtomicI64::new(0),
+            pending: Default::default(),
+        }
+    }
+
+    /// Sets how long calls wait for others to batch with.
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Sets the maximum number of calls in a batch.
+    pub fn with_max_batch(mut self, max_batch: usize) -> Self {
+        self.max_batch = max_batch.max(1);
+        self
+    }
+
+    async fn flush(&self, batch: Vec<PendingCall<T::Error>>) {
+        if batch.is_empty() {
+            return;
+        }
+        let mut reqs = Vec::with_capacity(batch.len());
+        let mut waiters = Vec::with_capacity(batch.len());
+        for (req, original_id, waiter) in batch {
+            waiters.push((req.id.clone(), original_id, waiter));
+            reqs.push(req);
+        }
+        match self.inner.call_raw_batch(reqs).await {
+            Ok(resps) => {
+                for mut resp in resps {
+                    if let Some((_, original_id, waiter)) =
+                        waiters.iter().find(|(id, _, _)| *id == resp.id)
+                    {
+                        resp.id = original_id.clone();
+                        let _ = waiter.try_send(Ok(resp));
+                    }
+                }
+            }
+            Err(err) => {
+                let err = Arc::new(err);
+                for (_, _, waiter) in waiters {
+                    let _ = waiter.try_send(Err(BatchError::Transport(err.clone())));
+                }
+            }
+        }
+    }
+
+    fn take_pending(&self) -> Vec<PendingCall<T::Error>> {
+        std::mem::take(&mut *self.pending.lock().unwrap())
+    }
+}
+
+#[async_trait]
+impl<T: RpcTransport> RpcTransport for BatchingTransport<T> {
+    type Error = BatchError<T::Error>;
+
+    async fn call_raw(&self, mut req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        let (send, recv) = async_channel::bounded(1);
+        let internal_id = JrpcId::Number(self.next_id.fetch_add(1, Ordering::Relaxed));
+        let original_id = std::mem::replace(&mut req.id, internal_id.clone());
+        let full = {
+            let mut pending = self.pending.lock().unwrap();
+            pending.push((req, original_id, send));
+            pending.len() >= self.max_batch
+        };
+        if full {
+            self.flush(self.take_pending()).await;
+        } else {
+            let answered = futures_lite::future::or(async { recv.recv().await.ok() }, async {
+                timer::sleep(self.window).await;
+                None
+            })
+            .await;
+            if let Some(answer) = answered {
+                return answer;
+            }
+            // only send the batch if nobody else already has
+            let ours = self
+                .pending
+                .lock()
+                .unwrap()
+                .iter()
+                .any(|(req, _, _)| req.id == internal_id);
+            if ours {
+                self.flush(self.take_pending()).await;
+            }
+        }
+        recv.recv()
+            .await
+            .unwrap_or(Err(BatchError::MissingResponse))
+    }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        self.inner
+            .call_raw_batch(reqs)
+            .await
+            .map_err(|err| BatchError::Transport(Arc::new(err)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use async_trait::async_trait;
+
+    use crate::{
+        BatchingTransport, FnService, JrpcRequest, JrpcResponse, RpcService, RpcTransport,
+    };
+
+    /// Counts how many batches it receives.
+    struct CountingTransport(FnService, AtomicUsize);
+
+    #[async_trait]
+    impl RpcTransport for CountingTransport {
+        type Error = ();
+
+        async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+            Ok(self.0.respond_raw(req).await)
+        }
+
+        async fn call_raw_batch(
+            &self,
+            reqs: Vec<JrpcRequest>,
+        ) -> Result<Vec<JrpcResponse>, Self::Error> {
+            self.1.fetch_add(1, Ordering::SeqCst);
+            let mut resps = vec![];
+            for req in reqs.into_iter().rev() {
+                resps.push(self.0.respond_raw(req).await);
+            }
+            Ok(resps)
+        }
+    }
+
+    #[test]
+    fn test_batching() {
+        smol::future::block_on(async move {
+            let transport = Arc::new(
+                BatchingTransport::new(CountingTransport(
+                    FnService::new(|_, params| async move { Some(Ok(params[0].clone())) }),
+                    AtomicUsize::new(0),
+                ))
+                .with_window(Duration::from_millis(20))
+                .with_max_batch(4),
+            );
+            let calls: Vec<_> = (0..6u64)
+                .map(|i| {
+                    let transport = transport.clone();
+                    smol::spawn(async move { transport.call("echo", &[i.into()]).await })
+                })
+                .collect();
+            for (i, call) in calls.into_iter().enumerate() {
+                assert_eq!(call.await.unwrap().unwrap().unwrap(), i as u64);
+            }
+            assert_eq!(transport.inner.1.load(Ordering::SeqCst), 2);
+        });
+    }
+}
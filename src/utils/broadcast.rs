@@ -0,0 +1,106 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use crate::JrpcNotification;
+
+/// What a [Broadcaster] does with a peer whose buffer is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlowPeerPolicy {
+    /// Skip the notification for that peer.
+    DropMessage,
+    /// Disconnect the peer, closing its receiver.
+    Disconnect,
+}
+
+/// A Broadcaster sends notifications to every subscribed peer, like the clients connected to a pub/sub server. Each peer has a bounded buffer, so that one slow peer can't hold up the others or exhaust memory; peers that went away are removed automatically.
+///
+/// Each subscription is a receiver of [JrpcNotification]s, which server adapters forward to their connection, for example through [crate::Multiplexer::send].
+pub struct Broadcaster {
+    peers: Mutex<HashMap<u64, async_channel::Sender<JrpcNotification>>>,
+    next_id: AtomicU64,
+    buffer: usize,
+    policy: SlowPeerPolicy,
+}
+
+impl Broadcaster {
+    /// Creates a new Broadcaster, buffering up to `buffer` notifications per peer and dropping notifications to peers whose buffer is full.
+    pub fn new(buffer: usize) -> Self {
+        Self {
+            peers: Default::default(),
+            next_id: AtomicU64::new(0),
+            buffer: buffer.max(1),
+            policy: SlowPeerPolicy::DropMessage,
+        }
+    }
+
+    /// Sets what happens to peers whose buffer is full.
+    pub fn with_slow_peer_policy(mut self, policy: SlowPeerPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Subscribes a new peer, returning the receiver of its notifications.
+    pub fn subscribe(&self) -> async_channel::Receiver<JrpcNotification> {
+        let (send, recv) = async_channel::bounded(self.buffer);
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.peers.lock().unwrap().insert(id, send);
+        recv
+    }
+
+    /// Returns the number of subscribed peers.
+    pub fn peer_count(&self) -> usize {
+        self.peers.lock().unwrap().len()
+    }
+
+    /// Sends a notification to every peer, returning the number of peers it was delivered to.
+    pub fn broadcast(&self, method: impl Into<String>, params: Vec<serde_json::Value>) -> usize {
+        let notification = JrpcNotification {
+            jsonrpc: "2.0".into(),
+            method: method.into(),
+            params,
+        };
+        let mut delivered = 0;
+        self.peers
+            .lock()
+            .unwrap()
+            .retain(|id, peer| match peer.try_send(notification.clone()) {
+                Ok(()) => {
+                    delivered += 1;
+                    true
+                }
+                Err(async_channel::TrySendError::Closed(_)) => false,
+                Err(async_channel::TrySendError::Full(_)) => {
+                    log::debug!("peer {} is too slow for {}", id, notification.method);
+                    self.policy == SlowPeerPolicy::DropMessage
+                }
+            });
+        delivered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Broadcaster, SlowPeerPolicy};
+
+    #[test]
+    fn test_broadcast() {
+        let broadcaster = Broadcaster::new(1).with_slow_peer_policy(SlowPeerPolicy::Disconnect);
+        let fast = broadcaster.subscribe();
+        let slow = broadcaster.subscribe();
+        let gone = broadcaster.subscribe();
+        drop(gone);
+        assert_eq!(broadcaster.broadcast("tick", vec![1.into()]), 2);
+        assert_eq!(fast.try_recv().unwrap().params[0], 1);
+        // the slow peer still hasn't read the first tick
+        assert_eq!(broadcaster.broadcast("tick", vec![2.into()]), 1);
+        assert_eq!(broadcaster.peer_count(), 1);
+        assert_eq!(fast.try_recv().unwrap().params[0], 2);
+        assert_eq!(slow.try_recv().unwrap().params[0], 1);
+        assert!(slow.try_recv().is_err() && slow.is_closed());
+    }
+}
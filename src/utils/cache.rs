@@ -0,0 +1,222 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+
+use crate::{Identity, RpcContext, RpcService, ServerError};
+
+/// The caller's identity, if any, the method, and the params.
+type CacheKey = (Option<String>, String, String);
+
+#[derive(Default)]
+struct Cache {
+    entries: HashMap<CacheKey, (serde_json::Value, Instant)>,
+    // insertion order, for evicting the oldest entries first
+    order: VecDeque<CacheKey>,
+}
+
+/// A CachingService caches successful results of idempotent methods, keyed by the method and its parameters. Only the listed methods are cached; errors and calls to other methods always go to the inner service.
+///
+/// Results are cached separately for every [Identity] attached to the context, like by an [crate::AuthService], so that one caller never gets another's results. Methods whose results depend on anything else in the context, like a tenant ID in the metadata, must not be cached.
+pub struct CachingService<S: RpcService> {
+    inner: S,
+    methods: HashSet<String>,
+    ttl: Duration,
+    capacity: usize,
+    cache: Mutex<Cache>,
+}
+
+impl<S: RpcService> CachingService<S> {
+    /// Creates a new CachingService caching the given methods, for 60 seconds and up to 1024 results by default.
+    pub fn new(inner: S, methods: &[&str]) -> Self {
+        Self {
+            inner,
+            methods: methods.iter().map(|s| s.to_string()).collect(),
+            ttl: Duration::from_secs(60),
+            capacity: 1024,
+            cache: Default::default(),
+        }
+    }
+
+    /// Sets how long results are cached.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Sets the maximum number of cached results. Once full, the oldest results are evicted first.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Removes every cached result.
+    pub fn invalidate(&self) {
+        *self.cache.lock().unwrap() = Cache::default();
+    }
+
+    fn get(&self, key: &CacheKey) -> Option<serde_json::Value> {
+        let mut cache = self.cache.lock().unwrap();
+        match cache.entries.get(key) {
+            Some((value, expiry)) if *expiry > Instant::now() => Some(value.clone()),
+            Some(_) => {
+                cache.entries.remove(key);
+                cache.order.retain(|k| k != key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert(&self, key: CacheKey, value: serde_json::Value) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut cache = self.cache.lock().unwrap();
+        let expiry = Instant::now() + self.ttl;
+        if cache.entries.insert(key.clone(), (value, expiry)).is_some() {
+            cache.order.retain(|k| k != &key);
+        }
+        cache.order.push_back(key);
+        while cache.entries.len() > self.capacity {
+            if let Some(oldest) = cache.order.pop_front() {
+                cache.entries.remove(&oldest);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<S: RpcService> RpcService for CachingService<S> {
+    async fn respond(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        self.respond_with_context(&RpcContext::default(), method, params)
+            .await
+    }
+
+    async fn respond_with_context(
+        &self,
+        ctx: &RpcContext,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        if !self.methods.contains(method) {
+            return self.inner.respond_with_context(ctx, method, params).await;
+        }
+        let key = (
+            ctx.extension::<Identity>().map(|i| i.id.clone()),
+            method.to_string(),
+            serde_json::to_string(params).unwrap(),
+        );
+        if let Some(value) = self.get(&key) {
+            return Some(Ok(value));
+        }
+        let response = self.inner.respond_with_context(ctx, method, params).await;
+        if let Some(Ok(value)) = &response {
+            self.insert(key, value.clone());
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use crate::{CachingService, FnService, Identity, RpcContext, RpcService, ServerError};
+
+    #[test]
+    fn test_caching() {
+        smol::future::block_on(async move {
+            let calls = Arc::new(AtomicU64::new(0));
+            let service = {
+                let calls = calls.clone();
+                FnService::new(move |_, _| {
+                    let n = calls.fetch_add(1, Ordering::SeqCst);
+                    async move { Some(Ok(n.into())) }
+                })
+            };
+            let service = CachingService::new(service, &["cached"])
+                .with_ttl(Duration::from_millis(100))
+                .with_capacity(2);
+            let call = |method: &'static str, param: u64| {
+                let service = &service;
+                async move { service.respond(method, &[param.into()]).await }
+            };
+            assert_eq!(call("cached", 1).await.unwrap().unwrap(), 0);
+            assert_eq!(call("cached", 1).await.unwrap().unwrap(), 0);
+            assert_eq!(call("cached", 2).await.unwrap().unwrap(), 1);
+            assert_eq!(call("uncached", 1).await.unwrap().unwrap(), 2);
+            assert_eq!(call("uncached", 1).await.unwrap().unwrap(), 3);
+            // evicts the result for 1
+            assert_eq!(call("cached", 3).await.unwrap().unwrap(), 4);
+            assert_eq!(call("cached", 1).await.unwrap().unwrap(), 5);
+            smol::Timer::after(Duration::from_millis(150)).await;
+            assert_eq!(call("cached", 1).await.unwrap().unwrap(), 6);
+        });
+    }
+
+    /// Answers with the ID of the caller's identity.
+    struct WhoAmI;
+
+    #[async_trait::async_trait]
+    impl RpcService for WhoAmI {
+        async fn respond(
+            &self,
+            method: &str,
+            params: &[serde_json::Value],
+        ) -> Option<Result<serde_json::Value, ServerError>> {
+            self.respond_with_context(&RpcContext::default(), method, params)
+                .await
+        }
+
+        async fn respond_with_context(
+            &self,
+            ctx: &RpcContext,
+            _method: &str,
+            _params: &[serde_json::Value],
+        ) -> Option<Result<serde_json::Value, ServerError>> {
+            Some(Ok(ctx.extension::<Identity>().map(|i| i.id.clone()).into()))
+        }
+    }
+
+    #[test]
+    fn test_caching_per_identity() {
+        smol::future::block_on(async move {
+            let service = CachingService::new(WhoAmI, &["whoami"]);
+            let call = |id: Option<&'static str>| {
+                let service = &service;
+                let mut ctx = RpcContext::new();
+                if let Some(id) = id {
+                    ctx.insert_extension(Identity {
+                        id: id.into(),
+                        roles: vec![],
+                    });
+                }
+                async move {
+                    service
+                        .respond_with_context(&ctx, "whoami", &[])
+                        .await
+                        .unwrap()
+                        .unwrap()
+                }
+            };
+            assert_eq!(call(Some("alice")).await, "alice");
+            assert_eq!(call(Some("bob")).await, "bob");
+            assert_eq!(call(None).await, serde_json::Value::Null);
+            assert_eq!(call(Some("alice")).await, "alice");
+        });
+    }
+}
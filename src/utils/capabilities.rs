@@ -0,0 +1,177 @@
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::{JrpcRequest, JrpcResponse, RpcContext, RpcService, RpcTransport, ServerError};
+
+/// The method answered by [CapabilitiesService].
+pub const CAPABILITIES_METHOD: &str = "rpc.capabilities";
+
+/// What a server supports, as returned by `rpc.capabilities`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    /// The version of the application protocol, as chosen by the server.
+    pub protocol_version: String,
+    /// The methods the server supports.
+    pub methods: Vec<String>,
+    /// Whether the server accepts JSON-RPC batches.
+    pub batch: bool,
+    /// The largest request, in bytes, that the server accepts, if there is a limit.
+    pub max_payload_size: Option<usize>,
+}
+
+impl Capabilities {
+    /// Creates a description of a server supporting the given methods, and batches. Protocols generated by `#[nanorpc_derive]` list their methods in `FooService::METHODS`.
+    pub fn new(protocol_version: impl Into<String>, methods: &[&str]) -> Self {
+        Self {
+            protocol_version: protocol_version.into(),
+            methods: methods.iter().map(|s| s.to_string()).collect(),
+            batch: true,
+            max_payload_size: None,
+        }
+    }
+
+    /// Returns whether the given method is supported.
+    pub fn supports(&self, method: &str) -> bool {
+        self.methods.iter().any(|m| m == method)
+    }
+}
+
+/// A CapabilitiesService answers `rpc.capabilities` with a fixed [Capabilities], and passes every other call to the inner service.
+pub struct CapabilitiesService<S: RpcService> {
+    inner: S,
+    capabilities: serde_json::Value,
+}
+
+impl<S: RpcService> CapabilitiesService<S> {
+    /// Creates a new CapabilitiesService.
+    pub fn new(inner: S, capabilities: Capabilities) -> Self {
+        Self {
+            inner,
+            capabilities: serde_json::to_value(capabilities).unwrap(),
+        }
+    }
+}
+
+#[async_trait]
+impl<S: RpcService> RpcService for CapabilitiesService<S> {
+    async fn respond(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        self.respond_with_context(&RpcContext::default(), method, params)
+            .await
+    }
+
+    async fn respond_with_context(
+        &self,
+        ctx: &RpcContext,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        if method == CAPABILITIES_METHOD {
+            Some(Ok(self.capabilities.clone()))
+        } else {
+            self.inner.respond_with_context(ctx, method, params).await
+        }
+    }
+}
+
+/// A CapabilitiesTransport wraps a transport, fetching the server's [Capabilities] the first time they are asked for and caching them afterwards. Calls are passed through unchanged.
+pub struct CapabilitiesTransport<T: RpcTransport> {
+    inner: T,
+    cached: Mutex<Option<Option<Capabilities>>>,
+}
+
+impl<T: RpcTransport> CapabilitiesTransport<T> {
+    /// Creates a new CapabilitiesTransport.
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// Returns the server's capabilities, or `None` if the server does not support `rpc.capabilities`. Only the first successful call goes to the server.
+    pub async fn capabilities(&self) -> Result<Option<Capabilities>, T::Error> {
+        if let Some(cached) = self.cached.lock().unwrap().clone() {
+            return Ok(cached);
+        }
+        let caps = match self.inner.call(CAPABILITIES_METHOD, &[]).await? {
+            Some(Ok(caps)) => match serde_json::from_value(caps) {
+                Ok(caps) => Some(caps),
+                Err(err) => {
+                    log::warn!("server returned malformed capabilities: {}", err);
+                    None
+                }
+            },
+            _ => None,
+        };
+        *self.cached.lock().unwrap() = Some(caps.clone());
+        Ok(caps)
+    }
+
+    /// Forgets the cached capabilities, so that they are fetched again next time.
+    pub fn invalidate(&self) {
+        *self.cached.lock().unwrap() = None;
+    }
+}
+
+#[async_trait]
+impl<T: RpcTransport> RpcTransport for CapabilitiesTransport<T> {
+    type Error = T::Error;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        self.inner.call_raw(req).await
+    }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        self.inner.call_raw_batch(reqs).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+    use crate::{FnService, FnTransport};
+
+    fn loopback(
+        service: impl RpcService,
+        count: Arc<AtomicUsize>,
+    ) -> FnTransport<std::convert::Infallible> {
+        FnTransport::loopback_with(service, move |_| {
+            count.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_capabilities_cached() {
+        smol::future::block_on(async move {
+            let caps = Capabilities::new("1.0", &["add"]);
+            let service =
+                CapabilitiesService::new(FnService::new(|_, _| async move { None }), caps.clone());
+            let count = Arc::new(AtomicUsize::new(0));
+            let transport = CapabilitiesTransport::new(loopback(service, count.clone()));
+            assert_eq!(transport.capabilities().await.unwrap(), Some(caps.clone()));
+            assert_eq!(transport.capabilities().await.unwrap(), Some(caps));
+            assert_eq!(count.load(Ordering::SeqCst), 1);
+
+            let bare = CapabilitiesTransport::new(loopback(
+                FnService::new(|_, _| async move { None }),
+                count,
+            ));
+            assert_eq!(bare.capabilities().await.unwrap(), None);
+        });
+    }
+}
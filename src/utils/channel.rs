@@ -0,0 +1,212 @@
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use futures_util::{Sink, Stream, StreamExt};
+
+use crate::{
+    serve_connection, timer, ConnInfo, JrpcMessage, JrpcRequest, JrpcResponse, MultiplexError,
+    Multiplexer, RpcTransport, ServerConfig, SessionFactory,
+};
+
+/// Faults injected into the link made by [channel_transport], for testing how clients and services cope with a bad network. The default is a perfect link.
+#[derive(Clone, Debug, Default)]
+pub struct LinkFaults {
+    latency: Duration,
+    jitter: Duration,
+    drop_rate: f64,
+    disconnect_after: Option<usize>,
+}
+
+impl LinkFaults {
+    /// Creates a perfect link.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delays every message by `latency`, plus a random amount up to `jitter`. Messages are still delivered in order.
+    pub fn with_latency(mut self, latency: Duration, jitter: Duration) -> Self {
+        self.latency = latency;
+        self.jitter = jitter;
+        self
+    }
+
+    /// Silently drops each message with the given probability.
+    pub fn with_drop_rate(mut self, drop_rate: f64) -> Self {
+        self.drop_rate = drop_rate;
+        self
+    }
+
+    /// Breaks the link once this many messages have been sent over it, in either direction.
+    pub fn with_disconnect_after(mut self, messages: usize) -> Self {
+        self.disconnect_after = Some(messages);
+        self
+    }
+}
+
+/// Makes one direction of a faulty link.
+fn link(
+    faults: LinkFaults,
+    sent: Arc<AtomicUsize>,
+) -> (
+    impl Sink<String, Error = MultiplexError> + Send + Unpin + 'static,
+    impl Stream<Item = String> + Send + Unpin + 'static,
+) {
+    let (send, recv) = async_channel::unbounded::<(Instant, String)>();
+    let sink =
+        futures_util::sink::unfold((send, Instant::now()), move |(send, last), msg: String| {
+            let faults = faults.clone();
+            let sent = sent.clone();
+            async move {
+                if let Some(limit) = faults.disconnect_after {
+                    if sent.fetch_add(1, Ordering::SeqCst) >= limit {
+                        send.close();
+                        return Err(MultiplexError::ConnectionLost);
+                    }
+                }
+                if fastrand::f64() < faults.drop_rate {
+                    return Ok((send, last));
+                }
+                let delay = faults.latency + faults.jitter.mul_f64(fastrand::f64());
+                // never deliver before an earlier message
+                let deliver = (Instant::now() + delay).max(last);
+                send.send((deliver, msg))
+                    .await
+                    .map_err(|_| MultiplexError::ConnectionLost)?;
+                Ok((send, deliver))
+            }
+        });
+    let stream = recv.then(|(deliver, msg)| async move {
+        let now = Instant::now();
+        if deliver > now {
+            timer::sleep(deliver - now).await;
+        }
+        msg
+    });
+    (Box::pin(sink), Box::pin(stream))
+}
+
+/// Connects a client to a service made by the factory through in-memory channels, returning the client together with a future that runs both ends of the connection. Unlike calling a service directly, every message is serialized and crosses task boundaries, and the link can be made faulty with [LinkFaults].
+///
+/// The future must be spawned on some runtime for calls to make progress; it resolves once the connection is lost or the client is dropped.
+pub fn channel_transport<F: SessionFactory>(
+    factory: F,
+    faults: LinkFaults,
+) -> (ChannelTransport, impl Future<Output = ()> + Send + 'static) {
+    let sent = Arc::new(AtomicUsize::new(0));
+    let (client_sink, server_stream) = link(faults.clone(), sent.clone());
+    let (server_sink, client_stream) = link(faults, sent);
+    let (mux, driver) = Multiplexer::new(client_sink, client_stream);
+    let server = async move {
+        serve_connection(
+            &factory,
+            ConnInfo::new(),
+            server_sink,
+            server_stream,
+            &ServerConfig::default(),
+        )
+        .await
+    };
+    let fut = async move {
+        futures_lite::future::zip(driver, server).await;
+    };
+    (ChannelTransport { mux }, fut)
+}
+
+/// The client side of a [channel_transport].
+pub struct ChannelTransport {
+    mux: Multiplexer,
+}
+
+impl ChannelTransport {
+    /// Sets a timeout for every call. Calls whose messages are dropped by [LinkFaults] otherwise never finish.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self {
+            mux: self.mux.with_timeout(timeout),
+        }
+    }
+
+    /// Waits for the next message from the service that is not a response, like a notification. Returns `None` once the connection is lost.
+    pub async fn next_incoming(&self) -> Option<JrpcMessage> {
+        self.mux.next_incoming().await
+    }
+}
+
+#[async_trait]
+impl RpcTransport for ChannelTransport {
+    type Error = MultiplexError;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        self.mux.call_raw(req).await
+    }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        self.mux.call_raw_batch(reqs).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::{channel_transport, ConnInfo, FnService, LinkFaults, MultiplexError, RpcTransport};
+
+    fn echo(_: &ConnInfo) -> impl crate::RpcService {
+        FnService::new(|_, params| async move { Some(Ok(params[0].clone())) })
+    }
+
+    #[test]
+    fn test_channel_transport() {
+        smol::future::block_on(async move {
+            let faults = LinkFaults::new().with_latency(Duration::from_millis(50), Duration::ZERO);
+            let (transport, fut) = channel_transport(echo, faults);
+            smol::spawn(fut).detach();
+            let start = Instant::now();
+            let (a, b) = futures_lite::future::zip(
+                transport.call("f", &[1.into()]),
+                transport.call("f", &[2.into()]),
+            )
+            .await;
+            assert_eq!(a.unwrap().unwrap().unwrap(), 1);
+            assert_eq!(b.unwrap().unwrap().unwrap(), 2);
+            // a round trip each way, but the calls overlap
+            let elapsed = start.elapsed();
+            assert!(elapsed >= Duration::from_millis(100) && elapsed < Duration::from_millis(180));
+
+            let (transport, fut) = channel_transport(echo, LinkFaults::new().with_drop_rate(1.0));
+            smol::spawn(fut).detach();
+            let transport = transport.with_timeout(Duration::from_millis(50));
+            assert_eq!(
+                transport.call("f", &[1.into()]).await.unwrap_err(),
+                MultiplexError::Timeout
+            );
+
+            let (transport, fut) =
+                channel_transport(echo, LinkFaults::new().with_disconnect_after(2));
+            let fut = smol::spawn(fut);
+            assert_eq!(
+                transport
+                    .call("f", &[1.into()])
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .unwrap(),
+                1
+            );
+            assert_eq!(
+                transport.call("f", &[2.into()]).await.unwrap_err(),
+                MultiplexError::ConnectionLost
+            );
+            fut.await;
+        });
+    }
+}
@@ -0,0 +1,110 @@
+use std::time::Duration;
+
+use async_lock::Semaphore;
+use async_trait::async_trait;
+
+use crate::{timer, RpcContext, RpcService, ServerError};
+
+/// The JSON-RPC error code for calls rejected because the server is too busy.
+pub const SERVER_BUSY_CODE: i32 = -32002;
+
+/// A ConcurrencyLimitService limits how many calls the inner service handles at once. Calls over the limit wait for a slot, up to an optional maximum wait, after which they are rejected with a [SERVER_BUSY_CODE] error.
+pub struct ConcurrencyLimitService<S: RpcService> {
+    inner: S,
+    semaphore: Semaphore,
+    max_wait: Option<Duration>,
+}
+
+impl<S: RpcService> ConcurrencyLimitService<S> {
+    /// Creates a new ConcurrencyLimitService, where calls over the limit wait as long as it takes.
+    pub fn new(inner: S, max_concurrent: usize) -> Self {
+        Self {
+            inner,
+            semaphore: Semaphore::new(max_concurrent),
+            max_wait: None,
+        }
+    }
+
+    /// Sets how long calls over the limit wait before they are rejected. With a zero duration, they are rejected immediately.
+    pub fn with_max_wait(mut self, max_wait: Duration) -> Self {
+        self.max_wait = Some(max_wait);
+        self
+    }
+}
+
+#[async_trait]
+impl<S: RpcService> RpcService for ConcurrencyLimitService<S> {
+    async fn respond(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        self.respond_with_context(&RpcContext::default(), method, params)
+            .await
+    }
+
+    async fn respond_with_context(
+        &self,
+        ctx: &RpcContext,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        let guard = match self.max_wait {
+            None => Some(self.semaphore.acquire().await),
+            Some(max_wait) if max_wait.is_zero() => self.semaphore.try_acquire(),
+            Some(max_wait) => match self.semaphore.try_acquire() {
+                Some(guard) => Some(guard),
+                None => timer::timeout(max_wait, self.semaphore.acquire()).await,
+            },
+        };
+        if guard.is_none() {
+            return Some(Err(ServerError::with_jrpc_code(
+                SERVER_BUSY_CODE,
+                "server busy",
+                serde_json::Value::Null,
+            )));
+        }
+        self.inner.respond_with_context(ctx, method, params).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{ConcurrencyLimitService, FnService, RpcService, SERVER_BUSY_CODE};
+
+    #[test]
+    fn test_concurrency_limit() {
+        smol::future::block_on(async move {
+            let service = ConcurrencyLimitService::new(
+                FnService::new(|_, params| async move {
+                    let ms = params[0].as_u64().unwrap();
+                    smol::Timer::after(Duration::from_millis(ms)).await;
+                    Some(Ok(ms.into()))
+                }),
+                1,
+            )
+            .with_max_wait(Duration::from_millis(50));
+            // the second call waits for the first one, then gives up
+            let (a, b) = futures_lite::future::zip(
+                service.respond("sleep", &[200.into()]),
+                service.respond("sleep", &[1.into()]),
+            )
+            .await;
+            assert_eq!(a.unwrap().unwrap(), 200);
+            assert_eq!(
+                b.unwrap().unwrap_err().jrpc_code(),
+                Some(SERVER_BUSY_CODE as i64)
+            );
+            // but succeeds when it doesn't have to wait for long
+            let (a, b) = futures_lite::future::zip(
+                service.respond("sleep", &[10.into()]),
+                service.respond("sleep", &[1.into()]),
+            )
+            .await;
+            assert_eq!(a.unwrap().unwrap(), 10);
+            assert_eq!(b.unwrap().unwrap(), 1);
+        });
+    }
+}
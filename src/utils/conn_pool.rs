@@ -0,0 +1,249 @@
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use futures_lite::future::Boxed;
+use thiserror::Error;
+
+use crate::{timer, JrpcRequest, JrpcResponse, Multiplexer, RpcTransport};
+
+/// A transport over a persistent connection, which can tell when the connection is lost, for a [ConnectionPool].
+pub trait PersistentTransport: RpcTransport {
+    /// Returns whether the connection has been lost.
+    fn is_closed(&self) -> bool;
+}
+
+impl PersistentTransport for Multiplexer {
+    fn is_closed(&self) -> bool {
+        Multiplexer::is_closed(self)
+    }
+}
+
+/// An error returned by a [ConnectionPool].
+#[derive(Error, Debug)]
+pub enum ConnectionPoolError<C, E> {
+    #[error("could not connect: {0}")]
+    Connect(C),
+    #[error(transparent)]
+    Transport(E),
+}
+
+pub(crate) type Connector<T, C> = Box<dyn Fn() -> Boxed<Result<T, C>> + Send + Sync + 'static>;
+
+/// A place for one connection, which is made again whenever it is missing or lost.
+pub(crate) struct ConnSlot<T>(async_lock::Mutex<Option<Arc<T>>>);
+
+impl<T: PersistentTransport> ConnSlot<T> {
+    pub(crate) fn new() -> Self {
+        Self(async_lock::Mutex::new(None))
+    }
+
+    /// Returns whether the slot holds a live connection right now. A connection being made doesn't count.
+    pub(crate) fn is_live(&self) -> bool {
+        self.0
+            .try_lock()
+            .map(|conn| conn.as_ref().map(|conn| !conn.is_closed()).unwrap_or(false))
+            .unwrap_or(false)
+    }
+
+    /// Returns the connection in the slot, making a new one with `connect` if it is missing or lost. Concurrent callers wait for the same new connection.
+    pub(crate) async fn get_or_connect<C, Fut: Future<Output = Result<T, C>>>(
+        &self,
+        connect: impl FnOnce() -> Fut,
+    ) -> Result<Arc<T>, C> {
+        let mut conn = self.0.lock().await;
+        if let Some(conn) = conn.as_ref().filter(|conn| !conn.is_closed()) {
+            return Ok(conn.clone());
+        }
+        let new_conn = Arc::new(connect().await?);
+        *conn = Some(new_conn.clone());
+        Ok(new_conn)
+    }
+}
+
+/// A ConnectionPool keeps several connections to the same endpoint, like [crate::TcpTransport]s or [crate::WsTransport]s, and spreads calls across them round-robin, so that bursts of calls don't queue up behind one socket.
+///
+/// Connections are made with the given function. A connection that turns out to be lost is replaced on the next call that would use it, or in the background while the future returned by [ConnectionPool::run_maintenance] is polled. Calls in flight on a lost connection still fail.
+pub struct ConnectionPool<T: PersistentTransport, C> {
+    connect: Connector<T, C>,
+    slots: Vec<ConnSlot<T>>,
+    next: AtomicUsize,
+}
+
+impl<T: PersistentTransport, C: Send + Sync + 'static> ConnectionPool<T, C> {
+    /// Creates a new ConnectionPool of `size` connections, made on first use. Panics if `size` is zero.
+    pub fn new<Fut, Fun>(size: usize, connect: Fun) -> Self
+    where
+        Fut: Future<Output = Result<T, C>> + Send + 'static,
+        Fun: Fn() -> Fut + Send + Sync + 'static,
+    {
+        assert!(size > 0, "ConnectionPool needs at least one connection");
+        Self {
+            connect: Box::new(move || Box::pin(connect())),
+            slots: (0..size).map(|_| ConnSlot::new()).collect(),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Makes every connection that is missing or lost, concurrently. Fails with the first error, though the other connections are still made.
+    pub async fn warm_up(&self) -> Result<(), C> {
+        let results =
+            futures_util::future::join_all(self.slots.iter().map(|slot| self.fill(slot))).await;
+        results.into_iter().try_for_each(|res| res.map(|_| ()))
+    }
+
+    /// Replaces lost connections every interval, forever, so that calls rarely have to wait for a new connection.
+    pub async fn run_maintenance(&self, interval: Duration)
+    where
+        C: std::fmt::Debug,
+    {
+        loop {
+            if let Err(err) = self.warm_up().await {
+                log::warn!("could not replace pooled connection: {:?}", err);
+            }
+            timer::sleep(interval).await;
+        }
+    }
+
+    /// Returns how many connections are currently live.
+    pub fn live_connections(&self) -> usize {
+        self.slots.iter().filter(|slot| slot.is_live()).count()
+    }
+
+    async fn connection(&self) -> Result<Arc<T>, C> {
+        let slot = &self.slots[self.next.fetch_add(1, Ordering::Relaxed) % self.slots.len()];
+        self.fill(slot).await
+    }
+
+    async fn fill(&self, slot: &ConnSlot<T>) -> Result<Arc<T>, C> {
+        slot.get_or_connect(|| (self.connect)()).await
+    }
+}
+
+#[async_trait]
+impl<T: PersistentTransport, C: Send + Sync + 'static> RpcTransport for ConnectionPool<T, C> {
+    type Error = ConnectionPoolError<C, T::Error>;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        let conn = self
+            .connection()
+            .await
+            .map_err(ConnectionPoolError::Connect)?;
+        conn.call_raw(req)
+            .await
+            .map_err(ConnectionPoolError::Transport)
+    }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        let conn = self
+            .connection()
+            .await
+            .map_err(ConnectionPoolError::Connect)?;
+        conn.call_raw_batch(reqs)
+            .await
+            .map_err(ConnectionPoolError::Transport)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    };
+
+    use async_trait::async_trait;
+
+    use crate::{
+        ConnectionPool, ConnectionPoolError, FnService, JrpcRequest, JrpcResponse,
+        PersistentTransport, RpcService, RpcTransport,
+    };
+
+    struct FakeConn {
+        id: usize,
+        closed: Arc<AtomicBool>,
+    }
+
+    #[async_trait]
+    impl RpcTransport for FakeConn {
+        type Error = ();
+
+        async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, ()> {
+            if self.is_closed() {
+                return Err(());
+            }
+            let id = self.id;
+            let service = FnService::new(move |_, _| async move { Some(Ok(id.into())) });
+            Ok(service.respond_raw(req).await)
+        }
+    }
+
+    impl PersistentTransport for FakeConn {
+        fn is_closed(&self) -> bool {
+            self.closed.load(Ordering::SeqCst)
+        }
+    }
+
+    #[test]
+    fn test_connection_pool() {
+        smol::future::block_on(async move {
+            let conns: Arc<Mutex<Vec<Arc<AtomicBool>>>> = Default::default();
+            let fail = Arc::new(AtomicBool::new(false));
+            let pool = ConnectionPool::new(2, {
+                let conns = conns.clone();
+                let fail = fail.clone();
+                move || {
+                    let mut conns = conns.lock().unwrap();
+                    let closed = Arc::new(AtomicBool::new(false));
+                    conns.push(closed.clone());
+                    let conn = FakeConn {
+                        id: conns.len(),
+                        closed,
+                    };
+                    let fail = fail.load(Ordering::SeqCst);
+                    async move {
+                        if fail {
+                            Err("refused")
+                        } else {
+                            Ok(conn)
+                        }
+                    }
+                }
+            });
+            assert_eq!(pool.live_connections(), 0);
+            pool.warm_up().await.unwrap();
+            assert_eq!(pool.live_connections(), 2);
+            let mut ids = vec![];
+            for _ in 0..4 {
+                ids.push(pool.call("f", &[]).await.unwrap().unwrap().unwrap());
+            }
+            ids.sort_by_key(|id| id.as_u64());
+            assert_eq!(ids, vec![1, 1, 2, 2]);
+
+            // a lost connection is replaced by the next call that lands on it
+            conns.lock().unwrap()[0].store(true, Ordering::SeqCst);
+            assert_eq!(pool.live_connections(), 1);
+            let a = pool.call("f", &[]).await.unwrap().unwrap().unwrap();
+            let b = pool.call("f", &[]).await.unwrap().unwrap().unwrap();
+            assert_eq!(a.as_u64().unwrap() + b.as_u64().unwrap(), 5);
+            assert_eq!(pool.live_connections(), 2);
+
+            fail.store(true, Ordering::SeqCst);
+            conns.lock().unwrap()[1].store(true, Ordering::SeqCst);
+            assert!(pool.warm_up().await.is_err());
+            let results = [pool.call("f", &[]).await, pool.call("f", &[]).await];
+            assert!(results
+                .iter()
+                .any(|res| matches!(res, Err(ConnectionPoolError::Connect("refused")))));
+        });
+    }
+}
@@ -0,0 +1,96 @@
+use std::{collections::HashMap, time::Duration};
+
+use async_trait::async_trait;
+
+use crate::{timer, RpcContext, RpcService, ServerError};
+
+/// The JSON-RPC error code for calls that the server gave up on because they took too long.
+pub const DEADLINE_EXCEEDED_CODE: i32 = -32003;
+
+/// A DeadlineService gives every call a time budget, cancelling calls that overrun it and answering them with a [DEADLINE_EXCEEDED_CODE] error. Methods can have their own budgets; the others share a default one, if any.
+pub struct DeadlineService<S: RpcService> {
+    inner: S,
+    default: Option<Duration>,
+    per_method: HashMap<String, Duration>,
+}
+
+impl<S: RpcService> DeadlineService<S> {
+    /// Creates a new DeadlineService with the given default budget.
+    pub fn new(inner: S, default: Option<Duration>) -> Self {
+        Self {
+            inner,
+            default,
+            per_method: HashMap::new(),
+        }
+    }
+
+    /// Gives a method its own budget.
+    pub fn with_method_deadline(mut self, method: impl Into<String>, deadline: Duration) -> Self {
+        self.per_method.insert(method.into(), deadline);
+        self
+    }
+}
+
+#[async_trait]
+impl<S: RpcService> RpcService for DeadlineService<S> {
+    async fn respond(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        self.respond_with_context(&RpcContext::default(), method, params)
+            .await
+    }
+
+    async fn respond_with_context(
+        &self,
+        ctx: &RpcContext,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        let deadline = self.per_method.get(method).copied().or(self.default);
+        let Some(deadline) = deadline else {
+            return self.inner.respond_with_context(ctx, method, params).await;
+        };
+        match timer::timeout(
+            deadline,
+            self.inner.respond_with_context(ctx, method, params),
+        )
+        .await
+        {
+            Some(response) => response,
+            None => {
+                log::warn!("{} exceeded its deadline of {:?}", method, deadline);
+                Some(Err(ServerError::with_jrpc_code(
+                    DEADLINE_EXCEEDED_CODE,
+                    "deadline exceeded",
+                    serde_json::json!({ "deadline_ms": deadline.as_millis() as u64 }),
+                )))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{DeadlineService, FnService, RpcService, DEADLINE_EXCEEDED_CODE};
+
+    #[test]
+    fn test_deadline() {
+        smol::future::block_on(async move {
+            let service = DeadlineService::new(
+                FnService::new(|_, _| async move {
+                    smol::Timer::after(Duration::from_millis(100)).await;
+                    Some(Ok(().into()))
+                }),
+                Some(Duration::from_millis(20)),
+            )
+            .with_method_deadline("slow", Duration::from_secs(1));
+            let err = service.respond("fast", &[]).await.unwrap().unwrap_err();
+            assert_eq!(err.jrpc_code(), Some(DEADLINE_EXCEEDED_CODE as i64));
+            assert!(service.respond("slow", &[]).await.unwrap().is_ok());
+        });
+    }
+}
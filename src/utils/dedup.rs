@@ -0,0 +1,227 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use event_listener::Event;
+
+use crate::{Identity, RpcContext, RpcService, ServerError};
+
+type Response = Option<Result<serde_json::Value, ServerError>>;
+
+enum Entry {
+    InFlight(Arc<Event>),
+    Done(Response, Instant),
+}
+
+/// A DedupService makes sure that duplicate requests, like those sent by retrying clients, are executed only once. A duplicate of a request that is still running waits for it, and a duplicate of a recently completed request gets its response.
+///
+/// Requests are identified by the idempotency key in their metadata (`"idempotency_key"` by default), together with the method and the caller. The caller is the [Identity] attached to the context, like by an [crate::AuthService], or failing that, the IP address of the peer's [SocketAddr], so that callers never get each other's responses. Requests without one are only deduplicated if [DedupService::with_params_dedup] is enabled, in which case identical methods and params count as duplicates.
+pub struct DedupService<S: RpcService> {
+    inner: S,
+    meta_key: String,
+    by_params: bool,
+    ttl: Duration,
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl<S: RpcService> DedupService<S> {
+    /// Creates a new DedupService, remembering completed requests for 5 minutes.
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            meta_key: "idempotency_key".into(),
+            by_params: false,
+            ttl: Duration::from_secs(300),
+            entries: Default::default(),
+        }
+    }
+
+    /// Sets the metadata key holding the idempotency key.
+    pub fn with_meta_key(mut self, key: impl Into<String>) -> Self {
+        self.meta_key = key.into();
+        self
+    }
+
+    /// Also deduplicates requests without an idempotency key, by their method and params.
+    pub fn with_params_dedup(mut self) -> Self {
+        self.by_params = true;
+        self
+    }
+
+    /// Sets how long completed requests are remembered.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    fn key(&self, ctx: &RpcContext, method: &str, params: &[serde_json::Value]) -> Option<String> {
+        let request = match ctx.meta.get(&self.meta_key) {
+            Some(key) => key.to_string(),
+            None if self.by_params => serde_json::to_string(params).unwrap(),
+            None => return None,
+        };
+        let caller = match (ctx.extension::<Identity>(), ctx.extension::<SocketAddr>()) {
+            (Some(identity), _) => format!("id:{}", identity.id),
+            (None, Some(peer)) => format!("ip:{}", peer.ip()),
+            (None, None) => String::new(),
+        };
+        Some(format!("{}\0{}\0{}", caller, method, request))
+    }
+}
+
+/// Removes the in-flight entry if the running request is cancelled, so that its duplicates don't wait forever.
+struct InFlightGuard<'a> {
+    entries: &'a Mutex<HashMap<String, Entry>>,
+    key: &'a str,
+    event: Arc<Event>,
+    done: bool,
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.entries.lock().unwrap().remove(self.key);
+        }
+        self.event.notify(usize::MAX);
+    }
+}
+
+#[async_trait]
+impl<S: RpcService> RpcService for DedupService<S> {
+    async fn respond(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        self.respond_with_context(&RpcContext::default(), method, params)
+            .await
+    }
+
+    async fn respond_with_context(
+        &self,
+        ctx: &RpcContext,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        let Some(key) = self.key(ctx, method, params) else {
+            return self.inner.respond_with_context(ctx, method, params).await;
+        };
+        let event = loop {
+            let listener = {
+                let mut entries = self.entries.lock().unwrap();
+                match entries.get(&key) {
+                    Some(Entry::Done(response, at)) if at.elapsed() < self.ttl => {
+                        return response.clone()
+                    }
+                    Some(Entry::InFlight(event)) => event.listen(),
+                    _ => {
+                        let event = Arc::new(Event::new());
+                        entries.insert(key.clone(), Entry::InFlight(event.clone()));
+                        break event;
+                    }
+                }
+            };
+            listener.await;
+        };
+        let mut guard = InFlightGuard {
+            entries: &self.entries,
+            key: &key,
+            event,
+            done: false,
+        };
+        let response = self.inner.respond_with_context(ctx, method, params).await;
+        {
+            let mut entries = self.entries.lock().unwrap();
+            let ttl = self.ttl;
+            entries.retain(|_, entry| match entry {
+                Entry::Done(_, at) => at.elapsed() < ttl,
+                Entry::InFlight(_) => true,
+            });
+            entries.insert(key.clone(), Entry::Done(response.clone(), Instant::now()));
+        }
+        guard.done = true;
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use crate::{DedupService, FnService, Identity, RpcContext, RpcService};
+
+    #[test]
+    fn test_dedup() {
+        smol::future::block_on(async move {
+            let executions = Arc::new(AtomicU64::new(0));
+            let service = {
+                let executions = executions.clone();
+                DedupService::new(FnService::new(move |_, _| {
+                    let n = executions.fetch_add(1, Ordering::SeqCst);
+                    async move {
+                        smol::Timer::after(Duration::from_millis(20)).await;
+                        Some(Ok(n.into()))
+                    }
+                }))
+            };
+            let mut ctx = RpcContext::new();
+            ctx.meta.insert("idempotency_key".into(), "abc".into());
+            let (a, b) = futures_lite::future::zip(
+                service.respond_with_context(&ctx, "transfer", &[]),
+                service.respond_with_context(&ctx, "transfer", &[]),
+            )
+            .await;
+            assert_eq!(a, b);
+            assert_eq!(service.respond_with_context(&ctx, "transfer", &[]).await, a);
+            assert_eq!(executions.load(Ordering::SeqCst), 1);
+            // without a key, every request runs
+            service.respond("transfer", &[]).await;
+            service.respond("transfer", &[]).await;
+            assert_eq!(executions.load(Ordering::SeqCst), 3);
+        });
+    }
+
+    #[test]
+    fn test_dedup_per_caller() {
+        smol::future::block_on(async move {
+            let executions = Arc::new(AtomicU64::new(0));
+            let service = {
+                let executions = executions.clone();
+                DedupService::new(FnService::new(move |_, _| {
+                    let n = executions.fetch_add(1, Ordering::SeqCst);
+                    async move { Some(Ok(n.into())) }
+                }))
+            };
+            let ctx = |id: &str| {
+                let mut ctx = RpcContext::new();
+                ctx.meta.insert("idempotency_key".into(), "abc".into());
+                ctx.insert_extension(Identity {
+                    id: id.into(),
+                    roles: vec![],
+                });
+                ctx
+            };
+            let (alice, bob) = (ctx("alice"), ctx("bob"));
+            let first = service.respond_with_context(&alice, "transfer", &[]).await;
+            assert_ne!(
+                service.respond_with_context(&bob, "transfer", &[]).await,
+                first
+            );
+            assert_eq!(
+                service.respond_with_context(&alice, "transfer", &[]).await,
+                first
+            );
+            assert_eq!(executions.load(Ordering::SeqCst), 2);
+        });
+    }
+}
@@ -0,0 +1,118 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use async_trait::async_trait;
+
+use crate::{JrpcRequest, JrpcResponse, RpcTransport};
+
+/// A FallbackTransport holds an ordered list of transports, usually to replicas of the same server. Calls go to the last transport that worked, failing over to the next one in the list on transport-level errors. Use [crate::DynRpcTransport] to mix different kinds of transports.
+pub struct FallbackTransport<T: RpcTransport> {
+    inner: Vec<T>,
+    healthy: AtomicUsize,
+}
+
+impl<T: RpcTransport> FallbackTransport<T> {
+    /// Creates a new FallbackTransport. Panics if there are no transports.
+    pub fn new(inner: Vec<T>) -> Self {
+        assert!(
+            !inner.is_empty(),
+            "FallbackTransport needs at least one transport"
+        );
+        Self {
+            inner,
+            healthy: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the index of the transport that calls currently go to first.
+    pub fn healthy_index(&self) -> usize {
+        self.healthy.load(Ordering::Relaxed)
+    }
+
+    /// Tries every transport, starting from the last healthy one, and returns the first success or the last error.
+    async fn try_all<'a, R, F, Fut>(&'a self, f: F) -> Result<R, T::Error>
+    where
+        F: Fn(&'a T) -> Fut,
+        Fut: std::future::Future<Output = Result<R, T::Error>>,
+    {
+        let start = self.healthy_index();
+        let mut last_err = None;
+        for offset in 0..self.inner.len() {
+            let idx = (start + offset) % self.inner.len();
+            match f(&self.inner[idx]).await {
+                Ok(res) => {
+                    if idx != start {
+                        log::debug!("failed over from transport {} to {}", start, idx);
+                        self.healthy.store(idx, Ordering::Relaxed);
+                    }
+                    return Ok(res);
+                }
+                Err(err) => last_err = Some(err),
+            }
+        }
+        Err(last_err.unwrap())
+    }
+}
+
+#[async_trait]
+impl<T: RpcTransport> RpcTransport for FallbackTransport<T> {
+    type Error = T::Error;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        self.try_all(|t| t.call_raw(req.clone())).await
+    }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        self.try_all(|t| t.call_raw_batch(reqs.clone())).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    use crate::{FallbackTransport, FnService, FnTransport, RpcTransport};
+
+    fn replica(name: &'static str, up: Arc<AtomicBool>) -> FnTransport<&'static str> {
+        FnTransport::loopback_with(
+            FnService::new(move |_, _| async move { Some(Ok(name.into())) }),
+            move |_| up.load(Ordering::SeqCst).then_some(()).ok_or("down"),
+        )
+    }
+
+    #[test]
+    fn test_fallback() {
+        smol::future::block_on(async move {
+            let a_up = Arc::new(AtomicBool::new(true));
+            let b_up = Arc::new(AtomicBool::new(true));
+            let transport = FallbackTransport::new(vec![
+                replica("a", a_up.clone()),
+                replica("b", b_up.clone()),
+            ]);
+            assert_eq!(
+                transport.call("f", &[]).await.unwrap().unwrap().unwrap(),
+                "a"
+            );
+            a_up.store(false, Ordering::SeqCst);
+            assert_eq!(
+                transport.call("f", &[]).await.unwrap().unwrap().unwrap(),
+                "b"
+            );
+            // b stays preferred even once a is back
+            a_up.store(true, Ordering::SeqCst);
+            assert_eq!(
+                transport.call("f", &[]).await.unwrap().unwrap().unwrap(),
+                "b"
+            );
+            assert_eq!(transport.healthy_index(), 1);
+            a_up.store(false, Ordering::SeqCst);
+            b_up.store(false, Ordering::SeqCst);
+            assert_eq!(transport.call("f", &[]).await.unwrap_err(), "down");
+        });
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+
+use crate::{CallOptions, DynRpcTransport, RpcContext, RpcService, RpcTransport, ServerError};
+
+/// The JSON-RPC error code for calls that a gateway could not forward to their upstream server.
+pub const UPSTREAM_ERROR_CODE: i32 = -32005;
+
+/// A GatewayService forwards calls to upstream servers, acting as a JSON-RPC reverse proxy. Upstreams are chosen by an explicit table of methods, then by method prefix, with the longest prefix winning.
+///
+/// Request metadata is forwarded along with the call. Transport-level errors reaching the upstream are turned into [UPSTREAM_ERROR_CODE] errors.
+#[derive(Default)]
+pub struct GatewayService {
+    methods: HashMap<String, DynRpcTransport>,
+    // (prefix, upstream), longest prefixes first
+    prefixes: Vec<(String, DynRpcTransport)>,
+}
+
+impl GatewayService {
+    /// Creates a GatewayService without any upstreams.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forwards calls to methods starting with the prefix to an upstream, with the prefix stripped.
+    pub fn route_prefix(mut self, prefix: impl Into<String>, upstream: DynRpcTransport) -> Self {
+        self.prefixes.push((prefix.into(), upstream));
+        self.prefixes
+            .sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        self
+    }
+
+    /// Forwards calls to a method to an upstream, with the method name unchanged.
+    pub fn route_method(mut self, method: impl Into<String>, upstream: DynRpcTransport) -> Self {
+        self.methods.insert(method.into(), upstream);
+        self
+    }
+
+    fn upstream<'a>(&'a self, method: &'a str) -> Option<(&'a DynRpcTransport, &'a str)> {
+        if let Some(upstream) = self.methods.get(method) {
+            return Some((upstream, method));
+        }
+        self.prefixes.iter().find_map(|(prefix, upstream)| {
+            method
+                .strip_prefix(prefix.as_str())
+                .map(|rest| (upstream, rest))
+        })
+    }
+}
+
+#[async_trait]
+impl RpcService for GatewayService {
+    async fn respond(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        self.respond_with_context(&RpcContext::default(), method, params)
+            .await
+    }
+
+    async fn respond_with_context(
+        &self,
+        ctx: &RpcContext,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        let (upstream, upstream_method) = self.upstream(method)?;
+        let opts = CallOptions {
+            meta: ctx.meta.clone(),
+        };
+        match upstream
+            .call_with_opts(upstream_method, params, &opts)
+            .await
+        {
+            Ok(response) => response,
+            Err(err) => {
+                log::warn!("could not forward {} upstream: {:?}", method, err);
+                Some(Err(ServerError::with_jrpc_code(
+                    UPSTREAM_ERROR_CODE,
+                    "upstream unavailable",
+                    serde_json::Value::Null,
+                )))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use crate::{
+        DynRpcTransport, FnService, FnTransport, GatewayService, RpcService, UPSTREAM_ERROR_CODE,
+    };
+
+    fn upstream(name: &'static str) -> DynRpcTransport {
+        DynRpcTransport::new(FnTransport::<Infallible>::loopback(FnService::new(
+            move |method, _| {
+                let reply = format!("{} {}", name, method);
+                async move { Some(Ok(reply.into())) }
+            },
+        )))
+    }
+
+    #[test]
+    fn test_gateway() {
+        smol::future::block_on(async move {
+            let gateway = GatewayService::new()
+                .route_prefix("wallet.", upstream("wallet"))
+                .route_method("status", upstream("status"))
+                .route_prefix(
+                    "down.",
+                    DynRpcTransport::from_fn(|_| async { anyhow::bail!("connection refused") }),
+                );
+            assert_eq!(
+                gateway
+                    .respond("wallet.balance", &[])
+                    .await
+                    .unwrap()
+                    .unwrap(),
+                "wallet balance"
+            );
+            assert_eq!(
+                gateway.respond("status", &[]).await.unwrap().unwrap(),
+                "status status"
+            );
+            assert!(gateway.respond("other", &[]).await.is_none());
+            assert_eq!(
+                gateway
+                    .respond("down.f", &[])
+                    .await
+                    .unwrap()
+                    .unwrap_err()
+                    .jrpc_code(),
+                Some(UPSTREAM_ERROR_CODE as i64)
+            );
+        });
+    }
+}
@@ -0,0 +1,100 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures_util::future::{select, Either};
+
+use crate::{timer, JrpcRequest, JrpcResponse, RpcTransport};
+
+/// A HedgingTransport sends each call to a primary transport, and if no response arrives within the hedge delay, sends it to a secondary transport as well. Whichever succeeds first wins, and the other call is cancelled.
+///
+/// Since calls may be executed twice, only use this for idempotent methods.
+pub struct HedgingTransport<T: RpcTransport> {
+    primary: T,
+    secondary: T,
+    delay: Duration,
+}
+
+impl<T: RpcTransport> HedgingTransport<T> {
+    /// Creates a new HedgingTransport.
+    pub fn new(primary: T, secondary: T, delay: Duration) -> Self {
+        Self {
+            primary,
+            secondary,
+            delay,
+        }
+    }
+
+    /// Runs a call against both transports, returning the first success, or the last error if both fail.
+    async fn hedge<'a, R, F, Fut>(&'a self, f: F) -> Result<R, T::Error>
+    where
+        F: Fn(&'a T) -> Fut,
+        Fut: std::future::Future<Output = Result<R, T::Error>>,
+    {
+        let primary = Box::pin(f(&self.primary));
+        let secondary = Box::pin(async {
+            timer::sleep(self.delay).await;
+            log::debug!("hedging call after {:?}", self.delay);
+            f(&self.secondary).await
+        });
+        match select(primary, secondary).await {
+            Either::Left((Ok(res), _)) | Either::Right((Ok(res), _)) => Ok(res),
+            Either::Left((Err(_), other)) => other.await,
+            Either::Right((Err(_), other)) => other.await,
+        }
+    }
+}
+
+#[async_trait]
+impl<T: RpcTransport> RpcTransport for HedgingTransport<T> {
+    type Error = T::Error;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        self.hedge(|t| t.call_raw(req.clone())).await
+    }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        self.hedge(|t| t.call_raw_batch(reqs.clone())).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{FnService, FnTransport, HedgingTransport, RpcTransport};
+
+    fn replica(name: &'static str, latency_ms: u64) -> FnTransport<()> {
+        let service = FnService::new(move |_, _| async move {
+            smol::Timer::after(Duration::from_millis(latency_ms)).await;
+            Some(Ok(name.into()))
+        });
+        FnTransport::loopback(service)
+    }
+
+    #[test]
+    fn test_hedging() {
+        smol::future::block_on(async move {
+            let transport = HedgingTransport::new(
+                replica("slow", 500),
+                replica("fast", 10),
+                Duration::from_millis(20),
+            );
+            assert_eq!(
+                transport.call("f", &[]).await.unwrap().unwrap().unwrap(),
+                "fast"
+            );
+            let transport = HedgingTransport::new(
+                replica("primary", 1),
+                replica("secondary", 1),
+                Duration::from_millis(20),
+            );
+            assert_eq!(
+                transport.call("f", &[]).await.unwrap().unwrap().unwrap(),
+                "primary"
+            );
+        });
+    }
+}
@@ -0,0 +1,139 @@
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+
+use crate::{Redactor, RpcContext, RpcService, ServerError};
+
+type RequestHook = Box<dyn Fn(&str, &[serde_json::Value]) + Send + Sync + 'static>;
+type ResponseHook = Box<
+    dyn Fn(&str, &[serde_json::Value], Duration, &Option<Result<serde_json::Value, ServerError>>)
+        + Send
+        + Sync
+        + 'static,
+>;
+
+/// A LoggingService calls hooks before and after every call to the inner service, so that applications can log calls however they like. The response hook gets the method, params, how long the call took, and its outcome.
+///
+/// Params hidden by the [Redactor] are replaced with `"[redacted]"` in what the hooks see, but not in what the inner service sees.
+pub struct LoggingService<S: RpcService> {
+    inner: S,
+    on_request: Option<RequestHook>,
+    on_response: Option<ResponseHook>,
+    redactor: Redactor,
+}
+
+impl<S: RpcService> LoggingService<S> {
+    /// Creates a new LoggingService, without any hooks.
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            on_request: None,
+            on_response: None,
+            redactor: Redactor::new(),
+        }
+    }
+
+    /// Sets the hook called before every call.
+    pub fn on_request(
+        mut self,
+        f: impl Fn(&str, &[serde_json::Value]) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_request = Some(Box::new(f));
+        self
+    }
+
+    /// Sets the hook called after every call.
+    pub fn on_response(
+        mut self,
+        f: impl Fn(
+                &str,
+                &[serde_json::Value],
+                Duration,
+                &Option<Result<serde_json::Value, ServerError>>,
+            ) + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.on_response = Some(Box::new(f));
+        self
+    }
+
+    /// Hides the param at the given index of the given method from the hooks.
+    pub fn with_redacted_param(mut self, method: impl Into<String>, index: usize) -> Self {
+        self.redactor = self.redactor.with_param(method, index);
+        self
+    }
+
+    /// Sets the [Redactor] that hides params from the hooks, replacing any params hidden so far.
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = redactor;
+        self
+    }
+}
+
+#[async_trait]
+impl<S: RpcService> RpcService for LoggingService<S> {
+    async fn respond(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        self.respond_with_context(&RpcContext::default(), method, params)
+            .await
+    }
+
+    async fn respond_with_context(
+        &self,
+        ctx: &RpcContext,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        let logged = self.redactor.redact_params(method, params);
+        if let Some(on_request) = &self.on_request {
+            on_request(method, &logged);
+        }
+        let start = Instant::now();
+        let response = self.inner.respond_with_context(ctx, method, params).await;
+        if let Some(on_response) = &self.on_response {
+            on_response(method, &logged, start.elapsed(), &response);
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{FnService, LoggingService, RpcService};
+
+    #[test]
+    fn test_logging_hooks() {
+        smol::future::block_on(async move {
+            let log = Arc::new(Mutex::new(vec![]));
+            let service = LoggingService::new(FnService::new(|_, params| async move {
+                Some(Ok(params[0].clone()))
+            }))
+            .on_request({
+                let log = log.clone();
+                move |method, params| log.lock().unwrap().push(format!("{} {:?}", method, params))
+            })
+            .on_response({
+                let log = log.clone();
+                move |method, _, _, outcome| {
+                    log.lock()
+                        .unwrap()
+                        .push(format!("{} -> {:?}", method, outcome))
+                }
+            })
+            .with_redacted_param("login", 1);
+            let result = service
+                .respond("login", &["alice".into(), "hunter2".into()])
+                .await;
+            assert_eq!(result.unwrap().unwrap(), "alice");
+            let log = log.lock().unwrap();
+            assert_eq!(log[0], r#"login [String("alice"), String("[redacted]")]"#);
+            assert_eq!(log[1], r#"login -> Some(Ok(String("alice")))"#);
+        });
+    }
+}
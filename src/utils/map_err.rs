@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+
+use crate::{
+    JrpcError, JrpcId, JrpcRequest, JrpcResponse, RpcContext, RpcService, RpcTransport, ServerError,
+};
+
+/// A MapErrService rewrites the errors returned by the inner service, for example to attach the name of the service or to translate error codes. The function gets the method name along with the error.
+pub struct MapErrService<S: RpcService, F> {
+    inner: S,
+    f: F,
+}
+
+impl<S: RpcService, F: Fn(&str, ServerError) -> ServerError + Send + Sync + 'static>
+    MapErrService<S, F>
+{
+    /// Creates a new MapErrService.
+    pub fn new(inner: S, f: F) -> Self {
+        Self { inner, f }
+    }
+}
+
+#[async_trait]
+impl<S: RpcService, F: Fn(&str, ServerError) -> ServerError + Send + Sync + 'static> RpcService
+    for MapErrService<S, F>
+{
+    async fn respond(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        self.respond_with_context(&RpcContext::default(), method, params)
+            .await
+    }
+
+    async fn respond_with_context(
+        &self,
+        ctx: &RpcContext,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        self.inner
+            .respond_with_context(ctx, method, params)
+            .await
+            .map(|res| res.map_err(|err| (self.f)(method, err)))
+    }
+}
+
+/// A MapErrTransport is the client-side counterpart of [MapErrService], rewriting the JSON-RPC errors in the responses the inner transport receives. Transport-level errors are left alone.
+pub struct MapErrTransport<T: RpcTransport, F> {
+    inner: T,
+    f: F,
+}
+
+impl<T: RpcTransport, F: Fn(&str, JrpcError) -> JrpcError + Send + Sync + 'static>
+    MapErrTransport<T, F>
+{
+    /// Creates a new MapErrTransport.
+    pub fn new(inner: T, f: F) -> Self {
+        Self { inner, f }
+    }
+
+    fn map(&self, method: &str, mut resp: JrpcResponse) -> JrpcResponse {
+        resp.error = resp.error.map(|err| (self.f)(method, err));
+        resp
+    }
+}
+
+#[async_trait]
+impl<T: RpcTransport, F: Fn(&str, JrpcError) -> JrpcError + Send + Sync + 'static> RpcTransport
+    for MapErrTransport<T, F>
+{
+    type Error = T::Error;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        let method = req.method.clone();
+        Ok(self.map(&method, self.inner.call_raw(req).await?))
+    }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        // responses may come back in any order, so they are matched to their requests by ID
+        let methods: HashMap<JrpcId, String> = reqs
+            .iter()
+            .map(|req| (req.id.clone(), req.method.clone()))
+            .collect();
+        let resps = self.inner.call_raw_batch(reqs).await?;
+        Ok(resps
+            .into_iter()
+            .map(|resp| {
+                let method = methods.get(&resp.id).cloned().unwrap_or_default();
+                self.map(&method, resp)
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        FnService, FnTransport, JrpcError, MapErrService, MapErrTransport, RpcTransport,
+        ServerError,
+    };
+
+    #[test]
+    fn test_map_err() {
+        smol::future::block_on(async move {
+            let service = MapErrService::new(
+                FnService::new(|_, _| async move {
+                    Some(Err(ServerError {
+                        code: 1,
+                        message: "failed".into(),
+                        details: serde_json::Value::Null,
+                    }))
+                }),
+                |method, mut err| {
+                    err.message = format!("wallet.{}: {}", method, err.message);
+                    err
+                },
+            );
+            let transport =
+                MapErrTransport::new(FnTransport::<()>::loopback(service), |_, err: JrpcError| {
+                    JrpcError {
+                        code: if err.code == -1 { 1000 } else { err.code },
+                        ..err
+                    }
+                });
+            let err = transport
+                .call("send", &[])
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap_err();
+            assert_eq!(err.code, 1000);
+            assert_eq!(err.message, "wallet.send: failed");
+        });
+    }
+}
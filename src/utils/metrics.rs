@@ -0,0 +1,184 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+
+use crate::{RpcContext, RpcService, ServerError};
+
+/// The upper bounds of the latency histogram buckets, in milliseconds.
+const LATENCY_BUCKETS_MS: [u64; 12] = [1, 2, 5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000];
+
+/// The method name that calls to methods the inner service doesn't have are recorded under, since clients can make up any number of them.
+pub const UNKNOWN_METHOD: &str = "<unknown>";
+
+/// A latency histogram with fixed buckets.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    /// The number of calls in each bucket, paired with the bucket's upper bound. Calls slower than every bound are only counted in `count`.
+    pub buckets: Vec<(Duration, u64)>,
+    /// The total number of calls.
+    pub count: u64,
+    /// The total time spent in calls.
+    pub sum: Duration,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            buckets: LATENCY_BUCKETS_MS
+                .iter()
+                .map(|&ms| (Duration::from_millis(ms), 0))
+                .collect(),
+            count: 0,
+            sum: Duration::ZERO,
+        }
+    }
+}
+
+impl LatencyHistogram {
+    fn record(&mut self, latency: Duration) {
+        if let Some(bucket) = self.buckets.iter_mut().find(|(bound, _)| latency <= *bound) {
+            bucket.1 += 1;
+        }
+        self.count += 1;
+        self.sum += latency;
+    }
+}
+
+/// The metrics recorded for one method by a [MetricsService].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MethodMetrics {
+    /// The number of calls.
+    pub calls: u64,
+    /// The number of calls that failed, by the JSON-RPC error code they failed with.
+    pub errors: BTreeMap<i64, u64>,
+    /// How long the calls took.
+    pub latency: LatencyHistogram,
+}
+
+/// A MetricsService records call counts, error counts, and latencies for every method of the inner service. Read them with [MetricsService::snapshot]. Calls to methods that don't exist are all recorded together, under [UNKNOWN_METHOD].
+///
+/// With the `metrics` feature enabled, the same numbers are also reported to the [`metrics`](https://docs.rs/metrics) crate, as `nanorpc_calls_total`, `nanorpc_errors_total`, and `nanorpc_call_duration_seconds`, labelled by method.
+pub struct MetricsService<S: RpcService> {
+    inner: S,
+    methods: Mutex<HashMap<String, MethodMetrics>>,
+}
+
+impl<S: RpcService> MetricsService<S> {
+    /// Creates a new MetricsService.
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            methods: Default::default(),
+        }
+    }
+
+    /// Returns the metrics recorded so far, by method.
+    pub fn snapshot(&self) -> HashMap<String, MethodMetrics> {
+        self.methods.lock().unwrap().clone()
+    }
+
+    /// Resets every metric to zero.
+    pub fn reset(&self) {
+        self.methods.lock().unwrap().clear();
+    }
+
+    fn record(&self, method: &str, latency: Duration, error: Option<i64>) {
+        let mut methods = self.methods.lock().unwrap();
+        let metrics = match methods.get_mut(method) {
+            Some(metrics) => metrics,
+            None => methods.entry(method.to_string()).or_default(),
+        };
+        metrics.calls += 1;
+        metrics.latency.record(latency);
+        if let Some(code) = error {
+            *metrics.errors.entry(code).or_default() += 1;
+        }
+        #[cfg(feature = "metrics")]
+        {
+            let method = method.to_string();
+            ::metrics::counter!("nanorpc_calls_total", "method" => method.clone()).increment(1);
+            ::metrics::histogram!("nanorpc_call_duration_seconds", "method" => method.clone())
+                .record(latency.as_secs_f64());
+            if let Some(code) = error {
+                ::metrics::counter!("nanorpc_errors_total", "method" => method, "code" => code.to_string())
+                    .increment(1);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<S: RpcService> RpcService for MetricsService<S> {
+    async fn respond(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        self.respond_with_context(&RpcContext::default(), method, params)
+            .await
+    }
+
+    async fn respond_with_context(
+        &self,
+        ctx: &RpcContext,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        let start = Instant::now();
+        let response = self.inner.respond_with_context(ctx, method, params).await;
+        let error = match &response {
+            Some(Ok(_)) => None,
+            Some(Err(err)) => Some(err.jrpc_code().unwrap_or(-1)),
+            None => Some(-32601),
+        };
+        let method = if response.is_some() {
+            method
+        } else {
+            UNKNOWN_METHOD
+        };
+        self.record(method, start.elapsed(), error);
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{FnService, MetricsService, RpcService, ServerError, UNKNOWN_METHOD};
+
+    #[test]
+    fn test_metrics() {
+        smol::future::block_on(async move {
+            let service = MetricsService::new(FnService::new(|method, _| {
+                let method = method.to_string();
+                async move {
+                    match method.as_str() {
+                        "ok" => Some(Ok(().into())),
+                        "fail" => Some(Err(ServerError {
+                            code: 1,
+                            message: "failed".into(),
+                            details: serde_json::Value::Null,
+                        })),
+                        _ => None,
+                    }
+                }
+            }));
+            service.respond("ok", &[]).await;
+            service.respond("ok", &[]).await;
+            service.respond("fail", &[]).await;
+            service.respond("missing", &[]).await;
+            service.respond("also missing", &[]).await;
+            let snapshot = service.snapshot();
+            assert_eq!(snapshot["ok"].calls, 2);
+            assert!(snapshot["ok"].errors.is_empty());
+            assert_eq!(snapshot["ok"].latency.count, 2);
+            assert_eq!(snapshot["ok"].latency.buckets[0].1, 2);
+            assert_eq!(snapshot["fail"].errors[&-1], 1);
+            assert_eq!(snapshot[UNKNOWN_METHOD].errors[&-32601], 2);
+            assert_eq!(snapshot.len(), 3);
+        });
+    }
+}
@@ -0,0 +1,237 @@
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+
+use crate::{server, JrpcRequest, JrpcResponse, RpcTransport};
+
+/// A MockTransport is a scripted transport for tests. Every expected call is declared upfront with [MockTransport::expect_call]; an unexpected call panics, and so does dropping the MockTransport while some expected calls were not made.
+///
+/// ```
+/// use nanorpc::{MockTransport, RpcTransport};
+/// use serde_json::json;
+///
+/// smol::future::block_on(async {
+///     let mock = MockTransport::new();
+///     mock.expect_call("add")
+///         .with_params(json!([1, 2]))
+///         .return_ok(json!(3))
+///         .times(1);
+///     assert_eq!(mock.call("add", &[1.into(), 2.into()]).await.unwrap().unwrap().unwrap(), 3);
+/// });
+/// ```
+#[derive(Default)]
+pub struct MockTransport {
+    state: Arc<Mutex<MockState>>,
+}
+
+#[derive(Default)]
+struct MockState {
+    expectations: Vec<Expectation>,
+    ordered: bool,
+}
+
+struct Expectation {
+    method: String,
+    params: Option<serde_json::Value>,
+    response: MockResponse,
+    times: usize,
+    calls: usize,
+}
+
+#[derive(Clone)]
+enum MockResponse {
+    Ok(serde_json::Value),
+    Err(i64, String, serde_json::Value),
+    NotFound,
+}
+
+impl Expectation {
+    fn matches(&self, req: &JrpcRequest) -> bool {
+        self.method == req.method
+            && self
+                .params
+                .as_ref()
+                .map(|params| params.as_array() == Some(&req.params))
+                .unwrap_or(true)
+    }
+}
+
+impl MockTransport {
+    /// Creates a MockTransport that accepts the expected calls in any order.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a MockTransport that requires the expected calls to be made in the order they were declared.
+    pub fn ordered() -> Self {
+        let mock = Self::default();
+        mock.state.lock().unwrap().ordered = true;
+        mock
+    }
+
+    /// Declares an expected call to the given method. By default, it is expected exactly once, with any params, and answered with a `null` result.
+    pub fn expect_call(&self, method: impl Into<String>) -> MockExpectation {
+        let mut state = self.state.lock().unwrap();
+        state.expectations.push(Expectation {
+            method: method.into(),
+            params: None,
+            response: MockResponse::Ok(serde_json::Value::Null),
+            times: 1,
+            calls: 0,
+        });
+        MockExpectation {
+            state: self.state.clone(),
+            idx: state.expectations.len() - 1,
+        }
+    }
+
+    /// Panics if some expected calls were not made.
+    pub fn verify(&self) {
+        let state = self.state.lock().unwrap();
+        for exp in state.expectations.iter() {
+            if exp.calls != exp.times {
+                panic!(
+                    "expected {} call(s) to {}, got {}",
+                    exp.times, exp.method, exp.calls
+                );
+            }
+        }
+    }
+}
+
+impl Drop for MockTransport {
+    fn drop(&mut self) {
+        if !std::thread::panicking() {
+            self.verify()
+        }
+    }
+}
+
+/// An expected call of a [MockTransport], configured with builder methods.
+pub struct MockExpectation {
+    state: Arc<Mutex<MockState>>,
+    idx: usize,
+}
+
+impl MockExpectation {
+    fn update(self, f: impl FnOnce(&mut Expectation)) -> Self {
+        f(&mut self.state.lock().unwrap().expectations[self.idx]);
+        self
+    }
+
+    /// Only matches calls with exactly these params, given as a JSON array.
+    pub fn with_params(self, params: serde_json::Value) -> Self {
+        self.update(|e| e.params = Some(params))
+    }
+
+    /// Answers the call with a successful result.
+    pub fn return_ok(self, result: serde_json::Value) -> Self {
+        self.update(|e| e.response = MockResponse::Ok(result))
+    }
+
+    /// Answers the call with an error.
+    pub fn return_err(
+        self,
+        code: i64,
+        message: impl Into<String>,
+        data: serde_json::Value,
+    ) -> Self {
+        let message = message.into();
+        self.update(|e| e.response = MockResponse::Err(code, message, data))
+    }
+
+    /// Answers the call as if the method did not exist.
+    pub fn return_not_found(self) -> Self {
+        self.update(|e| e.response = MockResponse::NotFound)
+    }
+
+    /// Sets how many times the call is expected.
+    pub fn times(self, times: usize) -> Self {
+        self.update(|e| e.times = times)
+    }
+}
+
+#[async_trait]
+impl RpcTransport for MockTransport {
+    type Error = std::convert::Infallible;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        let mut state = self.state.lock().unwrap();
+        let ordered = state.ordered;
+        let mut remaining = state.expectations.iter_mut().filter(|e| e.calls < e.times);
+        let exp = if ordered {
+            remaining.next().filter(|e| e.matches(&req))
+        } else {
+            remaining.find(|e| e.matches(&req))
+        };
+        let Some(exp) = exp else {
+            drop(state);
+            panic!("unexpected call: {}", req)
+        };
+        exp.calls += 1;
+        Ok(match exp.response.clone() {
+            MockResponse::Ok(result) => JrpcResponse {
+                jsonrpc: "2.0".into(),
+                result: Some(result),
+                error: None,
+                id: req.id,
+            },
+            MockResponse::Err(code, message, data) => {
+                let mut resp = server::error_response(req.id, code, message);
+                resp.error.as_mut().unwrap().data = data;
+                resp
+            }
+            MockResponse::NotFound => server::error_response(req.id, -32601, "Method not found"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{MockTransport, RpcTransport};
+
+    #[test]
+    fn test_mock_ordered() {
+        smol::future::block_on(async move {
+            let mock = MockTransport::ordered();
+            mock.expect_call("login")
+                .return_err(-32001, "unauthorized", json!(null));
+            mock.expect_call("get")
+                .with_params(json!(["key"]))
+                .return_ok(json!("value"))
+                .times(2);
+            mock.expect_call("gone").return_not_found();
+            assert!(mock.call("login", &[]).await.unwrap().unwrap().is_err());
+            for _ in 0..2 {
+                assert_eq!(
+                    mock.call("get", &["key".into()])
+                        .await
+                        .unwrap()
+                        .unwrap()
+                        .unwrap(),
+                    "value"
+                );
+            }
+            assert!(mock.call("gone", &[]).await.unwrap().is_none());
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "expected 1 call(s) to add, got 0")]
+    fn test_mock_missing_call() {
+        let mock = MockTransport::new();
+        mock.expect_call("add");
+    }
+
+    #[test]
+    #[should_panic(expected = "unexpected call")]
+    fn test_mock_unexpected_call() {
+        smol::future::block_on(async move {
+            let mock = MockTransport::new();
+            mock.expect_call("add").with_params(json!([1, 2]));
+            let _ = mock.call("add", &[1.into(), 3.into()]).await;
+        });
+    }
+}
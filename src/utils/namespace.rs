@@ -0,0 +1,105 @@
+use async_trait::async_trait;
+
+use crate::{JrpcRequest, JrpcResponse, RpcContext, RpcService, RpcTransport, ServerError};
+
+/// A NamespaceService puts an existing service under a method prefix: a call to `admin.foo` reaches the inner service as a call to `foo`, and calls without the prefix are not found.
+pub struct NamespaceService<S: RpcService> {
+    inner: S,
+    prefix: String,
+}
+
+impl<S: RpcService> NamespaceService<S> {
+    /// Creates a new NamespaceService.
+    pub fn new(inner: S, prefix: impl Into<String>) -> Self {
+        Self {
+            inner,
+            prefix: prefix.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl<S: RpcService> RpcService for NamespaceService<S> {
+    async fn respond(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        self.respond_with_context(&RpcContext::default(), method, params)
+            .await
+    }
+
+    async fn respond_with_context(
+        &self,
+        ctx: &RpcContext,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        let method = method.strip_prefix(self.prefix.as_str())?;
+        self.inner.respond_with_context(ctx, method, params).await
+    }
+}
+
+/// A NamespaceTransport is the client-side counterpart of [NamespaceService], prepending a prefix to the method of every call.
+pub struct NamespaceTransport<T: RpcTransport> {
+    inner: T,
+    prefix: String,
+}
+
+impl<T: RpcTransport> NamespaceTransport<T> {
+    /// Creates a new NamespaceTransport.
+    pub fn new(inner: T, prefix: impl Into<String>) -> Self {
+        Self {
+            inner,
+            prefix: prefix.into(),
+        }
+    }
+
+    fn prefixed(&self, mut req: JrpcRequest) -> JrpcRequest {
+        req.method = format!("{}{}", self.prefix, req.method);
+        req
+    }
+}
+
+#[async_trait]
+impl<T: RpcTransport> RpcTransport for NamespaceTransport<T> {
+    type Error = T::Error;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        self.inner.call_raw(self.prefixed(req)).await
+    }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        let reqs = reqs.into_iter().map(|req| self.prefixed(req)).collect();
+        self.inner.call_raw_batch(reqs).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        FnService, FnTransport, NamespaceService, NamespaceTransport, RpcService, RpcTransport,
+    };
+
+    #[test]
+    fn test_namespace_roundtrip() {
+        smol::future::block_on(async move {
+            let service = NamespaceService::new(
+                FnService::new(|method, _| {
+                    let method = method.to_string();
+                    async move { Some(Ok(method.into())) }
+                }),
+                "admin.",
+            );
+            assert!(service.respond("foo", &[]).await.is_none());
+            let transport = NamespaceTransport::new(FnTransport::<()>::loopback(service), "admin.");
+            assert_eq!(
+                transport.call("foo", &[]).await.unwrap().unwrap().unwrap(),
+                "foo"
+            );
+        });
+    }
+}
@@ -0,0 +1,135 @@
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+
+use crate::{RpcContext, RpcService, ServerError};
+
+/// A lifecycle event of a call, emitted by an [ObservableService].
+#[derive(Debug, Clone, Copy)]
+pub enum RpcEvent<'a> {
+    /// The call was received.
+    Received {
+        method: &'a str,
+        params: &'a [serde_json::Value],
+    },
+    /// The inner service started handling the call.
+    Started { method: &'a str },
+    /// The call succeeded.
+    Finished { method: &'a str, duration: Duration },
+    /// The call failed with an error.
+    Failed {
+        method: &'a str,
+        duration: Duration,
+        error: &'a ServerError,
+    },
+    /// The method does not exist.
+    NotFound { method: &'a str, duration: Duration },
+}
+
+/// Receives the events emitted by an [ObservableService]. This is implemented for closures of the form `Fn(&RpcContext, &RpcEvent)`.
+pub trait EventSink: Send + Sync + 'static {
+    /// Handles an event. This is called inline with the call, so it should not block.
+    fn on_event(&self, ctx: &RpcContext, event: &RpcEvent<'_>);
+}
+
+impl<F: Fn(&RpcContext, &RpcEvent<'_>) + Send + Sync + 'static> EventSink for F {
+    fn on_event(&self, ctx: &RpcContext, event: &RpcEvent<'_>) {
+        self(ctx, event)
+    }
+}
+
+/// An ObservableService emits an [RpcEvent] to a sink at every stage of every call, giving metrics, audit, and logging pipelines a single integration point.
+pub struct ObservableService<S: RpcService, E: EventSink> {
+    inner: S,
+    sink: E,
+}
+
+impl<S: RpcService, E: EventSink> ObservableService<S, E> {
+    /// Creates a new ObservableService.
+    pub fn new(inner: S, sink: E) -> Self {
+        Self { inner, sink }
+    }
+}
+
+#[async_trait]
+impl<S: RpcService, E: EventSink> RpcService for ObservableService<S, E> {
+    async fn respond(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        self.respond_with_context(&RpcContext::default(), method, params)
+            .await
+    }
+
+    async fn respond_with_context(
+        &self,
+        ctx: &RpcContext,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        self.sink
+            .on_event(ctx, &RpcEvent::Received { method, params });
+        let start = Instant::now();
+        self.sink.on_event(ctx, &RpcEvent::Started { method });
+        let response = self.inner.respond_with_context(ctx, method, params).await;
+        let duration = start.elapsed();
+        let event = match &response {
+            Some(Ok(_)) => RpcEvent::Finished { method, duration },
+            Some(Err(error)) => RpcEvent::Failed {
+                method,
+                duration,
+                error,
+            },
+            None => RpcEvent::NotFound { method, duration },
+        };
+        self.sink.on_event(ctx, &event);
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{FnService, ObservableService, RpcContext, RpcEvent, RpcService};
+
+    #[test]
+    fn test_events() {
+        smol::future::block_on(async move {
+            let events = Arc::new(Mutex::new(vec![]));
+            let service = ObservableService::new(
+                FnService::new(|method, _| {
+                    let found = method == "f";
+                    async move { found.then(|| Ok(().into())) }
+                }),
+                {
+                    let events = events.clone();
+                    move |_: &RpcContext, event: &RpcEvent<'_>| {
+                        let name = match event {
+                            RpcEvent::Received { .. } => "received",
+                            RpcEvent::Started { .. } => "started",
+                            RpcEvent::Finished { .. } => "finished",
+                            RpcEvent::Failed { .. } => "failed",
+                            RpcEvent::NotFound { .. } => "not found",
+                        };
+                        events.lock().unwrap().push(name);
+                    }
+                },
+            );
+            service.respond("f", &[]).await;
+            service.respond("g", &[]).await;
+            assert_eq!(
+                *events.lock().unwrap(),
+                vec![
+                    "received",
+                    "started",
+                    "finished",
+                    "received",
+                    "started",
+                    "not found"
+                ]
+            );
+        });
+    }
+}
@@ -0,0 +1,102 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+
+use crate::{RpcContext, RpcService, ServerError};
+
+/// The method answered by [PingService].
+pub const PING_METHOD: &str = "rpc.ping";
+
+/// A PingService answers the standard `rpc.ping` liveness check, and passes every other call to the inner service. The response looks like `{"time": 1670000000000, "version": "1.2.3"}`, where `time` is the server's UNIX time in milliseconds.
+pub struct PingService<S: RpcService> {
+    inner: S,
+    version: Option<String>,
+}
+
+impl<S: RpcService> PingService<S> {
+    /// Creates a new PingService.
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            version: None,
+        }
+    }
+
+    /// Sets the build or version string included in ping responses.
+    pub fn with_version(mut self, version: impl Into<String>) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+}
+
+#[async_trait]
+impl<S: RpcService> RpcService for PingService<S> {
+    async fn respond(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        self.respond_with_context(&RpcContext::default(), method, params)
+            .await
+    }
+
+    async fn respond_with_context(
+        &self,
+        ctx: &RpcContext,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        if method == PING_METHOD {
+            let time = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64;
+            Some(Ok(serde_json::json!({
+                "time": time,
+                "version": self.version,
+            })))
+        } else {
+            self.inner.respond_with_context(ctx, method, params).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use super::{PingService, PING_METHOD};
+    use crate::{FnService, RpcService};
+
+    fn echo() -> FnService {
+        FnService::new(|method, params| {
+            let method = method.to_string();
+            async move { (method == "echo").then(|| Ok(params[0].clone())) }
+        })
+    }
+
+    #[test]
+    fn test_ping() {
+        smol::future::block_on(async move {
+            let before = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis() as u64;
+            let service = PingService::new(echo());
+            let pong = service.respond(PING_METHOD, &[]).await.unwrap().unwrap();
+            let fields = pong.as_object().unwrap();
+            assert_eq!(fields.len(), 2);
+            assert!(pong["time"].as_u64().unwrap() >= before);
+            assert!(pong["version"].is_null());
+            let service = PingService::new(echo()).with_version("1.2.3");
+            let pong = service.respond(PING_METHOD, &[]).await.unwrap().unwrap();
+            assert_eq!(pong["version"], "1.2.3");
+            // everything else goes to the inner service
+            assert_eq!(
+                service.respond("echo", &[5.into()]).await.unwrap().unwrap(),
+                5
+            );
+            assert!(service.respond("missing", &[]).await.is_none());
+        });
+    }
+}
@@ -0,0 +1,265 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use thiserror::Error;
+
+use crate::{timer, JrpcRequest, JrpcResponse, RpcTransport, PING_METHOD};
+
+/// An error returned by a [HealthCheckedPool].
+#[derive(Error, Debug)]
+pub enum PoolError<E> {
+    #[error("no healthy backends in the pool")]
+    NoHealthyBackends,
+    #[error(transparent)]
+    Transport(E),
+}
+
+/// The state of one backend in a [HealthCheckedPool], for monitoring.
+#[derive(Clone, Debug)]
+pub struct BackendState {
+    /// The name the backend was added with.
+    pub name: String,
+    /// Whether the backend is in rotation.
+    pub healthy: bool,
+    /// The number of probes failed in a row.
+    pub consecutive_failures: u32,
+    /// When the backend was last probed.
+    pub last_probe: Option<Instant>,
+    /// How long the last successful probe took.
+    pub last_latency: Option<Duration>,
+}
+
+struct Member<T> {
+    transport: T,
+    state: Mutex<BackendState>,
+}
+
+/// A HealthCheckedPool distributes calls round-robin across named backends, while periodically probing every backend with `rpc.ping` (see [crate::PingService]) or another method. Backends that fail several probes in a row are removed from rotation, and re-added as soon as a probe succeeds again.
+///
+/// Probing only happens while the future returned by [HealthCheckedPool::run_health_checks] is polled, typically in a spawned task. Backends start out healthy.
+pub struct HealthCheckedPool<T: RpcTransport> {
+    members: Mutex<Vec<Arc<Member<T>>>>,
+    next: AtomicUsize,
+    probe_method: String,
+    interval: Duration,
+    probe_timeout: Duration,
+    failure_threshold: u32,
+}
+
+impl<T: RpcTransport> Default for HealthCheckedPool<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: RpcTransport> HealthCheckedPool<T> {
+    /// Creates a new, empty HealthCheckedPool.
+    pub fn new() -> Self {
+        Self {
+            members: Default::default(),
+            next: AtomicUsize::new(0),
+            probe_method: PING_METHOD.into(),
+            interval: Duration::from_secs(10),
+            probe_timeout: Duration::from_secs(5),
+            failure_threshold: 2,
+        }
+    }
+
+    /// Adds a backend to the pool.
+    pub fn with_backend(self, name: impl Into<String>, transport: T) -> Self {
+        self.insert(name, transport);
+        self
+    }
+
+    /// Sets the method used to probe backends. Defaults to `rpc.ping`. A probe succeeds if the backend answers it at all, even with an error, but not if the method is not found.
+    pub fn with_probe_method(mut self, method: impl Into<String>) -> Self {
+        self.probe_method = method.into();
+        self
+    }
+
+    /// Sets how often backends are probed, and how long a probe may take before it counts as failed.
+    pub fn with_interval(mut self, interval: Duration, probe_timeout: Duration) -> Self {
+        self.interval = interval;
+        self.probe_timeout = probe_timeout;
+        self
+    }
+
+    /// Sets how many probes a backend must fail in a row before it is removed from rotation.
+    pub fn with_failure_threshold(mut self, failure_threshold: u32) -> Self {
+        self.failure_threshold = failure_threshold.max(1);
+        self
+    }
+
+    /// Adds a backend, replacing any backend with the same name.
+    pub fn insert(&self, name: impl Into<String>, transport: T) {
+        let name = name.into();
+        let member = Arc::new(Member {
+            transport,
+            state: Mutex::new(BackendState {
+                name: name.clone(),
+                healthy: true,
+                consecutive_failures: 0,
+                last_probe: None,
+                last_latency: None,
+            }),
+        });
+        let mut members = self.members.lock().unwrap();
+        members.retain(|m| m.state.lock().unwrap().name != name);
+        members.push(member);
+    }
+
+    /// Removes a backend, returning whether it was present. Calls already in flight to it are not affected.
+    pub fn remove(&self, name: &str) -> bool {
+        let mut members = self.members.lock().unwrap();
+        let before = members.len();
+        members.retain(|m| m.state.lock().unwrap().name != name);
+        members.len() != before
+    }
+
+    /// Returns the state of every backend in the pool.
+    pub fn state(&self) -> Vec<BackendState> {
+        self.members
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|m| m.state.lock().unwrap().clone())
+            .collect()
+    }
+
+    /// Probes every backend once, concurrently, updating their health.
+    pub async fn probe_all(&self) {
+        let members = self.members.lock().unwrap().clone();
+        futures_util::future::join_all(members.iter().map(|member| self.probe(member))).await;
+    }
+
+    /// Probes every backend every interval, forever.
+    pub async fn run_health_checks(&self) {
+        loop {
+            self.probe_all().await;
+            timer::sleep(self.interval).await;
+        }
+    }
+
+    async fn probe(&self, member: &Member<T>) {
+        let start = Instant::now();
+        let ok = matches!(
+            timer::timeout(
+                self.probe_timeout,
+                member.transport.call(&self.probe_method, &[])
+            )
+            .await,
+            Some(Ok(Some(_)))
+        );
+        let mut state = member.state.lock().unwrap();
+        state.last_probe = Some(start);
+        if ok {
+            if !state.healthy {
+                log::info!("backend {} recovered", state.name);
+            }
+            state.healthy = true;
+            state.consecutive_failures = 0;
+            state.last_latency = Some(start.elapsed());
+        } else {
+            state.consecutive_failures += 1;
+            if state.healthy && state.consecutive_failures >= self.failure_threshold {
+                log::warn!(
+                    "backend {} failed {} probes, removing it from rotation",
+                    state.name,
+                    state.consecutive_failures
+                );
+                state.healthy = false;
+            }
+        }
+    }
+
+    fn pick(&self) -> Option<Arc<Member<T>>> {
+        let members = self.members.lock().unwrap();
+        let healthy: Vec<&Arc<Member<T>>> = members
+            .iter()
+            .filter(|m| m.state.lock().unwrap().healthy)
+            .collect();
+        if healthy.is_empty() {
+            return None;
+        }
+        Some(healthy[self.next.fetch_add(1, Ordering::Relaxed) % healthy.len()].clone())
+    }
+}
+
+#[async_trait]
+impl<T: RpcTransport> RpcTransport for HealthCheckedPool<T> {
+    type Error = PoolError<T::Error>;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        let member = self.pick().ok_or(PoolError::NoHealthyBackends)?;
+        member
+            .transport
+            .call_raw(req)
+            .await
+            .map_err(PoolError::Transport)
+    }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        let member = self.pick().ok_or(PoolError::NoHealthyBackends)?;
+        member
+            .transport
+            .call_raw_batch(reqs)
+            .await
+            .map_err(PoolError::Transport)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    use crate::{FnService, FnTransport, HealthCheckedPool, PingService, PoolError, RpcTransport};
+
+    fn backend(name: &'static str, up: Arc<AtomicBool>) -> FnTransport<()> {
+        FnTransport::loopback_with(
+            PingService::new(FnService::new(
+                move |_, _| async move { Some(Ok(name.into())) },
+            )),
+            move |_| up.load(Ordering::SeqCst).then_some(()).ok_or(()),
+        )
+    }
+
+    #[test]
+    fn test_pool_eviction() {
+        smol::future::block_on(async move {
+            let b_up = Arc::new(AtomicBool::new(true));
+            let pool = HealthCheckedPool::new()
+                .with_backend("a", backend("a", Arc::new(AtomicBool::new(true))))
+                .with_backend("b", backend("b", b_up.clone()))
+                .with_failure_threshold(2);
+            b_up.store(false, Ordering::SeqCst);
+            pool.probe_all().await;
+            assert!(pool.state()[1].healthy);
+            pool.probe_all().await;
+            assert!(!pool.state()[1].healthy);
+            for _ in 0..4 {
+                assert_eq!(pool.call("f", &[]).await.unwrap().unwrap().unwrap(), "a");
+            }
+            b_up.store(true, Ordering::SeqCst);
+            pool.probe_all().await;
+            assert!(pool.state()[1].healthy);
+            assert!(pool.state()[1].last_latency.is_some());
+            assert!(pool.remove("a") && pool.remove("b"));
+            assert!(matches!(
+                pool.call("f", &[]).await,
+                Err(PoolError::NoHealthyBackends)
+            ));
+        });
+    }
+}
@@ -0,0 +1,172 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    sync::Mutex,
+};
+
+use async_trait::async_trait;
+use event_listener::Event;
+
+use crate::{RpcContext, RpcService, ServerError};
+
+#[derive(Default)]
+struct Slots {
+    available: usize,
+    // waiting calls, by priority and then by arrival order
+    waiting: BinaryHeap<(u8, Reverse<u64>)>,
+    next_seq: u64,
+}
+
+/// A PriorityService limits how many calls the inner service handles at once, like [crate::ConcurrencyLimitService], but lets waiting calls to higher-priority methods go first. This keeps a flood of cheap queries from starving critical methods like health checks.
+///
+/// Priorities are numbers, with higher numbers going first. Methods without a configured priority have priority 0.
+pub struct PriorityService<S: RpcService> {
+    inner: S,
+    priorities: HashMap<String, u8>,
+    slots: Mutex<Slots>,
+    released: Event,
+}
+
+impl<S: RpcService> PriorityService<S> {
+    /// Creates a new PriorityService, handling up to `max_concurrent` calls at once.
+    pub fn new(inner: S, max_concurrent: usize) -> Self {
+        Self {
+            inner,
+            priorities: HashMap::new(),
+            slots: Mutex::new(Slots {
+                available: max_concurrent,
+                ..Default::default()
+            }),
+            released: Event::new(),
+        }
+    }
+
+    /// Sets the priority of a method.
+    pub fn with_priority(mut self, method: impl Into<String>, priority: u8) -> Self {
+        self.priorities.insert(method.into(), priority);
+        self
+    }
+
+    async fn acquire(&self, priority: u8) -> SlotGuard<'_, S> {
+        let key = {
+            let mut slots = self.slots.lock().unwrap();
+            if slots.available > 0 && slots.waiting.is_empty() {
+                slots.available -= 1;
+                return SlotGuard {
+                    service: self,
+                    waiting: None,
+                };
+            }
+            let key = (priority, Reverse(slots.next_seq));
+            slots.next_seq += 1;
+            slots.waiting.push(key);
+            key
+        };
+        // removes us from the queue if we are cancelled while waiting
+        let mut guard = SlotGuard {
+            service: self,
+            waiting: Some(key),
+        };
+        loop {
+            let listener = self.released.listen();
+            {
+                let mut slots = self.slots.lock().unwrap();
+                if slots.available > 0 && slots.waiting.peek() == Some(&key) {
+                    slots.waiting.pop();
+                    slots.available -= 1;
+                    guard.waiting = None;
+                    return guard;
+                }
+            }
+            listener.await;
+        }
+    }
+}
+
+struct SlotGuard<'a, S: RpcService> {
+    service: &'a PriorityService<S>,
+    waiting: Option<(u8, Reverse<u64>)>,
+}
+
+impl<S: RpcService> Drop for SlotGuard<'_, S> {
+    fn drop(&mut self) {
+        let mut slots = self.service.slots.lock().unwrap();
+        match self.waiting {
+            Some(key) => slots.waiting.retain(|k| *k != key),
+            None => slots.available += 1,
+        }
+        drop(slots);
+        self.service.released.notify(usize::MAX);
+    }
+}
+
+#[async_trait]
+impl<S: RpcService> RpcService for PriorityService<S> {
+    async fn respond(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        self.respond_with_context(&RpcContext::default(), method, params)
+            .await
+    }
+
+    async fn respond_with_context(
+        &self,
+        ctx: &RpcContext,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        let priority = self.priorities.get(method).copied().unwrap_or(0);
+        let _slot = self.acquire(priority).await;
+        self.inner.respond_with_context(ctx, method, params).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use crate::{FnService, PriorityService, RpcService};
+
+    #[test]
+    fn test_priority() {
+        smol::future::block_on(async move {
+            let order = Arc::new(Mutex::new(vec![]));
+            let service = Arc::new(
+                PriorityService::new(
+                    FnService::new({
+                        let order = order.clone();
+                        move |method, _| {
+                            order.lock().unwrap().push(method.to_string());
+                            async move {
+                                smol::Timer::after(Duration::from_millis(20)).await;
+                                Some(Ok(().into()))
+                            }
+                        }
+                    }),
+                    1,
+                )
+                .with_priority("health", 10),
+            );
+            let mut tasks = vec![];
+            for method in ["query", "query", "query", "health"] {
+                let service = service.clone();
+                tasks.push(smol::spawn(
+                    async move { service.respond(method, &[]).await },
+                ));
+                smol::Timer::after(Duration::from_millis(2)).await;
+            }
+            for task in tasks {
+                task.await;
+            }
+            assert_eq!(
+                *order.lock().unwrap(),
+                vec!["query", "health", "query", "query"]
+            );
+        });
+    }
+}
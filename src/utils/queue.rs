@@ -0,0 +1,227 @@
+use std::{
+    collections::VecDeque,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use event_listener::Event;
+use thiserror::Error;
+
+use crate::{timer, JrpcRequest, JrpcResponse, RpcTransport};
+
+/// An error returned by a [QueueingTransport].
+#[derive(Error, Debug)]
+pub enum QueueError<E> {
+    #[error("queue of calls waiting for the connection is full")]
+    Full,
+    #[error("call expired while waiting for the connection")]
+    Expired(Option<E>),
+}
+
+#[derive(Default)]
+struct Queue {
+    tickets: VecDeque<u64>,
+    next_ticket: u64,
+}
+
+/// A QueueingTransport keeps calls from failing while the inner transport is down. A call that fails at the transport level is queued, and queued calls are retried in order until they succeed, once connectivity returns. New calls made while others are queued join the end of the queue, so order is preserved.
+///
+/// The queue is bounded, and every queued call expires after a while, failing with the last error it got.
+pub struct QueueingTransport<T: RpcTransport> {
+    inner: T,
+    queue: Mutex<Queue>,
+    changed: Event,
+    capacity: usize,
+    ttl: Duration,
+    retry_interval: Duration,
+}
+
+impl<T: RpcTransport> QueueingTransport<T> {
+    /// Creates a new QueueingTransport, queueing up to 1024 calls for up to a minute each.
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            queue: Default::default(),
+            changed: Event::new(),
+            capacity: 1024,
+            ttl: Duration::from_secs(60),
+            retry_interval: Duration::from_secs(1),
+        }
+    }
+
+    /// Sets the maximum number of queued calls.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Sets how long a call may wait in the queue.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Sets how often the call at the head of the queue is retried.
+    pub fn with_retry_interval(mut self, retry_interval: Duration) -> Self {
+        self.retry_interval = retry_interval;
+        self
+    }
+
+    /// Returns the number of queued calls.
+    pub fn queued(&self) -> usize {
+        self.queue.lock().unwrap().tickets.len()
+    }
+
+    async fn call_queued<R, F, Fut>(&self, f: F) -> Result<R, QueueError<T::Error>>
+    where
+        F: Fn() -> Fut,
+        Fut: std::future::Future<Output = Result<R, T::Error>>,
+    {
+        let start = Instant::now();
+        let mut last_err = None;
+        if self.queued() == 0 {
+            match f().await {
+                Ok(res) => return Ok(res),
+                Err(err) => last_err = Some(err),
+            }
+        }
+        let ticket = {
+            let mut queue = self.queue.lock().unwrap();
+            if queue.tickets.len() >= self.capacity {
+                return Err(QueueError::Full);
+            }
+            let ticket = queue.next_ticket;
+            queue.next_ticket += 1;
+            queue.tickets.push_back(ticket);
+            ticket
+        };
+        let _guard = TicketGuard {
+            transport: self,
+            ticket,
+        };
+        loop {
+            let Some(remaining) = self.ttl.checked_sub(start.elapsed()) else {
+                return Err(QueueError::Expired(last_err));
+            };
+            let listener = self.changed.listen();
+            if self.queue.lock().unwrap().tickets.front() != Some(&ticket) {
+                if timer::timeout(remaining, listener).await.is_none() {
+                    return Err(QueueError::Expired(last_err));
+                }
+                continue;
+            }
+            if last_err.is_some() {
+                timer::sleep(self.retry_interval.min(remaining)).await;
+                if start.elapsed() >= self.ttl {
+                    return Err(QueueError::Expired(last_err));
+                }
+            }
+            match f().await {
+                Ok(res) => return Ok(res),
+                Err(err) => {
+                    log::debug!("queued call failed, will retry");
+                    last_err = Some(err)
+                }
+            }
+        }
+    }
+}
+
+/// Removes a call from the queue once it is done, whether it succeeded, expired, or was cancelled.
+struct TicketGuard<'a, T: RpcTransport> {
+    transport: &'a QueueingTransport<T>,
+    ticket: u64,
+}
+
+impl<T: RpcTransport> Drop for TicketGuard<'_, T> {
+    fn drop(&mut self) {
+        self.transport
+            .queue
+            .lock()
+            .unwrap()
+            .tickets
+            .retain(|t| *t != self.ticket);
+        self.transport.changed.notify(usize::MAX);
+    }
+}
+
+#[async_trait]
+impl<T: RpcTransport> RpcTransport for QueueingTransport<T> {
+    type Error = QueueError<T::Error>;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        self.call_queued(|| self.inner.call_raw(req.clone())).await
+    }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        self.call_queued(|| self.inner.call_raw_batch(reqs.clone()))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    };
+
+    use crate::{FnService, FnTransport, QueueError, QueueingTransport, RpcTransport};
+
+    #[test]
+    fn test_queueing() {
+        smol::future::block_on(async move {
+            let up = Arc::new(AtomicBool::new(false));
+            let log = Arc::new(Mutex::new(vec![]));
+            let service = FnService::new({
+                let log = log.clone();
+                move |_, params| {
+                    log.lock().unwrap().push(params[0].as_u64().unwrap());
+                    async move { Some(Ok(().into())) }
+                }
+            });
+            let transport = Arc::new(
+                QueueingTransport::new(FnTransport::loopback_with(service, {
+                    let up = up.clone();
+                    move |_| up.load(Ordering::SeqCst).then_some(()).ok_or(())
+                }))
+                .with_retry_interval(Duration::from_millis(5))
+                .with_capacity(3),
+            );
+            let mut calls = vec![];
+            for i in 0..3u64 {
+                let transport = transport.clone();
+                calls.push(smol::spawn(async move {
+                    transport.call("f", &[i.into()]).await
+                }));
+                smol::Timer::after(Duration::from_millis(10)).await;
+            }
+            assert_eq!(transport.queued(), 3);
+            assert!(matches!(
+                transport.call("f", &[3.into()]).await,
+                Err(QueueError::Full)
+            ));
+            up.store(true, Ordering::SeqCst);
+            for call in calls {
+                assert!(call.await.is_ok());
+            }
+            assert_eq!(*log.lock().unwrap(), vec![0, 1, 2]);
+
+            let transport =
+                QueueingTransport::new(FnTransport::new(|_| async { Err::<_, ()>(()) }))
+                    .with_ttl(Duration::from_millis(30))
+                    .with_retry_interval(Duration::from_millis(5));
+            assert!(matches!(
+                transport.call("f", &[]).await,
+                Err(QueueError::Expired(Some(())))
+            ));
+        });
+    }
+}
@@ -0,0 +1,317 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::SocketAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+
+use crate::{Identity, RpcContext, RpcService, ServerError};
+
+/// The JSON-RPC error code for calls rejected because of rate limiting.
+pub const RATE_LIMITED_CODE: i32 = -32029;
+
+/// A token bucket, refilling at `rate` tokens per second up to `burst` tokens.
+#[derive(Debug)]
+pub(crate) struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(rate: f64, burst: u32) -> Self {
+        Self {
+            rate,
+            burst: burst as f64,
+            tokens: burst as f64,
+            last: Instant::now(),
+        }
+    }
+
+    /// Takes a token, or returns how long until one is available.
+    pub(crate) fn take(&mut self) -> Result<(), Duration> {
+        let now = Instant::now();
+        self.tokens =
+            (self.tokens + now.duration_since(self.last).as_secs_f64() * self.rate).min(self.burst);
+        self.last = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else if self.rate > 0.0 {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+        } else {
+            Err(Duration::MAX)
+        }
+    }
+
+    /// Gives back a token taken for a call that was rejected anyway.
+    pub(crate) fn refund(&mut self) {
+        self.tokens = (self.tokens + 1.0).min(self.burst);
+    }
+}
+
+/// Returns the error for a rate-limited call, telling the caller when to retry.
+pub(crate) fn rate_limited(retry_after: Duration) -> ServerError {
+    ServerError::with_jrpc_code(
+        RATE_LIMITED_CODE,
+        "rate limited",
+        serde_json::json!({ "retry_after_ms": retry_after.as_millis() as u64 }),
+    )
+}
+
+/// How a [RateLimitService] tells clients apart for per-client limits, and how a [crate::TenantService] finds the tenant of a call.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ClientKey {
+    /// The [Identity::id] attached to the context, like by an [crate::AuthService].
+    Identity,
+    /// The request metadata entry with the given key, like an API key.
+    Meta(String),
+    /// The IP address of the [SocketAddr] that the server adapter attached to the context as an extension.
+    PeerIp,
+}
+
+impl ClientKey {
+    pub(crate) fn extract(&self, ctx: &RpcContext) -> Option<String> {
+        match self {
+            ClientKey::Identity => ctx.extension::<Identity>().map(|i| i.id.clone()),
+            ClientKey::Meta(key) => ctx.meta.get(key).map(|v| match v {
+                serde_json::Value::String(s) => s.clone(),
+                v => v.to_string(),
+            }),
+            ClientKey::PeerIp => ctx.extension::<SocketAddr>().map(|a| a.ip().to_string()),
+        }
+    }
+}
+
+/// Token buckets for each client, forgetting the least recently used clients beyond a capacity.
+struct ClientBuckets {
+    key: ClientKey,
+    rate: f64,
+    burst: u32,
+    capacity: usize,
+    buckets: HashMap<String, (TokenBucket, u64)>,
+    // last use -> client, for finding the least recently used client
+    recency: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+impl ClientBuckets {
+    fn take(&mut self, client: String) -> Result<(), Duration> {
+        self.tick += 1;
+        let tick = self.tick;
+        let (bucket, last_used) = match self.buckets.get_mut(&client) {
+            Some(entry) => entry,
+            None => {
+                if self.buckets.len() >= self.capacity {
+                    if let Some((_, lru)) = self.recency.pop_first() {
+                        self.buckets.remove(&lru);
+                    }
+                }
+                self.buckets
+                    .entry(client.clone())
+                    .or_insert((TokenBucket::new(self.rate, self.burst), tick))
+            }
+        };
+        self.recency.remove(last_used);
+        *last_used = tick;
+        let taken = bucket.take();
+        self.recency.insert(tick, client);
+        taken
+    }
+
+    fn refund(&mut self, client: &str) {
+        if let Some((bucket, _)) = self.buckets.get_mut(client) {
+            bucket.refund();
+        }
+    }
+}
+
+/// A RateLimitService limits the rate of calls to the inner service with token buckets. Calls over the limit are rejected with a [RATE_LIMITED_CODE] error, whose details look like `{"retry_after_ms": 250}`.
+///
+/// Methods given their own limit with [RateLimitService::with_method_limit] have their own bucket; all other methods share one. With [RateLimitService::with_client_limit], every client also has its own bucket, so that one abusive client doesn't use up the limits of everybody else.
+pub struct RateLimitService<S: RpcService> {
+    inner: S,
+    default: Mutex<TokenBucket>,
+    per_method: HashMap<String, Mutex<TokenBucket>>,
+    per_client: Option<Mutex<ClientBuckets>>,
+}
+
+impl<S: RpcService> RateLimitService<S> {
+    /// Creates a new RateLimitService, allowing `rate` calls per second on average and bursts of up to `burst` calls.
+    pub fn new(inner: S, rate: f64, burst: u32) -> Self {
+        Self {
+            inner,
+            default: Mutex::new(TokenBucket::new(rate, burst)),
+            per_method: HashMap::new(),
+            per_client: None,
+        }
+    }
+
+    /// Gives a method its own limit.
+    pub fn with_method_limit(mut self, method: impl Into<String>, rate: f64, burst: u32) -> Self {
+        self.per_method
+            .insert(method.into(), Mutex::new(TokenBucket::new(rate, burst)));
+        self
+    }
+
+    /// Limits every client to `rate` calls per second and bursts of `burst` calls, on top of the other limits. Calls whose client can't be determined are only subject to the other limits.
+    ///
+    /// The buckets of up to 10000 recently seen clients are kept; see [RateLimitService::with_max_clients].
+    pub fn with_client_limit(mut self, key: ClientKey, rate: f64, burst: u32) -> Self {
+        self.per_client = Some(Mutex::new(ClientBuckets {
+            key,
+            rate,
+            burst,
+            capacity: 10000,
+            buckets: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+        }));
+        self
+    }
+
+    /// Sets how many clients' buckets are kept. Beyond that, the least recently seen client is forgotten, as if its bucket were full again.
+    pub fn with_max_clients(mut self, max_clients: usize) -> Self {
+        if let Some(per_client) = &mut self.per_client {
+            per_client.get_mut().unwrap().capacity = max_clients.max(1);
+        }
+        self
+    }
+}
+
+#[async_trait]
+impl<S: RpcService> RpcService for RateLimitService<S> {
+    async fn respond(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        self.respond_with_context(&RpcContext::default(), method, params)
+            .await
+    }
+
+    async fn respond_with_context(
+        &self,
+        ctx: &RpcContext,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        let mut charged = None;
+        if let Some(per_client) = &self.per_client {
+            let mut per_client = per_client.lock().unwrap();
+            if let Some(client) = per_client.key.extract(ctx) {
+                if let Err(retry_after) = per_client.take(client.clone()) {
+                    return Some(Err(rate_limited(retry_after)));
+                }
+                charged = Some(client);
+            }
+        }
+        let bucket = self.per_method.get(method).unwrap_or(&self.default);
+        let taken = bucket.lock().unwrap().take();
+        if let Err(retry_after) = taken {
+            // the client doesn't pay for a call that the shared limits turned away
+            if let (Some(per_client), Some(client)) = (&self.per_client, charged) {
+                per_client.lock().unwrap().refund(&client);
+            }
+            return Some(Err(rate_limited(retry_after)));
+        }
+        self.inner.respond_with_context(ctx, method, params).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ClientKey, FnService, RateLimitService, RpcContext, RpcService, RATE_LIMITED_CODE,
+    };
+
+    #[test]
+    fn test_rate_limit() {
+        smol::future::block_on(async move {
+            let service = RateLimitService::new(
+                FnService::new(|_, _| async move { Some(Ok(().into())) }),
+                0.0,
+                2,
+            )
+            .with_method_limit("cheap", 1000.0, 1000);
+            assert!(service.respond("f", &[]).await.unwrap().is_ok());
+            assert!(service.respond("g", &[]).await.unwrap().is_ok());
+            for _ in 0..10 {
+                assert!(service.respond("cheap", &[]).await.unwrap().is_ok());
+            }
+            let err = service.respond("f", &[]).await.unwrap().unwrap_err();
+            assert_eq!(err.jrpc_code(), Some(RATE_LIMITED_CODE as i64));
+            assert!(err.details["retry_after_ms"].is_u64());
+
+            let req = serde_json::from_value(serde_json::json!({
+                "jsonrpc": "2.0", "method": "f", "params": [], "id": 1
+            }))
+            .unwrap();
+            let resp = service.respond_raw(req).await;
+            assert_eq!(resp.error.unwrap().code, RATE_LIMITED_CODE as i64);
+        });
+    }
+
+    #[test]
+    fn test_client_rate_limit() {
+        smol::future::block_on(async move {
+            let service = RateLimitService::new(
+                FnService::new(|_, _| async move { Some(Ok(().into())) }),
+                1000.0,
+                1000,
+            )
+            .with_client_limit(ClientKey::Meta("api_key".into()), 0.0, 2)
+            .with_max_clients(2);
+            let call = |key: &'static str| {
+                let service = &service;
+                let mut ctx = RpcContext::new();
+                ctx.meta.insert("api_key".into(), key.into());
+                async move { service.respond_with_context(&ctx, "f", &[]).await.unwrap() }
+            };
+            assert!(call("abuser").await.is_ok());
+            assert!(call("abuser").await.is_ok());
+            assert!(call("abuser").await.is_err());
+            assert!(call("a").await.is_ok());
+            assert!(service.respond("f", &[]).await.unwrap().is_ok());
+            // "b" pushes out "abuser", who was seen least recently
+            assert!(call("a").await.is_ok());
+            assert!(call("b").await.is_ok());
+            assert!(call("a").await.is_err());
+            assert!(call("abuser").await.is_ok());
+        });
+    }
+
+    #[test]
+    fn test_client_not_charged_when_globally_limited() {
+        smol::future::block_on(async move {
+            let service = RateLimitService::new(
+                FnService::new(|_, _| async move { Some(Ok(().into())) }),
+                0.0,
+                1,
+            )
+            .with_method_limit("other", 1000.0, 1000)
+            .with_client_limit(ClientKey::Meta("api_key".into()), 0.0, 2);
+            let call = |method: &'static str| {
+                let service = &service;
+                let mut ctx = RpcContext::new();
+                ctx.meta.insert("api_key".into(), "a".into());
+                async move {
+                    service
+                        .respond_with_context(&ctx, method, &[])
+                        .await
+                        .unwrap()
+                }
+            };
+            assert!(call("f").await.is_ok());
+            // rejected by the shared bucket, which must not use up the client's second token
+            for _ in 0..5 {
+                assert!(call("f").await.is_err());
+            }
+            assert!(call("other").await.is_ok());
+            assert!(call("other").await.is_err());
+        });
+    }
+}
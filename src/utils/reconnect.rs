@@ -0,0 +1,259 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use thiserror::Error;
+
+use crate::{
+    timer,
+    utils::conn_pool::{ConnSlot, Connector},
+    JrpcRequest, JrpcResponse, PersistentTransport, RpcTransport,
+};
+
+/// An error returned by a [ReconnectingTransport].
+#[derive(Error, Debug)]
+pub enum ReconnectError<C, E> {
+    #[error("could not reconnect: {0}")]
+    Connect(C),
+    #[error(transparent)]
+    Transport(E),
+}
+
+/// A ReconnectingTransport keeps one connection made with the given function, like a [crate::WsTransport], and makes it again whenever it is lost, so that a dropped connection doesn't break the client for good.
+///
+/// Reconnecting is retried with exponential backoff and jitter, up to 5 attempts by default, while calls wait for it. Calls in flight when the connection is lost fail, except for the methods marked as idempotent with [ReconnectingTransport::replay_if], which are sent again over the new connection, up to as many times as there are connection attempts.
+pub struct ReconnectingTransport<T: PersistentTransport, C> {
+    connect: Connector<T, C>,
+    conn: ConnSlot<T>,
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    replay_if: Box<dyn Fn(&str) -> bool + Send + Sync + 'static>,
+}
+
+impl<T: PersistentTransport, C: Send + Sync + 'static> ReconnectingTransport<T, C> {
+    /// Creates a new ReconnectingTransport. The connection is made on first use.
+    pub fn new<Fut, Fun>(connect: Fun) -> Self
+    where
+        Fut: Future<Output = Result<T, C>> + Send + 'static,
+        Fun: Fn() -> Fut + Send + Sync + 'static,
+    {
+        Self {
+            connect: Box::new(move || Box::pin(connect())),
+            conn: ConnSlot::new(),
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            replay_if: Box::new(|_| false),
+        }
+    }
+
+    /// Sets the maximum number of connection attempts each time the connection has to be made, which is also the maximum number of times an idempotent call is sent.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Sets the backoff before the second connection attempt, and the cap that the backoff doubles up to.
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Sets which methods are idempotent, and so are sent again if the connection is lost while they are in flight. A batch is only sent again if all its methods are idempotent.
+    pub fn replay_if(mut self, f: impl Fn(&str) -> bool + Send + Sync + 'static) -> Self {
+        self.replay_if = Box::new(f);
+        self
+    }
+
+    /// Returns whether there is a live connection right now.
+    pub fn is_connected(&self) -> bool {
+        self.conn.is_live()
+    }
+
+    /// Returns the live connection, making it again if it is missing or lost.
+    async fn connection(&self) -> Result<Arc<T>, C> {
+        self.conn.get_or_connect(|| self.reconnect()).await
+    }
+
+    /// Makes a new connection, retrying with backoff.
+    async fn reconnect(&self) -> Result<T, C> {
+        let mut attempt = 0;
+        loop {
+            match (self.connect)().await {
+                Ok(new_conn) => return Ok(new_conn),
+                Err(err) if attempt + 1 >= self.max_attempts => return Err(err),
+                Err(_) => {
+                    let backoff = self
+                        .initial_backoff
+                        .mul_f64(2f64.powi(attempt as i32))
+                        .min(self.max_backoff);
+                    log::debug!("could not connect, trying again in {:?}", backoff);
+                    timer::sleep(backoff.mul_f64(1.0 - 0.5 * fastrand::f64())).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<T: PersistentTransport, C: Send + Sync + 'static> RpcTransport
+    for ReconnectingTransport<T, C>
+{
+    type Error = ReconnectError<C, T::Error>;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        let replay = (self.replay_if)(&req.method);
+        let mut sent = 0;
+        loop {
+            let conn = self.connection().await.map_err(ReconnectError::Connect)?;
+            sent += 1;
+            match conn.call_raw(req.clone()).await {
+                Err(_) if replay && conn.is_closed() && sent < self.max_attempts => {
+                    log::debug!("connection lost, replaying {}", req.method)
+                }
+                res => return res.map_err(ReconnectError::Transport),
+            }
+        }
+    }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        let replay = reqs.iter().all(|req| (self.replay_if)(&req.method));
+        let mut sent = 0;
+        loop {
+            let conn = self.connection().await.map_err(ReconnectError::Connect)?;
+            sent += 1;
+            match conn.call_raw_batch(reqs.clone()).await {
+                Err(_) if replay && conn.is_closed() && sent < self.max_attempts => {
+                    log::debug!("connection lost, replaying batch of {}", reqs.len())
+                }
+                res => return res.map_err(ReconnectError::Transport),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    };
+
+    use async_trait::async_trait;
+
+    use crate::{
+        FnService, JrpcRequest, JrpcResponse, PersistentTransport, ReconnectError,
+        ReconnectingTransport, RpcService, RpcTransport,
+    };
+
+    /// A connection answering its ID, which odd-numbered connections lose while calling `drop`, and every connection loses while calling `kill`.
+    struct FlakyConn {
+        id: usize,
+        closed: Arc<AtomicBool>,
+    }
+
+    #[async_trait]
+    impl RpcTransport for FlakyConn {
+        type Error = ();
+
+        async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, ()> {
+            if req.method == "kill" || req.method == "drop" && self.id % 2 == 1 {
+                self.closed.store(true, Ordering::SeqCst);
+            }
+            if self.is_closed() {
+                return Err(());
+            }
+            let id = self.id;
+            let service = FnService::new(move |_, _| async move { Some(Ok(id.into())) });
+            Ok(service.respond_raw(req).await)
+        }
+    }
+
+    impl PersistentTransport for FlakyConn {
+        fn is_closed(&self) -> bool {
+            self.closed.load(Ordering::SeqCst)
+        }
+    }
+
+    type Conns = Arc<Mutex<Vec<Arc<AtomicBool>>>>;
+
+    fn flaky(
+        refusals: Arc<AtomicUsize>,
+    ) -> (ReconnectingTransport<FlakyConn, &'static str>, Conns) {
+        let conns: Conns = Default::default();
+        let transport = ReconnectingTransport::new({
+            let conns = conns.clone();
+            move || {
+                let refused = refusals
+                    .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                    .is_ok();
+                let conn = (!refused).then(|| {
+                    let mut conns = conns.lock().unwrap();
+                    let closed = Arc::new(AtomicBool::new(false));
+                    conns.push(closed.clone());
+                    FlakyConn {
+                        id: conns.len(),
+                        closed,
+                    }
+                });
+                async move { conn.ok_or("refused") }
+            }
+        })
+        .with_max_attempts(3)
+        .with_backoff(Duration::from_millis(1), Duration::from_millis(5));
+        (transport, conns)
+    }
+
+    #[test]
+    fn test_reconnecting_transport() {
+        smol::future::block_on(async move {
+            let refusals = Arc::new(AtomicUsize::new(0));
+            let (transport, conns) = flaky(refusals.clone());
+            assert!(!transport.is_connected());
+            assert_eq!(transport.call("f", &[]).await.unwrap().unwrap().unwrap(), 1);
+            assert!(transport.is_connected());
+
+            // the call in flight fails, and the next one reconnects despite some refusals
+            refusals.store(2, Ordering::SeqCst);
+            assert!(matches!(
+                transport.call("drop", &[]).await,
+                Err(ReconnectError::Transport(()))
+            ));
+            assert!(!transport.is_connected());
+            assert_eq!(transport.call("f", &[]).await.unwrap().unwrap().unwrap(), 2);
+
+            conns.lock().unwrap()[1].store(true, Ordering::SeqCst);
+            refusals.store(3, Ordering::SeqCst);
+            assert!(matches!(
+                transport.call("f", &[]).await,
+                Err(ReconnectError::Connect("refused"))
+            ));
+            assert_eq!(transport.call("f", &[]).await.unwrap().unwrap().unwrap(), 3);
+
+            // idempotent calls are sent again over the new connection
+            let (transport, _) = flaky(refusals.clone());
+            let transport = transport.replay_if(|method| method == "drop");
+            assert_eq!(
+                transport.call("drop", &[]).await.unwrap().unwrap().unwrap(),
+                2
+            );
+
+            // but not forever, if they keep losing the connection
+            let (transport, conns) = flaky(refusals.clone());
+            let transport = transport.replay_if(|method| method == "kill");
+            assert!(matches!(
+                transport.call("kill", &[]).await,
+                Err(ReconnectError::Transport(()))
+            ));
+            assert_eq!(conns.lock().unwrap().len(), 3);
+        });
+    }
+}
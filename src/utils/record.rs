@@ -0,0 +1,289 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{BufRead, Write},
+    sync::Mutex,
+};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{JrpcId, JrpcRequest, JrpcResponse, Redactor, RpcTransport};
+
+/// One recorded call, as written by [RecordingTransport] and read by [ReplayTransport].
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RecordedCall {
+    pub request: JrpcRequest,
+    pub response: JrpcResponse,
+}
+
+/// A RecordingTransport wraps a transport, writing every request and its response to a writer, as one JSON [RecordedCall] per line. Calls that fail at the transport level are not recorded.
+///
+/// Sensitive values can be kept out of recordings with [RecordingTransport::with_redactor]; replaying such a recording needs the same [Redactor] on the [ReplayTransport].
+pub struct RecordingTransport<T: RpcTransport, W: Write + Send + 'static> {
+    inner: T,
+    writer: Mutex<W>,
+    redactor: Redactor,
+}
+
+impl<T: RpcTransport, W: Write + Send + 'static> RecordingTransport<T, W> {
+    /// Creates a new RecordingTransport.
+    pub fn new(inner: T, writer: W) -> Self {
+        Self {
+            inner,
+            writer: Mutex::new(writer),
+            redactor: Redactor::new(),
+        }
+    }
+
+    /// Sets the [Redactor] that hides sensitive values in recorded requests.
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = redactor;
+        self
+    }
+
+    /// Returns the writer, for example to get at a recording made in memory.
+    pub fn into_writer(self) -> W {
+        self.writer.into_inner().unwrap()
+    }
+
+    fn record(&self, request: JrpcRequest, response: JrpcResponse) {
+        let request = if self.redactor.is_empty() {
+            request
+        } else {
+            self.redactor.redact_request(&request)
+        };
+        let mut line = serde_json::to_vec(&RecordedCall { request, response }).unwrap();
+        line.push(b'\n');
+        let mut writer = self.writer.lock().unwrap();
+        if let Err(err) = writer.write_all(&line).and_then(|_| writer.flush()) {
+            log::warn!("could not record call: {}", err);
+        }
+    }
+}
+
+#[async_trait]
+impl<T: RpcTransport, W: Write + Send + 'static> RpcTransport for RecordingTransport<T, W> {
+    type Error = T::Error;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        let resp = self.inner.call_raw(req.clone()).await?;
+        self.record(req, resp.clone());
+        Ok(resp)
+    }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        // responses may come back in any order, and notifications get none, so they are matched to their requests by ID
+        let mut by_id: HashMap<JrpcId, VecDeque<JrpcRequest>> = HashMap::new();
+        for req in reqs.iter() {
+            by_id
+                .entry(req.id.clone())
+                .or_default()
+                .push_back(req.clone());
+        }
+        let resps = self.inner.call_raw_batch(reqs).await?;
+        for resp in resps.iter() {
+            if let Some(req) = by_id.get_mut(&resp.id).and_then(|reqs| reqs.pop_front()) {
+                self.record(req, resp.clone());
+            }
+        }
+        Ok(resps)
+    }
+}
+
+/// An error returned by a [ReplayTransport].
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ReplayError {
+    #[error("no recorded response for {method}({params})")]
+    NotRecorded { method: String, params: String },
+}
+
+/// A ReplayTransport answers calls with responses recorded by a [RecordingTransport], matching them by method and params, for deterministic tests against servers that are not always available.
+///
+/// Identical calls recorded several times are answered in the order they were recorded, after which the last response keeps being repeated.
+pub struct ReplayTransport {
+    recorded: Mutex<HashMap<(String, String), VecDeque<JrpcResponse>>>,
+    redactor: Redactor,
+}
+
+impl ReplayTransport {
+    /// Creates a ReplayTransport from recorded calls.
+    pub fn new(calls: impl IntoIterator<Item = RecordedCall>) -> Self {
+        let mut recorded: HashMap<_, VecDeque<_>> = HashMap::new();
+        for call in calls {
+            recorded
+                .entry(key(&call.request))
+                .or_default()
+                .push_back(call.response);
+        }
+        Self {
+            recorded: Mutex::new(recorded),
+            redactor: Redactor::new(),
+        }
+    }
+
+    /// Sets the [Redactor] that the calls were recorded with, so that calls are matched with their params redacted the same way.
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = redactor;
+        self
+    }
+
+    /// Creates a ReplayTransport from a recording in the JSON-lines format written by [RecordingTransport].
+    pub fn from_reader(reader: impl BufRead) -> std::io::Result<Self> {
+        let mut calls = vec![];
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            calls.push(serde_json::from_str(&line)?);
+        }
+        Ok(Self::new(calls))
+    }
+}
+
+fn key(req: &JrpcRequest) -> (String, String) {
+    (
+        req.method.clone(),
+        serde_json::to_string(&req.params).unwrap(),
+    )
+}
+
+#[async_trait]
+impl RpcTransport for ReplayTransport {
+    type Error = ReplayError;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        let key = key(&self.redactor.redact_request(&req));
+        let mut recorded = self.recorded.lock().unwrap();
+        let queue = match recorded.get_mut(&key) {
+            Some(queue) if !queue.is_empty() => queue,
+            _ => {
+                let (method, params) = key;
+                return Err(ReplayError::NotRecorded { method, params });
+            }
+        };
+        let mut resp = if queue.len() > 1 {
+            queue.pop_front().unwrap()
+        } else {
+            queue[0].clone()
+        };
+        resp.id = req.id;
+        Ok(resp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    };
+
+    use async_trait::async_trait;
+
+    use crate::{
+        new_jrpc_request, FnService, FnTransport, JrpcRequest, JrpcResponse, RecordedCall,
+        RecordingTransport, Redactor, ReplayError, ReplayTransport, RpcTransport,
+    };
+
+    /// Answers batches with their responses in reverse order.
+    struct Reversed(FnTransport<()>);
+
+    #[async_trait]
+    impl RpcTransport for Reversed {
+        type Error = ();
+
+        async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, ()> {
+            self.0.call_raw(req).await
+        }
+
+        async fn call_raw_batch(&self, reqs: Vec<JrpcRequest>) -> Result<Vec<JrpcResponse>, ()> {
+            let mut resps = self.0.call_raw_batch(reqs).await?;
+            resps.reverse();
+            Ok(resps)
+        }
+    }
+
+    #[test]
+    fn test_record_replay() {
+        smol::future::block_on(async move {
+            let counter = Arc::new(AtomicU64::new(0));
+            let service = FnService::new(move |_, params| {
+                let n = counter.fetch_add(1, Ordering::SeqCst);
+                async move { Some(Ok(serde_json::json!([n, params]))) }
+            });
+            let recording = RecordingTransport::new(FnTransport::<()>::loopback(service), vec![]);
+            let mut live = vec![];
+            for param in [1, 1, 2] {
+                live.push(recording.call("f", &[param.into()]).await.unwrap());
+            }
+            let recorded = recording.into_writer();
+            assert_eq!(recorded.iter().filter(|b| **b == b'\n').count(), 3);
+
+            let replay = ReplayTransport::from_reader(recorded.as_slice()).unwrap();
+            for (param, live) in [1, 1, 2].into_iter().zip(live) {
+                assert_eq!(replay.call("f", &[param.into()]).await.unwrap(), live);
+            }
+            assert_eq!(
+                replay
+                    .call("f", &[1.into()])
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .unwrap(),
+                serde_json::json!([1, [1]])
+            );
+            assert!(matches!(
+                replay.call("f", &[3.into()]).await,
+                Err(ReplayError::NotRecorded { .. })
+            ));
+        });
+    }
+
+    #[test]
+    fn test_record_redacted() {
+        smol::future::block_on(async move {
+            let service = FnService::new(|_, _| async move { Some(Ok(1.into())) });
+            let redactor = Redactor::new().with_param("login", 1);
+            let recording = RecordingTransport::new(FnTransport::<()>::loopback(service), vec![])
+                .with_redactor(redactor.clone());
+            recording
+                .call("login", &["alice".into(), "hunter2".into()])
+                .await
+                .unwrap();
+            let recorded = recording.into_writer();
+            assert!(!String::from_utf8_lossy(&recorded).contains("hunter2"));
+            let replay = ReplayTransport::from_reader(recorded.as_slice())
+                .unwrap()
+                .with_redactor(redactor);
+            assert!(replay
+                .call("login", &["alice".into(), "other".into()])
+                .await
+                .is_ok());
+        });
+    }
+
+    #[test]
+    fn test_record_batch() {
+        smol::future::block_on(async move {
+            let service = FnService::new(|_, params| async move { Some(Ok(params[0].clone())) });
+            let recording =
+                RecordingTransport::new(Reversed(FnTransport::loopback(service)), vec![]);
+            let reqs = (1..=3)
+                .map(|i| new_jrpc_request("f", &[i.into()]))
+                .collect();
+            recording.call_raw_batch(reqs).await.unwrap();
+            let recorded = String::from_utf8(recording.into_writer()).unwrap();
+            assert_eq!(recorded.lines().count(), 3);
+            for line in recorded.lines() {
+                let call: RecordedCall = serde_json::from_str(line).unwrap();
+                assert_eq!(call.response.id, call.request.id);
+                assert_eq!(call.response.result.unwrap(), call.request.params[0]);
+            }
+        });
+    }
+}
@@ -0,0 +1,122 @@
+use std::borrow::Cow;
+
+use crate::{utils::acl::glob_matches, JrpcRequest};
+
+/// What redacted values are replaced with.
+pub const REDACTED: &str = "[redacted]";
+
+/// A Redactor hides sensitive values in calls before they are logged, recorded, or audited, by [crate::LoggingService], [crate::RecordingTransport], and [crate::AuditService].
+///
+/// Rules apply to methods matching a glob pattern, like in [crate::AclService], and name the values to hide with JSON pointers into the params array: `"/1"` is the second param, and `"/0/password"` is the `password` field of the first one. Pointers that don't resolve are ignored. Request metadata entries can be hidden too, like credentials passed there.
+#[derive(Clone, Debug, Default)]
+pub struct Redactor {
+    pointers: Vec<(String, String)>,
+    meta_keys: Vec<String>,
+}
+
+impl Redactor {
+    /// Creates a new Redactor, which hides nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hides the param at the given index in methods matching the pattern.
+    pub fn with_param(self, pattern: impl Into<String>, index: usize) -> Self {
+        self.with_pointer(pattern, format!("/{}", index))
+    }
+
+    /// Hides the value at the given JSON pointer into the params, in methods matching the pattern.
+    pub fn with_pointer(mut self, pattern: impl Into<String>, pointer: impl Into<String>) -> Self {
+        self.pointers.push((pattern.into(), pointer.into()));
+        self
+    }
+
+    /// Hides the request metadata entry with the given key, in every method.
+    pub fn with_meta_key(mut self, key: impl Into<String>) -> Self {
+        self.meta_keys.push(key.into());
+        self
+    }
+
+    /// Returns whether the Redactor hides anything.
+    pub fn is_empty(&self) -> bool {
+        self.pointers.is_empty() && self.meta_keys.is_empty()
+    }
+
+    /// Returns the params of a call to a method, with sensitive values hidden.
+    pub fn redact_params<'a>(
+        &self,
+        method: &str,
+        params: &'a [serde_json::Value],
+    ) -> Cow<'a, [serde_json::Value]> {
+        let mut pointers = self
+            .pointers
+            .iter()
+            .filter(|(pattern, _)| glob_matches(pattern.as_bytes(), method.as_bytes()))
+            .map(|(_, pointer)| pointer)
+            .peekable();
+        if pointers.peek().is_none() {
+            return Cow::Borrowed(params);
+        }
+        let mut params = serde_json::Value::Array(params.to_vec());
+        for pointer in pointers {
+            if let Some(value) = params.pointer_mut(pointer) {
+                *value = REDACTED.into();
+            }
+        }
+        match params {
+            serde_json::Value::Array(params) => Cow::Owned(params),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns a request with sensitive params and metadata hidden.
+    pub fn redact_request(&self, req: &JrpcRequest) -> JrpcRequest {
+        let mut req = req.clone();
+        if let Cow::Owned(params) = self.redact_params(&req.method, &req.params) {
+            req.params = params;
+        }
+        for key in self.meta_keys.iter() {
+            if let Some(value) = req.meta.get_mut(key) {
+                *value = REDACTED.into();
+            }
+        }
+        req
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{JrpcId, JrpcRequest, Redactor};
+
+    #[test]
+    fn test_redactor() {
+        let redactor = Redactor::new()
+            .with_param("login", 1)
+            .with_pointer("account.*", "/0/password")
+            .with_pointer("account.*", "/5")
+            .with_meta_key("auth");
+        let params = [json!("alice"), json!("hunter2")];
+        assert_eq!(
+            redactor.redact_params("login", &params).as_ref(),
+            [json!("alice"), json!("[redacted]")]
+        );
+        assert_eq!(redactor.redact_params("other", &params).as_ref(), params);
+
+        let mut req = JrpcRequest {
+            jsonrpc: "2.0".into(),
+            method: "account.create".into(),
+            params: vec![json!({"name": "alice", "password": "hunter2"})],
+            id: JrpcId::Number(1),
+            meta: Default::default(),
+        };
+        req.meta.insert("auth".into(), "secret".into());
+        let redacted = redactor.redact_request(&req);
+        assert_eq!(
+            redacted.params[0],
+            json!({"name": "alice", "password": "[redacted]"})
+        );
+        assert_eq!(redacted.meta["auth"], "[redacted]");
+    }
+}
@@ -0,0 +1,173 @@
+use std::{cmp::Reverse, sync::RwLock};
+
+use async_trait::async_trait;
+
+use crate::{DynRpcService, RpcContext, RpcService, ServerError};
+
+#[derive(Default)]
+struct Entries {
+    // (prefix, service), longest prefixes first
+    prefixed: Vec<(String, DynRpcService)>,
+    // (name, service), in registration order
+    named: Vec<(String, DynRpcService)>,
+}
+
+/// A RegistryService holds services that can be registered and unregistered while the server is running, like the protocols of dynamically loaded plugins.
+///
+/// A service registered under a prefix handles the calls whose method starts with it, with the prefix stripped, the longest matching prefix winning. Calls that match no prefix are offered to the services registered under a name, in registration order.
+#[derive(Default)]
+pub struct RegistryService {
+    entries: RwLock<Entries>,
+}
+
+impl RegistryService {
+    /// Creates an empty RegistryService.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a service under a prefix, returning the service previously registered under it.
+    pub fn register_prefix(
+        &self,
+        prefix: impl Into<String>,
+        service: impl RpcService,
+    ) -> Option<DynRpcService> {
+        let prefix = prefix.into();
+        let old = self.unregister_prefix(&prefix);
+        let mut entries = self.entries.write().unwrap();
+        entries.prefixed.push((prefix, DynRpcService::new(service)));
+        entries
+            .prefixed
+            .sort_by_key(|(prefix, _)| Reverse(prefix.len()));
+        old
+    }
+
+    /// Unregisters the service registered under a prefix.
+    pub fn unregister_prefix(&self, prefix: &str) -> Option<DynRpcService> {
+        let mut entries = self.entries.write().unwrap();
+        let idx = entries.prefixed.iter().position(|(p, _)| p == prefix)?;
+        Some(entries.prefixed.remove(idx).1)
+    }
+
+    /// Registers a service under a name, returning the service previously registered under it. A replaced service keeps its place in the order.
+    pub fn register(
+        &self,
+        name: impl Into<String>,
+        service: impl RpcService,
+    ) -> Option<DynRpcService> {
+        let name = name.into();
+        let service = DynRpcService::new(service);
+        let mut entries = self.entries.write().unwrap();
+        match entries.named.iter_mut().find(|(n, _)| *n == name) {
+            Some(entry) => Some(std::mem::replace(&mut entry.1, service)),
+            None => {
+                entries.named.push((name, service));
+                None
+            }
+        }
+    }
+
+    /// Unregisters the service registered under a name.
+    pub fn unregister(&self, name: &str) -> Option<DynRpcService> {
+        let mut entries = self.entries.write().unwrap();
+        let idx = entries.named.iter().position(|(n, _)| n == name)?;
+        Some(entries.named.remove(idx).1)
+    }
+
+    /// Returns the names and prefixes currently registered.
+    pub fn registered(&self) -> Vec<String> {
+        let entries = self.entries.read().unwrap();
+        entries
+            .prefixed
+            .iter()
+            .chain(entries.named.iter())
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+}
+
+#[async_trait]
+impl RpcService for RegistryService {
+    async fn respond(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        self.respond_with_context(&RpcContext::default(), method, params)
+            .await
+    }
+
+    async fn respond_with_context(
+        &self,
+        ctx: &RpcContext,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        // never hold the lock across an await, so that registration doesn't wait on calls
+        let (prefixed, named) = {
+            let entries = self.entries.read().unwrap();
+            let prefixed = entries
+                .prefixed
+                .iter()
+                .find(|(prefix, _)| method.starts_with(prefix.as_str()))
+                .map(|(prefix, service)| (prefix.len(), service.clone()));
+            let named: Vec<DynRpcService> = if prefixed.is_none() {
+                entries.named.iter().map(|(_, s)| s.clone()).collect()
+            } else {
+                vec![]
+            };
+            (prefixed, named)
+        };
+        if let Some((len, service)) = prefixed {
+            return service
+                .respond_with_context(ctx, &method[len..], params)
+                .await;
+        }
+        for service in named {
+            if let Some(res) = service.respond_with_context(ctx, method, params).await {
+                return Some(res);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{FnService, RegistryService, RpcService};
+
+    fn echo_method() -> FnService {
+        FnService::new(|method, _| {
+            let method = method.to_string();
+            async move { Some(Ok(method.into())) }
+        })
+    }
+
+    #[test]
+    fn test_registry() {
+        smol::future::block_on(async move {
+            let registry = RegistryService::new();
+            assert!(registry.respond("plugin.f", &[]).await.is_none());
+            registry.register_prefix("plugin.", echo_method());
+            registry.register_prefix("plugin.inner.", echo_method());
+            assert_eq!(
+                registry.respond("plugin.f", &[]).await.unwrap().unwrap(),
+                "f"
+            );
+            assert_eq!(
+                registry
+                    .respond("plugin.inner.f", &[])
+                    .await
+                    .unwrap()
+                    .unwrap(),
+                "f"
+            );
+            registry.register("core", echo_method());
+            assert_eq!(registry.respond("g", &[]).await.unwrap().unwrap(), "g");
+            assert!(registry.unregister_prefix("plugin.").is_some());
+            assert!(registry.unregister("core").is_some());
+            assert_eq!(registry.registered(), vec!["plugin.inner.".to_string()]);
+            assert!(registry.respond("plugin.f", &[]).await.is_none());
+        });
+    }
+}
@@ -0,0 +1,358 @@
+use std::{
+    collections::BTreeSet,
+    net::ToSocketAddrs,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use thiserror::Error;
+
+use crate::{timer, HealthCheckedPool, JrpcRequest, JrpcResponse, RpcTransport};
+
+/// A Resolver discovers the current set of endpoints for a service, for keeping a [HealthCheckedPool] up to date with [HealthCheckedPool::run_discovery].
+///
+/// Endpoints are opaque strings, typically `host:port` addresses. Other discovery mechanisms, like DNS SRV records or a service registry, can be plugged in by implementing this trait.
+#[async_trait]
+pub trait Resolver: Send + Sync + 'static {
+    /// Resolves the current set of endpoints.
+    async fn resolve(&self) -> std::io::Result<Vec<String>>;
+}
+
+/// A [Resolver] that always returns the same endpoints.
+pub struct StaticResolver(pub Vec<String>);
+
+#[async_trait]
+impl Resolver for StaticResolver {
+    async fn resolve(&self) -> std::io::Result<Vec<String>> {
+        Ok(self.0.clone())
+    }
+}
+
+/// A DnsResolver resolves a `host:port` name to one endpoint per A or AAAA record, using the system resolver. This is what a headless Kubernetes service needs, where the name resolves to every ready replica.
+///
+/// Lookups run on a short-lived background thread, so they never block the async runtime.
+pub struct DnsResolver {
+    name: String,
+}
+
+impl DnsResolver {
+    /// Creates a new DnsResolver for a name like `"backend.default.svc.cluster.local:8080"`.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into() }
+    }
+}
+
+#[async_trait]
+impl Resolver for DnsResolver {
+    async fn resolve(&self) -> std::io::Result<Vec<String>> {
+        let name = self.name.clone();
+        let (send, recv) = async_channel::bounded(1);
+        std::thread::Builder::new()
+            .name("nanorpc-dns".into())
+            .spawn(move || {
+                let result = name
+                    .to_socket_addrs()
+                    .map(|addrs| addrs.map(|addr| addr.to_string()).collect());
+                let _ = send.try_send(result);
+            })?;
+        recv.recv()
+            .await
+            .unwrap_or_else(|_| Err(std::io::Error::other("resolver thread panicked")))
+    }
+}
+
+impl<T: RpcTransport> HealthCheckedPool<T> {
+    /// Resolves the endpoints once, adding a backend for each new endpoint with `connect` and removing the backends whose endpoints are gone. Backends are named after their endpoints. On failure, the pool is left unchanged.
+    pub async fn refresh(
+        &self,
+        resolver: &impl Resolver,
+        connect: impl Fn(&str) -> T,
+    ) -> std::io::Result<()> {
+        let endpoints: BTreeSet<String> = resolver.resolve().await?.into_iter().collect();
+        let current: BTreeSet<String> = self.state().into_iter().map(|s| s.name).collect();
+        for gone in current.difference(&endpoints) {
+            log::info!("endpoint {} is gone, removing it from the pool", gone);
+            self.remove(gone);
+        }
+        for new in endpoints.difference(&current) {
+            log::info!("discovered endpoint {}", new);
+            self.insert(new.clone(), connect(new));
+        }
+        Ok(())
+    }
+
+    /// Refreshes the backends from the resolver every interval, forever. Resolution failures are logged and otherwise ignored, keeping the last known backends.
+    pub async fn run_discovery(
+        &self,
+        resolver: impl Resolver,
+        connect: impl Fn(&str) -> T,
+        interval: Duration,
+    ) {
+        loop {
+            if let Err(err) = self.refresh(&resolver, &connect).await {
+                log::warn!("could not resolve endpoints: {}", err);
+            }
+            timer::sleep(interval).await;
+        }
+    }
+}
+
+/// An error returned by a [ResolvingTransport].
+#[derive(Error, Debug)]
+pub enum ResolveError<E> {
+    #[error("could not resolve endpoints: {0}")]
+    Resolve(std::io::Error),
+    #[error("no endpoints resolved")]
+    NoEndpoints,
+    #[error(transparent)]
+    Transport(E),
+}
+
+struct Resolved<T> {
+    endpoints: Vec<(String, Arc<T>)>,
+    resolved_at: Option<Instant>,
+    stale: bool,
+}
+
+/// A ResolvingTransport spreads calls round-robin across every endpoint that a [Resolver], like a [DnsResolver], returns for a name, so that long-lived clients follow servers as their addresses change, without a restart.
+///
+/// Endpoints are resolved on first use, and again once the re-resolution interval has passed, or after a call fails. Transports are made for new endpoints with the given function, and kept for endpoints that remain. If resolving fails, the last known endpoints are kept. Calls wait while endpoints are resolved.
+pub struct ResolvingTransport<T: RpcTransport, R: Resolver> {
+    resolver: R,
+    connect: Box<dyn Fn(&str) -> T + Send + Sync + 'static>,
+    interval: Duration,
+    state: async_lock::Mutex<Resolved<T>>,
+    next: AtomicUsize,
+}
+
+impl<T: RpcTransport, R: Resolver> ResolvingTransport<T, R> {
+    /// Creates a new ResolvingTransport, re-resolving every 30 seconds.
+    pub fn new(resolver: R, connect: impl Fn(&str) -> T + Send + Sync + 'static) -> Self {
+        Self {
+            resolver,
+            connect: Box::new(connect),
+            interval: Duration::from_secs(30),
+            state: async_lock::Mutex::new(Resolved {
+                endpoints: vec![],
+                resolved_at: None,
+                stale: false,
+            }),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Sets how often endpoints are resolved again.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Returns the endpoints currently in rotation.
+    pub async fn endpoints(&self) -> Vec<String> {
+        let state = self.state.lock().await;
+        state
+            .endpoints
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// Returns the transport to use for the next call, resolving endpoints first if they are due.
+    async fn pick(&self) -> Result<Arc<T>, ResolveError<T::Error>> {
+        let mut state = self.state.lock().await;
+        let due = state.stale
+            || state
+                .resolved_at
+                .is_none_or(|at| at.elapsed() >= self.interval);
+        if due {
+            match self.resolver.resolve().await {
+                Ok(endpoints) => {
+                    let mut old = std::mem::take(&mut state.endpoints);
+                    let mut seen = BTreeSet::new();
+                    for endpoint in endpoints {
+                        if !seen.insert(endpoint.clone()) {
+                            continue;
+                        }
+                        let transport = match old.iter().position(|(name, _)| *name == endpoint) {
+                            Some(idx) => old.swap_remove(idx).1,
+                            None => {
+                                log::info!("resolved new endpoint {}", endpoint);
+                                Arc::new((self.connect)(&endpoint))
+                            }
+                        };
+                        state.endpoints.push((endpoint, transport));
+                    }
+                }
+                Err(err) if !state.endpoints.is_empty() => {
+                    log::warn!(
+                        "could not resolve endpoints, keeping the last ones: {}",
+                        err
+                    )
+                }
+                Err(err) => return Err(ResolveError::Resolve(err)),
+            }
+            state.resolved_at = Some(Instant::now());
+            state.stale = false;
+        }
+        if state.endpoints.is_empty() {
+            return Err(ResolveError::NoEndpoints);
+        }
+        let idx = self.next.fetch_add(1, Ordering::Relaxed) % state.endpoints.len();
+        Ok(state.endpoints[idx].1.clone())
+    }
+
+    /// Makes sure the endpoints are resolved again before the next call.
+    async fn mark_stale(&self) {
+        self.state.lock().await.stale = true;
+    }
+}
+
+#[async_trait]
+impl<T: RpcTransport, R: Resolver> RpcTransport for ResolvingTransport<T, R> {
+    type Error = ResolveError<T::Error>;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        let transport = self.pick().await?;
+        match transport.call_raw(req).await {
+            Ok(resp) => Ok(resp),
+            Err(err) => {
+                self.mark_stale().await;
+                Err(ResolveError::Transport(err))
+            }
+        }
+    }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        let transport = self.pick().await?;
+        match transport.call_raw_batch(reqs).await {
+            Ok(resps) => Ok(resps),
+            Err(err) => {
+                self.mark_stale().await;
+                Err(ResolveError::Transport(err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    };
+
+    use async_trait::async_trait;
+
+    use crate::{
+        DnsResolver, FnService, FnTransport, HealthCheckedPool, ResolveError, Resolver,
+        ResolvingTransport, RpcTransport,
+    };
+
+    /// Resolves to each set of endpoints in turn, then fails.
+    struct Scripted(Mutex<Vec<Vec<String>>>);
+
+    #[async_trait]
+    impl Resolver for Scripted {
+        async fn resolve(&self) -> std::io::Result<Vec<String>> {
+            let mut script = self.0.lock().unwrap();
+            if script.is_empty() {
+                return Err(std::io::Error::other("resolver down"));
+            }
+            Ok(script.remove(0))
+        }
+    }
+
+    #[test]
+    fn test_discovery() {
+        smol::future::block_on(async move {
+            let resolver = Scripted(Mutex::new(vec![
+                vec!["a".into(), "b".into()],
+                vec!["b".into(), "c".into()],
+            ]));
+            let connect = |endpoint: &str| {
+                let endpoint = endpoint.to_string();
+                FnTransport::<()>::loopback(FnService::new(move |_, _| {
+                    let endpoint = endpoint.clone();
+                    async move { Some(Ok(endpoint.into())) }
+                }))
+            };
+            let pool = HealthCheckedPool::new();
+            let names = |pool: &HealthCheckedPool<_>| {
+                let mut names: Vec<String> = pool.state().into_iter().map(|s| s.name).collect();
+                names.sort();
+                names
+            };
+            pool.refresh(&resolver, connect).await.unwrap();
+            assert_eq!(names(&pool), vec!["a", "b"]);
+            pool.refresh(&resolver, connect).await.unwrap();
+            assert_eq!(names(&pool), vec!["b", "c"]);
+            let mut seen = vec![];
+            for _ in 0..2 {
+                seen.push(pool.call("f", &[]).await.unwrap().unwrap().unwrap());
+            }
+            seen.sort_by_key(|v| v.to_string());
+            assert_eq!(seen, vec!["b", "c"]);
+
+            let localhost = DnsResolver::new("localhost:1234").resolve().await.unwrap();
+            assert!(localhost.iter().all(|addr| addr.ends_with(":1234")));
+        });
+    }
+
+    #[test]
+    fn test_resolving_transport() {
+        smol::future::block_on(async move {
+            let resolver = Scripted(Mutex::new(vec![
+                vec!["a".into(), "b".into()],
+                vec!["b".into(), "c".into(), "c".into()],
+            ]));
+            let connects = Arc::new(AtomicUsize::new(0));
+            let transport = ResolvingTransport::new(resolver, {
+                let connects = connects.clone();
+                move |endpoint: &str| {
+                    connects.fetch_add(1, Ordering::SeqCst);
+                    let endpoint = endpoint.to_string();
+                    FnTransport::loopback_with(
+                        FnService::new(move |_, _| {
+                            let endpoint = endpoint.clone();
+                            async move { Some(Ok(endpoint.into())) }
+                        }),
+                        |req| {
+                            if req.method == "fail" {
+                                Err("failed")
+                            } else {
+                                Ok(())
+                            }
+                        },
+                    )
+                }
+            });
+            let mut seen = vec![];
+            for _ in 0..2 {
+                seen.push(transport.call("f", &[]).await.unwrap().unwrap().unwrap());
+            }
+            seen.sort_by_key(|v| v.to_string());
+            assert_eq!(seen, vec!["a", "b"]);
+
+            // a failure makes the next call resolve again, keeping the transport of the endpoint that remains
+            assert!(matches!(
+                transport.call("fail", &[]).await,
+                Err(ResolveError::Transport("failed"))
+            ));
+            transport.call("f", &[]).await.unwrap();
+            assert_eq!(transport.endpoints().await, vec!["b", "c"]);
+            assert_eq!(connects.load(Ordering::SeqCst), 3);
+
+            // when resolving fails, the last endpoints stay in rotation
+            assert!(transport.call("fail", &[]).await.is_err());
+            transport.call("f", &[]).await.unwrap();
+            assert_eq!(transport.endpoints().await, vec!["b", "c"]);
+        });
+    }
+}
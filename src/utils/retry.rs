@@ -0,0 +1,179 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::{timer, JrpcRequest, JrpcResponse, RpcTransport};
+
+/// A RetryTransport wraps a transport, retrying failed calls with exponential backoff and jitter.
+///
+/// By default, every transport-level error is retried, and no server errors are. Since a call that failed at the transport level may still have reached the server, only wrap transports used for idempotent methods, or narrow down the retried errors with [RetryTransport::retry_if].
+#[allow(clippy::type_complexity)]
+pub struct RetryTransport<T: RpcTransport> {
+    inner: T,
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: f64,
+    jitter: f64,
+    retry_if: Box<dyn Fn(&T::Error) -> bool + Send + Sync + 'static>,
+    retry_codes: Vec<i64>,
+}
+
+impl<T: RpcTransport> RetryTransport<T> {
+    /// Creates a new RetryTransport, making up to 3 attempts with a backoff starting at 100 milliseconds.
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            multiplier: 2.0,
+            jitter: 0.5,
+            retry_if: Box::new(|_| true),
+            retry_codes: vec![],
+        }
+    }
+
+    /// Sets the maximum number of attempts, including the first one.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Sets the backoff before the first retry, and the cap that the backoff grows to.
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Sets the factor by which the backoff grows after every retry.
+    pub fn with_multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    /// Sets the jitter, as the fraction of each backoff that is randomly subtracted from it. `0.0` disables jitter.
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets which transport-level errors are retried.
+    pub fn retry_if(mut self, f: impl Fn(&T::Error) -> bool + Send + Sync + 'static) -> Self {
+        self.retry_if = Box::new(f);
+        self
+    }
+
+    /// Also retries calls that the server answered with one of these error codes.
+    pub fn retry_on_codes(mut self, codes: &[i64]) -> Self {
+        self.retry_codes.extend_from_slice(codes);
+        self
+    }
+
+    fn backoff(&self, retry: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .mul_f64(self.multiplier.powi(retry as i32))
+            .min(self.max_backoff);
+        backoff.mul_f64(1.0 - self.jitter * fastrand::f64())
+    }
+
+    fn is_retryable(&self, resp: &JrpcResponse) -> bool {
+        resp.error
+            .as_ref()
+            .map(|err| self.retry_codes.contains(&err.code))
+            .unwrap_or(false)
+    }
+}
+
+#[async_trait]
+impl<T: RpcTransport> RpcTransport for RetryTransport<T> {
+    type Error = T::Error;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        let mut retry = 0;
+        loop {
+            let last = retry + 1 >= self.max_attempts;
+            match self.inner.call_raw(req.clone()).await {
+                Ok(resp) if last || !self.is_retryable(&resp) => return Ok(resp),
+                Err(err) if last || !(self.retry_if)(&err) => return Err(err),
+                Ok(resp) => log::debug!("retrying {} after error response: {}", req.method, resp),
+                Err(_) => log::debug!("retrying {} after transport error", req.method),
+            }
+            timer::sleep(self.backoff(retry)).await;
+            retry += 1;
+        }
+    }
+
+    /// Retries the whole batch on transport-level errors. Individual server errors within the batch are not retried.
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        let mut retry = 0;
+        loop {
+            match self.inner.call_raw_batch(reqs.clone()).await {
+                Err(err) if retry + 1 < self.max_attempts && (self.retry_if)(&err) => {
+                    log::debug!("retrying batch of {} after transport error", reqs.len())
+                }
+                result => return result,
+            }
+            timer::sleep(self.backoff(retry)).await;
+            retry += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use crate::{FnTransport, JrpcResponse, RetryTransport, RpcTransport};
+
+    #[test]
+    fn test_retry() {
+        smol::future::block_on(async move {
+            // fails at the transport level twice, then with a server error, then succeeds
+            let attempts = Arc::new(AtomicU32::new(0));
+            let flaky = {
+                let attempts = attempts.clone();
+                FnTransport::new(move |req| {
+                    let n = attempts.fetch_add(1, Ordering::SeqCst);
+                    async move {
+                        let resp = match n {
+                            0 | 1 => return Err("flaky"),
+                            2 => crate::server::error_response(req.id, -32029, "rate limited"),
+                            _ => JrpcResponse {
+                                jsonrpc: "2.0".into(),
+                                result: Some(n.into()),
+                                error: None,
+                                id: req.id,
+                            },
+                        };
+                        Ok(resp)
+                    }
+                })
+            };
+            let retry = RetryTransport::new(flaky.clone())
+                .with_backoff(Duration::from_millis(1), Duration::from_millis(10))
+                .with_max_attempts(4);
+            assert!(retry.call("f", &[]).await.unwrap().unwrap().is_err());
+            assert_eq!(attempts.swap(0, Ordering::SeqCst), 3);
+
+            let retry = retry.retry_on_codes(&[-32029]);
+            assert_eq!(retry.call("f", &[]).await.unwrap().unwrap().unwrap(), 3);
+            assert_eq!(attempts.swap(0, Ordering::SeqCst), 4);
+
+            let retry = RetryTransport::new(flaky).retry_if(|err| *err != "flaky");
+            assert_eq!(retry.call("f", &[]).await.unwrap_err(), "flaky");
+            assert_eq!(attempts.load(Ordering::SeqCst), 1);
+        });
+    }
+}
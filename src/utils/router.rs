@@ -0,0 +1,129 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use thiserror::Error;
+
+use crate::{RpcContext, RpcService, ServerError};
+
+/// The error returned when mounting a service in a [RouterService] would make routing ambiguous.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("prefix {new:?} conflicts with already mounted prefix {existing:?}")]
+pub struct MountConflict {
+    pub new: String,
+    pub existing: String,
+}
+
+/// A RouterService dispatches calls to services mounted under method prefixes. A service mounted under `"wallet."` handles calls like `wallet.balance`, and sees them as calls to `balance`. Calls that match no prefix go to the fallback service, if any.
+///
+/// No mounted prefix may be a prefix of another, so that every method is routed to at most one service.
+#[derive(Default)]
+pub struct RouterService {
+    mounts: Vec<(String, Arc<dyn RpcService>)>,
+    fallback: Option<Arc<dyn RpcService>>,
+}
+
+impl RouterService {
+    /// Creates a RouterService without any mounted services.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mounts a service under a prefix.
+    pub fn mount(
+        &mut self,
+        prefix: impl Into<String>,
+        service: impl RpcService,
+    ) -> Result<&mut Self, MountConflict> {
+        let prefix = prefix.into();
+        if let Some((existing, _)) = self
+            .mounts
+            .iter()
+            .find(|(existing, _)| existing.starts_with(&prefix) || prefix.starts_with(existing))
+        {
+            return Err(MountConflict {
+                new: prefix,
+                existing: existing.clone(),
+            });
+        }
+        self.mounts.push((prefix, Arc::new(service)));
+        Ok(self)
+    }
+
+    /// Sets the service handling calls that match no prefix, with the method name unchanged.
+    pub fn set_fallback(&mut self, service: impl RpcService) -> &mut Self {
+        self.fallback = Some(Arc::new(service));
+        self
+    }
+}
+
+#[async_trait]
+impl RpcService for RouterService {
+    async fn respond(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        self.respond_with_context(&RpcContext::default(), method, params)
+            .await
+    }
+
+    async fn respond_with_context(
+        &self,
+        ctx: &RpcContext,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        for (prefix, service) in self.mounts.iter() {
+            if let Some(rest) = method.strip_prefix(prefix.as_str()) {
+                return service.respond_with_context(ctx, rest, params).await;
+            }
+        }
+        match &self.fallback {
+            Some(fallback) => fallback.respond_with_context(ctx, method, params).await,
+            None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{FnService, RouterService, RpcService};
+
+    fn echo_method(name: &'static str) -> FnService {
+        FnService::new(move |method, _| {
+            let found = method == "get";
+            let reply = format!("{} {}", name, method);
+            async move { found.then(|| Ok(reply.into())) }
+        })
+    }
+
+    #[test]
+    fn test_router() {
+        smol::future::block_on(async move {
+            let mut router = RouterService::new();
+            router
+                .mount("wallet.", echo_method("wallet"))
+                .unwrap()
+                .mount("admin.", echo_method("admin"))
+                .unwrap();
+            assert!(router.mount("wallet.", echo_method("again")).is_err());
+            assert!(router.mount("wallet.x.", echo_method("nested")).is_err());
+            assert!(router.mount("wal", echo_method("short")).is_err());
+            assert_eq!(
+                router.respond("wallet.get", &[]).await.unwrap().unwrap(),
+                "wallet get"
+            );
+            assert_eq!(
+                router.respond("admin.get", &[]).await.unwrap().unwrap(),
+                "admin get"
+            );
+            assert!(router.respond("admin.set", &[]).await.is_none());
+            assert!(router.respond("get", &[]).await.is_none());
+            router.set_fallback(echo_method("root"));
+            assert_eq!(
+                router.respond("get", &[]).await.unwrap().unwrap(),
+                "root get"
+            );
+        });
+    }
+}
@@ -0,0 +1,91 @@
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+use async_trait::async_trait;
+
+use crate::{DynRpcService, JrpcRequest, JrpcResponse, RpcContext, RpcService, ServerError};
+
+/// A SwappableService lets the inner service be replaced atomically while the server is running, for example on configuration reloads. Calls already in flight finish on the service they started on.
+///
+/// To swap between services of different types, use the default inner type, [DynRpcService].
+pub struct SwappableService<S: RpcService = DynRpcService> {
+    inner: ArcSwap<S>,
+}
+
+impl<S: RpcService> SwappableService<S> {
+    /// Creates a new SwappableService.
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner: ArcSwap::from_pointee(inner),
+        }
+    }
+
+    /// Replaces the inner service, returning the previous one.
+    pub fn swap(&self, inner: S) -> Arc<S> {
+        self.inner.swap(Arc::new(inner))
+    }
+
+    /// Returns the current inner service.
+    pub fn current(&self) -> Arc<S> {
+        self.inner.load_full()
+    }
+}
+
+#[async_trait]
+impl<S: RpcService> RpcService for SwappableService<S> {
+    async fn respond(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        self.respond_with_context(&RpcContext::default(), method, params)
+            .await
+    }
+
+    async fn respond_with_context(
+        &self,
+        ctx: &RpcContext,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        self.current()
+            .respond_with_context(ctx, method, params)
+            .await
+    }
+
+    async fn respond_raw_with_context(
+        &self,
+        ctx: RpcContext,
+        jrpc_req: JrpcRequest,
+    ) -> JrpcResponse {
+        self.current().respond_raw_with_context(ctx, jrpc_req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{DynRpcService, FnService, RpcService, SwappableService};
+
+    fn version(v: u64) -> DynRpcService {
+        DynRpcService::new(FnService::new(move |_, _| async move {
+            smol::Timer::after(Duration::from_millis(20)).await;
+            Some(Ok(v.into()))
+        }))
+    }
+
+    #[test]
+    fn test_swap() {
+        smol::future::block_on(async move {
+            let service = SwappableService::new(version(1));
+            let (old, _) = futures_lite::future::zip(service.respond("f", &[]), async {
+                smol::Timer::after(Duration::from_millis(5)).await;
+                service.swap(version(2));
+            })
+            .await;
+            assert_eq!(old.unwrap().unwrap(), 1);
+            assert_eq!(service.respond("f", &[]).await.unwrap().unwrap(), 2);
+        });
+    }
+}
@@ -0,0 +1,167 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+
+use crate::{ClientKey, RpcContext, RpcService, ServerError};
+
+struct Tenants<S> {
+    live: HashMap<String, (Arc<S>, Instant)>,
+    last_sweep: Instant,
+}
+
+/// A TenantService routes every call to the service of the tenant it belongs to, for hosting isolated customer namespaces behind one endpoint. The tenant ID is taken from the context, like the [ClientKey] of a [crate::RateLimitService].
+///
+/// Tenant services are made with the factory on first use, and dropped after being idle for a while (10 minutes by default). Calls without a tenant ID, or for a tenant that the factory doesn't know, are treated as calls to unknown methods.
+pub struct TenantService<S: RpcService, F: Fn(&str) -> Option<S> + Send + Sync + 'static> {
+    key: ClientKey,
+    factory: F,
+    idle_timeout: Duration,
+    tenants: Mutex<Tenants<S>>,
+}
+
+impl<S: RpcService, F: Fn(&str) -> Option<S> + Send + Sync + 'static> TenantService<S, F> {
+    /// Creates a new TenantService, taking tenant IDs from the given key and making tenant services with the factory.
+    pub fn new(key: ClientKey, factory: F) -> Self {
+        Self {
+            key,
+            factory,
+            idle_timeout: Duration::from_secs(600),
+            tenants: Mutex::new(Tenants {
+                live: HashMap::new(),
+                last_sweep: Instant::now(),
+            }),
+        }
+    }
+
+    /// Sets how long a tenant service may be idle before it is dropped.
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Returns the IDs of the tenants whose services are currently live.
+    pub fn live_tenants(&self) -> Vec<String> {
+        let mut tenants: Vec<String> = self.tenants.lock().unwrap().live.keys().cloned().collect();
+        tenants.sort();
+        tenants
+    }
+
+    /// Drops the services of the tenants that have been idle for too long. This also happens automatically as calls come in.
+    pub fn evict_idle(&self) {
+        let mut tenants = self.tenants.lock().unwrap();
+        self.sweep(&mut tenants);
+    }
+
+    fn sweep(&self, tenants: &mut Tenants<S>) {
+        let now = Instant::now();
+        tenants.live.retain(|tenant, (_, last_used)| {
+            let keep = now.duration_since(*last_used) < self.idle_timeout;
+            if !keep {
+                log::debug!("dropping idle tenant {}", tenant);
+            }
+            keep
+        });
+        tenants.last_sweep = now;
+    }
+
+    fn tenant(&self, tenant: String) -> Option<Arc<S>> {
+        let mut tenants = self.tenants.lock().unwrap();
+        if tenants.last_sweep.elapsed() >= self.idle_timeout {
+            self.sweep(&mut tenants);
+        }
+        let now = Instant::now();
+        if let Some((service, last_used)) = tenants.live.get_mut(&tenant) {
+            *last_used = now;
+            return Some(service.clone());
+        }
+        let service = Arc::new((self.factory)(&tenant)?);
+        tenants.live.insert(tenant, (service.clone(), now));
+        Some(service)
+    }
+}
+
+#[async_trait]
+impl<S: RpcService, F: Fn(&str) -> Option<S> + Send + Sync + 'static> RpcService
+    for TenantService<S, F>
+{
+    async fn respond(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        self.respond_with_context(&RpcContext::default(), method, params)
+            .await
+    }
+
+    async fn respond_with_context(
+        &self,
+        ctx: &RpcContext,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Option<Result<serde_json::Value, ServerError>> {
+        let service = self.tenant(self.key.extract(ctx)?)?;
+        service.respond_with_context(ctx, method, params).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use crate::{ClientKey, FnService, RpcContext, RpcService, TenantService};
+
+    #[test]
+    fn test_tenants() {
+        smol::future::block_on(async move {
+            let made = Arc::new(AtomicU64::new(0));
+            let service = TenantService::new(ClientKey::Meta("tenant".into()), {
+                let made = made.clone();
+                move |tenant: &str| {
+                    if tenant == "unknown" {
+                        return None;
+                    }
+                    made.fetch_add(1, Ordering::SeqCst);
+                    let tenant = tenant.to_string();
+                    Some(FnService::new(move |_, _| {
+                        let tenant = tenant.clone();
+                        async move { Some(Ok(tenant.into())) }
+                    }))
+                }
+            })
+            .with_idle_timeout(Duration::from_millis(50));
+            let call = |tenant: &'static str| {
+                let service = &service;
+                let mut ctx = RpcContext::new();
+                ctx.meta.insert("tenant".into(), tenant.into());
+                async move {
+                    service
+                        .respond_with_context(&ctx, "f", &[])
+                        .await
+                        .map(|r| r.unwrap())
+                }
+            };
+            assert_eq!(call("a").await.unwrap(), "a");
+            assert_eq!(call("a").await.unwrap(), "a");
+            assert_eq!(call("b").await.unwrap(), "b");
+            assert!(call("unknown").await.is_none());
+            assert!(service.respond("f", &[]).await.is_none());
+            assert_eq!(made.load(Ordering::SeqCst), 2);
+            assert_eq!(service.live_tenants(), vec!["a", "b"]);
+            smol::Timer::after(Duration::from_millis(60)).await;
+            service.evict_idle();
+            assert!(service.live_tenants().is_empty());
+            assert_eq!(call("a").await.unwrap(), "a");
+            assert_eq!(made.load(Ordering::SeqCst), 3);
+        });
+    }
+}
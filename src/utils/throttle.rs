@@ -0,0 +1,85 @@
+use std::sync::Mutex;
+
+use async_lock::Semaphore;
+use async_trait::async_trait;
+
+use crate::{timer, utils::ratelimit::TokenBucket, JrpcRequest, JrpcResponse, RpcTransport};
+
+/// A ThrottleTransport keeps calls toward a server within a rate limit, and optionally a concurrency limit, by making excess calls wait. This is the client-side counterpart of [crate::RateLimitService], for staying within the quotas of public APIs. A batch counts as a single call.
+pub struct ThrottleTransport<T: RpcTransport> {
+    inner: T,
+    bucket: Mutex<TokenBucket>,
+    concurrency: Option<Semaphore>,
+}
+
+impl<T: RpcTransport> ThrottleTransport<T> {
+    /// Creates a new ThrottleTransport, allowing `rate` calls per second on average and bursts of up to `burst` calls.
+    pub fn new(inner: T, rate: f64, burst: u32) -> Self {
+        Self {
+            inner,
+            bucket: Mutex::new(TokenBucket::new(rate, burst)),
+            concurrency: None,
+        }
+    }
+
+    /// Also limits how many calls may be in flight at once.
+    pub fn with_max_concurrent(mut self, max_concurrent: usize) -> Self {
+        self.concurrency = Some(Semaphore::new(max_concurrent));
+        self
+    }
+
+    async fn throttled<R>(
+        &self,
+        call: impl std::future::Future<Output = Result<R, T::Error>>,
+    ) -> Result<R, T::Error> {
+        let _guard = match &self.concurrency {
+            Some(semaphore) => Some(semaphore.acquire().await),
+            None => None,
+        };
+        loop {
+            let taken = self.bucket.lock().unwrap().take();
+            match taken {
+                Ok(()) => break,
+                Err(wait) => timer::sleep(wait).await,
+            }
+        }
+        call.await
+    }
+}
+
+#[async_trait]
+impl<T: RpcTransport> RpcTransport for ThrottleTransport<T> {
+    type Error = T::Error;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        self.throttled(self.inner.call_raw(req)).await
+    }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        self.throttled(self.inner.call_raw_batch(reqs)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::{FnService, FnTransport, RpcTransport, ThrottleTransport};
+
+    #[test]
+    fn test_throttle() {
+        smol::future::block_on(async move {
+            let service = FnService::new(|_, _| async move { Some(Ok(().into())) });
+            let transport = ThrottleTransport::new(FnTransport::<()>::loopback(service), 50.0, 2);
+            let start = Instant::now();
+            // two calls in the burst, then three more at 20ms intervals
+            for _ in 0..5 {
+                transport.call("f", &[]).await.unwrap();
+            }
+            assert!(start.elapsed() >= Duration::from_millis(55));
+        });
+    }
+}
@@ -0,0 +1,85 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use thiserror::Error;
+
+use crate::{timer, JrpcRequest, JrpcResponse, RpcTransport};
+
+/// An error returned by a [TimeoutTransport].
+#[derive(Error, Debug)]
+pub enum TimeoutError<E> {
+    #[error("call timed out")]
+    Timeout,
+    #[error(transparent)]
+    Transport(E),
+}
+
+/// A TimeoutTransport wraps a transport, failing calls that take longer than a fixed duration with [TimeoutError::Timeout]. A batch counts as a single call.
+pub struct TimeoutTransport<T: RpcTransport> {
+    inner: T,
+    timeout: Duration,
+}
+
+impl<T: RpcTransport> TimeoutTransport<T> {
+    /// Creates a new TimeoutTransport.
+    pub fn new(inner: T, timeout: Duration) -> Self {
+        Self { inner, timeout }
+    }
+}
+
+#[async_trait]
+impl<T: RpcTransport> RpcTransport for TimeoutTransport<T> {
+    type Error = TimeoutError<T::Error>;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        timer::timeout(self.timeout, self.inner.call_raw(req))
+            .await
+            .ok_or(TimeoutError::Timeout)?
+            .map_err(TimeoutError::Transport)
+    }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        timer::timeout(self.timeout, self.inner.call_raw_batch(reqs))
+            .await
+            .ok_or(TimeoutError::Timeout)?
+            .map_err(TimeoutError::Transport)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{FnService, FnTransport, RpcTransport, TimeoutError, TimeoutTransport};
+
+    #[test]
+    fn test_timeout() {
+        smol::future::block_on(async move {
+            let service = FnService::new(|_, params| async move {
+                let ms = params[0].as_u64().unwrap();
+                smol::Timer::after(Duration::from_millis(ms)).await;
+                Some(Ok(ms.into()))
+            });
+            let transport = TimeoutTransport::new(
+                FnTransport::<()>::loopback(service),
+                Duration::from_millis(100),
+            );
+            assert_eq!(
+                transport
+                    .call("sleep", &[10.into()])
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .unwrap(),
+                10
+            );
+            assert!(matches!(
+                transport.call("sleep", &[500.into()]).await,
+                Err(TimeoutError::Timeout)
+            ));
+        });
+    }
+}
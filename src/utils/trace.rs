@@ -0,0 +1,195 @@
+use std::time::Instant;
+
+use async_trait::async_trait;
+use tracing::{field::Empty, Instrument};
+
+use crate::{JrpcRequest, JrpcResponse, RpcTransport};
+
+/// A TracingTransport wraps a transport, running every call within a `tracing` span. The span records the method, request ID, duration, and outcome of the call; failed calls also record their error class, one of `"transport"`, `"not_found"`, or `"server"`, and calls that got an error response its `error_code`.
+pub struct TracingTransport<T: RpcTransport> {
+    inner: T,
+}
+
+impl<T: RpcTransport> TracingTransport<T> {
+    /// Creates a new TracingTransport.
+    pub fn new(inner: T) -> Self {
+        Self { inner }
+    }
+}
+
+fn error_class(resp: &JrpcResponse) -> Option<&'static str> {
+    match &resp.error {
+        Some(err) if err.code == -32601 => Some("not_found"),
+        Some(_) => Some("server"),
+        None => None,
+    }
+}
+
+#[async_trait]
+impl<T: RpcTransport> RpcTransport for TracingTransport<T> {
+    type Error = T::Error;
+
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        let span = tracing::info_span!(
+            "rpc_call",
+            method = %req.method,
+            id = %req.id,
+            duration_ms = Empty,
+            outcome = Empty,
+            error = Empty,
+            error_code = Empty,
+        );
+        let start = Instant::now();
+        let result = self.inner.call_raw(req).instrument(span.clone()).await;
+        span.record("duration_ms", start.elapsed().as_millis() as u64);
+        match &result {
+            Ok(resp) => match error_class(resp) {
+                Some(class) => {
+                    span.record("outcome", "error");
+                    span.record("error", class);
+                    if let Some(err) = &resp.error {
+                        span.record("error_code", err.code);
+                    }
+                }
+                None => {
+                    span.record("outcome", "ok");
+                }
+            },
+            Err(_) => {
+                span.record("outcome", "error");
+                span.record("error", "transport");
+            }
+        }
+        result
+    }
+
+    async fn call_raw_batch(
+        &self,
+        reqs: Vec<JrpcRequest>,
+    ) -> Result<Vec<JrpcResponse>, Self::Error> {
+        let span = tracing::info_span!(
+            "rpc_batch",
+            len = reqs.len(),
+            duration_ms = Empty,
+            outcome = Empty,
+        );
+        let start = Instant::now();
+        let result = self
+            .inner
+            .call_raw_batch(reqs)
+            .instrument(span.clone())
+            .await;
+        span.record("duration_ms", start.elapsed().as_millis() as u64);
+        span.record("outcome", if result.is_ok() { "ok" } else { "error" });
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeMap,
+        fmt::Debug,
+        sync::{Arc, Mutex},
+    };
+
+    use tracing::{
+        field::{Field, Visit},
+        span::{Attributes, Id, Record},
+        Event, Metadata, Subscriber,
+    };
+
+    use crate::{FnService, FnTransport, RpcTransport, ServerError, TracingTransport};
+
+    type Spans = Arc<Mutex<Vec<(&'static str, BTreeMap<String, String>)>>>;
+
+    /// Remembers the name and fields of every span.
+    struct Capture(Spans);
+
+    struct Fields<'a>(&'a mut BTreeMap<String, String>);
+
+    impl Visit for Fields<'_> {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.insert(field.name().into(), value.into());
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            self.0.insert(field.name().into(), format!("{:?}", value));
+        }
+    }
+
+    impl Subscriber for Capture {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut spans = self.0.lock().unwrap();
+            let mut fields = BTreeMap::new();
+            span.record(&mut Fields(&mut fields));
+            spans.push((span.metadata().name(), fields));
+            Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, span: &Id, values: &Record<'_>) {
+            let mut spans = self.0.lock().unwrap();
+            values.record(&mut Fields(&mut spans[span.into_u64() as usize - 1].1));
+        }
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, _: &Event<'_>) {}
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn test_tracing_spans() {
+        let spans = Spans::default();
+        let _guard = tracing::subscriber::set_default(Capture(spans.clone()));
+        smol::future::block_on(async move {
+            let service = FnService::new(|method, _| {
+                let method = method.to_string();
+                async move {
+                    match method.as_str() {
+                        "f" => Some(Ok(1.into())),
+                        "fail" => Some(Err(ServerError::with_jrpc_code(
+                            -32042,
+                            "failed",
+                            serde_json::Value::Null,
+                        ))),
+                        _ => None,
+                    }
+                }
+            });
+            let transport = TracingTransport::new(FnTransport::<()>::loopback(service));
+            assert_eq!(transport.call("f", &[]).await.unwrap().unwrap().unwrap(), 1);
+            assert!(transport.call("g", &[]).await.unwrap().is_none());
+            assert!(transport.call("fail", &[]).await.unwrap().unwrap().is_err());
+        });
+        let spans = spans.lock().unwrap();
+        assert_eq!(spans.len(), 3);
+        assert!(spans.iter().all(|(name, _)| *name == "rpc_call"));
+        let fields: Vec<_> = spans
+            .iter()
+            .map(|(_, fields)| {
+                (
+                    fields["method"].as_str(),
+                    fields["outcome"].as_str(),
+                    fields.get("error").map(|s| s.as_str()),
+                    fields.get("error_code").map(|s| s.as_str()),
+                )
+            })
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("f", "ok", None, None),
+                ("g", "error", Some("not_found"), Some("-32601")),
+                ("fail", "error", Some("server"), Some("-32042")),
+            ]
+        );
+    }
+}